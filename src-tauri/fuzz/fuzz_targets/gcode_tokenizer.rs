@@ -0,0 +1,13 @@
+#![no_main]
+
+use cnc_lib::gcode;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let program = String::from_utf8_lossy(data);
+    for line in program.lines() {
+        for word in gcode::tokenize_line(line) {
+            assert!(word.letter.is_ascii_uppercase());
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use cnc_lib::grbl_protocol;
+use libfuzzer_sys::fuzz_target;
+
+// `$$`, `$#`, `$G`, `$I` and the welcome banner all arrive on the same untrusted stream
+fuzz_target!(|data: &[u8]| {
+    let response = String::from_utf8_lossy(data);
+    let _ = grbl_protocol::parse_settings(&response);
+    let _ = grbl_protocol::parse_offsets(&response);
+    let _ = grbl_protocol::parse_parser_state(&response);
+    let _ = grbl_protocol::parse_build_info(&response);
+    for line in response.lines() {
+        let _ = grbl_protocol::parse_welcome_banner(line);
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use cnc_lib::grbl_protocol;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let response = String::from_utf8_lossy(data);
+    if let Some(report) = grbl_protocol::parse_status_report(&response) {
+        // The state always comes from inside the brackets
+        assert!(!report.state.contains('<') && !report.state.contains('>'));
+    }
+});
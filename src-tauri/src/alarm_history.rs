@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Oldest records are dropped beyond this
+const MAX_RECORDS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmKind {
+    Alarm,
+    Error,
+}
+
+/// One alarm or error with the machine context it happened in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmRecord {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub kind: AlarmKind,
+    pub code: Option<u32>,
+    /// Decoded description of the code
+    pub message: String,
+    /// Line as received, e.g. `ALARM:2`
+    pub raw: String,
+    pub machine_state: Option<String>,
+    pub machine_pos: Option<Vec<f32>>,
+    /// Last job line the controller had accepted, if a job was running
+    pub job_line: Option<usize>,
+    /// Command that was rejected, for errors answering a command we waited on
+    pub command: Option<String>,
+    /// Machine the record came from (see `CncDevice::machine_key`)
+    pub machine: Option<String>,
+}
+
+/// Persistent list of alarms and errors, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlarmHistory {
+    pub records: Vec<AlarmRecord>,
+}
+
+impl AlarmHistory {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("alarm_history.json")
+    }
+
+    pub fn push(&mut self, record: AlarmRecord) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            let excess = self.records.len() - MAX_RECORDS;
+            self.records.drain(..excess);
+        }
+    }
+
+    /// Newest first, optionally only one kind and/or code
+    pub fn query(
+        &self,
+        kind: Option<AlarmKind>,
+        code: Option<u32>,
+        limit: Option<usize>,
+    ) -> Vec<AlarmRecord> {
+        self.records
+            .iter()
+            .rev()
+            .filter(|r| kind.is_none_or(|k| r.kind == k))
+            .filter(|r| code.is_none_or(|c| r.code == Some(c)))
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Machine events that can send an alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertEvent {
+    JobComplete,
+    /// Stopped before the end, or ended without the streamer saying how
+    JobStopped,
+    Alarm,
+    StreamStall,
+}
+
+impl AlertEvent {
+    fn name(self) -> &'static str {
+        match self {
+            AlertEvent::JobComplete => "job_complete",
+            AlertEvent::JobStopped => "job_stopped",
+            AlertEvent::Alarm => "alarm",
+            AlertEvent::StreamStall => "stream_stall",
+        }
+    }
+}
+
+/// HTTP POST sent for one event. `body` may use `{{event}}`, `{{machine}}`, `{{job_name}}`,
+/// `{{duration}}`, `{{duration_seconds}}`, `{{error}}` and `{{timestamp}}`; values are
+/// escaped for JSON when the content type is JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertTemplate {
+    pub event: AlertEvent,
+    pub url: String,
+    /// `application/json` when not given
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl AlertTemplate {
+    fn content_type(&self) -> &str {
+        self.content_type.as_deref().unwrap_or("application/json")
+    }
+}
+
+/// Alert templates, any number per event (e.g. Slack and ntfy for the same alarm)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertWebhooks {
+    pub templates: Vec<AlertTemplate>,
+}
+
+impl AlertWebhooks {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("alert_webhooks.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(template) = self
+            .templates
+            .iter()
+            .find(|t| !t.url.starts_with("http://") && !t.url.starts_with("https://"))
+        {
+            return Err(anyhow!(
+                "The {} alert needs an http:// or https:// URL",
+                template.event.name()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Post every template for `event` from a background thread, so a slow or unreachable
+    /// server never holds up the machine
+    pub fn send(&self, event: AlertEvent, context: &AlertContext) {
+        for template in self.templates.iter().filter(|t| t.event == event) {
+            let template = template.clone();
+            let body = render(&template, event, context);
+            thread::spawn(move || {
+                if let Err(e) = post(&template, &body) {
+                    println!(
+                        "⚠️  {} alert to {} failed: {}",
+                        event.name(),
+                        template.url,
+                        e
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// What an alert can say about the event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlertContext {
+    pub machine: Option<String>,
+    pub job_name: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Template body with the placeholders filled in; unknown placeholders are left as they are
+pub fn render(template: &AlertTemplate, event: AlertEvent, context: &AlertContext) -> String {
+    let json = template.content_type().contains("json");
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let value = |name: &str| -> Option<String> {
+        Some(match name {
+            "event" => event.name().to_string(),
+            "machine" => context.machine.clone().unwrap_or_default(),
+            "job_name" => context.job_name.clone().unwrap_or_default(),
+            "duration" => context
+                .duration_seconds
+                .map(format_duration)
+                .unwrap_or_default(),
+            "duration_seconds" => context
+                .duration_seconds
+                .map(|s| format!("{:.0}", s))
+                .unwrap_or_default(),
+            "error" => context.error.clone().unwrap_or_default(),
+            "timestamp" => timestamp.to_string(),
+            _ => return None,
+        })
+    };
+
+    let mut out = String::new();
+    let mut rest = template.body.as_str();
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = after[..end].trim();
+        match value(name) {
+            Some(text) if json => escape_json(&text, &mut out),
+            Some(text) => out.push_str(&text),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `text` as the inside of a JSON string
+fn escape_json(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+/// `1h 02m 03s`, `4m 05s` or `12s`
+fn format_duration(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}h {:02}m {:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+pub fn post(template: &AlertTemplate, body: &str) -> Result<()> {
+    ureq::post(&template.url)
+        .timeout(HTTP_TIMEOUT)
+        .set("Content-Type", template.content_type())
+        .send_string(body)?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::machine_profile::AXIS_LETTERS;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How the axes shown in the UI relate to the machine's, for machines whose physical
+/// orientation doesn't match Grbl's configured directions. Applied to displayed positions
+/// and to jogs; G-code and raw status reports stay in machine axes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisMapping {
+    /// UI axes that move and count the opposite way to the machine axis behind them
+    pub inverted: Vec<char>,
+    /// The UI's X is the machine's Y and the other way round
+    pub swap_xy: bool,
+    /// Names shown for UI axes, e.g. "Y" -> "Front/Back"
+    pub labels: BTreeMap<String, String>,
+}
+
+impl AxisMapping {
+    pub fn validate(&self) -> Result<()> {
+        let unknown = self
+            .inverted
+            .iter()
+            .map(|c| c.to_ascii_uppercase())
+            .chain(self.labels.keys().filter_map(|k| k.chars().next()))
+            .find(|c| !AXIS_LETTERS.contains(c));
+        if let Some(axis) = unknown {
+            return Err(anyhow!("Unknown axis {} in axis mapping", axis));
+        }
+        if self.labels.keys().any(|k| k.chars().count() != 1) {
+            return Err(anyhow!("Axis labels are keyed by a single axis letter"));
+        }
+        Ok(())
+    }
+
+    /// The other axis of the swapped pair; the mapping is its own inverse
+    fn swapped(&self, axis: char) -> char {
+        match (self.swap_xy, axis) {
+            (true, 'X') => 'Y',
+            (true, 'Y') => 'X',
+            _ => axis,
+        }
+    }
+
+    fn is_inverted(&self, ui_axis: char) -> bool {
+        self.inverted
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&ui_axis))
+    }
+
+    /// Machine axis and signed distance for a jog the user asked for on a UI axis
+    pub fn jog_to_machine(&self, ui_axis: &str, distance: f32) -> (String, f32) {
+        let Some(letter) = ui_axis
+            .trim()
+            .chars()
+            .next()
+            .map(|c| c.to_ascii_uppercase())
+        else {
+            return (ui_axis.to_string(), distance);
+        };
+        let sign = if self.is_inverted(letter) { -1.0 } else { 1.0 };
+        (self.swapped(letter).to_string(), distance * sign)
+    }
+
+    /// A position in machine axis order as UI axes and values, in UI axis order
+    pub fn to_display(&self, values: &[f32]) -> Vec<(char, f32)> {
+        AXIS_LETTERS
+            .iter()
+            .take(values.len())
+            .filter_map(|ui_axis| {
+                let machine = AXIS_LETTERS
+                    .iter()
+                    .position(|a| *a == self.swapped(*ui_axis))?;
+                let value = *values.get(machine)?;
+                let sign = if self.is_inverted(*ui_axis) {
+                    -1.0
+                } else {
+                    1.0
+                };
+                Some((*ui_axis, value * sign))
+            })
+            .collect()
+    }
+
+    /// Name to show for a UI axis
+    pub fn label(&self, ui_axis: char) -> String {
+        self.labels
+            .get(&ui_axis.to_string())
+            .cloned()
+            .unwrap_or_else(|| ui_axis.to_string())
+    }
+}
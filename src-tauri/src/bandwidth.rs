@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Seconds of history kept per connection
+const HISTORY_SECONDS: usize = 3600;
+
+/// Traffic during one interval of the connection
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BandwidthSample {
+    /// Start of the interval, seconds since the Unix epoch
+    pub second: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub lines_sent: u64,
+    pub lines_received: u64,
+}
+
+impl BandwidthSample {
+    fn add(&mut self, other: &BandwidthSample) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.lines_sent += other.lines_sent;
+        self.lines_received += other.lines_received;
+    }
+}
+
+/// Rates over the last minute
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BandwidthRates {
+    pub bytes_sent_per_minute: f64,
+    pub bytes_received_per_minute: f64,
+    pub lines_sent_per_second: f64,
+    pub lines_received_per_second: f64,
+}
+
+/// Traffic over time for graphing, oldest bucket first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BandwidthReport {
+    pub bucket_seconds: u64,
+    /// Buckets with no traffic are included so the series has no gaps
+    pub buckets: Vec<BandwidthSample>,
+    pub last_minute: BandwidthRates,
+}
+
+/// Per-second traffic counts for the current connection
+#[derive(Debug, Default)]
+pub struct BandwidthHistory {
+    seconds: VecDeque<BandwidthSample>,
+}
+
+impl BandwidthHistory {
+    pub fn record_sent(&mut self, now_ms: u64, bytes: usize, lines: usize) {
+        let sample = self.sample_at(now_ms / 1000);
+        sample.bytes_sent += bytes as u64;
+        sample.lines_sent += lines as u64;
+    }
+
+    pub fn record_received(&mut self, now_ms: u64, bytes: usize, lines: usize) {
+        let sample = self.sample_at(now_ms / 1000);
+        sample.bytes_received += bytes as u64;
+        sample.lines_received += lines as u64;
+    }
+
+    fn sample_at(&mut self, second: u64) -> &mut BandwidthSample {
+        // A clock stepping back lands in the latest bucket rather than reordering them
+        if self.seconds.back().is_none_or(|s| s.second < second) {
+            self.seconds.push_back(BandwidthSample {
+                second,
+                ..Default::default()
+            });
+            if self.seconds.len() > HISTORY_SECONDS {
+                self.seconds.pop_front();
+            }
+        }
+        self.seconds.back_mut().expect("bucket was just added")
+    }
+
+    /// Traffic in buckets of `bucket_seconds` up to `now_ms`, at most `limit` of the newest
+    pub fn report(
+        &self,
+        now_ms: u64,
+        bucket_seconds: u64,
+        limit: Option<usize>,
+    ) -> BandwidthReport {
+        let bucket_seconds = bucket_seconds.max(1);
+        let now = now_ms / 1000;
+        let mut buckets: Vec<BandwidthSample> = Vec::new();
+        if let Some(first) = self.seconds.front() {
+            // After a long quiet spell the series still only spans the kept history
+            let oldest = first.second.max(now.saturating_sub(HISTORY_SECONDS as u64));
+            let start = oldest - oldest % bucket_seconds;
+            let mut second = start;
+            while second <= now {
+                buckets.push(BandwidthSample {
+                    second,
+                    ..Default::default()
+                });
+                second += bucket_seconds;
+            }
+            for sample in &self.seconds {
+                let index = ((sample.second.saturating_sub(start)) / bucket_seconds) as usize;
+                if let Some(bucket) = buckets.get_mut(index) {
+                    bucket.add(sample);
+                }
+            }
+        }
+        if let Some(limit) = limit {
+            let excess = buckets.len().saturating_sub(limit);
+            buckets.drain(..excess);
+        }
+
+        // The current second is still filling up, so the minute ends before it
+        let mut minute = BandwidthSample::default();
+        for sample in self
+            .seconds
+            .iter()
+            .filter(|s| s.second < now && s.second + 60 >= now)
+        {
+            minute.add(sample);
+        }
+        BandwidthReport {
+            bucket_seconds,
+            buckets,
+            last_minute: BandwidthRates {
+                bytes_sent_per_minute: minute.bytes_sent as f64,
+                bytes_received_per_minute: minute.bytes_received as f64,
+                lines_sent_per_second: minute.lines_sent as f64 / 60.0,
+                lines_received_per_second: minute.lines_received as f64 / 60.0,
+            },
+        }
+    }
+}
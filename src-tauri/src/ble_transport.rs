@@ -0,0 +1,295 @@
+use crate::cnc_comm::CncDevice;
+use crate::transport::{CncTransport, SocketOptions, TransportKind};
+use anyhow::{anyhow, Result};
+use btleplug::api::{
+    Central, CharPropFlags, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use futures::stream::StreamExt;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+/// GATT layout of a BLE serial bridge
+struct UartProfile {
+    name: &'static str,
+    service: Uuid,
+    /// Characteristic we write commands to
+    rx: Uuid,
+    /// Characteristic the controller's output arrives on as notifications
+    tx: Uuid,
+}
+
+/// Nordic UART Service, used by most BLE serial bridges
+const NUS_SERVICE: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+/// HM-10 and its clones (CC41, JDY-08, MLT-BT05): one characteristic for both directions
+const HM10_SERVICE: Uuid = Uuid::from_u128(0x0000ffe0_0000_1000_8000_00805f9b34fb);
+const HM10_DATA: Uuid = Uuid::from_u128(0x0000ffe1_0000_1000_8000_00805f9b34fb);
+
+/// Tried in order when connecting
+const UART_PROFILES: [UartProfile; 2] = [
+    UartProfile {
+        name: "Nordic UART",
+        service: NUS_SERVICE,
+        rx: Uuid::from_u128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e),
+        tx: Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e),
+    },
+    UartProfile {
+        name: "HM-10",
+        service: HM10_SERVICE,
+        rx: HM10_DATA,
+        tx: HM10_DATA,
+    },
+];
+
+fn uart_services() -> Vec<Uuid> {
+    UART_PROFILES.iter().map(|p| p.service).collect()
+}
+
+/// Largest write that fits the default ATT MTU
+const BLE_CHUNK_SIZE: usize = 20;
+const READ_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// btleplug is async; the rest of the manager is blocking, so each transport drives its own runtime
+fn runtime() -> Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?)
+}
+
+async fn first_adapter() -> Result<Adapter> {
+    let manager = Manager::new().await?;
+    manager
+        .adapters()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No Bluetooth adapter found"))
+}
+
+/// Scan for peripherals advertising a BLE UART service.
+/// For BLE devices `ip` holds the Bluetooth address and `port` is unused.
+pub fn scan(timeout_ms: u64) -> Result<Vec<CncDevice>> {
+    let runtime = runtime()?;
+    runtime.block_on(async {
+        let central = first_adapter().await?;
+        central
+            .start_scan(ScanFilter {
+                services: uart_services(),
+            })
+            .await?;
+        tokio::time::sleep(Duration::from_millis(timeout_ms)).await;
+        central.stop_scan().await?;
+
+        let mut devices = Vec::new();
+        for peripheral in central.peripherals().await? {
+            let Some(properties) = peripheral.properties().await? else {
+                continue;
+            };
+            // Not every platform honours the scan filter
+            if !properties
+                .services
+                .iter()
+                .any(|s| uart_services().contains(s))
+            {
+                continue;
+            }
+            let address = properties.address.to_string();
+            println!("📶 Found BLE UART device {}", address);
+            devices.push(CncDevice {
+                name: properties
+                    .local_name
+                    .unwrap_or_else(|| format!("BLE UART {}", address)),
+                ip: address.clone(),
+                port: 0,
+                mac: Some(address),
+                firmware: None,
+                transport: TransportKind::Ble,
+                baud_rate: None,
+                certificate_pin: None,
+                socket: SocketOptions::default(),
+            });
+        }
+        Ok(devices)
+    })
+}
+
+/// Grbl stream over a BLE UART service
+pub struct BleTransport {
+    runtime: Runtime,
+    peripheral: Peripheral,
+    rx_char: Characteristic,
+    write_type: WriteType,
+    incoming: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    address: String,
+}
+
+impl BleTransport {
+    /// Find the peripheral by address, connect, and subscribe to its UART output.
+    /// Modules that require a PIN must be paired with the OS first.
+    pub fn connect(address: &str, scan_timeout_ms: u64) -> Result<Self> {
+        let runtime = runtime()?;
+        let (sender, incoming) = mpsc::channel();
+
+        let (peripheral, rx_char) = runtime.block_on(async {
+            let central = first_adapter().await?;
+            central
+                .start_scan(ScanFilter {
+                    services: uart_services(),
+                })
+                .await?;
+
+            let deadline = Instant::now() + Duration::from_millis(scan_timeout_ms);
+            let peripheral = loop {
+                let mut found = None;
+                for peripheral in central.peripherals().await? {
+                    if peripheral
+                        .address()
+                        .to_string()
+                        .eq_ignore_ascii_case(address)
+                    {
+                        found = Some(peripheral);
+                        break;
+                    }
+                }
+                if let Some(peripheral) = found {
+                    break peripheral;
+                }
+                if Instant::now() > deadline {
+                    let _ = central.stop_scan().await;
+                    return Err(anyhow!("BLE device {} not found", address));
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            };
+            central.stop_scan().await?;
+
+            if !peripheral.is_connected().await? {
+                peripheral.connect().await?;
+            }
+            peripheral.discover_services().await?;
+
+            let characteristics = peripheral.characteristics();
+            let find = |service: Uuid, uuid: Uuid| {
+                characteristics
+                    .iter()
+                    .find(|c| c.service_uuid == service && c.uuid == uuid)
+                    .cloned()
+            };
+            let (profile, rx_char, tx_char) = UART_PROFILES
+                .iter()
+                .find_map(|p| Some((p, find(p.service, p.rx)?, find(p.service, p.tx)?)))
+                .ok_or_else(|| anyhow!("{} has no BLE UART service", address))?;
+            let tx_uuid = tx_char.uuid;
+            println!("📶 {} speaks {}", address, profile.name);
+
+            peripheral.subscribe(&tx_char).await?;
+            let mut notifications = peripheral.notifications().await?;
+            tokio::spawn(async move {
+                while let Some(notification) = notifications.next().await {
+                    if notification.uuid == tx_uuid && sender.send(notification.value).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Ok::<_, anyhow::Error>((peripheral, rx_char))
+        })?;
+
+        let write_type = if rx_char
+            .properties
+            .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+        {
+            WriteType::WithoutResponse
+        } else {
+            WriteType::WithResponse
+        };
+
+        println!("📶 Connected to BLE UART {}", address);
+        Ok(Self {
+            runtime,
+            peripheral,
+            rx_char,
+            write_type,
+            incoming,
+            pending: Vec::new(),
+            address: address.to_string(),
+        })
+    }
+}
+
+impl Read for BleTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.incoming.recv_timeout(READ_TIMEOUT) {
+                Ok(data) => self.pending = data,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "BLE read timed out",
+                    ))
+                }
+                // Notification stream ended: the peripheral disconnected
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+            // Notifications are tiny; gather whatever else has already arrived
+            while let Ok(more) = self.incoming.try_recv() {
+                self.pending.extend(more);
+            }
+        }
+
+        let size = buf.len().min(self.pending.len());
+        buf[..size].copy_from_slice(&self.pending[..size]);
+        self.pending.drain(..size);
+        Ok(size)
+    }
+}
+
+impl Write for BleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.chunks(BLE_CHUNK_SIZE) {
+            self.runtime
+                .block_on(self.peripheral.write(&self.rx_char, chunk, self.write_type))
+                .map_err(io::Error::other)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CncTransport for BleTransport {
+    fn describe(&self) -> String {
+        format!("ble://{}", self.address)
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.runtime
+            .block_on(self.peripheral.disconnect())
+            .map_err(io::Error::other)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Ok(more) = self.incoming.try_recv() {
+            self.pending.extend(more);
+        }
+        if self.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "No BLE data waiting",
+            ));
+        }
+        self.read(buf)
+    }
+}
+
+impl Drop for BleTransport {
+    fn drop(&mut self) {
+        let _ = self.runtime.block_on(self.peripheral.disconnect());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A named note on one line of a program, e.g. "start of pocket 3"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// 1-based line number when the bookmark was made
+    pub line: usize,
+    pub name: String,
+    pub note: Option<String>,
+    /// Text of the line, used to find it again if the program is edited
+    pub line_text: String,
+}
+
+/// A bookmark resolved against the program as it is now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkLocation {
+    pub bookmark: Bookmark,
+    /// Where the bookmarked line is now; None if it can't be found any more
+    pub current_line: Option<usize>,
+}
+
+/// One program line for peeking around a bookmark
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramLine {
+    pub line: usize,
+    pub text: String,
+}
+
+/// Bookmarks for one program in the G-code library
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProgramBookmarks {
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl ProgramBookmarks {
+    pub fn path_for(data_dir: &Path, program_name: &str) -> PathBuf {
+        data_dir
+            .join("bookmarks")
+            .join(format!("{}.json", storage::file_key(program_name)))
+    }
+
+    /// Bookmark a line, replacing any bookmark already on it
+    pub fn add(
+        &mut self,
+        program: &str,
+        line: usize,
+        name: &str,
+        note: Option<String>,
+    ) -> Result<()> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("Bookmarks need a name"));
+        }
+        let text = program
+            .lines()
+            .nth(line.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("Line {} is not in the program", line))?;
+
+        self.bookmarks.retain(|b| b.line != line);
+        self.bookmarks.push(Bookmark {
+            line,
+            name: name.trim().to_string(),
+            note: note.filter(|n| !n.trim().is_empty()),
+            line_text: text.trim().to_string(),
+        });
+        self.bookmarks.sort_by_key(|b| b.line);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, line: usize) -> bool {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|b| b.line != line);
+        self.bookmarks.len() != before
+    }
+
+    /// Resolve every bookmark against the current program. A line that no longer matches
+    /// is looked for nearby, so inserting or deleting lines doesn't lose bookmarks.
+    pub fn locate(&self, program: &str) -> Vec<BookmarkLocation> {
+        let lines: Vec<&str> = program.lines().map(str::trim).collect();
+        self.bookmarks
+            .iter()
+            .map(|bookmark| BookmarkLocation {
+                bookmark: bookmark.clone(),
+                current_line: find_line(&lines, bookmark),
+            })
+            .collect()
+    }
+
+    pub fn find(&self, program: &str, name: &str) -> Result<BookmarkLocation> {
+        self.locate(program)
+            .into_iter()
+            .find(|l| l.bookmark.name == name)
+            .ok_or_else(|| anyhow!("No bookmark named {}", name))
+    }
+}
+
+/// The matching line closest to where the bookmark was made
+fn find_line(lines: &[&str], bookmark: &Bookmark) -> Option<usize> {
+    let original = bookmark.line.checked_sub(1)?;
+    let matches = |index: usize| lines.get(index) == Some(&bookmark.line_text.as_str());
+    (0..lines.len().max(original + 1))
+        .flat_map(|distance| {
+            [
+                original.checked_sub(distance),
+                original.checked_add(distance),
+            ]
+        })
+        .flatten()
+        .find(|index| matches(*index))
+        .map(|index| index + 1)
+}
+
+/// `context` lines either side of a line, clamped to the program
+pub fn peek(program: &str, line: usize, context: usize) -> Vec<ProgramLine> {
+    let first = line.saturating_sub(context).max(1);
+    program
+        .lines()
+        .enumerate()
+        .skip(first - 1)
+        .take(line.saturating_add(context).saturating_sub(first) + 1)
+        .map(|(index, text)| ProgramLine {
+            line: index + 1,
+            text: text.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,291 @@
+use crate::gcode::format_value;
+use crate::gcode_preprocess::BacklashCompensation;
+use crate::machine_profile::AXIS_LETTERS;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f32::consts::SQRT_2;
+
+/// Grbl's steps/mm setting for X; Y and Z follow
+const STEPS_PER_MM_SETTING: u16 = 100;
+
+/// Shape cut by a calibration program. Everything sits in work coordinates with X0 Y0 at
+/// the lower left of the test and Z0 on the stock surface.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "test", rename_all = "snake_case")]
+pub enum CalibrationTest {
+    /// Square boss of `size` mm, for steps/mm on X and Y
+    Square { size: f32 },
+    /// Circle inside a diamond inside a square, each one level higher than the last.
+    /// The circle shows backlash and axis mismatch, the diamond squareness.
+    CircleDiamondSquare { size: f32 },
+    /// A row of holes, for the size a pin or insert actually fits
+    HoleSizes { diameters: Vec<f32>, spacing: f32 },
+}
+
+/// Tool and feeds for a calibration cut
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationCut {
+    pub test: CalibrationTest,
+    pub tool_diameter: f32,
+    /// Total depth, mm below the surface
+    pub depth: f32,
+    pub step_down: f32,
+    pub feed_rate: f32,
+    pub plunge_rate: f32,
+    pub spindle_rpm: f32,
+    /// Work Z for travel between cuts
+    pub safe_z: f32,
+}
+
+impl CalibrationCut {
+    fn validate(&self) -> Result<()> {
+        let positive = [
+            self.tool_diameter,
+            self.depth,
+            self.step_down,
+            self.feed_rate,
+            self.plunge_rate,
+            self.safe_z,
+        ];
+        if positive.iter().any(|v| !v.is_finite() || *v <= 0.0) {
+            return Err(anyhow!(
+                "Tool diameter, depth, step down, feeds and safe Z must be above zero"
+            ));
+        }
+        match &self.test {
+            CalibrationTest::Square { size } | CalibrationTest::CircleDiamondSquare { size } => {
+                if !size.is_finite() || *size <= 4.0 * self.tool_diameter {
+                    return Err(anyhow!(
+                        "The test must be more than four tool diameters across"
+                    ));
+                }
+            }
+            CalibrationTest::HoleSizes { diameters, spacing } => {
+                if diameters.is_empty() {
+                    return Err(anyhow!("Give at least one hole diameter"));
+                }
+                if diameters.iter().any(|d| *d <= self.tool_diameter) {
+                    return Err(anyhow!("Every hole must be wider than the tool"));
+                }
+                if !spacing.is_finite() || *spacing < 0.0 {
+                    return Err(anyhow!("Hole spacing can't be negative"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Program for a calibration cut
+pub fn generate(cut: &CalibrationCut) -> Result<String> {
+    cut.validate()?;
+    let mut writer = CutWriter::new(cut);
+    let r = cut.tool_diameter / 2.0;
+    match &cut.test {
+        CalibrationTest::Square { size } => {
+            writer.comment(&format!("Square {}mm: measure across X and Y", size));
+            writer.polygon(&square(0.0, *size, r), cut.depth);
+        }
+        CalibrationTest::CircleDiamondSquare { size } => {
+            writer.comment(&format!(
+                "Circle-diamond-square {}mm: measure the circle across X and Y",
+                size
+            ));
+            // Each shape is inset a tool width from the last so the grooves stay apart
+            let center = size / 2.0;
+            let diamond_half = center - cut.tool_diameter * 1.5;
+            let circle_radius = diamond_half / SQRT_2 - cut.tool_diameter * 1.5;
+            writer.circle(center, center, circle_radius + r, cut.depth / 3.0);
+            let offset = r * SQRT_2;
+            writer.polygon(
+                &[
+                    (center, center - diamond_half - offset),
+                    (center + diamond_half + offset, center),
+                    (center, center + diamond_half + offset),
+                    (center - diamond_half - offset, center),
+                ],
+                cut.depth * 2.0 / 3.0,
+            );
+            writer.polygon(&square(0.0, *size, r), cut.depth);
+        }
+        CalibrationTest::HoleSizes { diameters, spacing } => {
+            let pitch = diameters.iter().cloned().fold(0.0, f32::max) + spacing;
+            let mut x = pitch / 2.0;
+            for diameter in diameters {
+                writer.comment(&format!("Hole {}mm", diameter));
+                writer.circle(x, pitch / 2.0, diameter / 2.0 - r, cut.depth);
+                x += pitch;
+            }
+        }
+    }
+    Ok(writer.finish())
+}
+
+/// Corners of the toolpath around a square boss from `min` to `max`
+fn square(min: f32, max: f32, r: f32) -> [(f32, f32); 4] {
+    [
+        (min - r, min - r),
+        (min - r, max + r),
+        (max + r, max + r),
+        (max + r, min - r),
+    ]
+}
+
+struct CutWriter<'a> {
+    cut: &'a CalibrationCut,
+    lines: Vec<String>,
+}
+
+impl<'a> CutWriter<'a> {
+    fn new(cut: &'a CalibrationCut) -> Self {
+        let lines = vec![
+            "G21 G90 G17".to_string(),
+            format!("G0 Z{}", num(cut.safe_z)),
+            format!("M3 S{}", format_value(cut.spindle_rpm as f64, 0)),
+        ];
+        Self { cut, lines }
+    }
+
+    fn comment(&mut self, text: &str) {
+        self.lines.push(format!("({})", text));
+    }
+
+    /// Depths of each pass down to `depth`
+    fn passes(&self, depth: f32) -> Vec<f32> {
+        let count = (depth / self.cut.step_down).ceil().max(1.0) as usize;
+        (1..=count)
+            .map(|i| -(depth * i as f32 / count as f32))
+            .collect()
+    }
+
+    fn plunge(&mut self, x: f32, y: f32, z: f32) {
+        self.lines.push(format!("G0 X{} Y{}", num(x), num(y)));
+        self.lines.push(format!(
+            "G1 Z{} F{}",
+            num(z),
+            format_value(self.cut.plunge_rate as f64, 0)
+        ));
+    }
+
+    fn retract(&mut self) {
+        self.lines.push(format!("G0 Z{}", num(self.cut.safe_z)));
+    }
+
+    /// Closed toolpath through `corners`, one loop per pass
+    fn polygon(&mut self, corners: &[(f32, f32)], depth: f32) {
+        let (start_x, start_y) = corners[0];
+        for z in self.passes(depth) {
+            self.plunge(start_x, start_y, z);
+            let feed = format_value(self.cut.feed_rate as f64, 0);
+            for (i, (x, y)) in corners.iter().skip(1).chain(&corners[..1]).enumerate() {
+                let f = if i == 0 {
+                    format!(" F{}", feed)
+                } else {
+                    String::new()
+                };
+                self.lines
+                    .push(format!("G1 X{} Y{}{}", num(*x), num(*y), f));
+            }
+        }
+        self.retract();
+    }
+
+    /// Full circles of toolpath `radius` around a center, one per pass, clockwise
+    fn circle(&mut self, cx: f32, cy: f32, radius: f32, depth: f32) {
+        let start_x = cx - radius;
+        for z in self.passes(depth) {
+            self.plunge(start_x, cy, z);
+            self.lines.push(format!(
+                "G2 X{} Y{} I{} J0 F{}",
+                num(start_x),
+                num(cy),
+                num(radius),
+                format_value(self.cut.feed_rate as f64, 0)
+            ));
+        }
+        self.retract();
+    }
+
+    fn finish(mut self) -> String {
+        self.lines.push("M5".to_string());
+        self.lines.push("M30".to_string());
+        self.lines.join("\n") + "\n"
+    }
+}
+
+fn num(value: f32) -> String {
+    format_value(value as f64, 3)
+}
+
+/// A dimension of the cut test piece, as designed and as measured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxisMeasurement {
+    pub axis: char,
+    pub expected: f32,
+    pub measured: f32,
+}
+
+/// New steps/mm for an axis that cut the wrong size
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepsPerMmSuggestion {
+    pub axis: char,
+    /// `$100`, `$101` or `$102`
+    pub setting: u16,
+    pub current: f32,
+    pub suggested: f32,
+    /// How far the measured size was off, positive when it came out too big
+    pub error_percent: f32,
+}
+
+fn check_measurement(m: &AxisMeasurement) -> Result<usize> {
+    let index = AXIS_LETTERS
+        .iter()
+        .take(3)
+        .position(|c| *c == m.axis.to_ascii_uppercase())
+        .ok_or_else(|| anyhow!("Calibration covers X, Y and Z, not {}", m.axis))?;
+    if !(m.expected > 0.0 && m.measured > 0.0) {
+        return Err(anyhow!("Sizes for {} must be above zero", m.axis));
+    }
+    Ok(index)
+}
+
+/// Scale each axis's steps/mm by how far its dimension was off. Measure from the square
+/// test: outside dimensions of a boss don't depend on backlash.
+pub fn suggest_steps_per_mm(
+    measurements: &[AxisMeasurement],
+    firmware_settings: &BTreeMap<u16, String>,
+) -> Result<Vec<StepsPerMmSuggestion>> {
+    measurements
+        .iter()
+        .map(|m| {
+            let index = check_measurement(m)?;
+            let setting = STEPS_PER_MM_SETTING + index as u16;
+            let current: f32 = firmware_settings
+                .get(&setting)
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(|| {
+                    anyhow!("${} isn't known; read the machine settings first", setting)
+                })?;
+            Ok(StepsPerMmSuggestion {
+                axis: m.axis.to_ascii_uppercase(),
+                setting,
+                current,
+                suggested: current * m.expected / m.measured,
+                error_percent: (m.measured / m.expected - 1.0) * 100.0,
+            })
+        })
+        .collect()
+}
+
+/// Backlash from circle diameters measured along each axis. Lost motion at each reversal
+/// shortens the toolpath by the backlash across that axis, for holes and bosses alike, so
+/// calibrate steps/mm first or the scale error shows up here too.
+pub fn suggest_backlash(measurements: &[AxisMeasurement]) -> Result<BacklashCompensation> {
+    let mut backlash = BacklashCompensation::default();
+    for m in measurements {
+        let index = check_measurement(m)?;
+        backlash.axes[index] = (m.expected - m.measured).max(0.0);
+    }
+    Ok(backlash)
+}
@@ -1,8 +1,88 @@
+use crate::alarm_history::{AlarmHistory, AlarmKind, AlarmRecord};
+use crate::alerts::{AlertContext, AlertEvent, AlertWebhooks};
+use crate::axis_mapping::AxisMapping;
+use crate::bandwidth::{BandwidthHistory, BandwidthReport};
+use crate::ble_transport;
+use crate::bookmarks::{BookmarkLocation, ProgramBookmarks};
+use crate::connection_diagnostics::{self, DiagnosticsOptions, DiagnosticsReport};
+use crate::connection_events::{
+    ConnectedEvent, ConnectionErrorEvent, DisconnectReason, DisconnectedEvent,
+};
+use crate::connection_health::{ConnectionHealth, HealthMonitor, HeartbeatConfig, LinkHealth};
+use crate::console_log::{
+    self, ConsoleBounds, ConsoleDirection, ConsoleEntry, ConsoleFilter, ConsoleLog,
+    ConsoleLogConfig,
+};
+use crate::controller_reboot::{self, RebootSession, RebootedEvent, RebootingEvent};
+use crate::controller_warnings::{self, ControllerWarningConfig, WarningSeverity};
+use crate::crash_guard::{self, CrashGuardConfig};
+use crate::discovery::{
+    self, DiscoveryConfig, DiscoveryWatch, SavedAddress, SavedAddresses, ScanProgress,
+};
+use crate::dro_format::{DroFormat, FormattedAxis};
+use crate::dust_collection::{DustCollectionConfig, DustCollectionSwitch, RelayControl};
+use crate::execution_heatmap::{ExecutionHeatmap, HeatmapRecorder};
+use crate::fault_injection::{FaultConfig, FaultInjector, SharedFaultConfig};
+use crate::feed_zones::{self, FeedZone, FeedZoneController, ZoneSpan};
+use crate::fixtures::{Fixture, FixtureZero, Fixtures};
+use crate::grbl_codes;
+use crate::grbl_protocol::{
+    self, BuildInfo, CoordinateOffsets, LineKind, Overrides, StatusReport, WelcomeBanner,
+};
+use crate::height_map::{HeightMap, HeightMapGrid, LeveledProgram};
+use crate::idle_policy::{IdlePolicy, IdlePowerDown};
+use crate::job_checkpoint::{JobCheckpoint, CHECKPOINT_INTERVAL};
+use crate::job_comparison::{self, JobComparison, JobRunRecorder};
+use crate::job_completion::{self, CompletionActions, JobCompletion};
+use crate::job_control::{JobLineMap, JobMonitor, JobState, JobTiming};
+use crate::job_history::{JobHistory, JobHistoryEntry};
+use crate::job_queue::{self, JobQueue, PreparedJob};
+use crate::job_resume::{self, ResumePlan};
+use crate::job_streamer::{JobStreamer, StreamFinished};
+use crate::jog::{ContinuousJog, JogFeedback, JogRequest, JogTracker};
+use crate::keyboard_jog::{KeyJogAction, KeyboardJog, KeyboardJogConfig};
+use crate::line_numbering::{self, LineNumbering};
+use crate::machine_profile::{
+    self, ClearanceHeights, GcodeMacro, MachineProfile, ProfileBundle, SettingChange,
+    SettingsApplyPlan, AXIS_LETTERS, PROFILE_BUNDLE_VERSION,
+};
+use crate::maintenance;
+use crate::min_z_guard::{MinZAction, MinZGuard, MinZTracker};
+use crate::modal_resync::{self, ModalResyncPolicy};
+use crate::motion_model::MotionSettings;
+use crate::motion_sequences;
+use crate::pre_run_checklist::{self, ChecklistConfig, ChecklistContext, ChecklistResult};
+use crate::probing::{ProbeFailure, ProbeOutcome, PROBE_FAIL_ALARMS};
+use crate::reconnect::{self, PendingReconnect, ReconnectPolicy, ReconnectedEvent};
+use crate::saved_devices::{CertificateCheck, SavedDevices};
+use crate::session_log::{self, MachineAction, OffsetTarget, SessionAction, SessionLog};
+use crate::settings_audit::{self, SettingAuditEntry, SettingSource, SettingsAudit};
+use crate::simulation::{Simulation, SimulationState};
+use crate::spindle_load::{SpindleLoadConfig, SpindleLoadMonitor};
+use crate::ssh_tunnel::{Tunnel, TunnelConfig, TunnelStatus, Tunnels};
+use crate::status_mask::{StatusReportMask, STATUS_MASK_SETTING};
+use crate::stock::{self, Stock, StockReport, ThicknessMeasurement};
+use crate::storage;
+use crate::stream_monitor::{StallConfig, StallDetector, StreamingPollConfig};
+use crate::tool_change::{ToolChangeMemory, ToolChangeSpot};
+use crate::tool_library::{self, Tool, ToolLibrary};
+use crate::transport::{
+    self, CncTransport, LineAssembler, SocketOptions, TimedLine, TransportKind,
+};
+use crate::unit_check::{self, UnitCheck, Units};
+use crate::wifi_provisioning::{self, WifiProvisioning, WifiStatus};
+use crate::work_offsets::{self, WorkOffsetChange};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
 use std::io::{Read, Write};
-use std::net::{Ipv4Addr, TcpStream, UdpSocket};
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CncDevice {
@@ -11,6 +91,177 @@ pub struct CncDevice {
     pub port: u16,
     pub mac: Option<String>,
     pub firmware: Option<String>,
+    #[serde(default)]
+    pub transport: TransportKind,
+    /// Serial devices only; None to find it by trying the common rates
+    #[serde(default)]
+    pub baud_rate: Option<u32>,
+    /// TLS only: SHA-256 fingerprint of the controller's certificate, pinned on the
+    /// first connection
+    #[serde(default)]
+    pub certificate_pin: Option<String>,
+    /// Network links only
+    #[serde(default)]
+    pub socket: SocketOptions,
+}
+
+impl CncDevice {
+    /// Stable identifier for per-machine data: the MAC when known, otherwise the address
+    pub fn machine_key(&self) -> String {
+        match &self.mac {
+            Some(mac) => mac.clone(),
+            None => format!("{}_{}", self.ip, self.port),
+        }
+    }
+}
+
+/// Payload of `cnc:certificate-pinned`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificatePinned {
+    pub device_name: String,
+    pub fingerprint: String,
+}
+
+/// Payload of `cnc:discovery-finished`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryFinished {
+    /// Everything found, announced devices first
+    pub devices: Vec<CncDevice>,
+    /// Stopped early by `cancel_discovery`
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+/// A discovery pass, independent of the manager so it can run while a job streams on
+/// the connected machine. Each controller is sent as `cnc:device-found` as it answers;
+/// one that announced itself can be sent again with its name and MAC, so the list is
+/// keyed by address.
+pub struct DiscoverySession {
+    config: DiscoveryConfig,
+    saved_addresses: SavedAddresses,
+    /// Listed as it is rather than probed
+    connected: Option<CncDevice>,
+    app_handle: Option<AppHandle>,
+    /// Set by `CncManager::cancel_discovery`
+    cancel: Arc<AtomicBool>,
+}
+
+impl DiscoverySession {
+    /// Discover in the background, sending `cnc:discovery-finished` at the end
+    pub fn spawn(self, timeout_ms: u64) {
+        thread::spawn(move || {
+            let result = self.discover_devices(timeout_ms);
+            let cancelled = self.cancel.load(Ordering::Relaxed);
+            let finished = match result {
+                Ok(devices) => DiscoveryFinished {
+                    devices,
+                    cancelled,
+                    error: None,
+                },
+                Err(e) => DiscoveryFinished {
+                    devices: Vec::new(),
+                    cancelled,
+                    error: Some(e.to_string()),
+                },
+            };
+            if let Some(app) = &self.app_handle {
+                let _ = app.emit("cnc:discovery-finished", finished);
+            }
+        });
+    }
+
+    /// Discover CNC devices: listen for announcements, then probe the configured hosts
+    pub fn discover_devices(&self, timeout_ms: u64) -> Result<Vec<CncDevice>> {
+        // BLE scanning takes as long as listening for announcements, so both run at once
+        let ble_scan = self
+            .config
+            .ble_scan
+            .then(|| thread::spawn(move || ble_transport::scan(timeout_ms)));
+        let connected = self.connected.as_ref();
+        let found = |device: &CncDevice| self.device_found(device);
+        let watch = DiscoveryWatch {
+            found: Some(&found),
+            cancel: Some(&self.cancel),
+        };
+        let mut devices = discovery::discover(
+            &self.config,
+            &self.saved_addresses,
+            connected,
+            timeout_ms,
+            watch,
+        )?;
+        if devices.is_empty() && self.config.subnet_scan && !watch.cancelled() {
+            devices = self.scan_subnet()?;
+        }
+        // A cancelled pass doesn't wait for the scan; the thread ends on its own
+        match ble_scan
+            .filter(|_| !watch.cancelled())
+            .map(|scan| scan.join())
+        {
+            Some(Ok(Ok(found))) => {
+                for device in &found {
+                    self.device_found(device);
+                }
+                devices.extend(found);
+            }
+            // No adapter, or Bluetooth switched off: the network results still stand
+            Some(Ok(Err(e))) => println!("📶 BLE scan skipped: {}", e),
+            Some(Err(_)) => println!("📶 BLE scan failed"),
+            None => {}
+        }
+        self.include_connected(&mut devices);
+        if watch.cancelled() {
+            println!(
+                "⏹️  Discovery cancelled with {} device(s) found",
+                devices.len()
+            );
+        }
+        Ok(devices)
+    }
+
+    /// Probe the whole local /24 for controllers, sending `cnc:subnet-scan-progress` as it goes
+    pub fn scan_subnet(&self) -> Result<Vec<CncDevice>> {
+        let app = self.app_handle.clone();
+        let connected = self.connected.as_ref();
+        let found = |device: &CncDevice| self.device_found(device);
+        let watch = DiscoveryWatch {
+            found: Some(&found),
+            cancel: Some(&self.cancel),
+        };
+        let mut devices = discovery::scan_subnet(
+            &self.config,
+            connected,
+            &move |progress: ScanProgress| {
+                if let Some(app) = &app {
+                    let _ = app.emit("cnc:subnet-scan-progress", progress);
+                }
+            },
+            watch,
+        )?;
+        self.include_connected(&mut devices);
+        Ok(devices)
+    }
+
+    fn device_found(&self, device: &CncDevice) {
+        if let Some(app) = &self.app_handle {
+            let _ = app.emit("cnc:device-found", device.clone());
+        }
+    }
+
+    /// The connected machine wasn't probed, and a BLE one stops advertising once
+    /// connected, so it's added as it is
+    fn include_connected(&self, devices: &mut Vec<CncDevice>) {
+        let Some(device) = &self.connected else {
+            return;
+        };
+        let listed = devices
+            .iter()
+            .any(|d| d.ip == device.ip && d.port == device.port);
+        if device.transport != TransportKind::Serial && !listed {
+            self.device_found(device);
+            devices.push(device.clone());
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,278 +270,5197 @@ pub struct CncConnection {
     pub connected: bool,
 }
 
-// Structure for UDP broadcast response from Genmitsu WiFi module
-#[derive(Debug, Deserialize)]
-struct GenmitsuBroadcast {
-    ip: String,
-    port: String, // Port comes as string from device
-    name: String,
-    uuid: String, // MAC address in uuid field
+pub struct CncManager {
+    current_connection: Option<Box<dyn CncTransport>>,
+    device_info: Option<CncDevice>,
+    machine_profile: MachineProfile,
+    last_status: Option<StatusReport>,
+    data_dir: Option<PathBuf>,
+    last_work_offset: Option<Vec<f32>>,
+    /// `Ov:` is only in some reports, so the last one seen
+    last_overrides: Option<Overrides>,
+    work_zero_set_at: Option<Instant>,
+    checklist_config: ChecklistConfig,
+    acknowledged_checks: HashSet<String>,
+    /// Whether the machine has been homed this power cycle; None until we know
+    homed: Option<bool>,
+    homing_in_progress: bool,
+    metrics: CommMetrics,
+    bandwidth: BandwidthHistory,
+    connected_at: Option<Instant>,
+    app_handle: Option<AppHandle>,
+    stall_detector: StallDetector,
+    streaming_poll: StreamingPollConfig,
+    /// When the frontend's last status poll was passed on to the controller
+    last_status_query: Option<Instant>,
+    spindle_load: SpindleLoadMonitor,
+    controller_warnings: ControllerWarningConfig,
+    /// Virtual playback of the loaded program
+    simulation: Option<Simulation>,
+    /// File being streamed by the background streamer, see `start_job`
+    job_streamer: Option<JobStreamer>,
+    /// Progress of the running job, mirrored to disk for crash recovery
+    job_checkpoint: Option<JobCheckpoint>,
+    last_checkpoint_save: Option<Instant>,
+    /// Unfinished job left by a previous session
+    recoverable_job: Option<JobCheckpoint>,
+    /// Banners seen mid-response, handled once the response is complete
+    pending_banners: Vec<WelcomeBanner>,
+    /// Active modal words from `$G`; None when stale (e.g. after a reset)
+    parser_state: Option<Vec<String>>,
+    /// Developer-mode fault injection, shared with the wrapped connection
+    fault_config: SharedFaultConfig,
+    fault_injector_installed: bool,
+    /// Received bytes not yet terminated by a newline
+    rx: LineAssembler,
+    /// Fire-and-forget commands whose ok/error hasn't arrived yet
+    unacked_commands: usize,
+    continuous_jog: Option<ContinuousJog>,
+    keyboard_jog: KeyboardJog,
+    job_monitor: JobMonitor,
+    alarm_history: AlarmHistory,
+    settings_audit: SettingsAudit,
+    session_log: SessionLog,
+    job_history: JobHistory,
+    job_run_recorder: JobRunRecorder,
+    heatmap_recorder: HeatmapRecorder,
+    tool_change: ToolChangeMemory,
+    /// Per-line timing of the last finished job
+    last_heatmap: Option<ExecutionHeatmap>,
+    console: ConsoleLog,
+    /// When `cnc:job-progress` was last sent
+    last_progress_emit: Option<Instant>,
+    /// Reduced-feed zones for the job being streamed
+    feed_zones: Option<FeedZoneController>,
+    /// Lowest work Z for the next job, until it ends
+    min_z_guard: Option<MinZGuard>,
+    /// The guard following the running job's lines
+    min_z_tracker: Option<MinZTracker>,
+    /// Job's modal state, captured before the first MDI line of the current pause
+    paused_modal_state: Option<Vec<String>>,
+    modal_resync_policy: ModalResyncPolicy,
+    /// Motion and spindle commands are refused while someone works on the machine
+    maintenance_mode: bool,
+    /// N-word and checksum framing, for controllers that support it
+    line_numbering: Option<LineNumbering>,
+    /// Observe-only connection: status queries are the only thing written
+    read_only: bool,
+    /// Controller speaks Grbl 0.9: no `$J=` jogging and no real-time overrides
+    legacy_grbl: bool,
+    dro_format: DroFormat,
+    discovery_config: DiscoveryConfig,
+    /// Stop switch of the latest discovery pass
+    discovery_cancel: Arc<AtomicBool>,
+    saved_addresses: SavedAddresses,
+    job_queue: JobQueue,
+    completion_actions: CompletionActions,
+    dust_collection: DustCollectionConfig,
+    idle_policy: IdlePolicy,
+    alert_webhooks: AlertWebhooks,
+    fixtures: Fixtures,
+    tool_library: ToolLibrary,
+    tunnel_configs: Tunnels,
+    /// Open tunnels, kept across connections
+    tunnels: Vec<Tunnel>,
+    reconnect_policy: ReconnectPolicy,
+    /// The link dropped and is being retried
+    pending_reconnect: Option<PendingReconnect>,
+    heartbeat: HeartbeatConfig,
+    health: HealthMonitor,
+    saved_devices: SavedDevices,
+    jog_tracker: JogTracker,
+    /// Last command from the user or motion of the machine
+    last_activity: Instant,
+    /// The idle policy ran and nothing has happened since
+    idle_powered_down: bool,
+    /// `$SLP` was sent; only a reset wakes Grbl
+    controller_asleep: bool,
+    /// The relay was last switched on; shared with the thread switching a network relay
+    dust_collection_on: Arc<AtomicBool>,
+    /// When the relay goes off after a job
+    dust_collection_off_at: Option<Instant>,
+    /// Code of the most recent `ALARM:` line
+    last_alarm: Option<u32>,
+    /// Wire time of the latest status report
+    last_status_received_ms: Option<u64>,
+    /// Last line written, to attribute an `error:` to the command that caused it
+    last_command: Option<String>,
+}
+
+/// Where an incoming line ended up after routing
+enum Routed {
+    /// Output for the command being waited on
+    Data(String),
+    /// The waiting command's `ok` or `error:`
+    Ack(String),
+    /// Welcome banner: the controller reset and nothing pending will be answered
+    Reset,
+}
+
+/// How long ordinary commands wait for their acknowledgement
+const COMMAND_TIMEOUT_MS: u64 = 5000;
+const STATUS_TIMEOUT_MS: u64 = 2000;
+/// A status report older than this says little about what the planner holds now
+const STATUS_FRESH_MS: u64 = 1000;
+/// Time for Grbl to come back from the reset that ends `$SLP`
+const WAKE_RESET_SETTLE: Duration = Duration::from_millis(250);
+const RESET_TIMEOUT_MS: u64 = 3000;
+/// How long stopping a job waits for the feed hold to bring the machine to rest
+const STOP_SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unacked jog lines are still in Grbl's serial buffer, where a jog cancel doesn't reach them
+const MAX_UNACKED_JOG_SEGMENTS: usize = 2;
+
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<std::io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+        )
+    })
+}
+
+/// How much of the performance log to include in a diagnostics bundle
+const DIAGNOSTIC_LOG_LINES: usize = 2000;
+
+/// Counters describing traffic on the current connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommMetrics {
+    pub commands_sent: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// `error:` responses from the controller
+    pub error_responses: u64,
+    /// Failed reads/writes, including timeouts
+    pub io_errors: u64,
+    pub connected_seconds: u64,
+    /// Time from a `?` query to its status report, smoothed; the link's round trip
+    /// since status queries skip the controller's line queue
+    pub round_trip_ms: Option<f64>,
+    pub last_round_trip_ms: Option<f64>,
+    /// Lines sent again because the controller asked for them (line-numbered mode)
+    pub resent_lines: u64,
+}
+
+impl CommMetrics {
+    fn record_round_trip(&mut self, rtt: Duration) {
+        let ms = rtt.as_secs_f64() * 1000.0;
+        self.last_round_trip_ms = Some(ms);
+        self.round_trip_ms = Some(match self.round_trip_ms {
+            Some(avg) => avg * 0.8 + ms * 0.2,
+            None => ms,
+        });
+    }
+}
+
+/// Structured machine status for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineStatus {
+    pub report: Option<StatusReport>,
+    pub homed: Option<bool>,
+    pub homing_in_progress: bool,
+    /// Positions formatted with the DRO settings
+    pub display: Option<DisplayPosition>,
+    /// When the report came off the wire, ms since the Unix epoch
+    pub received_at_ms: Option<u64>,
+    /// Estimate of when the controller took the sample: received time minus half the round trip
+    pub sampled_at_ms: Option<u64>,
+}
+
+/// Alarm the controller is sitting in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveAlarm {
+    pub code: u32,
+    pub message: String,
+}
+
+/// Everything the frontend stores hold, so they can be rebuilt in one call after a reload
+/// instead of waiting for the next round of events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FullState {
+    pub connection: Option<CncConnection>,
+    pub read_only: bool,
+    pub maintenance_mode: bool,
+    pub legacy_grbl: bool,
+    pub line_numbering: bool,
+    /// Last status received; not re-queried, so it may be a poll interval old
+    pub status: MachineStatus,
+    pub job_state: JobState,
+    pub job_timing: Option<JobTiming>,
+    pub job_lines: Option<JobLineMap>,
+    pub overrides: Option<Overrides>,
+    pub alarm: Option<ActiveAlarm>,
+    pub recoverable_job: Option<JobCheckpoint>,
+}
+
+/// Result of re-reading the controller's build info
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfoRefresh {
+    pub device: Option<CncDevice>,
+    pub build_info: BuildInfo,
+    /// Build the machine profile was last checked against, None the first time
+    pub previous: Option<BuildInfo>,
+    /// Version or build options differ from `previous`; settings should be re-validated
+    pub firmware_changed: bool,
+    /// `$$` values that changed, when the firmware changed and settings were re-read
+    pub setting_changes: Vec<SettingChange>,
+}
+
+/// Machine and work position as the DRO shows them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayPosition {
+    pub machine: Vec<FormattedAxis>,
+    pub work: Vec<FormattedAxis>,
 }
 
-pub struct CncManager {
-    current_connection: Option<TcpStream>,
-    device_info: Option<CncDevice>,
-}
+impl CncManager {
+    pub fn new() -> Self {
+        Self {
+            current_connection: None,
+            device_info: None,
+            machine_profile: MachineProfile::default(),
+            last_status: None,
+            data_dir: None,
+            last_work_offset: None,
+            last_overrides: None,
+            work_zero_set_at: None,
+            checklist_config: ChecklistConfig::default(),
+            acknowledged_checks: HashSet::new(),
+            homed: None,
+            homing_in_progress: false,
+            metrics: CommMetrics::default(),
+            bandwidth: BandwidthHistory::default(),
+            connected_at: None,
+            app_handle: None,
+            stall_detector: StallDetector::new(StallConfig::default()),
+            streaming_poll: StreamingPollConfig::default(),
+            last_status_query: None,
+            spindle_load: SpindleLoadMonitor::new(SpindleLoadConfig::default()),
+            controller_warnings: ControllerWarningConfig::default(),
+            simulation: None,
+            job_streamer: None,
+            job_checkpoint: None,
+            last_checkpoint_save: None,
+            recoverable_job: None,
+            pending_banners: Vec::new(),
+            parser_state: None,
+            fault_config: Arc::new(Mutex::new(None)),
+            fault_injector_installed: false,
+            rx: LineAssembler::default(),
+            unacked_commands: 0,
+            continuous_jog: None,
+            keyboard_jog: KeyboardJog::new(KeyboardJogConfig::default()),
+            job_monitor: JobMonitor::new(),
+            alarm_history: AlarmHistory::default(),
+            settings_audit: SettingsAudit::default(),
+            session_log: SessionLog::default(),
+            job_history: JobHistory::default(),
+            job_run_recorder: JobRunRecorder::default(),
+            heatmap_recorder: HeatmapRecorder::default(),
+            tool_change: ToolChangeMemory::default(),
+            last_heatmap: None,
+            console: ConsoleLog::default(),
+            last_progress_emit: None,
+            feed_zones: None,
+            min_z_guard: None,
+            min_z_tracker: None,
+            paused_modal_state: None,
+            modal_resync_policy: ModalResyncPolicy::default(),
+            maintenance_mode: false,
+            line_numbering: None,
+            read_only: false,
+            legacy_grbl: false,
+            dro_format: DroFormat::default(),
+            discovery_config: DiscoveryConfig::default(),
+            discovery_cancel: Arc::new(AtomicBool::new(false)),
+            saved_addresses: SavedAddresses::default(),
+            job_queue: JobQueue::default(),
+            completion_actions: CompletionActions::default(),
+            dust_collection: DustCollectionConfig::default(),
+            idle_policy: IdlePolicy::default(),
+            alert_webhooks: AlertWebhooks::default(),
+            fixtures: Fixtures::default(),
+            tool_library: ToolLibrary::default(),
+            tunnel_configs: Tunnels::default(),
+            tunnels: Vec::new(),
+            reconnect_policy: ReconnectPolicy::default(),
+            pending_reconnect: None,
+            heartbeat: HeartbeatConfig::default(),
+            health: HealthMonitor::default(),
+            saved_devices: SavedDevices::default(),
+            jog_tracker: JogTracker::default(),
+            last_activity: Instant::now(),
+            idle_powered_down: false,
+            controller_asleep: false,
+            dust_collection_on: Arc::new(AtomicBool::new(false)),
+            dust_collection_off_at: None,
+            last_alarm: None,
+            last_status_received_ms: None,
+            last_command: None,
+        }
+    }
+
+    /// Directory for per-machine data such as saved profiles
+    pub fn set_data_dir(&mut self, dir: PathBuf) {
+        self.checklist_config = storage::load_json(&ChecklistConfig::path_in(&dir));
+        self.stall_detector
+            .set_config(storage::load_json(&dir.join("stall_detection.json")));
+        self.spindle_load
+            .set_config(storage::load_json(&dir.join("spindle_load.json")));
+        self.controller_warnings = storage::load_json(&dir.join("controller_warnings.json"));
+        self.console
+            .set_config(storage::load_json(&dir.join("console_log.json")));
+        self.streaming_poll = storage::load_json(&dir.join("streaming_poll.json"));
+        self.alarm_history = storage::load_json(&AlarmHistory::path_in(&dir));
+        self.settings_audit = storage::load_json(&SettingsAudit::path_in(&dir));
+        self.job_history = storage::load_json(&JobHistory::path_in(&dir));
+        self.last_heatmap = storage::load_json(&ExecutionHeatmap::path_in(&dir));
+        self.recoverable_job = storage::load_json(&JobCheckpoint::path_in(&dir));
+        if let Some(job) = &self.recoverable_job {
+            println!(
+                "♻️  Found an unfinished job from a previous session: {} at line {}",
+                job.program_name.as_deref().unwrap_or("unnamed program"),
+                job.last_acked_line.unwrap_or(0)
+            );
+        }
+        self.dro_format = storage::load_json(&DroFormat::path_in(&dir));
+        self.discovery_config = storage::load_json(&DiscoveryConfig::path_in(&dir));
+        self.saved_addresses = storage::load_json(&SavedAddresses::path_in(&dir));
+        self.job_queue = storage::load_json(&JobQueue::path_in(&dir));
+        self.completion_actions = storage::load_json(&CompletionActions::path_in(&dir));
+        self.dust_collection = storage::load_json(&DustCollectionConfig::path_in(&dir));
+        self.idle_policy = storage::load_json(&IdlePolicy::path_in(&dir));
+        self.alert_webhooks = storage::load_json(&AlertWebhooks::path_in(&dir));
+        self.fixtures = storage::load_json(&Fixtures::path_in(&dir));
+        self.tool_library = storage::load_json(&ToolLibrary::path_in(&dir));
+        self.tunnel_configs = storage::load_json(&Tunnels::path_in(&dir));
+        self.reconnect_policy = storage::load_json(&ReconnectPolicy::path_in(&dir));
+        self.heartbeat = storage::load_json(&HeartbeatConfig::path_in(&dir));
+        self.saved_devices = storage::load_json(&SavedDevices::path_in(&dir));
+        self.modal_resync_policy = storage::load_json(&dir.join("modal_resync.json"));
+        self.keyboard_jog = KeyboardJog::new(storage::load_json(&KeyboardJogConfig::path_in(&dir)));
+        self.data_dir = Some(dir);
+    }
+
+    /// Handle used to emit events to the frontend
+    pub fn set_app_handle(&mut self, app: AppHandle) {
+        self.app_handle = Some(app);
+    }
+
+    fn emit<S: Serialize + Clone>(&mut self, event: &str, payload: S) {
+        let text = match serde_json::to_string(&payload) {
+            Ok(json) => format!("{} {}", event, json),
+            Err(_) => event.to_string(),
+        };
+        self.console.record(
+            transport::unix_millis(SystemTime::now()),
+            ConsoleDirection::Event,
+            &text,
+        );
+        if let Some(app) = &self.app_handle {
+            if let Err(e) = app.emit(event, payload) {
+                println!("⚠️  Failed to emit {}: {}", event, e);
+            }
+        }
+    }
+
+    fn profile_path(&self) -> Option<PathBuf> {
+        let device = self.device_info.as_ref()?;
+        let dir = self.data_dir.as_ref()?;
+        Some(MachineProfile::path_for(dir, &device.machine_key()))
+    }
+
+    fn save_machine_profile(&self) -> Result<()> {
+        if let Some(path) = self.profile_path() {
+            storage::save_json(&path, &self.machine_profile)?;
+        }
+        Ok(())
+    }
+
+    /// What discovery needs, copied so a pass can run without holding the manager
+    pub fn discovery_session(&mut self) -> DiscoverySession {
+        self.discovery_cancel = Arc::new(AtomicBool::new(false));
+        DiscoverySession {
+            config: self.discovery_config.clone(),
+            saved_addresses: self.saved_addresses.clone(),
+            // Also while reconnecting: a probe would compete with the retries
+            connected: self.device_info.clone(),
+            app_handle: self.app_handle.clone(),
+            cancel: self.discovery_cancel.clone(),
+        }
+    }
+
+    /// Stop the latest discovery pass or subnet scan; it ends with what it found so far
+    pub fn cancel_discovery(&self) {
+        self.discovery_cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Connect over TCP to an address the user typed in, and remember it once it works
+    pub fn connect_to_address(&mut self, ip: &str, port: u16, read_only: bool) -> Result<()> {
+        let ip = ip.trim();
+        if ip.is_empty() {
+            return Err(anyhow!("Enter the machine's IP address or host name"));
+        }
+        let device = CncDevice {
+            name: format!("CNC at {}:{}", ip, port),
+            ip: ip.to_string(),
+            port,
+            mac: None,
+            firmware: None,
+            transport: TransportKind::Tcp,
+            baud_rate: None,
+            certificate_pin: None,
+            socket: SocketOptions::default(),
+        };
+        self.connect(&device, read_only)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.saved_addresses.remember(ip, port, now);
+        self.save_saved_addresses();
+        Ok(())
+    }
+
+    pub fn saved_addresses(&self) -> &[SavedAddress] {
+        &self.saved_addresses.addresses
+    }
+
+    pub fn forget_address(&mut self, ip: &str, port: u16) -> Result<()> {
+        if !self.saved_addresses.forget(ip, port) {
+            return Err(anyhow!("{}:{} isn't saved", ip, port));
+        }
+        self.save_saved_addresses();
+        Ok(())
+    }
+
+    fn save_saved_addresses(&self) {
+        if let Some(dir) = &self.data_dir {
+            let path = SavedAddresses::path_in(dir);
+            if let Err(e) = storage::save_json(&path, &self.saved_addresses) {
+                println!("⚠️  Could not save addresses: {}", e);
+            }
+        }
+    }
+
+    pub fn discovery_config(&self) -> DiscoveryConfig {
+        self.discovery_config.clone()
+    }
+
+    pub fn set_discovery_config(&mut self, config: DiscoveryConfig) -> Result<()> {
+        if config.listen_ports.is_empty() && config.manual_hosts.is_empty() {
+            return Err(anyhow!(
+                "Discovery needs at least one listen port or manual host"
+            ));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&DiscoveryConfig::path_in(dir), &config)?;
+        }
+        self.discovery_config = config;
+        Ok(())
+    }
+
+    /// Connect to a device. A read-only connection only polls status and reads what the
+    /// controller prints, for watching a machine driven by another sender or a pendant.
+    pub fn connect(&mut self, device: &CncDevice, read_only: bool) -> Result<()> {
+        let mut device = device.clone();
+        device.certificate_pin = self.saved_devices.certificate_pin(&device);
+        let device = &device;
+        let stream = transport::connect(device)?;
+        self.attach(stream, device, read_only)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if self.saved_devices.mark_connected(device, now) {
+            self.save_saved_devices()?;
+        }
+        Ok(())
+    }
+
+    pub fn saved_devices(&self) -> &SavedDevices {
+        &self.saved_devices
+    }
+
+    /// Keep a device for connecting to without discovery
+    pub fn save_device(&mut self, mut device: CncDevice) -> Result<()> {
+        device.socket.validate()?;
+        let connected = self
+            .device_info
+            .as_ref()
+            .filter(|d| d.machine_key() == device.machine_key());
+        if let Some(current) = connected {
+            if device.certificate_pin.is_none() {
+                device.certificate_pin = current.certificate_pin.clone();
+            }
+        }
+        let connected = connected.is_some();
+        self.saved_devices.save(device.clone());
+        if connected {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            self.saved_devices.mark_connected(&device, now);
+        }
+        self.save_saved_devices()
+    }
+
+    pub fn forget_device(&mut self, machine_key: &str) -> Result<()> {
+        if !self.saved_devices.forget(machine_key) {
+            return Err(anyhow!("No saved device {}", machine_key));
+        }
+        self.save_saved_devices()
+    }
+
+    pub fn set_auto_connect(&mut self, enabled: bool) -> Result<()> {
+        self.saved_devices.auto_connect = enabled;
+        self.save_saved_devices()
+    }
+
+    /// Trust the certificate seen on the first TLS connection from now on
+    fn pin_certificate(&mut self, device: &mut CncDevice, fingerprint: String) {
+        println!(
+            "🔒 Pinned the certificate of {}: {}",
+            device.name, fingerprint
+        );
+        device.certificate_pin = Some(fingerprint.clone());
+        if self
+            .saved_devices
+            .pin_certificate(&device.machine_key(), &fingerprint)
+        {
+            if let Err(e) = self.save_saved_devices() {
+                println!("⚠️  Could not save the pinned certificate: {}", e);
+            }
+        }
+        self.emit(
+            "cnc:certificate-pinned",
+            CertificatePinned {
+                device_name: device.name.clone(),
+                fingerprint,
+            },
+        );
+    }
+
+    /// Forget a saved device's certificate, after the controller was given a new one;
+    /// the next connection pins whatever it presents
+    pub fn clear_certificate_pin(&mut self, machine_key: &str) -> Result<()> {
+        if !self.saved_devices.clear_certificate_pin(machine_key) {
+            return Err(anyhow!("No saved device {}", machine_key));
+        }
+        self.save_saved_devices()
+    }
+
+    fn save_saved_devices(&self) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&SavedDevices::path_in(dir), &self.saved_devices)?;
+        }
+        Ok(())
+    }
+
+    /// On launch: connect to the last-used saved device if auto-connect is on, and tell the
+    /// frontend with `cnc:auto-connected`
+    pub fn auto_connect(&mut self) {
+        let Some(device) = self.saved_devices.auto_connect_device().cloned() else {
+            return;
+        };
+        println!("🔌 Auto-connecting to {}", device.name);
+        match self.connect(&device, false) {
+            Ok(()) => self.emit("cnc:auto-connected", device),
+            Err(e) => println!("⚠️  Auto-connect to {} failed: {}", device.name, e),
+        }
+    }
+
+    /// Take over an open transport as the connection to `device` and initialize it as
+    /// `connect` does
+    pub fn attach(
+        &mut self,
+        stream: Box<dyn CncTransport>,
+        device: &CncDevice,
+        read_only: bool,
+    ) -> Result<()> {
+        let mut device = device.clone();
+        let first_certificate = match stream.certificate_fingerprint() {
+            Some(fingerprint) => match self
+                .saved_devices
+                .check_certificate(&device, &fingerprint)?
+            {
+                CertificateCheck::Matches => {
+                    device.certificate_pin = Some(fingerprint);
+                    None
+                }
+                CertificateCheck::FirstSeen => Some(fingerprint),
+            },
+            None => None,
+        };
+        if let (Some(_), Some(previous)) = (&self.current_connection, self.device_info.clone()) {
+            self.emit(
+                "cnc:disconnected",
+                DisconnectedEvent {
+                    device: previous,
+                    reason: DisconnectReason::User,
+                    message: format!("Replaced by a connection to {}", device.name),
+                    reconnecting: false,
+                },
+            );
+        }
+        self.close_connection();
+        let reconnected = self.pending_reconnect.take().is_some();
+        println!("🔌 Connected via {}", stream.describe());
+        if let Some(fingerprint) = first_certificate {
+            self.pin_certificate(&mut device, fingerprint);
+        }
+        let device = &device;
+
+        self.current_connection = Some(stream);
+        self.fault_injector_installed = false;
+        self.rx.clear();
+        self.unacked_commands = 0;
+        if self.fault_injection().is_some() {
+            self.install_fault_injector();
+        }
+        self.device_info = Some(device.clone());
+        self.read_only = read_only;
+        self.metrics = CommMetrics::default();
+        self.bandwidth = BandwidthHistory::default();
+        self.connected_at = Some(Instant::now());
+        self.health = HealthMonitor::connected();
+        self.emit("cnc:connection-health", self.connection_health());
+        self.last_activity = Instant::now();
+        self.idle_powered_down = false;
+        self.controller_asleep = false;
+        self.machine_profile = self
+            .profile_path()
+            .map(|path| storage::load_json(&path))
+            .unwrap_or_default();
+
+        // Initialize connection - send wake up command
+        self.homed = None;
+        self.homing_in_progress = false;
+        let _ = self.get_status();
+        if read_only {
+            println!("👀 Observe-only connection: commands are blocked");
+        } else if let Err(e) = self.refresh_machine_settings() {
+            // Pull travel limits and other settings into the machine profile
+            println!("⚠️  Could not read machine settings: {}", e);
+        }
+
+        // The link may already have dropped again while initializing
+        if self.current_connection.is_some() {
+            self.emit(
+                "cnc:connected",
+                ConnectedEvent {
+                    device: device.clone(),
+                    read_only,
+                    reconnected,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Send a command to the connected CNC and return its output, ending with the `ok`/`error:` line
+    pub fn send_command(&mut self, command: &str) -> Result<String> {
+        let trimmed = command.trim();
+        // A status query is answered with a report rather than an ok
+        if trimmed == "?" {
+            return self.get_status();
+        }
+        if let [byte @ (b'!' | b'~')] = trimmed.as_bytes() {
+            if *byte == b'~' {
+                self.resync_modal_state()?;
+                self.finish_tool_change();
+            }
+            self.job_monitor.note_app_command(*byte);
+        } else if !self.job_monitor.accepts_lines() {
+            return Err(anyhow!(
+                "Job is paused ({:?}); resume before sending more lines",
+                self.job_monitor.state()
+            ));
+        }
+
+        // While the frontend streams a job its lines come through here; with the backend
+        // streaming, anything sent here is MDI during a pause
+        let job_line = self
+            .job_streamer
+            .is_none()
+            .then(|| self.job_monitor.next_line());
+        if !matches!(trimmed, "!" | "~") {
+            self.check_min_z(job_line, trimmed)?;
+        }
+        // Grbl answers every line terminator, so a whole program gets one response per line
+        let lines = trimmed.matches(['\n', '\r']).count() + 1;
+        if lines > 1 {
+            let bytes = self.write_line(trimmed)?;
+            self.unacked_commands += lines;
+            if let Some(first) = job_line {
+                for index in 0..lines {
+                    let bytes = if index == 0 { bytes } else { 0 };
+                    self.job_monitor.note_sent(Some(first + index), bytes);
+                }
+            }
+            return Ok(format!("Queued {} lines", lines));
+        }
+        if job_line.is_some() && !matches!(trimmed, "!" | "~") {
+            self.job_monitor.note_sent(job_line, trimmed.len() + 1);
+        }
+        let Some((number, value)) = settings_audit::parse_setting_write(trimmed) else {
+            let pending = self.pending_session_action(trimmed);
+            let response = self.send_line(trimmed)?;
+            if let Some(pending) = pending.filter(|_| response.ends_with("ok")) {
+                self.finish_session_action(pending);
+            }
+            return Ok(response);
+        };
+        let previous = self.machine_profile.firmware_settings.get(&number).cloned();
+        let response = self.send_line(trimmed)?;
+        if response.ends_with("ok") {
+            self.record_setting_change(number, previous, value, SettingSource::User);
+        }
+        Ok(response)
+    }
+
+    /// Write one line and wait for its output and `ok`/`error:`
+    fn send_line(&mut self, line: &str) -> Result<String> {
+        self.write_line(line)?;
+        let (mut lines, ack) =
+            self.read_response(line, Duration::from_millis(COMMAND_TIMEOUT_MS))?;
+        lines.push(ack);
+        self.handle_controller_reset();
+
+        Ok(lines.join("\n"))
+    }
+
+    /// A line typed by the user. Unlike job lines, these are allowed while the job is paused;
+    /// the job's modal state is captured first so resuming can check nothing was left changed.
+    pub fn send_mdi_command(&mut self, command: &str) -> Result<String> {
+        let trimmed = command.trim();
+        if self.job_monitor.accepts_lines() || matches!(trimmed, "?" | "!" | "~") {
+            return self.send_command(trimmed);
+        }
+        if trimmed.contains(['\n', '\r']) {
+            return Err(anyhow!(
+                "Send one MDI line at a time while the job is paused"
+            ));
+        }
+        if self.paused_modal_state.is_none() {
+            self.paused_modal_state = Some(self.refresh_parser_state()?);
+        }
+        self.send_line(trimmed)
+    }
+
+    /// Before cycle start: if MDI during the pause changed units, WCS, motion mode and so on,
+    /// put the job's state back or refuse to resume, depending on the policy
+    fn resync_modal_state(&mut self) -> Result<()> {
+        let Some(expected) = self.paused_modal_state.clone() else {
+            return Ok(());
+        };
+        let actual = self.refresh_parser_state()?;
+        let changes = modal_resync::modal_changes(&expected, &actual);
+        if changes.is_empty() {
+            self.paused_modal_state = None;
+            return Ok(());
+        }
+
+        let restore = match self.modal_resync_policy {
+            ModalResyncPolicy::Restore => modal_resync::restore_line(&changes),
+            ModalResyncPolicy::Block => Err(changes),
+        };
+        match restore {
+            Ok(line) => {
+                println!("🔁 Restoring job modal state before resume: {}", line);
+                self.send_command_until_ok(&line, 2000)?;
+                self.parser_state = None;
+                self.paused_modal_state = None;
+                Ok(())
+            }
+            Err(blocking) => Err(anyhow!(
+                "MDI during the pause changed {}; restore it before resuming",
+                modal_resync::describe(&blocking)
+            )),
+        }
+    }
+
+    pub fn modal_resync_policy(&self) -> ModalResyncPolicy {
+        self.modal_resync_policy
+    }
+
+    pub fn set_modal_resync_policy(&mut self, policy: ModalResyncPolicy) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("modal_resync.json"), &policy)?;
+        }
+        self.modal_resync_policy = policy;
+        Ok(())
+    }
+
+    /// Send a command and collect response lines until the controller answers `ok` or `error:`
+    /// Needed for multi-line responses like `$$` that arrive across several reads
+    pub fn send_command_until_ok(&mut self, command: &str, timeout_ms: u64) -> Result<Vec<String>> {
+        let pending = self.pending_session_action(command);
+        self.write_line(command)?;
+        let (lines, ack) = self.read_response(command, Duration::from_millis(timeout_ms))?;
+        if ack.starts_with("error:") {
+            return Err(anyhow!("{} rejected: {}", command, ack));
+        }
+        if let Some(pending) = pending {
+            self.finish_session_action(pending);
+        }
+        Ok(lines)
+    }
+
+    /// Send a single real-time command byte (`!`, `~`, `?`, 0x18...) with no newline
+    pub fn send_realtime(&mut self, byte: u8) -> Result<()> {
+        if self.read_only && byte != b'?' {
+            return Err(anyhow!("Connected read-only: only status queries are sent"));
+        }
+        if byte == b'~' {
+            self.check_maintenance_mode("~")?;
+        }
+        if byte != b'?' {
+            self.note_activity();
+        }
+        let Some(ref mut stream) = self.current_connection else {
+            return Err(self.not_connected());
+        };
+        if let Err(e) = stream.write_all(&[byte]).and_then(|_| stream.flush()) {
+            return Err(self.link_lost(e.into()));
+        }
+        self.metrics.bytes_sent += 1;
+        let text = match byte {
+            b'?' | b'!' | b'~' => (byte as char).to_string(),
+            _ => format!("0x{:02X}", byte),
+        };
+        let now = transport::unix_millis(SystemTime::now());
+        self.bandwidth.record_sent(now, 1, 0);
+        self.console.record(now, ConsoleDirection::Sent, &text);
+        self.job_monitor.note_app_command(byte);
+        Ok(())
+    }
+
+    /// Send a command without waiting for response (fire and forget)
+    /// Useful for long-running commands like homing that block the communication
+    pub fn send_command_no_wait(&mut self, command: &str) -> Result<()> {
+        self.write_line(command)?;
+        // Its ok/error still arrives later and must not be taken as another command's answer
+        self.unacked_commands += 1;
+        Ok(())
+    }
+
+    fn write_line(&mut self, command: &str) -> Result<usize> {
+        // Acks are matched to streamed lines in order, so nothing may go in between. While
+        // the job is held nothing more is streamed, but lines already in the receive buffer
+        // aren't acked until the planner makes room: anything sent then would overflow the
+        // buffer's count and wait for an ok that only comes after resuming.
+        if let Some(streamer) = &self.job_streamer {
+            if self.job_monitor.accepts_lines() {
+                return Err(anyhow!(
+                    "A job is streaming; hold or stop it before sending {}",
+                    command.trim()
+                ));
+            }
+            if streamer.in_flight() > 0 {
+                return Err(anyhow!(
+                    "The controller still holds {} of the job's lines; resume or stop the job before sending {}",
+                    streamer.in_flight(),
+                    command.trim()
+                ));
+            }
+        }
+        self.write_numbered(command)
+    }
+
+    /// Write a line, numbered if line numbering is on; returns the bytes written
+    fn write_numbered(&mut self, command: &str) -> Result<usize> {
+        if self.read_only {
+            return Err(anyhow!(
+                "Connected read-only: {} was not sent",
+                command.trim()
+            ));
+        }
+        self.check_maintenance_mode(command)?;
+        if self.current_connection.is_none() {
+            return Err(self.not_connected());
+        }
+        self.note_activity();
+
+        let text = match self.line_numbering.as_mut() {
+            Some(numbering) => command
+                .lines()
+                .map(|line| match line.trim() {
+                    // Realtime bytes and Grbl system commands aren't part of the numbered stream
+                    "" | "?" | "!" | "~" => line.to_string(),
+                    trimmed if trimmed.starts_with('$') => line.to_string(),
+                    trimmed => numbering.number(trimmed),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => command.to_string(),
+        };
+        self.write_raw(&text)?;
+        self.last_command = Some(command.to_string());
+        Ok(text.len() + 1)
+    }
+
+    /// Write text and a newline as-is
+    fn write_raw(&mut self, text: &str) -> Result<()> {
+        self.write_raw_logged_as(text, text)
+    }
+
+    /// Write text as-is but show `logged` in the console, to keep secrets out of the log
+    fn write_raw_logged_as(&mut self, text: &str, logged: &str) -> Result<()> {
+        let Some(ref mut stream) = self.current_connection else {
+            return Err(self.not_connected());
+        };
+        let cmd_with_newline = format!("{}\n", text);
+        // Flush so the line is sent immediately
+        let written = stream
+            .write_all(cmd_with_newline.as_bytes())
+            .and_then(|_| stream.flush());
+        if let Err(e) = written {
+            self.metrics.io_errors += 1;
+            return Err(self.link_lost(e.into()));
+        }
+        self.metrics.commands_sent += 1;
+        self.metrics.bytes_sent += cmd_with_newline.len() as u64;
+        let now = transport::unix_millis(SystemTime::now());
+        self.bandwidth
+            .record_sent(now, cmd_with_newline.len(), text.lines().count().max(1));
+        for line in logged.lines() {
+            self.console.record(now, ConsoleDirection::Sent, line);
+        }
+        Ok(())
+    }
+
+    pub fn line_numbering(&self) -> bool {
+        self.line_numbering.is_some()
+    }
+
+    /// Turn N-word and checksum framing on or off. Only for controllers that understand it
+    /// (Marlin, some grblHAL builds); stock Grbl rejects numbered lines with checksums.
+    pub fn set_line_numbering(&mut self, enabled: bool) -> Result<()> {
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!(
+                "Line numbering can't be changed while a job is running"
+            ));
+        }
+        self.line_numbering = None;
+        if enabled {
+            if self.current_connection.is_some() {
+                self.send_command_until_ok(line_numbering::RESET_COMMAND, 2000)?;
+            }
+            self.line_numbering = Some(LineNumbering::default());
+        }
+        println!("🔢 Line numbering {}", if enabled { "on" } else { "off" });
+        Ok(())
+    }
+
+    /// Send the lines the controller asked for again; each gets its own response
+    fn handle_resend(&mut self, number: u32) {
+        let Some(numbering) = self.line_numbering.as_mut() else {
+            return;
+        };
+        let lines = match numbering.resend_from(number) {
+            Ok(lines) => lines,
+            Err(e) => {
+                println!("⚠️  {}", e);
+                return;
+            }
+        };
+        if lines.is_empty() {
+            return;
+        }
+        println!("🔁 Resending {} lines from N{}", lines.len(), number);
+        match self.write_raw(&lines.join("\n")) {
+            Ok(()) => {
+                self.unacked_commands += lines.len();
+                self.metrics.resent_lines += lines.len() as u64;
+            }
+            Err(e) => println!("⚠️  Resend failed: {}", e),
+        }
+    }
+
+    fn check_maintenance_mode(&self, command: &str) -> Result<()> {
+        if !self.maintenance_mode {
+            return Ok(());
+        }
+        match maintenance::blocked_reason(command) {
+            Some(reason) => Err(anyhow!(
+                "Maintenance mode is on: {} is disabled ({})",
+                reason,
+                command.trim()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn maintenance_mode(&self) -> bool {
+        self.maintenance_mode
+    }
+
+    /// Turn maintenance mode on or off. Turning it on stops any jog and the spindle and
+    /// coolant; it is refused while a job is running.
+    pub fn set_maintenance_mode(&mut self, enabled: bool) -> Result<()> {
+        if enabled && self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Stop the job before entering maintenance mode"));
+        }
+        if enabled && self.current_connection.is_some() {
+            self.keyboard_jog.release_all();
+            self.stop_continuous_jog()?;
+            // Best effort: in an alarm state Grbl refuses these, but nothing is running then
+            if let Err(e) = self.send_command_until_ok("M5 M9", 2000) {
+                println!("⚠️  Could not stop spindle/coolant: {}", e);
+            }
+        }
+        self.maintenance_mode = enabled;
+        println!(
+            "🔧 Maintenance mode {}",
+            if enabled {
+                "on: motion disabled"
+            } else {
+                "off"
+            }
+        );
+        self.emit("cnc:maintenance-mode", enabled);
+        Ok(())
+    }
+
+    /// Next complete line from the controller, blocking up to the transport's read timeout
+    fn read_line(&mut self) -> Result<TimedLine> {
+        loop {
+            if let Some(line) = self.rx.next_line() {
+                self.console
+                    .record(line.received_at_ms, ConsoleDirection::Received, &line.text);
+                return Ok(line);
+            }
+            self.receive(false)?;
+        }
+    }
+
+    /// A line that has already arrived, without waiting for one
+    fn try_read_line(&mut self) -> Result<Option<TimedLine>> {
+        loop {
+            if let Some(line) = self.rx.next_line() {
+                self.console
+                    .record(line.received_at_ms, ConsoleDirection::Received, &line.text);
+                return Ok(Some(line));
+            }
+            if !self.receive(true)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Read one chunk from the link into the line splitter. With `available_only` it gives
+    /// false straight away when nothing has arrived instead of waiting out the read timeout.
+    fn receive(&mut self, available_only: bool) -> Result<bool> {
+        let Some(ref mut stream) = self.current_connection else {
+            return Err(self.not_connected());
+        };
+        let mut buffer = [0; 1024];
+        let read = if available_only {
+            stream.read_available(&mut buffer)
+        } else {
+            stream.read(&mut buffer)
+        };
+        let size = match read {
+            Ok(0) => {
+                let closed = std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Connection closed by controller",
+                );
+                return Err(self.link_lost(closed.into()));
+            }
+            Ok(size) => size,
+            // Nothing waiting isn't a failed read
+            Err(e) if available_only && e.kind() == std::io::ErrorKind::WouldBlock => {
+                return Ok(false);
+            }
+            Err(e) => {
+                self.metrics.io_errors += 1;
+                return Err(self.link_lost(e.into()));
+            }
+        };
+        self.metrics.bytes_received += size as u64;
+        if self.health.note_heard() {
+            self.emit("cnc:connection-health", self.connection_health());
+        }
+        let lines = buffer[..size].iter().filter(|b| **b == b'\n').count();
+        self.bandwidth
+            .record_received(transport::unix_millis(SystemTime::now()), size, lines);
+        self.rx.push(&buffer[..size]);
+        Ok(true)
+    }
+
+    /// Hand an incoming line to its consumer: status reports to the status tracker, alarms,
+    /// messages and probe results to the frontend, acknowledgements of fire-and-forget
+    /// commands to the unacked counter. Anything left belongs to the command being waited on.
+    fn route_line(&mut self, timed: TimedLine) -> Option<Routed> {
+        let line = timed.text.clone();
+        let kind = grbl_protocol::classify_line(&line);
+        match kind {
+            LineKind::Status => {
+                self.record_status(&timed);
+                None
+            }
+            LineKind::Ok | LineKind::Error => {
+                // With the backend streaming, acks other than its lines' answer MDI sent while
+                // it's paused
+                if self.job_streamer.as_ref().is_none_or(|s| s.in_flight() > 0) {
+                    self.job_monitor.note_ack();
+                    // Keeps progress moving when status polling is throttled during the job
+                    self.emit_job_progress();
+                }
+                // Streamed lines went out before anything else still unacked
+                let streamed = self.job_streamer.as_ref().filter(|s| s.in_flight() > 0);
+                if kind == LineKind::Error {
+                    self.metrics.error_responses += 1;
+                    // Only the command being waited on is known for sure to be the culprit
+                    let command = match streamed {
+                        Some(streamer) => streamer.oldest_unacked().map(str::to_string),
+                        None if self.unacked_commands == 0 => self.last_command.clone(),
+                        None => None,
+                    };
+                    self.record_alarm(AlarmKind::Error, &timed, command);
+                }
+                if let Some(streamer) = self.job_streamer.as_mut().filter(|s| s.in_flight() > 0) {
+                    streamer.note_ack(&line);
+                    return None;
+                }
+                if self.unacked_commands == 0 {
+                    return Some(Routed::Ack(line));
+                }
+                self.unacked_commands -= 1;
+                if kind == LineKind::Error {
+                    println!("⚠️  Queued command rejected: {}", line);
+                    self.emit("cnc:command-error", timed);
+                }
+                None
+            }
+            LineKind::Alarm => {
+                println!("🚨 Controller alarm: {}", line);
+                self.last_alarm = grbl_codes::parse_code(&line, "ALARM:");
+                self.record_alarm(AlarmKind::Alarm, &timed, None);
+                self.update_homed_state("Alarm");
+                let error = match self.last_alarm {
+                    Some(code) => format!("{}: {}", line, grbl_codes::alarm_message(code)),
+                    None => line.clone(),
+                };
+                self.emit("cnc:alarm", timed);
+                self.send_alert(
+                    AlertEvent::Alarm,
+                    AlertContext {
+                        error: Some(error),
+                        ..Default::default()
+                    },
+                );
+                None
+            }
+            LineKind::Message => {
+                self.react_to_controller_warning(&line);
+                self.emit("cnc:message", timed);
+                Some(Routed::Data(line))
+            }
+            LineKind::Probe => {
+                if let Some(probe) = grbl_protocol::parse_probe(&line) {
+                    self.emit("cnc:probe", probe);
+                }
+                Some(Routed::Data(line))
+            }
+            LineKind::Banner => {
+                // A reset throws away everything queued, including unacknowledged commands
+                self.unacked_commands = 0;
+                if let Some(streamer) = self.job_streamer.as_mut() {
+                    streamer.controller_reset();
+                }
+                if let Some(banner) = grbl_protocol::parse_welcome_banner(&line) {
+                    self.pending_banners.push(banner);
+                }
+                Some(Routed::Reset)
+            }
+            LineKind::Data => match line_numbering::parse_resend(&line) {
+                Some(number) if self.line_numbering.is_some() => {
+                    self.handle_resend(number);
+                    None
+                }
+                _ => Some(Routed::Data(line)),
+            },
+        }
+    }
+
+    /// Add an alarm or error to the persistent history along with the machine context
+    fn record_alarm(&mut self, kind: AlarmKind, timed: &TimedLine, command: Option<String>) {
+        if self.job_monitor.state() != JobState::Idle {
+            self.job_run_recorder.note_alarm(kind);
+        }
+        let line = timed.text.as_str();
+        let code = match kind {
+            AlarmKind::Alarm => grbl_codes::parse_code(line, "ALARM:"),
+            AlarmKind::Error => grbl_codes::parse_code(line, "error:"),
+        };
+        let message = match (kind, code) {
+            (AlarmKind::Alarm, Some(code)) => grbl_codes::alarm_message(code),
+            (AlarmKind::Error, Some(code)) => grbl_codes::error_message(code),
+            (_, None) => "Unrecognised code",
+        };
+        let status = self.last_status.as_ref();
+        let record = AlarmRecord {
+            timestamp: timed.received_at_ms / 1000,
+            kind,
+            code,
+            message: message.to_string(),
+            raw: line.to_string(),
+            machine_state: status.map(|s| s.state.clone()),
+            machine_pos: status.and_then(|s| s.machine_pos.clone()),
+            job_line: self.job_monitor.current_line(),
+            command,
+            machine: self.device_info.as_ref().map(|d| d.machine_key()),
+        };
+        self.alarm_history.push(record);
+
+        if let Some(dir) = &self.data_dir {
+            if let Err(e) = storage::save_json(&AlarmHistory::path_in(dir), &self.alarm_history) {
+                println!("⚠️  Could not save alarm history: {}", e);
+            }
+        }
+    }
+
+    /// Write one `$` setting and record it in the settings audit log
+    fn write_setting(&mut self, number: u16, value: &str, source: SettingSource) -> Result<()> {
+        let previous = self.machine_profile.firmware_settings.get(&number).cloned();
+        // EEPROM writes are slow and stall the controller briefly
+        self.send_command_until_ok(&format!("${}={}", number, value), 2000)?;
+        self.record_setting_change(number, previous, value.to_string(), source);
+        Ok(())
+    }
+
+    fn record_setting_change(
+        &mut self,
+        number: u16,
+        previous: Option<String>,
+        value: String,
+        source: SettingSource,
+    ) -> SettingAuditEntry {
+        println!(
+            "📝 ${} changed from {} to {} ({:?})",
+            number,
+            previous.as_deref().unwrap_or("?"),
+            value,
+            source
+        );
+        // Keep the cached value current so the next change records the right previous value
+        self.machine_profile
+            .firmware_settings
+            .insert(number, value.clone());
+        let entry = self.settings_audit.push(SettingAuditEntry {
+            id: 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            number,
+            previous: previous.clone(),
+            value: value.clone(),
+            source,
+            machine: self.device_info.as_ref().map(|d| d.machine_key()),
+        });
+        self.log_session_action(MachineAction::Setting {
+            number,
+            previous,
+            value,
+            audit_id: entry.id,
+        });
+        if let Some(dir) = &self.data_dir {
+            if let Err(e) = storage::save_json(&SettingsAudit::path_in(dir), &self.settings_audit) {
+                println!("⚠️  Could not save settings audit log: {}", e);
+            }
+        }
+        entry
+    }
+
+    /// Setting changes made through the app, newest first
+    pub fn settings_audit(
+        &self,
+        number: Option<u16>,
+        limit: Option<usize>,
+    ) -> Vec<SettingAuditEntry> {
+        self.settings_audit.recent(number, limit)
+    }
+
+    /// Put a setting back to its value before a logged change
+    pub fn revert_setting_change(&mut self, id: u64) -> Result<MachineProfile> {
+        let entry = self
+            .settings_audit
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No setting change {} in the audit log", id))?;
+        let previous = entry.previous.ok_or_else(|| {
+            anyhow!(
+                "The value of ${} before that change is unknown",
+                entry.number
+            )
+        })?;
+        let machine = self.device_info.as_ref().map(|d| d.machine_key());
+        if entry.machine.is_some() && entry.machine != machine {
+            return Err(anyhow!(
+                "That change was made on a different machine ({})",
+                entry.machine.unwrap_or_default()
+            ));
+        }
+        self.write_setting(entry.number, &previous, SettingSource::Revert)?;
+        self.refresh_machine_settings()
+    }
+
+    fn log_session_action(&mut self, action: MachineAction) {
+        let entry = self.session_log.push(SessionAction {
+            id: 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            machine: self.device_info.as_ref().map(|d| d.machine_key()),
+            action,
+            reverted: false,
+        });
+        self.emit("cnc:session-action", entry);
+    }
+
+    /// Before a line that moves a work offset or selects a WCS outside a job, note what it
+    /// is about to replace. Costs a `$G` and a `$#`, so only for lines that need it.
+    fn pending_session_action(&mut self, line: &str) -> Option<MachineAction> {
+        if self.job_monitor.state() != JobState::Idle {
+            return None;
+        }
+        session_log::offset_target(line, &[])?;
+        // P0 and the selection being replaced depend on the active WCS
+        let modal_state = self.refresh_parser_state().ok()?;
+        match session_log::offset_target(line, &modal_state)? {
+            OffsetTarget::Offset(wcs) => {
+                let previous = self
+                    .coordinate_offsets()
+                    .ok()
+                    .and_then(|o| o.offsets.get(&wcs).cloned());
+                Some(MachineAction::WorkOffset {
+                    wcs,
+                    command: line.trim().to_string(),
+                    previous,
+                    value: None,
+                })
+            }
+            OffsetTarget::Select(value) => Some(MachineAction::WcsSelected {
+                previous: work_offsets::active_wcs(&modal_state).to_string(),
+                value,
+            }),
+        }
+    }
+
+    /// The line from `pending_session_action` was accepted: log it unless nothing changed
+    fn finish_session_action(&mut self, pending: MachineAction) {
+        let action = match pending {
+            MachineAction::WorkOffset {
+                wcs,
+                command,
+                previous,
+                ..
+            } => {
+                let value = self
+                    .coordinate_offsets()
+                    .ok()
+                    .and_then(|o| o.offsets.get(&wcs).cloned());
+                if value.is_some() && value == previous {
+                    return;
+                }
+                MachineAction::WorkOffset {
+                    wcs,
+                    command,
+                    previous,
+                    value,
+                }
+            }
+            MachineAction::WcsSelected { previous, value } => {
+                self.parser_state = None;
+                if previous == value {
+                    return;
+                }
+                MachineAction::WcsSelected { previous, value }
+            }
+            other => other,
+        };
+        self.log_session_action(action);
+    }
+
+    /// Machine changes made this session, newest first
+    pub fn session_log(&self, limit: Option<usize>) -> Vec<SessionAction> {
+        self.session_log.recent(limit)
+    }
+
+    /// Put back what a logged action replaced. The revert is logged as an action too.
+    pub fn revert_session_action(&mut self, id: u64) -> Result<SessionAction> {
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Stop the job before reverting changes"));
+        }
+        let entry = self
+            .session_log
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No action {} in this session's log", id))?;
+        if entry.reverted {
+            return Err(anyhow!("Action {} was already reverted", id));
+        }
+        let machine = self.device_info.as_ref().map(|d| d.machine_key());
+        if entry.machine.is_some() && entry.machine != machine {
+            return Err(anyhow!(
+                "That change was made on a different machine ({})",
+                entry.machine.unwrap_or_default()
+            ));
+        }
+        match entry.action {
+            MachineAction::WorkOffset { wcs, previous, .. } => {
+                let previous = previous
+                    .ok_or_else(|| anyhow!("The {} offset before that change is unknown", wcs))?;
+                let modal_state = self.refresh_parser_state()?;
+                let command = work_offsets::restore_command(&modal_state, &wcs, &previous)?;
+                self.send_command_until_ok(&command, 2000)?;
+                self.work_zero_set_at = Some(Instant::now());
+            }
+            MachineAction::WcsSelected { previous, .. } => {
+                self.send_command_until_ok(&previous, 2000)?;
+            }
+            MachineAction::Setting { audit_id, .. } => {
+                self.revert_setting_change(audit_id)?;
+            }
+            MachineAction::Overrides { previous, .. } => {
+                let previous = previous
+                    .ok_or_else(|| anyhow!("The overrides before that change are unknown"))?;
+                self.set_overrides(
+                    Some(previous.feed),
+                    Some(previous.rapid),
+                    Some(previous.spindle),
+                )?;
+            }
+        }
+        println!("↩️  Reverted session action {}", id);
+        self.session_log
+            .mark_reverted(id)
+            .ok_or_else(|| anyhow!("Action {} dropped out of the log", id))
+    }
+
+    /// Set the feed, rapid and spindle overrides; None leaves one as it is
+    pub fn set_overrides(
+        &mut self,
+        feed: Option<u32>,
+        rapid: Option<u32>,
+        spindle: Option<u32>,
+    ) -> Result<Overrides> {
+        if self.legacy_grbl {
+            return Err(anyhow!("Grbl 0.9 has no overrides"));
+        }
+        for (name, percent) in [("Feed", feed), ("Spindle", spindle)] {
+            if percent.is_some_and(|p| !(10..=200).contains(&p)) {
+                return Err(anyhow!("{} override must be 10 to 200%", name));
+            }
+        }
+        let rapid_byte = rapid.map(feed_zones::rapid_override_byte).transpose()?;
+        let previous = self.last_overrides;
+        let current = previous.unwrap_or(Overrides {
+            feed: 100,
+            rapid: 100,
+            spindle: 100,
+        });
+        let mut bytes = Vec::new();
+        bytes.extend(
+            feed.map(feed_zones::feed_override_bytes)
+                .unwrap_or_default(),
+        );
+        bytes.extend(rapid_byte);
+        bytes.extend(
+            spindle
+                .map(feed_zones::spindle_override_bytes)
+                .unwrap_or_default(),
+        );
+        for byte in bytes {
+            self.send_realtime(byte)?;
+        }
+        let value = Overrides {
+            feed: feed.unwrap_or(current.feed),
+            rapid: rapid.unwrap_or(current.rapid),
+            spindle: spindle.unwrap_or(current.spindle),
+        };
+        // Reports only show the new values a few polls later
+        self.last_overrides = Some(value);
+        if previous != Some(value) {
+            println!(
+                "🎚️  Overrides: feed {}%, rapid {}%, spindle {}%",
+                value.feed, value.rapid, value.spindle
+            );
+            self.log_session_action(MachineAction::Overrides { previous, value });
+        }
+        Ok(value)
+    }
+
+    /// Alarms and errors, newest first, optionally filtered by kind and code
+    pub fn alarm_history(
+        &self,
+        kind: Option<AlarmKind>,
+        code: Option<u32>,
+        limit: Option<usize>,
+    ) -> Vec<AlarmRecord> {
+        self.alarm_history.query(kind, code, limit)
+    }
+
+    pub fn clear_alarm_history(&mut self) -> Result<()> {
+        self.alarm_history = AlarmHistory::default();
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&AlarmHistory::path_in(dir), &self.alarm_history)?;
+        }
+        Ok(())
+    }
+
+    /// Route incoming lines until the waiting command is acknowledged.
+    /// Returns the command's output lines and its `ok`/`error:` line.
+    fn read_response(&mut self, command: &str, timeout: Duration) -> Result<(Vec<String>, String)> {
+        let start_time = Instant::now();
+        let mut lines = Vec::new();
+
+        while start_time.elapsed() < timeout {
+            let line = match self.read_line() {
+                Ok(line) => line,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            match self.route_line(line) {
+                Some(Routed::Ack(ack)) => return Ok((lines, ack)),
+                Some(Routed::Data(line)) => lines.push(line),
+                Some(Routed::Reset) => {
+                    return Err(anyhow!(
+                        "Controller reset while waiting for response to {}",
+                        command
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        // The ack may still turn up; make sure it isn't mistaken for the next command's
+        self.unacked_commands += 1;
+        Err(anyhow!("Timed out waiting for response to {}", command))
+    }
+
+    /// Traffic counters for the current connection
+    pub fn comm_metrics(&self) -> CommMetrics {
+        let mut metrics = self.metrics.clone();
+        metrics.connected_seconds = self.connected_at.map_or(0, |t| t.elapsed().as_secs());
+        metrics
+    }
+
+    /// Traffic over time on the current connection, for seeing whether the link keeps up
+    pub fn bandwidth_report(&self, bucket_seconds: u64, limit: Option<usize>) -> BandwidthReport {
+        self.bandwidth.report(
+            transport::unix_millis(SystemTime::now()),
+            bucket_seconds,
+            limit,
+        )
+    }
+
+    /// Gather everything useful for a bug report into a zip file
+    pub fn export_diagnostics(&mut self, path: &Path, log_path: &Path) -> Result<()> {
+        let mut entries: Vec<(String, String)> = Vec::new();
+
+        if self.current_connection.is_some() {
+            for (name, command) in [
+                ("settings.txt", "$$"),
+                ("build_info.txt", "$I"),
+                ("parameters.txt", "$#"),
+            ] {
+                let contents = match self.send_command_until_ok(command, 5000) {
+                    Ok(lines) => lines.join("\n"),
+                    Err(e) => format!("Failed to read {}: {}", command, e),
+                };
+                entries.push((name.to_string(), contents));
+            }
+            if let Ok(status) = self.send_command("?") {
+                entries.push(("status.txt".to_string(), status));
+            }
+        }
+
+        entries.push((
+            "device.json".to_string(),
+            serde_json::to_string_pretty(&self.device_info)?,
+        ));
+        entries.push((
+            "machine_profile.json".to_string(),
+            serde_json::to_string_pretty(&self.machine_profile)?,
+        ));
+        entries.push((
+            "checklist.json".to_string(),
+            serde_json::to_string_pretty(&self.checklist_config)?,
+        ));
+        entries.push((
+            "comm_metrics.json".to_string(),
+            serde_json::to_string_pretty(&self.comm_metrics())?,
+        ));
+
+        // Only the tail of the log; it grows without bound
+        if let Ok(log) = fs::read_to_string(log_path) {
+            let lines: Vec<&str> = log.lines().collect();
+            let start = lines.len().saturating_sub(DIAGNOSTIC_LOG_LINES);
+            entries.push(("performance.log".to_string(), lines[start..].join("\n")));
+        }
+
+        let file = fs::File::create(path)?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, contents) in entries {
+            zip.start_file(name, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+
+        println!("🩺 Diagnostics exported to {}", path.display());
+        Ok(())
+    }
+
+    /// Shut the link down before dropping it
+    fn close_connection(&mut self) {
+        if let Some(mut stream) = self.current_connection.take() {
+            if let Err(e) = stream.shutdown() {
+                println!("⚠️  Closing {} failed: {}", stream.describe(), e);
+            }
+        }
+    }
+
+    fn not_connected(&self) -> anyhow::Error {
+        match &self.pending_reconnect {
+            Some(pending) => anyhow!(
+                "Connection to {} lost; reconnecting (attempt {})",
+                pending.device.name,
+                pending.attempts + 1
+            ),
+            None => anyhow!("Not connected to any device"),
+        }
+    }
+
+    /// Called with every read or write failure. If the link itself is gone, drop it and
+    /// start retrying; the error is handed back for the command that hit it.
+    fn link_lost(&mut self, error: anyhow::Error) -> anyhow::Error {
+        // Reads time out all the time while waiting for output
+        if is_timeout(&error) {
+            return error;
+        }
+        let reason = DisconnectReason::of(&error);
+        if let Some(device) = &self.device_info {
+            let event = ConnectionErrorEvent {
+                device_name: device.name.clone(),
+                reason,
+                message: error.to_string(),
+            };
+            self.emit("cnc:error", event);
+        }
+        if reconnect::is_link_broken(&error) {
+            self.drop_link(reason, &error.to_string());
+        }
+        error
+    }
+
+    /// The link is gone: close it and start retrying, or disconnect if reconnecting is off
+    fn drop_link(&mut self, reason: DisconnectReason, message: &str) {
+        self.hand_to_reconnect(reason, message, None);
+    }
+
+    /// Close the link and leave it to `reconnect_tick`; a restart is waited for even
+    /// with reconnecting turned off
+    fn hand_to_reconnect(
+        &mut self,
+        reason: DisconnectReason,
+        message: &str,
+        reboot: Option<RebootSession>,
+    ) {
+        if self.current_connection.is_none() {
+            return;
+        }
+        let Some(device) = self.device_info.clone() else {
+            return;
+        };
+        println!("📡 Lost the link to {}: {}", device.name, message);
+        self.close_connection();
+        self.rx.clear();
+        self.unacked_commands = 0;
+        self.continuous_jog = None;
+        self.keyboard_jog.release_all();
+        self.jog_tracker.clear();
+        // Lines in flight are lost with the link, so a job can only be recovered from
+        // its checkpoint, never picked up where it was
+        self.save_job_checkpoint(true);
+        let checkpoint = self.job_checkpoint.clone();
+        let job_interrupted = checkpoint.is_some();
+        if self.job_streamer.is_some() {
+            self.end_stream(Some(format!("Lost the link: {}", message)));
+        } else if job_interrupted {
+            if let Err(e) = self.finish_job(false) {
+                println!("⚠️  End-of-job actions failed: {}", e);
+            }
+        }
+        if let Some(checkpoint) = checkpoint {
+            // Ending the job discarded the checkpoint, which is what it's recovered from
+            if let Some(dir) = &self.data_dir {
+                if let Err(e) = storage::save_json(&JobCheckpoint::path_in(dir), &checkpoint) {
+                    println!("⚠️  Could not save job checkpoint: {}", e);
+                }
+            }
+            self.recoverable_job = Some(checkpoint);
+        }
+
+        if !self.reconnect_policy.enabled && reboot.is_none() {
+            self.disconnect_with(reason, message);
+            return;
+        }
+        self.emit(
+            "cnc:disconnected",
+            DisconnectedEvent {
+                device: device.clone(),
+                reason,
+                message: message.to_string(),
+                reconnecting: true,
+            },
+        );
+        let mut pending = PendingReconnect::new(
+            device,
+            self.read_only,
+            self.line_numbering.is_some(),
+            message.to_string(),
+            &self.reconnect_policy,
+        );
+        pending.job_interrupted = job_interrupted;
+        if reboot.is_some() {
+            pending.next_attempt_at = Instant::now() + controller_reboot::REBOOT_SETTLE;
+            pending.reboot = reboot;
+        }
+        self.emit(
+            "cnc:reconnecting",
+            pending.reconnecting_event(&self.reconnect_policy),
+        );
+        self.pending_reconnect = Some(pending);
+        self.emit("cnc:connection-health", self.connection_health());
+    }
+
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy.clone()
+    }
+
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) -> Result<()> {
+        policy.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&ReconnectPolicy::path_in(dir), &policy)?;
+        }
+        if !policy.enabled && self.pending_reconnect.is_some() {
+            println!("🔄 Reconnecting turned off; giving up on the dropped link");
+            self.disconnect_with(
+                DisconnectReason::ReconnectFailed,
+                "Reconnecting was turned off",
+            );
+        }
+        self.reconnect_policy = policy;
+        Ok(())
+    }
+
+    /// Make the next reconnect attempt if one is due. Called regularly by the supervisor
+    /// thread, so the waits between attempts don't hold the manager.
+    pub fn reconnect_tick(&mut self) {
+        if !self.pending_reconnect.as_ref().is_some_and(|p| p.due()) {
+            return;
+        }
+        // Left in place so attach can tell this is a reconnect
+        let Some(mut pending) = self.pending_reconnect.clone() else {
+            return;
+        };
+        println!(
+            "🔄 Reconnecting to {} (attempt {})",
+            pending.device.name,
+            pending.attempts + 1
+        );
+        let attached = transport::connect(&pending.device)
+            .and_then(|stream| self.attach(stream, &pending.device, pending.read_only));
+        // The link can drop again while attaching, which starts a fresh retry; this one
+        // keeps its count instead
+        let result = match attached {
+            Ok(()) if self.current_connection.is_none() => {
+                Err(anyhow!("Link dropped again while reconnecting"))
+            }
+            other => other,
+        };
+        if let Err(e) = result {
+            self.close_connection();
+            if pending.reboot.is_some() {
+                self.rediscover(&mut pending.device);
+            }
+            if pending.attempt_failed(&self.reconnect_policy, &e.to_string()) {
+                self.emit(
+                    "cnc:reconnecting",
+                    pending.reconnecting_event(&self.reconnect_policy),
+                );
+                self.pending_reconnect = Some(pending);
+            } else {
+                println!(
+                    "📡 Gave up reconnecting to {} after {} attempts",
+                    pending.device.name, pending.attempts
+                );
+                self.disconnect_with(DisconnectReason::ReconnectFailed, &e.to_string());
+            }
+            return;
+        }
+
+        if pending.line_numbering {
+            // The controller's line counter doesn't match ours any more
+            self.line_numbering = None;
+            if let Err(e) = self.set_line_numbering(true) {
+                println!("⚠️  Could not restore line numbering: {}", e);
+            }
+        }
+        pending.attempts += 1;
+        println!(
+            "✅ Reconnected to {} after {} attempt(s)",
+            pending.device.name, pending.attempts
+        );
+        self.emit(
+            "cnc:reconnected",
+            ReconnectedEvent {
+                device_name: pending.device.name.clone(),
+                attempts: pending.attempts,
+                down_ms: pending.lost_at.elapsed().as_millis() as u64,
+                job_interrupted: pending.job_interrupted,
+            },
+        );
+        if let Some(session) = pending.reboot.take() {
+            self.restore_after_reboot(&pending, session);
+        }
+    }
+
+    /// Restart the controller with `command`, or `$Bye` on FluidNC, and reconnect once it's
+    /// back with the modal state and feed override it had. `cnc:rebooting` is sent now and
+    /// `cnc:rebooted` when the session is restored.
+    pub fn reboot_controller(&mut self, command: Option<String>) -> Result<()> {
+        let Some(device) = self
+            .device_info
+            .clone()
+            .filter(|_| self.current_connection.is_some())
+        else {
+            return Err(self.not_connected());
+        };
+        if self.read_only {
+            return Err(anyhow!(
+                "Connected read-only: the controller can't be restarted"
+            ));
+        }
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Stop the job before restarting the controller"));
+        }
+        let firmware = device.firmware.clone().or_else(|| {
+            self.machine_profile
+                .build_info
+                .as_ref()
+                .and_then(|b| b.version.clone())
+        });
+        let command = match command.map(|c| c.trim().to_string()) {
+            Some(command) if !command.is_empty() => command,
+            _ => controller_reboot::restart_command(firmware.as_deref())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No known restart command for {}; give the one your controller uses",
+                        firmware.as_deref().unwrap_or("this firmware")
+                    )
+                })?
+                .to_string(),
+        };
+
+        let modal_state = match self.refresh_parser_state() {
+            Ok(state) => Some(state),
+            Err(e) => {
+                println!(
+                    "⚠️  Could not read the parser state before restarting: {}",
+                    e
+                );
+                self.parser_state.clone()
+            }
+        };
+        let feed_override = self
+            .last_status
+            .as_ref()
+            .and_then(|s| s.overrides)
+            .map(|o| o.feed)
+            .filter(|feed| *feed != 100);
+
+        println!("🔁 Restarting {} with {}", device.name, command);
+        // The controller may go down before answering, so no ok is waited for
+        self.write_line(&command)?;
+        self.emit(
+            "cnc:rebooting",
+            RebootingEvent {
+                device_name: device.name.clone(),
+                command: command.clone(),
+            },
+        );
+        self.hand_to_reconnect(
+            DisconnectReason::Reboot,
+            "Controller restarting",
+            Some(RebootSession {
+                command,
+                modal_state,
+                feed_override,
+            }),
+        );
+        Ok(())
+    }
+
+    /// A restarted controller can come back on another DHCP address; look for its MAC
+    fn rediscover(&mut self, device: &mut CncDevice) {
+        let (
+            Some(mac),
+            TransportKind::Tcp
+            | TransportKind::Telnet
+            | TransportKind::WebSocket
+            | TransportKind::Tls,
+        ) = (&device.mac, device.transport)
+        else {
+            return;
+        };
+        let found = discovery::discover(
+            &self.discovery_config,
+            &self.saved_addresses,
+            None,
+            controller_reboot::REDISCOVERY_TIMEOUT_MS,
+            DiscoveryWatch::default(),
+        );
+        let Some(moved) = found.ok().and_then(|devices| {
+            devices.into_iter().find(|d| {
+                d.mac.as_ref() == Some(mac) && (d.ip != device.ip || d.port != device.port)
+            })
+        }) else {
+            return;
+        };
+        println!(
+            "🔎 {} came back at {}:{} (was {}:{})",
+            device.name, moved.ip, moved.port, device.ip, device.port
+        );
+        device.ip = moved.ip;
+        device.port = moved.port;
+    }
+
+    /// Send an AT command to the WiFi module and collect its reply up to `OK`. The module
+    /// answers these itself instead of passing them on to Grbl.
+    fn send_at_command(&mut self, command: &str) -> Result<Vec<String>> {
+        if self.read_only {
+            return Err(anyhow!("Connected read-only: {} was not sent", command));
+        }
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Stop the job before changing the WiFi settings"));
+        }
+        self.write_raw(command)?;
+        let start_time = Instant::now();
+        let mut lines = Vec::new();
+        while start_time.elapsed() < wifi_provisioning::AT_TIMEOUT {
+            let line = match self.read_line() {
+                Ok(line) => line,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            let Some(Routed::Data(line)) = self.route_line(line) else {
+                continue;
+            };
+            match wifi_provisioning::final_result(&line) {
+                Some(true) => return Ok(lines),
+                Some(false) => return Err(anyhow!("WiFi module rejected {}", command)),
+                // The module may echo the command back
+                None if line.trim() == command => {}
+                None => lines.push(line),
+            }
+        }
+        Err(anyhow!(
+            "No answer to {}; is this a WiFi module that takes AT commands?",
+            command
+        ))
+    }
+
+    /// Network and address the WiFi module is on
+    pub fn wifi_status(&mut self) -> Result<WifiStatus> {
+        let mut status = WifiStatus::default();
+        let joined = self.send_at_command("AT+CWJAP?")?;
+        wifi_provisioning::parse_status(&joined, &mut status);
+        let address = self.send_at_command("AT+CIPSTA?")?;
+        wifi_provisioning::parse_status(&address, &mut status);
+        Ok(status)
+    }
+
+    /// Move the WiFi module to another network. The connection drops when it leaves this
+    /// one, so it's closed here; with a static address the saved device follows it,
+    /// otherwise discovery finds it on the new network.
+    pub fn provision_wifi(&mut self, config: WifiProvisioning) -> Result<()> {
+        config.validate()?;
+        let Some(device) = self
+            .device_info
+            .clone()
+            .filter(|_| self.current_connection.is_some())
+        else {
+            return Err(self.not_connected());
+        };
+        for command in config.address_commands() {
+            self.send_at_command(&command)?;
+        }
+        println!("📶 Moving {} to WiFi network {}", device.name, config.ssid);
+        // The module leaves this network before it can answer
+        self.write_raw_logged_as(
+            &config.join_command(),
+            &format!("AT+CWJAP=\"{}\",\"********\"", config.ssid),
+        )?;
+
+        if let Some(static_ip) = &config.static_ip {
+            let key = device.machine_key();
+            if let Some(saved) = self
+                .saved_devices
+                .devices
+                .iter_mut()
+                .find(|d| d.device.machine_key() == key)
+            {
+                saved.device.ip = static_ip.ip.clone();
+                self.save_saved_devices()?;
+            }
+        }
+        self.disconnect_with(
+            DisconnectReason::User,
+            &format!("Moved to WiFi network {}", config.ssid),
+        );
+        Ok(())
+    }
+
+    /// Put back what the restart reset: modal state that's safe to send, and the feed override
+    fn restore_after_reboot(&mut self, pending: &PendingReconnect, session: RebootSession) {
+        let mut restored = Vec::new();
+        let mut not_restored = Vec::new();
+        if let Some(expected) = &session.modal_state {
+            match self.refresh_parser_state() {
+                Ok(actual) => {
+                    let (automatic, manual): (Vec<_>, Vec<_>) =
+                        modal_resync::modal_changes(expected, &actual)
+                            .into_iter()
+                            .partition(|c| c.restorable());
+                    if let Ok(line) = modal_resync::restore_line(&automatic) {
+                        if !line.is_empty() {
+                            println!("🔁 Restoring modal state after restart: {}", line);
+                            match self.send_command_until_ok(&line, 2000) {
+                                Ok(_) => {
+                                    self.parser_state = None;
+                                    restored.extend(automatic.iter().map(|c| c.expected.clone()));
+                                }
+                                Err(e) => {
+                                    println!("⚠️  Could not restore modal state: {}", e);
+                                    not_restored.extend(automatic);
+                                }
+                            }
+                        }
+                    }
+                    not_restored.extend(manual);
+                }
+                Err(e) => println!(
+                    "⚠️  Could not read the parser state after restarting: {}",
+                    e
+                ),
+            }
+        }
+        if let Some(feed) = session.feed_override {
+            match self.send_feed_override(feed) {
+                Ok(()) => restored.push(format!("feed override {}%", feed)),
+                Err(e) => println!("⚠️  Could not restore feed override: {}", e),
+            }
+        }
+        println!(
+            "✅ {} restarted ({})",
+            pending.device.name,
+            if restored.is_empty() {
+                "nothing to restore".to_string()
+            } else {
+                restored.join(", ")
+            }
+        );
+        self.emit(
+            "cnc:rebooted",
+            RebootedEvent {
+                device_name: pending.device.name.clone(),
+                command: session.command,
+                down_ms: pending.lost_at.elapsed().as_millis() as u64,
+                restored,
+                not_restored,
+            },
+        );
+    }
+
+    pub fn connection_health(&self) -> ConnectionHealth {
+        if let Some(pending) = &self.pending_reconnect {
+            return ConnectionHealth {
+                state: LinkHealth::Reconnecting,
+                reason: Some(pending.reason.clone()),
+                ..self.health.report()
+            };
+        }
+        if self.current_connection.is_none() {
+            return ConnectionHealth::default();
+        }
+        self.health.report()
+    }
+
+    pub fn heartbeat_config(&self) -> HeartbeatConfig {
+        self.heartbeat.clone()
+    }
+
+    pub fn set_heartbeat_config(&mut self, config: HeartbeatConfig) -> Result<()> {
+        config.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&HeartbeatConfig::path_in(dir), &config)?;
+        }
+        self.heartbeat = config;
+        Ok(())
+    }
+
+    /// Send a status query if the link has been quiet for a heartbeat interval. Called
+    /// regularly by the supervisor thread; a link that stops answering is dropped and
+    /// handed to reconnecting.
+    pub fn heartbeat_tick(&mut self) {
+        if !self.heartbeat.enabled
+            || self.current_connection.is_none()
+            || !self.health.heartbeat_due(&self.heartbeat)
+            // The policy asked for the WiFi module to be left to idle
+            || (self.idle_powered_down && self.idle_policy.stop_polling)
+        {
+            return;
+        }
+        let sent_at = Instant::now();
+        let round_trip = match self.get_status() {
+            Ok(_) => Some(sent_at.elapsed()),
+            Err(e) => {
+                println!("💓 Heartbeat unanswered: {}", e);
+                None
+            }
+        };
+        // The query itself can find the link broken and drop it
+        if self.current_connection.is_none() {
+            return;
+        }
+        if !self.health.record_heartbeat(&self.heartbeat, round_trip) {
+            return;
+        }
+        let health = self.connection_health();
+        println!(
+            "💓 Link {:?}{}",
+            health.state,
+            health
+                .reason
+                .as_ref()
+                .map_or(String::new(), |r| format!(": {}", r))
+        );
+        self.emit("cnc:connection-health", health.clone());
+        if health.state == LinkHealth::Dead {
+            let reason = health.reason.unwrap_or_default();
+            self.drop_link(DisconnectReason::Timeout, &reason);
+        }
+    }
+
+    /// Disconnect from current device
+    pub fn disconnect(&mut self) {
+        self.disconnect_with(DisconnectReason::User, "Disconnected");
+    }
+
+    /// Disconnect and tell the frontend why with `cnc:disconnected`
+    fn disconnect_with(&mut self, reason: DisconnectReason, message: &str) {
+        // Still set while a dropped link is retried, and cleared below
+        if let Some(device) = self.device_info.clone() {
+            self.emit(
+                "cnc:disconnected",
+                DisconnectedEvent {
+                    device,
+                    reason,
+                    message: message.to_string(),
+                    reconnecting: false,
+                },
+            );
+        }
+        self.close_connection();
+        self.pending_reconnect = None;
+        if self.health.state() != LinkHealth::Disconnected {
+            self.health = HealthMonitor::default();
+            self.emit("cnc:connection-health", self.connection_health());
+        }
+        self.fault_injector_installed = false;
+        self.rx.clear();
+        self.unacked_commands = 0;
+        self.continuous_jog = None;
+        self.keyboard_jog.release_all();
+        self.jog_tracker.clear();
+        self.job_monitor.set_streaming(false);
+        self.device_info = None;
+        self.last_status = None;
+        self.last_status_received_ms = None;
+        self.last_work_offset = None;
+        self.last_overrides = None;
+        self.work_zero_set_at = None;
+        self.acknowledged_checks.clear();
+        self.homed = None;
+        self.homing_in_progress = false;
+        self.connected_at = None;
+        self.pending_banners.clear();
+        self.parser_state = None;
+        self.paused_modal_state = None;
+        self.feed_zones = None;
+        self.last_alarm = None;
+        self.legacy_grbl = false;
+        self.read_only = false;
+        self.line_numbering = None;
+    }
+
+    /// Send a jog and report whether it was queued or rejected; `cnc:jog-feedback` follows
+    /// once status reports show it finished
+    pub fn jog(&mut self, axis: &str, distance: f32, feed_rate: u32) -> Result<JogFeedback> {
+        let (axis, distance) = self
+            .machine_profile
+            .axis_mapping
+            .jog_to_machine(axis, distance);
+        let request = self.validate_jog(&axis, distance, feed_rate)?;
+        let lines = if self.legacy_grbl {
+            request.legacy_commands().to_vec()
+        } else {
+            vec![request.command()]
+        };
+        let mut responses = Vec::new();
+        for line in &lines {
+            let response = self.send_command(line)?;
+            let rejected = response.lines().any(|l| l.starts_with("error:"));
+            responses.push(response);
+            if rejected {
+                break;
+            }
+        }
+        let id = self.jog_tracker.next_id();
+        let feedback = JogFeedback::from_response(id, &lines[0], &responses.join("\n"));
+        if let Some(message) = &feedback.message {
+            println!("🚫 Jog {} rejected: {}", lines[0], message);
+        }
+        self.jog_tracker.track(&feedback);
+        Ok(feedback)
+    }
+
+    /// Send jog command (non-blocking)
+    pub fn jog_no_wait(&mut self, axis: &str, distance: f32, feed_rate: u32) -> Result<()> {
+        let (axis, distance) = self
+            .machine_profile
+            .axis_mapping
+            .jog_to_machine(axis, distance);
+        let request = self.validate_jog(&axis, distance, feed_rate)?;
+        if self.legacy_grbl {
+            for line in request.legacy_commands() {
+                self.send_command_no_wait(&line)?;
+            }
+            return Ok(());
+        }
+        self.send_command_no_wait(&request.command())
+    }
+
+    /// Start hold-to-jog. Call `continue_jog` periodically while the button is held
+    /// and `stop_continuous_jog` when it is released.
+    pub fn start_continuous_jog(
+        &mut self,
+        axis: &str,
+        direction: f32,
+        feed_rate: u32,
+    ) -> Result<()> {
+        self.stop_continuous_jog()?;
+        if self.legacy_grbl {
+            // Without $J there is no jog cancel to stop queued segments
+            return Err(anyhow!("Continuous jogging needs Grbl 1.1 or later"));
+        }
+        let (axis, direction) = self
+            .machine_profile
+            .axis_mapping
+            .jog_to_machine(axis, direction);
+        let jog = ContinuousJog::plan(&self.machine_profile, &axis, direction, feed_rate)?;
+        println!(
+            "🎮 Continuous jog {} at {:.0} mm/min: up to {} x {:.3} mm segments queued",
+            jog.segment.axis, jog.segment.feed_rate, jog.max_queued, jog.segment.distance
+        );
+        self.continuous_jog = Some(jog);
+        self.top_up_jog()
+    }
+
+    /// Keep a continuous jog moving; motion stops on its own shortly after these calls stop
+    pub fn continue_jog(&mut self) -> Result<()> {
+        if self.continuous_jog.is_none() {
+            return Ok(());
+        }
+        // Reading a status report also collects the acks that arrived before it
+        self.get_status()?;
+        self.top_up_jog()
+    }
+
+    fn top_up_jog(&mut self) -> Result<()> {
+        let Some(mut jog) = self.continuous_jog.take() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let due = jog
+            .segments_due(now)
+            .min(MAX_UNACKED_JOG_SEGMENTS.saturating_sub(self.unacked_commands));
+
+        // Check the whole queue against the limits, not just the next segment
+        let lookahead = JogRequest {
+            distance: jog.segment.distance * jog.max_queued as f32,
+            ..jog.segment.clone()
+        };
+        self.check_jog_soft_limits(&lookahead)?;
+
+        for _ in 0..due {
+            self.send_command_no_wait(&jog.segment.command())?;
+            jog.record_sent(now);
+        }
+        self.continuous_jog = Some(jog);
+        Ok(())
+    }
+
+    /// Cancel a continuous jog and make sure no buffered segment restarts it
+    pub fn stop_continuous_jog(&mut self) -> Result<()> {
+        if self.continuous_jog.take().is_none() {
+            return Ok(());
+        }
+        self.send_realtime(grbl_protocol::JOG_CANCEL)?;
+
+        // Segments still in the serial buffer run after the cancel, so let them drain and cancel again
+        let had_unacked = self.unacked_commands > 0;
+        let start_time = Instant::now();
+        while self.unacked_commands > 0 && start_time.elapsed() < Duration::from_millis(1000) {
+            match self.read_line() {
+                Ok(line) => {
+                    let _ = self.route_line(line);
+                }
+                Err(e) if is_timeout(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if had_unacked {
+            self.send_realtime(grbl_protocol::JOG_CANCEL)?;
+        }
+        Ok(())
+    }
+
+    pub fn keyboard_jog_config(&self) -> &KeyboardJogConfig {
+        self.keyboard_jog.config()
+    }
+
+    pub fn set_keyboard_jog_config(&mut self, config: KeyboardJogConfig) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&KeyboardJogConfig::path_in(dir), &config)?;
+        }
+        let actions = self.keyboard_jog.set_config(config);
+        self.apply_key_jog_actions(actions)
+    }
+
+    /// Key-down or key-up from the frontend's keyboard handler
+    pub fn keyboard_jog_key(&mut self, key: &str, pressed: bool) -> Result<()> {
+        let actions = if pressed {
+            self.keyboard_jog.key_down(key, Instant::now())
+        } else {
+            self.keyboard_jog.key_up(key)
+        };
+        self.apply_key_jog_actions(actions)
+    }
+
+    /// Call periodically while a key is held: switches step to continuous, ramps the
+    /// feed and keeps the continuous jog queue topped up
+    pub fn keyboard_jog_tick(&mut self) -> Result<()> {
+        let actions = self.keyboard_jog.tick(Instant::now());
+        self.apply_key_jog_actions(actions)?;
+        self.continue_jog()
+    }
+
+    /// Stop keyboard jogging, e.g. when the window loses focus
+    pub fn keyboard_jog_release(&mut self) -> Result<()> {
+        let actions = self.keyboard_jog.release_all();
+        self.apply_key_jog_actions(actions)
+    }
+
+    fn apply_key_jog_actions(&mut self, actions: Vec<KeyJogAction>) -> Result<()> {
+        for action in actions {
+            let result = match action {
+                KeyJogAction::Step {
+                    axis,
+                    distance,
+                    feed_rate,
+                } => self.jog_no_wait(&axis, distance, feed_rate),
+                KeyJogAction::StartContinuous {
+                    axis,
+                    direction,
+                    feed_rate,
+                } => self.start_continuous_jog(&axis, direction, feed_rate),
+                KeyJogAction::SetFeed(feed_rate) => self.set_continuous_jog_feed(feed_rate),
+                KeyJogAction::StopContinuous => self.stop_continuous_jog(),
+            };
+            if let Err(e) = result {
+                // Forget the key so a refused jog isn't retried on every tick
+                self.keyboard_jog.release_all();
+                let _ = self.stop_continuous_jog();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Change the feed of the running continuous jog; takes effect with the next segments
+    fn set_continuous_jog_feed(&mut self, feed_rate: u32) -> Result<()> {
+        let Some(jog) = &self.continuous_jog else {
+            return Ok(());
+        };
+        self.continuous_jog = Some(jog.with_feed(&self.machine_profile, feed_rate)?);
+        self.top_up_jog()
+    }
+
+    /// Speed up or slow down the running continuous jog by `steps` notches (negative slows
+    /// it), e.g. from a scroll wheel while creeping up to an edge. Segments already queued
+    /// keep their feed; the next ones sent use the new one. Returns the feed now in use,
+    /// or None when no continuous jog is running.
+    pub fn nudge_jog_feed(&mut self, steps: i32) -> Result<Option<f32>> {
+        let Some(jog) = &self.continuous_jog else {
+            return Ok(None);
+        };
+        if steps != 0 {
+            let feed_rate = jog.nudged_feed(steps);
+            self.set_continuous_jog_feed(feed_rate)?;
+        }
+        Ok(self.continuous_jog.as_ref().map(|j| j.segment.feed_rate))
+    }
+
+    fn validate_jog(&self, axis: &str, distance: f32, feed_rate: u32) -> Result<JogRequest> {
+        let request = JogRequest::validate(&self.machine_profile, axis, distance, feed_rate)?;
+        self.check_jog_soft_limits(&request)?;
+        Ok(request)
+    }
+
+    /// Reject jogs that would leave the machine envelope, based on the last known position
+    fn check_jog_soft_limits(&self, request: &JogRequest) -> Result<()> {
+        // Machine coordinates only mean anything once the machine has been homed
+        if !self.is_homed() {
+            return Ok(());
+        }
+        let Some(machine_pos) = self
+            .last_status
+            .as_ref()
+            .and_then(|s| s.machine_pos.as_ref())
+        else {
+            return Ok(());
+        };
+        let index = AXIS_LETTERS.iter().position(|a| *a == request.axis);
+        match index.and_then(|i| machine_pos.get(i)) {
+            Some(current) => self
+                .machine_profile
+                .check_soft_limits(request.axis, current + request.distance),
+            None => Ok(()),
+        }
+    }
+
+    /// Get machine status
+    pub fn get_status(&mut self) -> Result<String> {
+        self.send_realtime(b'?')?;
+        let sent_at = Instant::now();
+
+        let Some(line) = self.next_status_line(Duration::from_millis(STATUS_TIMEOUT_MS))? else {
+            return Err(anyhow!("Timed out waiting for status report"));
+        };
+        // Only a report that arrived after this query measures the round trip
+        if line.received_at > sent_at {
+            self.metrics
+                .record_round_trip(line.received_at.duration_since(sent_at));
+        }
+        self.record_status(&line);
+        self.handle_controller_reset();
+        Ok(line.text)
+    }
+
+    /// Read until a status report arrives, passing other lines on; None on timeout. The
+    /// caller records the report.
+    fn next_status_line(&mut self, timeout: Duration) -> Result<Option<TimedLine>> {
+        let start_time = Instant::now();
+        while start_time.elapsed() < timeout {
+            let line = match self.read_line() {
+                Ok(line) => line,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if grbl_protocol::classify_line(&line.text) == LineKind::Status {
+                return Ok(Some(line));
+            }
+            match self.route_line(line) {
+                Some(Routed::Ack(ack)) => {
+                    println!("⚠️  Unexpected {} with no command pending", ack)
+                }
+                Some(Routed::Data(data)) => println!("📨 {}", data),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Time `?` round trips to tell a slow or lossy link from a slow app. Holds the
+    /// connection for the whole run, so it's refused while a job streams.
+    pub fn run_connection_diagnostics(
+        &mut self,
+        options: DiagnosticsOptions,
+    ) -> Result<DiagnosticsReport> {
+        options.validate()?;
+        let Some(device) = self
+            .device_info
+            .clone()
+            .filter(|_| self.current_connection.is_some())
+        else {
+            return Err(self.not_connected());
+        };
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Connection diagnostics can't run during a job"));
+        }
+        let transport = self
+            .current_connection
+            .as_ref()
+            .map_or_else(String::new, |c| c.describe());
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        println!(
+            "🩺 Timing {} status queries to {}",
+            options.samples, transport
+        );
+
+        let timeout = Duration::from_millis(options.timeout_ms);
+        let interval = Duration::from_millis(options.interval_ms);
+        let mut round_trips = Vec::new();
+        let mut late = 0;
+        for _ in 0..options.samples {
+            let sent_at = Instant::now();
+            self.send_realtime(b'?')?;
+            let mut round_trip = None;
+            while let Some(line) =
+                self.next_status_line(timeout.saturating_sub(sent_at.elapsed()))?
+            {
+                self.record_status(&line);
+                // Read before this query was sent, so not its answer
+                if line.received_at <= sent_at {
+                    continue;
+                }
+                let rtt = line.received_at.duration_since(sent_at);
+                // A read can block past the timeout; an answer that slow counts as lost
+                if rtt <= timeout {
+                    round_trip = Some(rtt.as_secs_f64() * 1000.0);
+                } else {
+                    late += 1;
+                }
+                break;
+            }
+            round_trips.push(round_trip);
+            if let Some(rest) = interval.checked_sub(sent_at.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+        self.handle_controller_reset();
+
+        let report = connection_diagnostics::summarize(
+            device.name,
+            transport,
+            started_at,
+            round_trips,
+            late,
+            self.heartbeat.slow_round_trip_ms,
+        );
+        println!(
+            "🩺 {}/{} answered, avg {:?}ms, jitter {:?}ms",
+            report.answered, report.samples, report.avg_ms, report.jitter_ms
+        );
+        Ok(report)
+    }
+
+    /// Remember the latest status report so position-dependent checks can use it
+    fn record_status(&mut self, line: &TimedLine) {
+        let response = line.text.as_str();
+        if let Some(mut report) = grbl_protocol::parse_status_report(response) {
+            self.last_status_received_ms = Some(line.received_at_ms);
+            // A bare `<Idle>` says nothing about the format
+            if response.contains('|') {
+                self.set_legacy_grbl(false);
+            } else if response.contains(',') {
+                self.set_legacy_grbl(true);
+            }
+            // WCO is only included every few reports, so keep the last one seen
+            if report.work_offset.is_some() {
+                self.last_work_offset = report.work_offset.clone();
+            }
+            if report.overrides.is_some() {
+                self.last_overrides = report.overrides;
+            }
+            if self.job_monitor.state() != JobState::Idle {
+                self.job_run_recorder
+                    .observe_overrides(self.last_overrides, Instant::now());
+            }
+            // $10 picks which position is reported; work out the other one from WCO
+            if let Some(offset) = self.last_work_offset.as_deref() {
+                let shift = |values: &Vec<f32>, sign: f32| -> Vec<f32> {
+                    values
+                        .iter()
+                        .zip(offset)
+                        .map(|(v, o)| v + sign * o)
+                        .collect()
+                };
+                match (&report.machine_pos, &report.work_pos) {
+                    (Some(machine), None) => report.work_pos = Some(shift(machine, -1.0)),
+                    (None, Some(work)) => report.machine_pos = Some(shift(work, 1.0)),
+                    _ => {}
+                }
+            }
+            self.update_homed_state(&report.state);
+            if let Some(feedback) = self.jog_tracker.observe(&report.state) {
+                self.emit("cnc:jog-feedback", feedback);
+            }
+            if let Some(change) = self.job_monitor.observe(&report.state) {
+                println!(
+                    "⏸️  Job {:?} -> {:?} ({}{})",
+                    change.previous,
+                    change.state,
+                    change.machine_state,
+                    if change.machine_initiated {
+                        ", from the machine"
+                    } else {
+                        ""
+                    }
+                );
+                if change.state == JobState::Running {
+                    // Resumed, possibly from the machine's own button; the snapshot is spent
+                    self.paused_modal_state = None;
+                }
+                self.job_run_recorder.note_state_change(&change);
+                self.emit("cnc:job-state", change);
+            }
+            let lines_waiting = match &self.job_streamer {
+                Some(streamer) => Some(streamer.lines_waiting()),
+                None => self.job_monitor.lines_waiting(),
+            };
+            if let Some(warning) = self.stall_detector.observe(&report, lines_waiting) {
+                println!(
+                    "🐢 Planner starved while streaming ({} blocks queued for {} ms)",
+                    warning.queued_blocks, warning.starved_ms
+                );
+                if warning.auto_paused {
+                    // Feed hold decelerates cleanly instead of dwelling in the cut
+                    let _ = self.send_realtime(b'!');
+                }
+                let error = format!(
+                    "Planner starved: {} blocks queued for {} ms",
+                    warning.queued_blocks, warning.starved_ms
+                );
+                self.emit("cnc:stream-stall", warning);
+                self.send_alert(
+                    AlertEvent::StreamStall,
+                    AlertContext {
+                        error: Some(error),
+                        ..Default::default()
+                    },
+                );
+            }
+            if self.job_monitor.state() != JobState::Idle {
+                let executing = self
+                    .job_monitor
+                    .line_map(self.planner_queued(&report))
+                    .and_then(|lines| lines.executing);
+                self.heatmap_recorder.observe(&report, executing);
+            }
+            self.update_feed_zone(&report);
+            self.react_to_spindle_load(&report);
+            self.last_status = Some(report);
+            self.emit_job_progress();
+            self.save_job_checkpoint(false);
+        }
+    }
+
+    /// At most once a second while a job runs, so the frontend can show active and paused time
+    fn emit_job_progress(&mut self) {
+        if self.job_monitor.state() == JobState::Idle {
+            return;
+        }
+        if self
+            .last_progress_emit
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(1))
+        {
+            return;
+        }
+        self.last_progress_emit = Some(Instant::now());
+        if let Some(progress) = self.job_monitor.progress(self.job_line_map()) {
+            self.emit("cnc:job-progress", progress);
+        }
+    }
+
+    /// Length of the running job in lines, so `cnc:job-progress` can give percent
+    /// complete and time remaining
+    pub fn set_job_total_lines(&mut self, total_lines: Option<usize>) {
+        self.job_monitor.set_total_lines(total_lines);
+    }
+
+    /// Tell the stall detector whether a job is currently being streamed
+    pub fn set_job_streaming(&mut self, streaming: bool, program_name: Option<String>) {
+        if streaming {
+            self.stall_detector.set_streaming(true);
+            self.job_monitor.set_streaming(true);
+            self.heatmap_recorder.start();
+            self.job_run_recorder.start();
+            self.tool_change.clear();
+            self.min_z_tracker = self.min_z_guard.clone().map(|guard| {
+                let work_pos = self
+                    .last_status
+                    .as_ref()
+                    .and_then(|s| s.work_pos.as_deref());
+                MinZTracker::new(guard, work_pos)
+            });
+            self.paused_modal_state = None;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            self.job_checkpoint = Some(JobCheckpoint {
+                program_name,
+                started_at: now,
+                updated_at: now,
+                state: self.job_monitor.state(),
+                last_acked_line: None,
+                executing_line: None,
+                machine: self.device_info.as_ref().map(|d| d.machine_key()),
+                machine_pos: None,
+                work_offset: self.last_work_offset.clone(),
+                modal_state: self.parser_state.clone(),
+            });
+            self.console
+                .set_job(Some(transport::unix_millis(SystemTime::now())));
+            if self.dust_collection.enabled {
+                self.dust_collection_off_at = None;
+                if !self.dust_collection_on.load(Ordering::Relaxed) {
+                    self.switch_dust_collection(true);
+                }
+            }
+            // A new job replaces whatever the last session left behind
+            self.recoverable_job = None;
+            self.save_job_checkpoint(true);
+            if let Some(mask) = self.cached_status_mask() {
+                if !mask.buffer_state {
+                    println!(
+                        "⚠️  $10={} leaves buffer state out of status reports",
+                        mask.value
+                    );
+                    self.emit("cnc:status-mask-warning", mask);
+                }
+            }
+        } else {
+            self.end_job(None);
+        }
+    }
+
+    /// Bring the running job's checkpoint up to date and write it, at most every
+    /// few seconds unless forced
+    fn save_job_checkpoint(&mut self, force: bool) {
+        if !force
+            && self
+                .last_checkpoint_save
+                .is_some_and(|at| at.elapsed() < CHECKPOINT_INTERVAL)
+        {
+            return;
+        }
+        let lines = self.job_line_map();
+        let state = self.job_monitor.state();
+        let machine_pos = self
+            .last_status
+            .as_ref()
+            .and_then(|s| s.machine_pos.clone());
+        let work_offset = self.last_work_offset.clone();
+        let (Some(checkpoint), Some(dir)) = (self.job_checkpoint.as_mut(), &self.data_dir) else {
+            return;
+        };
+        checkpoint.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        checkpoint.state = state;
+        if let Some(lines) = lines {
+            checkpoint.last_acked_line = Some(lines.last_acked);
+            checkpoint.executing_line = lines.executing;
+        }
+        checkpoint.machine_pos = machine_pos.or(checkpoint.machine_pos.take());
+        checkpoint.work_offset = work_offset.or(checkpoint.work_offset.take());
+        if let Err(e) = storage::save_json(&JobCheckpoint::path_in(dir), checkpoint) {
+            println!("⚠️  Could not save job checkpoint: {}", e);
+        }
+        self.last_checkpoint_save = Some(Instant::now());
+    }
+
+    /// Unfinished job from a previous session, for the UI to offer recovery
+    pub fn recoverable_job(&self) -> Option<JobCheckpoint> {
+        self.recoverable_job.clone()
+    }
+
+    /// Forget the previous session's unfinished job
+    pub fn dismiss_recoverable_job(&mut self) -> Result<()> {
+        if self.recoverable_job.take().is_none() || self.job_checkpoint.is_some() {
+            return Ok(());
+        }
+        if let Some(dir) = &self.data_dir {
+            let path = JobCheckpoint::path_in(dir);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop tracking the job and add it to the history; returns how its time was spent
+    fn end_job(&mut self, completed: Option<bool>) -> Option<JobTiming> {
+        let program_name = self
+            .job_checkpoint
+            .as_ref()
+            .and_then(|job| job.program_name.clone());
+        // The job ended on purpose; nothing to recover
+        if self.job_checkpoint.take().is_some() {
+            if let Some(dir) = &self.data_dir {
+                let _ = fs::remove_file(JobCheckpoint::path_in(dir));
+            }
+        }
+        self.last_checkpoint_save = None;
+        let job_id = self.console.job_id();
+        self.console.set_job(None);
+        if self.dust_collection.enabled && self.dust_collection_on.load(Ordering::Relaxed) {
+            let delay = Duration::from_secs_f32(self.dust_collection.off_delay_seconds);
+            self.dust_collection_off_at = Some(Instant::now() + delay);
+        }
+        let timing = self.job_monitor.timing();
+        let lines = self.job_monitor.current_line();
+        let stats = self.job_run_recorder.finish();
+        self.stall_detector.set_streaming(false);
+        self.job_monitor.set_streaming(false);
+        self.paused_modal_state = None;
+        self.last_progress_emit = None;
+        self.tool_change.clear();
+        self.min_z_guard = None;
+        self.min_z_tracker = None;
+        // Zones belong to the job; put the user's override back if it ended inside one
+        if let Some(feed) = self.feed_zones.take().and_then(|mut z| z.finish()) {
+            let _ = self.send_feed_override(feed);
+        }
+        if let Some(feed) = self.spindle_load.finish() {
+            let _ = self.send_feed_override(feed);
+        }
+
+        let timing = timing?;
+        println!(
+            "⏱️  Job ran {:.0}s: {:.0}s cutting, {:.0}s paused",
+            timing.elapsed_seconds, timing.active_seconds, timing.paused_seconds
+        );
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if let Some(heatmap) =
+            self.heatmap_recorder
+                .finish(program_name.clone(), job_id, finished_at, completed)
+        {
+            if let Some(dir) = &self.data_dir {
+                if let Err(e) = storage::save_json(&ExecutionHeatmap::path_in(dir), &heatmap) {
+                    println!("⚠️  Could not save execution heatmap: {}", e);
+                }
+            }
+            self.emit("cnc:execution-heatmap", heatmap.clone());
+            self.last_heatmap = Some(heatmap);
+        }
+        self.job_history.push(JobHistoryEntry {
+            started_at: finished_at.saturating_sub(timing.elapsed_seconds as u64),
+            finished_at,
+            job_id,
+            program_name: program_name.clone(),
+            completed,
+            lines,
+            timing,
+            stats: Some(stats),
+        });
+        if let Some(dir) = &self.data_dir {
+            if let Err(e) = storage::save_json(&JobHistory::path_in(dir), &self.job_history) {
+                println!("⚠️  Could not save job history: {}", e);
+            }
+        }
+        let event = if completed == Some(true) {
+            AlertEvent::JobComplete
+        } else {
+            AlertEvent::JobStopped
+        };
+        self.send_alert(
+            event,
+            AlertContext {
+                job_name: program_name,
+                duration_seconds: Some(timing.elapsed_seconds),
+                ..Default::default()
+            },
+        );
+        Some(timing)
+    }
+
+    /// Time split of the running job, None when idle
+    pub fn job_timing(&self) -> Option<JobTiming> {
+        self.job_monitor.timing()
+    }
+
+    /// Logged traffic and events, oldest first
+    pub fn console_entries(&self, filter: &ConsoleFilter) -> Vec<ConsoleEntry> {
+        self.console.entries(filter)
+    }
+
+    /// Logged entries containing `query`, oldest first
+    pub fn search_console(&self, filter: &ConsoleFilter, query: &str) -> Vec<ConsoleEntry> {
+        self.console.search(filter, query)
+    }
+
+    pub fn console_bounds(&self) -> ConsoleBounds {
+        self.console.bounds()
+    }
+
+    pub fn console_log_config(&self) -> &ConsoleLogConfig {
+        self.console.config()
+    }
+
+    pub fn set_console_log_config(&mut self, config: ConsoleLogConfig) -> Result<()> {
+        if config.capacity < console_log::MIN_CAPACITY {
+            return Err(anyhow!(
+                "Keep at least {} console entries",
+                console_log::MIN_CAPACITY
+            ));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("console_log.json"), &config)?;
+        }
+        self.console.set_config(config);
+        Ok(())
+    }
+
+    /// Finished jobs, newest first
+    pub fn job_history(&self, limit: Option<usize>) -> Vec<JobHistoryEntry> {
+        self.job_history.recent(limit)
+    }
+
+    /// How a recorded job run differed from a baseline run, both by job id. With no ids,
+    /// the last two runs of `program_name`.
+    pub fn compare_job_runs(
+        &self,
+        baseline: Option<u64>,
+        run: Option<u64>,
+        program_name: Option<&str>,
+    ) -> Result<JobComparison> {
+        let find = |job_id: u64| {
+            self.job_history
+                .get(job_id)
+                .ok_or_else(|| anyhow!("No job {} in the history", job_id))
+        };
+        let (baseline, run) = match (baseline, run, program_name) {
+            (Some(baseline), Some(run), _) => (find(baseline)?, find(run)?),
+            (None, None, Some(name)) => self
+                .job_history
+                .last_two_runs(name)
+                .ok_or_else(|| anyhow!("{} hasn't been run twice yet", name))?,
+            _ => {
+                return Err(anyhow!(
+                    "Give two job ids, or a program name to compare its last two runs"
+                ))
+            }
+        };
+        Ok(job_comparison::compare_runs(baseline, run))
+    }
+
+    /// Where the last finished job spent its time, line by line
+    pub fn execution_heatmap(&self) -> Option<ExecutionHeatmap> {
+        self.last_heatmap.clone()
+    }
+
+    /// Stream a G-code file from the background with character counting: lines go out as
+    /// long as their bytes fit in the controller's receive buffer, and `stream_tick` keeps
+    /// it topped up until the job ends with `cnc:stream-finished`
+    pub fn start_job(&mut self, path: &Path) -> Result<()> {
+        self.check_can_stream()?;
+        let streamer = JobStreamer::load(path)?;
+        println!(
+            "▶️  Streaming {} ({} lines)",
+            streamer.program_name,
+            streamer.total_lines()
+        );
+        self.begin_stream(streamer);
+        Ok(())
+    }
+
+    /// Stream a file from `line` on, e.g. after a broken bit: the modal state the lines
+    /// before it left behind is replayed, then the tool comes down on the spot from
+    /// `safe_z` (program units; the highest Z reached before the line by default)
+    pub fn start_job_from_line(
+        &mut self,
+        path: &Path,
+        line: usize,
+        safe_z: Option<f64>,
+        spindle_dwell_seconds: Option<f32>,
+    ) -> Result<ResumePlan> {
+        self.check_can_stream()?;
+        let program = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        let plan = job_resume::plan_resume(&program, line, safe_z, spindle_dwell_seconds)?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().to_string(),
+        );
+        let streamer = JobStreamer::from_program(name, &program)?.resume_at(&plan)?;
+        println!(
+            "▶️  Streaming {} from line {} ({} setup lines)",
+            streamer.program_name,
+            line,
+            plan.preamble.len()
+        );
+        for warning in &plan.warnings {
+            println!("⚠️  {}", warning);
+        }
+        self.begin_stream(streamer);
+        Ok(plan)
+    }
+
+    /// Stream lines as another process produces them, e.g. piped from a CAM post. Lines
+    /// are queued with `queue_job_line`, and the job can't finish before `close_job_input`.
+    pub fn start_open_job(&mut self, program_name: String) -> Result<()> {
+        self.check_can_stream()?;
+        println!("▶️  Streaming {} as it arrives", program_name);
+        self.begin_stream(JobStreamer::open(program_name));
+        Ok(())
+    }
+
+    /// Add a line to an open stream. A line the stream can't take stops the job.
+    pub fn queue_job_line(&mut self, source_line: usize, line: &str) -> Result<()> {
+        let streamer = self
+            .job_streamer
+            .as_mut()
+            .ok_or_else(|| anyhow!("No job is streaming"))?;
+        if let Err(e) = streamer.push_line(source_line, line) {
+            streamer.stop(e.to_string());
+        }
+        Ok(())
+    }
+
+    /// Lines queued and not yet sent, for holding back input until they're wanted
+    pub fn job_lines_waiting(&self) -> usize {
+        self.job_streamer.as_ref().map_or(0, |s| s.lines_waiting())
+    }
+
+    /// The open stream has all its lines, so its length is known. With `error` the input
+    /// broke off and the job is stopped instead.
+    pub fn close_job_input(&mut self, error: Option<String>) {
+        let Some(streamer) = self.job_streamer.as_mut() else {
+            return;
+        };
+        streamer.close_input();
+        let last_line = streamer.last_source_line();
+        match error {
+            Some(error) => streamer.stop(error),
+            None => self.set_job_total_lines(Some(last_line)),
+        }
+    }
+
+    fn check_can_stream(&self) -> Result<()> {
+        if self.current_connection.is_none() {
+            return Err(self.not_connected());
+        }
+        if self.job_streamer.is_some() || self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("A job is already running"));
+        }
+        if self.line_numbering.is_some() {
+            return Err(anyhow!(
+                "Resent numbered lines would upset the character count; turn line numbering off to stream a file"
+            ));
+        }
+        if self.unacked_commands > 0 {
+            return Err(anyhow!("Wait for queued commands to finish first"));
+        }
+        Ok(())
+    }
+
+    fn begin_stream(&mut self, streamer: JobStreamer) {
+        self.set_job_streaming(true, Some(streamer.program_name.clone()));
+        self.set_job_total_lines(Some(streamer.last_source_line()));
+        self.job_streamer = Some(streamer);
+    }
+
+    /// Feed hold the running job. No more lines go out from here on, even before the
+    /// controller reports the hold.
+    pub fn pause_job(&mut self) -> Result<()> {
+        if self.job_monitor.state() == JobState::Idle {
+            return Err(anyhow!("No job is running"));
+        }
+        self.send_realtime(b'!')
+    }
+
+    /// Cycle start after a hold or a closed door; lines flow again once the controller
+    /// reports Run
+    pub fn resume_job(&mut self) -> Result<()> {
+        if !self.job_monitor.paused() {
+            return Err(anyhow!("The job isn't paused"));
+        }
+        self.resync_modal_state()?;
+        self.finish_tool_change();
+        self.send_realtime(b'~')
+    }
+
+    /// Stop the job: feed hold, then a reset once the machine is at rest, which keeps its
+    /// position and throws away whatever the controller still had queued. A file stream
+    /// does this from its own task; any other job waits for the machine here.
+    pub fn stop_job(&mut self) -> Result<()> {
+        if let Some(streamer) = self.job_streamer.as_mut() {
+            streamer.stop("Stopped by the user".to_string());
+            return self.stream_hold();
+        }
+        if self.job_monitor.state() == JobState::Idle {
+            return Err(anyhow!("No job is running"));
+        }
+        self.send_realtime(b'!')?;
+        let deadline = Instant::now() + STOP_SETTLE_TIMEOUT;
+        loop {
+            let report = self.get_status()?;
+            let state = grbl_protocol::parse_status_report(&report)
+                .map(|r| r.state)
+                .unwrap_or_default();
+            if matches!(state.as_str(), "Hold:0" | "Idle") || state.starts_with("Alarm") {
+                break;
+            }
+            if Instant::now() >= deadline {
+                println!(
+                    "⚠️  Machine still {} after the hold; resetting anyway",
+                    state
+                );
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        self.reset()?;
+        println!("⛔ Job stopped by the user");
+        self.finish_job(false).map(|_| ())
+    }
+
+    /// One pass of the background streamer. Returns false once the job has ended.
+    pub fn stream_tick(&mut self) -> bool {
+        self.job_streamer.is_some() && self.stream_pass().is_none()
+    }
+
+    /// One pass of the streamer; how the stream ended once it has
+    pub fn stream_pass(&mut self) -> Option<StreamFinished> {
+        let error = match self.stream_step() {
+            Ok(false) => return None,
+            Ok(true) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        self.end_stream(error)
+    }
+
+    /// Collect the acks that have arrived and top up the receive buffer. Status is queried
+    /// only as often as the streaming poll config allows. Ok(true) once every line has run.
+    fn stream_step(&mut self) -> Result<bool> {
+        self.drain_incoming()?;
+        if self.job_monitor.accepts_lines() {
+            while let Some(line) = self
+                .job_streamer
+                .as_ref()
+                .and_then(|s| s.next_line())
+                .map(str::to_string)
+            {
+                let source_line = self
+                    .job_streamer
+                    .as_ref()
+                    .and_then(|s| s.next_source_line());
+                match self.enforce_min_z(source_line, &line)? {
+                    // The line stays unsent and the job held, for the operator to stop it
+                    Some((MinZAction::Hold, _)) => break,
+                    Some((MinZAction::Abort, error)) => return Err(anyhow!("{}", error)),
+                    None => {}
+                }
+                let bytes = self.write_numbered(&line)?;
+                if let Some(streamer) = self.job_streamer.as_mut() {
+                    let source_line = streamer.note_sent(bytes);
+                    self.job_monitor.note_sent(source_line, bytes);
+                }
+            }
+        }
+        self.stream_hold()?;
+
+        // A stop waits on the hold coming to rest, which only a report shows
+        let stopping = self
+            .job_streamer
+            .as_ref()
+            .is_some_and(|s| s.stopping().is_some());
+        let polled = stopping || self.streaming_poll.query_due(self.last_status_query);
+        if polled {
+            self.last_status_query = Some(Instant::now());
+            self.get_status()?;
+        }
+        let state = self
+            .last_status
+            .as_ref()
+            .map(|r| r.state.clone())
+            .unwrap_or_default();
+        if state.starts_with("Alarm") {
+            return Err(anyhow!("Controller alarm"));
+        }
+        let Some(streamer) = self.job_streamer.as_mut() else {
+            return Err(anyhow!("Streaming stopped"));
+        };
+        if let Some(reason) = streamer.failed() {
+            return Err(anyhow!("{}", reason));
+        }
+        if let Some(reason) = streamer.stopping().map(str::to_string) {
+            // Grbl keeps its position through a reset once a feed hold has come to rest
+            if state == "Hold:0" || state == "Idle" {
+                self.reset()?;
+                return Err(anyhow!("{}", reason));
+            }
+            return Ok(false);
+        }
+        if !self.streaming_poll.enabled {
+            // No reports to go on, so the job is done once its last line is acked
+            return Ok(streamer.note_idle(true));
+        }
+        Ok(polled && streamer.note_idle(state == "Idle"))
+    }
+
+    /// Route every line that has already arrived without waiting for more
+    fn drain_incoming(&mut self) -> Result<()> {
+        while let Some(line) = self.try_read_line()? {
+            match self.route_line(line) {
+                Some(Routed::Ack(ack)) => {
+                    println!("⚠️  Unexpected {} with no command pending", ack)
+                }
+                Some(Routed::Data(data)) => println!("📨 {}", data),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Feed hold for a stopping stream, once
+    fn stream_hold(&mut self) -> Result<()> {
+        if self.job_streamer.as_mut().is_some_and(|s| s.take_hold()) {
+            self.send_realtime(b'!')?;
+            self.job_monitor.note_app_command(b'!');
+        }
+        Ok(())
+    }
+
+    fn end_stream(&mut self, error: Option<String>) -> Option<StreamFinished> {
+        let streamer = self.job_streamer.take()?;
+        if let Some(error) = &error {
+            println!("⛔ Stream of {} stopped: {}", streamer.program_name, error);
+        }
+        let completion = match self.finish_job(error.is_none()) {
+            Ok(completion) => Some(completion),
+            Err(e) => {
+                println!("⚠️  End-of-job actions failed: {}", e);
+                None
+            }
+        };
+        let finished = StreamFinished {
+            program_name: streamer.program_name.clone(),
+            completed: error.is_none(),
+            lines_sent: streamer.lines_sent(),
+            total_lines: streamer.total_lines(),
+            error,
+            completion,
+        };
+        self.emit("cnc:stream-finished", finished.clone());
+        Some(finished)
+    }
+
+    /// The streamer is done with the job. After a job that ran to the end, the configured
+    /// machine actions run (spindle off, macro, park, peripherals); the snapshot, webhook and
+    /// notification happen either way. The snapshot and webhook run in the background, so
+    /// their results only come with `cnc:job-complete`.
+    pub fn finish_job(&mut self, completed: bool) -> Result<JobCompletion> {
+        let timing = self.end_job(Some(completed));
+        let actions = self.completion_actions.clone();
+        let mut completion = JobCompletion {
+            completed,
+            timing,
+            ..Default::default()
+        };
+        if !actions.enabled {
+            return Ok(completion);
+        }
+
+        if completed {
+            // Each machine action assumes the previous one worked
+            let mut machine_ok = true;
+            if actions.spindle_off {
+                let result = self.run_sequence(&["M5 M9".to_string()], 2000);
+                machine_ok = completion.record("spindle off", result.map(|_| String::new()));
+            }
+            if let Some(name) = actions.macro_name.filter(|_| machine_ok) {
+                let result = self.run_macro(&name).map(|_| name.clone());
+                machine_ok = completion.record("macro", result);
+            }
+            if actions.park && machine_ok {
+                let result = self.park().map(|_| String::new());
+                machine_ok = completion.record("park", result);
+            }
+            if let Some(gcode) = actions.peripherals_off_gcode.filter(|_| machine_ok) {
+                let result = self
+                    .run_sequence(std::slice::from_ref(&gcode), 2000)
+                    .map(|_| gcode);
+                completion.record("peripherals off", result);
+            }
+        }
+
+        let mut snapshot = None;
+        if let Some(url) = &actions.snapshot_url {
+            match self.snapshot_path() {
+                Ok(path) => snapshot = Some((url.clone(), path)),
+                Err(e) => {
+                    completion.record("snapshot", Err(e));
+                }
+            }
+        }
+        if snapshot.is_some() || actions.webhook_url.is_some() {
+            let app = self.app_handle.clone().filter(|_| actions.notify);
+            job_completion::spawn_network_actions(
+                completion.clone(),
+                snapshot,
+                actions.webhook_url.clone(),
+                move |completion| {
+                    if let Some(app) = app {
+                        let _ = app.emit("cnc:job-complete", completion);
+                    }
+                },
+            );
+        } else if actions.notify {
+            self.emit("cnc:job-complete", completion.clone());
+        }
+        println!(
+            "🏁 Job {}: {} end-of-job actions",
+            if completed { "finished" } else { "stopped" },
+            completion.actions.len()
+        );
+        Ok(completion)
+    }
+
+    fn snapshot_path(&self) -> Result<PathBuf> {
+        let dir = self
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("No data directory for snapshots"))?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Ok(dir.join("snapshots").join(format!("job_{}.jpg", stamp)))
+    }
+
+    pub fn alert_webhooks(&self) -> &AlertWebhooks {
+        &self.alert_webhooks
+    }
+
+    pub fn set_alert_webhooks(&mut self, webhooks: AlertWebhooks) -> Result<()> {
+        webhooks.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&AlertWebhooks::path_in(dir), &webhooks)?;
+        }
+        self.alert_webhooks = webhooks;
+        Ok(())
+    }
+
+    /// Post the alerts configured for `event`, naming the connected machine
+    fn send_alert(&self, event: AlertEvent, mut context: AlertContext) {
+        if context.machine.is_none() {
+            context.machine = self.device_info.as_ref().map(|d| d.name.clone());
+        }
+        self.alert_webhooks.send(event, &context);
+    }
+
+    pub fn idle_policy(&self) -> &IdlePolicy {
+        &self.idle_policy
+    }
+
+    pub fn set_idle_policy(&mut self, policy: IdlePolicy) -> Result<()> {
+        policy.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&IdlePolicy::path_in(dir), &policy)?;
+        }
+        self.idle_policy = policy;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// The user did something: restart the idle clock and undo an idle power-down
+    pub fn note_activity(&mut self) {
+        self.last_activity = Instant::now();
+        if !self.idle_powered_down {
+            return;
+        }
+        self.idle_powered_down = false;
+        if std::mem::take(&mut self.controller_asleep) {
+            println!("⏰ Waking the controller from sleep");
+            if let Some(stream) = self.current_connection.as_mut() {
+                let _ = stream.write_all(&[0x18]).and_then(|_| stream.flush());
+            }
+            thread::sleep(WAKE_RESET_SETTLE);
+        }
+        self.emit("cnc:idle-wake", ());
+    }
+
+    /// Run the idle policy once the machine has sat idle, with no job, for long enough
+    fn check_idle_power_down(&mut self) {
+        let policy = self.idle_policy.clone();
+        if !policy.enabled || self.idle_powered_down || self.job_monitor.state() != JobState::Idle {
+            return;
+        }
+        let machine_state = self.last_status.as_ref().map(|r| r.state.as_str());
+        match machine_state {
+            Some("Idle") => {}
+            Some(state) if state.starts_with("Alarm") => {}
+            // Moving, homing, holding: that counts as activity
+            Some(_) => {
+                self.last_activity = Instant::now();
+                return;
+            }
+            None => return,
+        }
+        if self.last_activity.elapsed() < Duration::from_secs_f32(policy.idle_minutes * 60.0) {
+            return;
+        }
+
+        println!("💤 Idle for {} minutes, powering down", policy.idle_minutes);
+        let mut power_down = IdlePowerDown::default();
+        if policy.spindle_off {
+            let result = self.send_command_until_ok("M5", COMMAND_TIMEOUT_MS);
+            power_down.record("spindle off", result);
+        }
+        if let Some(name) = &policy.macro_name {
+            let result = self.run_macro(name);
+            power_down.record("macro", result);
+        } else if policy.park {
+            let result = self.park();
+            power_down.record("park", result);
+        }
+        if policy.sleep {
+            let result = self.send_command_until_ok("$SLP", COMMAND_TIMEOUT_MS);
+            self.controller_asleep = power_down.record("sleep", result);
+        }
+        power_down.polling_stopped = policy.stop_polling;
+        self.idle_powered_down = true;
+        self.emit("cnc:idle-power-down", power_down);
+    }
+
+    pub fn dust_collection_config(&self) -> &DustCollectionConfig {
+        &self.dust_collection
+    }
+
+    pub fn set_dust_collection_config(&mut self, config: DustCollectionConfig) -> Result<()> {
+        config.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&DustCollectionConfig::path_in(dir), &config)?;
+        }
+        self.dust_collection = config;
+        Ok(())
+    }
+
+    /// Switch the dust collector by hand; cancels a pending switch-off. A network relay's
+    /// result only comes with `cnc:dust-collection`.
+    pub fn set_dust_collection(&mut self, on: bool) -> Result<()> {
+        self.dust_collection_off_at = None;
+        match self.switch_dust_collection(on).and_then(|s| s.error) {
+            Some(error) => Err(anyhow!("{}", error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Switch the relay, sending `cnc:dust-collection`. A failure is reported, not returned:
+    /// it mustn't stop a job from starting. Webhook and MQTT relays are switched from a
+    /// background thread, so a slow server never holds the manager; None for those.
+    fn switch_dust_collection(&mut self, on: bool) -> Option<DustCollectionSwitch> {
+        let relay = self.dust_collection.relay.clone();
+        if let RelayControl::Gcode {
+            on: on_line,
+            off: off_line,
+        } = relay
+        {
+            let line = if on { on_line } else { off_line };
+            let result = self
+                .send_command_until_ok(&line, COMMAND_TIMEOUT_MS)
+                .map(|_| ());
+            let switch = DustCollectionSwitch::record(on, result, &self.dust_collection_on);
+            self.emit("cnc:dust-collection", switch.clone());
+            return Some(switch);
+        }
+        let relay_on = self.dust_collection_on.clone();
+        let app = self.app_handle.clone();
+        thread::spawn(move || {
+            let switch = DustCollectionSwitch::record(on, relay.switch_remote(on), &relay_on);
+            if let Some(app) = app {
+                let _ = app.emit("cnc:dust-collection", switch);
+            }
+        });
+        None
+    }
+
+    pub fn completion_actions(&self) -> &CompletionActions {
+        &self.completion_actions
+    }
+
+    pub fn set_completion_actions(&mut self, actions: CompletionActions) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&CompletionActions::path_in(dir), &actions)?;
+        }
+        self.completion_actions = actions;
+        Ok(())
+    }
+
+    /// Zones of the program about to be streamed where the feed override is reduced
+    pub fn set_feed_zones(&mut self, program: &str, zones: &[FeedZone]) -> Result<Vec<ZoneSpan>> {
+        if self.legacy_grbl && !zones.is_empty() {
+            return Err(anyhow!("Feed zones need Grbl 1.1 feed overrides"));
+        }
+        let spans = feed_zones::resolve_zones(zones, program)?;
+        self.feed_zones = (!spans.is_empty()).then(|| FeedZoneController::new(spans.clone()));
+        Ok(spans)
+    }
+
+    /// Guard the next job against going below a work Z; None removes the guard. It
+    /// lasts until the job ends.
+    pub fn set_min_z_guard(&mut self, guard: Option<MinZGuard>) -> Result<()> {
+        if let Some(guard) = &guard {
+            guard.validate()?;
+        }
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Set the minimum Z before starting the job"));
+        }
+        self.min_z_guard = guard;
+        Ok(())
+    }
+
+    pub fn min_z_guard(&self) -> Option<MinZGuard> {
+        self.min_z_guard.clone()
+    }
+
+    /// Refuse job lines that would take the tool below the job's minimum Z, and hold or
+    /// stop the machine as the guard says
+    fn check_min_z(&mut self, job_line: Option<usize>, text: &str) -> Result<()> {
+        match self.enforce_min_z(job_line, text)? {
+            Some((_, error)) => Err(anyhow!("{}", error)),
+            None => Ok(()),
+        }
+    }
+
+    /// The guard's side of `check_min_z`: on a violation, hold or reset the machine and
+    /// return what was done and why
+    fn enforce_min_z(
+        &mut self,
+        job_line: Option<usize>,
+        text: &str,
+    ) -> Result<Option<(MinZAction, String)>> {
+        if self.job_monitor.state() == JobState::Idle {
+            return Ok(None);
+        }
+        let Some(tracker) = self.min_z_tracker.as_mut() else {
+            return Ok(None);
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        let Err(violation) = tracker.check(job_line, &lines) else {
+            return Ok(None);
+        };
+
+        let which = match violation.job_line {
+            Some(line) => format!("Job line {}", line),
+            None => "Line".to_string(),
+        };
+        let error = format!(
+            "{} ({}) goes to Z{:.3}, below the minimum Z{:.3}",
+            which, violation.command, violation.z, violation.min_z
+        );
+        println!("🛑 {}; {:?}", error, violation.action);
+        let action = violation.action;
+        self.emit("cnc:min-z-violation", violation);
+        match action {
+            MinZAction::Hold => {
+                self.send_realtime(b'!')?;
+                self.job_monitor.note_app_command(b'!');
+            }
+            MinZAction::Abort => {
+                self.reset()?;
+            }
+        }
+        Ok(Some((action, error)))
+    }
+
+    /// Enter or leave feed zones based on roughly which line of the file is being cut: the
+    /// last lines the controller accepted, less those still queued in the planner. Arcs
+    /// take several planner blocks, so the estimate only ever runs behind.
+    fn update_feed_zone(&mut self, report: &StatusReport) {
+        let Some(line) = self
+            .job_monitor
+            .line_map(self.planner_queued(report))
+            .and_then(|map| map.executing)
+        else {
+            return;
+        };
+        let Some(zones) = self.feed_zones.as_mut() else {
+            return;
+        };
+        let Some(change) = zones.update(line, report.overrides.map(|o| o.feed)) else {
+            return;
+        };
+
+        match &change.zone {
+            Some(zone) => println!(
+                "🐌 Entering feed zone {} at line {}: {}%",
+                zone, change.line, change.feed_percent
+            ),
+            None => println!(
+                "🏃 Leaving feed zones at line {}: back to {}%",
+                change.line, change.feed_percent
+            ),
+        }
+        if let Err(e) = self.send_feed_override(change.feed_percent) {
+            println!("⚠️  Could not change feed override: {}", e);
+        }
+        self.emit("cnc:feed-zone", change);
+    }
+
+    /// Status report mask as last read from the controller's settings
+    fn cached_status_mask(&self) -> Option<StatusReportMask> {
+        let value = self
+            .machine_profile
+            .firmware_settings
+            .get(&STATUS_MASK_SETTING)?
+            .parse()
+            .ok()?;
+        Some(StatusReportMask::decode(value, self.legacy_grbl))
+    }
+
+    /// Read `$10`, re-reading the settings if they haven't been read yet
+    pub fn status_report_mask(&mut self) -> Result<StatusReportMask> {
+        if self.cached_status_mask().is_none() && self.current_connection.is_some() {
+            self.refresh_machine_settings()?;
+        }
+        self.cached_status_mask()
+            .ok_or_else(|| anyhow!("Status report mask ($10) not read yet; connect first"))
+    }
+
+    /// Write the fields to include in status reports to `$10`
+    pub fn set_status_report_mask(&mut self, mask: StatusReportMask) -> Result<StatusReportMask> {
+        let current = self.status_report_mask()?;
+        let requested = StatusReportMask {
+            value: current.value,
+            legacy: self.legacy_grbl,
+            ..mask
+        };
+        let value = requested.encode();
+        if value != current.value {
+            self.write_setting(
+                STATUS_MASK_SETTING,
+                &value.to_string(),
+                SettingSource::Profile,
+            )?;
+        }
+        let updated = StatusReportMask::decode(value, self.legacy_grbl);
+        for warning in &updated.warnings {
+            println!("⚠️  {}", warning);
+        }
+        Ok(updated)
+    }
+
+    /// Slow the feed while the spindle is overloaded, and put it back once the load drops
+    fn react_to_spindle_load(&mut self, report: &StatusReport) {
+        if self.job_monitor.state() != JobState::Running {
+            return;
+        }
+        let Some(reaction) = self.spindle_load.observe(report) else {
+            return;
+        };
+        if reaction.overloaded {
+            println!(
+                "🔥 Spindle load {:.0}%: feed override down to {}%",
+                reaction.load_percent, reaction.feed_percent
+            );
+        } else {
+            println!(
+                "✅ Spindle load back to {:.0}%: feed override {}%",
+                reaction.load_percent, reaction.feed_percent
+            );
+        }
+        if let Err(e) = self.send_feed_override(reaction.feed_percent) {
+            println!("⚠️  Could not change feed override: {}", e);
+        }
+        self.emit("cnc:spindle-load", reaction);
+    }
+
+    /// Pass FluidNC warnings and driver faults on as `cnc:controller-warning`, holding a
+    /// running job on a critical fault if configured to
+    fn react_to_controller_warning(&mut self, line: &str) {
+        let Some(mut warning) = controller_warnings::parse_controller_warning(line) else {
+            return;
+        };
+        if warning.severity == WarningSeverity::Critical
+            && self.controller_warnings.hold_on_critical
+            && self.job_monitor.state() == JobState::Running
+        {
+            match self.send_realtime(b'!') {
+                Ok(()) => {
+                    self.job_monitor.note_app_command(b'!');
+                    warning.held = true;
+                }
+                Err(e) => println!("⚠️  Could not hold the job: {}", e),
+            }
+        }
+        println!(
+            "🌡️  Controller {:?} ({:?}){}: {}",
+            warning.kind,
+            warning.severity,
+            if warning.held { ", job held" } else { "" },
+            warning.message
+        );
+        self.emit("cnc:controller-warning", warning);
+    }
+
+    pub fn controller_warning_config(&self) -> &ControllerWarningConfig {
+        &self.controller_warnings
+    }
+
+    pub fn set_controller_warning_config(&mut self, config: ControllerWarningConfig) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("controller_warnings.json"), &config)?;
+        }
+        self.controller_warnings = config;
+        Ok(())
+    }
+
+    pub fn spindle_load_config(&self) -> &SpindleLoadConfig {
+        self.spindle_load.config()
+    }
+
+    pub fn streaming_poll_config(&self) -> &StreamingPollConfig {
+        &self.streaming_poll
+    }
+
+    pub fn set_streaming_poll_config(&mut self, config: StreamingPollConfig) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("streaming_poll.json"), &config)?;
+        }
+        self.streaming_poll = config;
+        Ok(())
+    }
+
+    pub fn set_spindle_load_config(&mut self, config: SpindleLoadConfig) -> Result<()> {
+        if config.recover_below_percent > config.threshold_percent {
+            return Err(anyhow!(
+                "The recovery load must not be above the overload threshold"
+            ));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("spindle_load.json"), &config)?;
+        }
+        self.spindle_load.set_config(config);
+        Ok(())
+    }
+
+    /// Blocks waiting in the planner according to a status report's `Bf:` field
+    fn planner_queued(&self, report: &StatusReport) -> usize {
+        report.buffer.map_or(0, |b| {
+            self.stall_detector
+                .planner_capacity()
+                .saturating_sub(b.planner_blocks_free) as usize
+        })
+    }
+
+    /// Which job lines are unacknowledged, planned and executing, from the latest status
+    /// report. Without a recent one the executing line falls back to the last acked line.
+    pub fn job_line_map(&self) -> Option<JobLineMap> {
+        let now_ms = transport::unix_millis(SystemTime::now());
+        let queued = self
+            .last_status
+            .as_ref()
+            .filter(|_| {
+                self.last_status_received_ms
+                    .is_some_and(|ms| now_ms.saturating_sub(ms) < STATUS_FRESH_MS)
+            })
+            .map_or(0, |report| self.planner_queued(report));
+        self.job_monitor.line_map(queued)
+    }
+
+    fn send_feed_override(&mut self, percent: u32) -> Result<()> {
+        if self.legacy_grbl {
+            return Err(anyhow!("Grbl 0.9 has no feed override"));
+        }
+        for byte in feed_zones::feed_override_bytes(percent) {
+            self.send_realtime(byte)?;
+        }
+        Ok(())
+    }
+
+    pub fn job_state(&self) -> JobState {
+        self.job_monitor.state()
+    }
+
+    /// Whether job lines have to wait: held, door open, or a hold sent and not yet reported
+    pub fn job_paused(&self) -> bool {
+        self.job_monitor.paused()
+    }
+
+    pub fn stall_config(&self) -> &StallConfig {
+        self.stall_detector.config()
+    }
+
+    pub fn set_stall_config(&mut self, config: StallConfig) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&dir.join("stall_detection.json"), &config)?;
+        }
+        self.stall_detector.set_config(config);
+        Ok(())
+    }
+
+    /// Current fault injection settings, None when disabled
+    pub fn fault_injection(&self) -> Option<FaultConfig> {
+        self.fault_config.lock().ok()?.clone()
+    }
+
+    /// Enable, change or disable (None) fault injection on the active and future connections
+    pub fn set_fault_injection(&mut self, config: Option<FaultConfig>) -> Result<()> {
+        if let Some(config) = &config {
+            for p in [config.drop_byte_probability, config.disconnect_probability] {
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(anyhow!("Probabilities must be between 0 and 1"));
+                }
+            }
+            println!("💥 Fault injection enabled: {:?}", config);
+        } else {
+            println!("💥 Fault injection disabled");
+        }
+
+        let enabling = config.is_some();
+        *self
+            .fault_config
+            .lock()
+            .map_err(|_| anyhow!("Fault injection config poisoned"))? = config;
+        if enabling {
+            self.install_fault_injector();
+        }
+        Ok(())
+    }
+
+    fn install_fault_injector(&mut self) {
+        if self.fault_injector_installed {
+            return;
+        }
+        if let Some(connection) = self.current_connection.take() {
+            self.current_connection = Some(Box::new(FaultInjector::new(
+                connection,
+                self.fault_config.clone(),
+            )));
+            self.fault_injector_installed = true;
+        }
+    }
+
+    /// Track homing from state transitions: Home -> Idle completes a cycle, any alarm
+    /// means the position can no longer be trusted
+    fn update_homed_state(&mut self, state: &str) {
+        if state.starts_with("Alarm") {
+            if self.homed != Some(false) {
+                println!("🏠 Machine position lost (alarm), homing required");
+            }
+            self.homed = Some(false);
+            self.homing_in_progress = false;
+        } else if state == "Home" {
+            self.homing_in_progress = true;
+        } else if self.homing_in_progress && state == "Idle" {
+            println!("🏠 Homing cycle completed");
+            self.homed = Some(true);
+            self.homing_in_progress = false;
+        }
+    }
+
+    /// True only when the machine has been homed this power cycle
+    pub fn is_homed(&self) -> bool {
+        self.homed == Some(true)
+    }
+
+    /// Query the controller and return the parsed status with homing state
+    /// While a job streams, polls are passed on only as often as the streaming poll config
+    /// allows; the others get the last report.
+    pub fn get_machine_status(&mut self) -> Result<MachineStatus> {
+        if self.idle_powered_down && self.idle_policy.stop_polling {
+            return Ok(self.machine_status());
+        }
+        let streaming = self.job_monitor.state() != JobState::Idle;
+        if !streaming || self.streaming_poll.query_due(self.last_status_query) {
+            self.last_status_query = Some(Instant::now());
+            self.get_status()?;
+        }
+        // The frontend polls steadily, which makes this the place for the delayed switch-off
+        if self
+            .dust_collection_off_at
+            .is_some_and(|at| Instant::now() >= at)
+        {
+            self.dust_collection_off_at = None;
+            self.switch_dust_collection(false);
+        }
+        self.check_idle_power_down();
+        Ok(self.machine_status())
+    }
+
+    /// Status as last reported, without asking the controller
+    fn machine_status(&self) -> MachineStatus {
+        MachineStatus {
+            report: self.last_status.clone(),
+            homed: self.homed,
+            homing_in_progress: self.homing_in_progress,
+            display: self.display_position(),
+            received_at_ms: self.last_status_received_ms,
+            sampled_at_ms: self.last_status_received_ms.map(|ms| {
+                let one_way = self.metrics.round_trip_ms.unwrap_or(0.0) / 2.0;
+                ms.saturating_sub(one_way.round() as u64)
+            }),
+        }
+    }
+
+    /// Snapshot of connection, status, job, overrides and alarm state from what's cached
+    pub fn full_state(&self) -> FullState {
+        let report = self.last_status.as_ref();
+        // Grbl keeps reporting Alarm until unlocked, even after the ALARM: line scrolled by
+        let alarm = self
+            .last_alarm
+            .filter(|_| report.is_none_or(|r| r.state.starts_with("Alarm")))
+            .map(|code| ActiveAlarm {
+                code,
+                message: grbl_codes::alarm_message(code).to_string(),
+            });
+        FullState {
+            connection: self.device_info.clone().map(|device| CncConnection {
+                device,
+                connected: self.current_connection.is_some(),
+            }),
+            read_only: self.read_only,
+            maintenance_mode: self.maintenance_mode(),
+            legacy_grbl: self.legacy_grbl,
+            line_numbering: self.line_numbering(),
+            status: self.machine_status(),
+            job_state: self.job_state(),
+            job_timing: self.job_timing(),
+            job_lines: self.job_line_map(),
+            overrides: report.and_then(|r| r.overrides),
+            alarm,
+            recoverable_job: self.recoverable_job(),
+        }
+    }
+
+    /// Last reported position in DRO format. Grbl sends either MPos or WPos depending
+    /// on $10, so the other is derived from the work offset.
+    fn display_position(&self) -> Option<DisplayPosition> {
+        let report = self.last_status.as_ref()?;
+        let offset = self.last_work_offset.as_deref();
+        let shift = |values: &[f32], sign: f32| -> Option<Vec<f32>> {
+            let offset = offset?;
+            Some(
+                values
+                    .iter()
+                    .zip(offset)
+                    .map(|(v, o)| v + sign * o)
+                    .collect(),
+            )
+        };
+        let (machine, work) = match (&report.machine_pos, &report.work_pos) {
+            (Some(m), Some(w)) => (m.clone(), w.clone()),
+            (Some(m), None) => (m.clone(), shift(m, -1.0)?),
+            (None, Some(w)) => (shift(w, 1.0)?, w.clone()),
+            (None, None) => return None,
+        };
+        let mapping = &self.machine_profile.axis_mapping;
+        Some(DisplayPosition {
+            machine: self.dro_format.format_mapped(mapping, &machine),
+            work: self.dro_format.format_mapped(mapping, &work),
+        })
+    }
+
+    pub fn dro_format(&self) -> &DroFormat {
+        &self.dro_format
+    }
+
+    pub fn set_dro_format(&mut self, format: DroFormat) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&DroFormat::path_in(dir), &format)?;
+        }
+        self.dro_format = format;
+        Ok(())
+    }
+
+    /// The controller printed its welcome banner, so it has reset: everything we
+    /// knew about its modal state is gone and settings may have changed
+    fn handle_controller_reset(&mut self) {
+        let Some(banner) = self.pending_banners.pop() else {
+            return;
+        };
+        self.pending_banners.clear();
+        println!("🔄 Controller reset detected: {}", banner.raw);
+
+        self.parser_state = None;
+        self.paused_modal_state = None;
+        if let Some(device) = self.device_info.as_mut() {
+            device.firmware = Some(format!("{} {}", banner.firmware, banner.version));
+        }
+        self.set_legacy_grbl(grbl_protocol::is_legacy_version(&banner.version));
+        self.emit("cnc:controller-reset", banner);
+
+        if let Err(e) = self.refresh_build_info() {
+            println!("⚠️  Could not read build info after reset: {}", e);
+        }
+        if let Err(e) = self.refresh_parser_state() {
+            println!("⚠️  Could not read parser state after reset: {}", e);
+        }
+        if let Err(e) = self.refresh_machine_settings() {
+            println!("⚠️  Could not read settings after reset: {}", e);
+        }
+    }
+
+    /// Re-read `$I` and record the firmware version on the device
+    pub fn refresh_build_info(&mut self) -> Result<BuildInfo> {
+        let lines = self.send_command_until_ok("$I", 2000)?;
+        let info = grbl_protocol::parse_build_info(&lines.join("\n"));
+        if let (Some(device), Some(version)) = (self.device_info.as_mut(), &info.version) {
+            device.firmware = Some(version.clone());
+        }
+        if let Some(version) = &info.version {
+            self.set_legacy_grbl(grbl_protocol::is_legacy_version(version));
+        }
+        if let Some(blocks) = info.planner_blocks {
+            self.stall_detector.set_planner_capacity(blocks);
+        }
+        Ok(info)
+    }
+
+    /// Re-read `$I` and compare it with the build the machine profile was last checked
+    /// against. After a firmware change (e.g. the user flashed an update) `$$` is re-read
+    /// so the profile reflects the new defaults, and `cnc:firmware-changed` is emitted.
+    pub fn refresh_device_info(&mut self) -> Result<DeviceInfoRefresh> {
+        let build_info = self.refresh_build_info()?;
+        let previous = self.machine_profile.build_info.clone();
+        let firmware_changed = previous.as_ref().is_some_and(|p| *p != build_info);
+
+        let mut setting_changes = Vec::new();
+        if firmware_changed {
+            println!(
+                "🆕 Firmware changed: {} -> {}",
+                previous
+                    .as_ref()
+                    .and_then(|p| p.version.as_deref())
+                    .unwrap_or("unknown"),
+                build_info.version.as_deref().unwrap_or("unknown")
+            );
+            let before = self.machine_profile.firmware_settings.clone();
+            let after = self.refresh_machine_settings()?.firmware_settings;
+            setting_changes = machine_profile::setting_changes(&before, &after);
+        }
+        self.machine_profile.build_info = Some(build_info.clone());
+        self.save_machine_profile()?;
 
-impl CncManager {
-    pub fn new() -> Self {
-        Self {
-            current_connection: None,
-            device_info: None,
+        let refresh = DeviceInfoRefresh {
+            device: self.device_info.clone(),
+            build_info,
+            previous,
+            firmware_changed,
+            setting_changes,
+        };
+        if firmware_changed {
+            self.emit("cnc:firmware-changed", refresh.clone());
         }
+        Ok(refresh)
     }
 
-    /// Discover CNC devices - now uses proper multicast discovery
-    pub fn discover_devices(&self, timeout_ms: u64) -> Result<Vec<CncDevice>> {
-        let mut devices = Vec::new();
+    /// True when the controller runs Grbl 0.9, from its version or status report format
+    pub fn legacy_grbl(&self) -> bool {
+        self.legacy_grbl
+    }
 
-        // First try multicast discovery (the correct method!)
-        println!("� Attempting multicast discovery on 224.0.0.251:1234...");
-        match self.multicast_discovery(timeout_ms) {
-            Ok(mut multicast_devices) => {
-                if !multicast_devices.is_empty() {
-                    println!(
-                        "✅ Found {} device(s) via multicast",
-                        multicast_devices.len()
-                    );
-                    devices.append(&mut multicast_devices);
-                    return Ok(devices);
+    fn set_legacy_grbl(&mut self, legacy: bool) {
+        if legacy != self.legacy_grbl {
+            println!(
+                "🦕 Controller speaks Grbl {}",
+                if legacy {
+                    "0.9: jogging with G91 G0, no overrides"
+                } else {
+                    "1.1"
                 }
+            );
+        }
+        self.legacy_grbl = legacy;
+    }
+
+    /// Re-read the active modal state with `$G`
+    pub fn refresh_parser_state(&mut self) -> Result<Vec<String>> {
+        let lines = self.send_command_until_ok("$G", 2000)?;
+        let state = grbl_protocol::parse_parser_state(&lines.join("\n"))
+            .ok_or_else(|| anyhow!("No parser state in $G response"))?;
+        self.parser_state = Some(state.clone());
+        Ok(state)
+    }
+
+    /// Read work coordinate systems, tool length offset and last probe result from `$#`
+    pub fn coordinate_offsets(&mut self) -> Result<CoordinateOffsets> {
+        let lines = self.send_command_until_ok("$#", 2000)?;
+        Ok(grbl_protocol::parse_offsets(&lines.join("\n")))
+    }
+
+    /// Active modal words, re-reading them if they are stale
+    pub fn parser_state(&mut self) -> Result<Vec<String>> {
+        match &self.parser_state {
+            Some(state) => Ok(state.clone()),
+            None => self.refresh_parser_state(),
+        }
+    }
+
+    /// Re-read `$$` and update the machine profile (travel limits, rates, soft limits)
+    pub fn refresh_machine_settings(&mut self) -> Result<MachineProfile> {
+        let lines = self.send_command_until_ok("$$", 5000)?;
+        let settings = grbl_protocol::parse_settings(&lines.join("\n"));
+        self.machine_profile.apply_settings(&settings);
+        self.save_machine_profile()?;
+        println!(
+            "📐 Machine profile updated: {} axes, soft limits {}",
+            self.machine_profile.axes.len(),
+            if self.machine_profile.soft_limits_enabled {
+                "on"
+            } else {
+                "off"
             }
-            Err(e) => {
-                println!("⚠️  Multicast discovery failed: {}", e);
+        );
+        Ok(self.machine_profile.clone())
+    }
+
+    /// Current machine profile
+    pub fn machine_profile(&self) -> &MachineProfile {
+        &self.machine_profile
+    }
+
+    /// Update the heights used by park, tool change and probing sequences
+    pub fn set_clearance_heights(&mut self, clearance: ClearanceHeights) -> Result<MachineProfile> {
+        MachineProfile::validate_clearance(&clearance)?;
+        self.machine_profile.clearance = clearance;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    pub fn set_crash_guard(&mut self, config: CrashGuardConfig) -> Result<MachineProfile> {
+        config.validate()?;
+        self.machine_profile.crash_guard = config;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    /// Units programs for this machine should be written in
+    pub fn set_expected_units(&mut self, units: Units) -> Result<MachineProfile> {
+        self.machine_profile.expected_units = units;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    /// Change how the UI shows and jogs this machine's axes
+    pub fn set_axis_mapping(&mut self, mapping: AxisMapping) -> Result<MachineProfile> {
+        mapping.validate()?;
+        self.machine_profile.axis_mapping = mapping;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    pub fn set_macros(&mut self, macros: Vec<GcodeMacro>) -> Result<MachineProfile> {
+        self.machine_profile.macros = macros;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    /// Write the current profile, settings and macros to a shareable file
+    pub fn export_profile_bundle(
+        &mut self,
+        path: &Path,
+        name: &str,
+        description: Option<String>,
+    ) -> Result<()> {
+        if self.current_connection.is_some() {
+            // Make sure the exported settings are what the controller has now
+            self.refresh_machine_settings()?;
+        }
+        if self.machine_profile.firmware_settings.is_empty() {
+            return Err(anyhow!(
+                "No machine settings read yet; connect to the machine first"
+            ));
+        }
+        let bundle = ProfileBundle {
+            format_version: PROFILE_BUNDLE_VERSION,
+            name: name.to_string(),
+            description,
+            firmware: self.device_info.as_ref().and_then(|d| d.firmware.clone()),
+            profile: self.machine_profile.clone(),
+        };
+        storage::save_json(path, &bundle)?;
+        println!("📦 Machine profile exported to {}", path.display());
+        Ok(())
+    }
+
+    /// Load a shared profile: clearance heights and macros are adopted, and when
+    /// `write_settings` is set every `$` setting that differs is written to the controller
+    pub fn import_profile_bundle(
+        &mut self,
+        path: &Path,
+        write_settings: bool,
+    ) -> Result<MachineProfile> {
+        let bundle = ProfileBundle::load(path)?;
+        println!("📦 Importing machine profile \"{}\"", bundle.name);
+
+        if write_settings {
+            if self.current_connection.is_none() {
+                return Err(anyhow!("Connect to the machine to write its settings"));
+            }
+            self.get_status()?;
+            let idle = self.last_status.as_ref().is_some_and(|s| s.state == "Idle");
+            if !idle {
+                return Err(anyhow!("Machine must be idle to change settings"));
+            }
+            let plan = self.plan_bundle_settings(&bundle)?;
+            for write in &plan.writes {
+                self.write_setting(write.number, &write.value, SettingSource::Import)?;
             }
+            self.refresh_machine_settings()?;
         }
 
-        // Fallback: Direct TCP connection to known IP
-        println!("🔄 Falling back to direct TCP connection...");
-        let cnc_ip = "192.168.86.23";
-        let cnc_port = 10086;
+        self.machine_profile.clearance = bundle.profile.clearance;
+        self.machine_profile.macros = bundle.profile.macros;
+        self.save_machine_profile()?;
+        Ok(self.machine_profile.clone())
+    }
+
+    /// Dry run of importing a shared profile with `write_settings`: the `$` writes it would
+    /// issue against the controller's current settings, without writing anything
+    pub fn preview_profile_bundle_import(&mut self, path: &Path) -> Result<SettingsApplyPlan> {
+        let bundle = ProfileBundle::load(path)?;
+        self.plan_bundle_settings(&bundle)
+    }
+
+    fn plan_bundle_settings(&mut self, bundle: &ProfileBundle) -> Result<SettingsApplyPlan> {
+        if self.current_connection.is_none() {
+            return Err(anyhow!("Connect to the machine to compare its settings"));
+        }
+        let current = self.refresh_machine_settings()?.firmware_settings;
+        Ok(machine_profile::plan_setting_writes(
+            &current,
+            &bundle.profile.firmware_settings,
+        ))
+    }
+
+    /// Send each command of a motion sequence, stopping at the first error
+    fn run_sequence(&mut self, commands: &[String], timeout_ms: u64) -> Result<Vec<String>> {
+        let mut responses = Vec::new();
+        for command in commands {
+            println!("➡️  {}", command);
+            responses.extend(self.send_command_until_ok(command, timeout_ms)?);
+        }
+        Ok(responses)
+    }
+
+    /// Run a macro from the machine profile line by line
+    pub fn run_macro(&mut self, name: &str) -> Result<Vec<String>> {
+        let gcode = self
+            .machine_profile
+            .macros
+            .iter()
+            .find(|m| m.name == name)
+            .map(|m| m.gcode.clone())
+            .ok_or_else(|| anyhow!("No macro named {}", name))?;
+        let commands: Vec<String> = gcode
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+        self.run_sequence(&commands, 2000)
+    }
+
+    /// Raise to safe Z and move to the machine XY origin
+    pub fn park(&mut self) -> Result<Vec<String>> {
+        let commands = motion_sequences::park(&self.machine_profile.clearance);
+        self.run_travel(&commands)
+    }
+
+    /// Raise to safe Z and move to work X0 Y0
+    pub fn return_to_work_zero(&mut self) -> Result<Vec<String>> {
+        let commands = motion_sequences::return_to_work_zero(&self.machine_profile.clearance);
+        self.run_travel(&commands)
+    }
 
-        match self.probe_device(cnc_ip, cnc_port) {
-            Ok(mut device) => {
-                println!("✅ Found CNC device via direct connection!");
-                device.name = "Genmitsu CNC (Direct)".to_string();
-                devices.push(device);
+    /// Stop the spindle and raise to the tool change height. Later tool changes in a job go
+    /// on to the XY where its first one was done.
+    pub fn move_to_tool_change(&mut self) -> Result<Vec<String>> {
+        let clearance = &self.machine_profile.clearance;
+        let commands = match self
+            .console
+            .job_id()
+            .and_then(|job| self.tool_change.begin(job))
+        {
+            Some(spot) => {
+                println!(
+                    "🔧 Tool change at this job's spot X{:.3} Y{:.3}",
+                    spot[0], spot[1]
+                );
+                motion_sequences::tool_change_at(clearance, spot)
             }
-            Err(e) => {
-                println!("❌ Direct connection also failed: {}", e);
+            None => motion_sequences::tool_change(clearance),
+        };
+        self.run_travel(&commands)
+    }
+
+    /// The new tool was touched off or the job resumed: take the job's first tool change
+    /// spot from where the machine is
+    fn finish_tool_change(&mut self) {
+        let xy = self
+            .last_status
+            .as_ref()
+            .and_then(|s| s.machine_pos.as_ref())
+            .filter(|p| p.len() >= 2)
+            .map(|p| [p[0], p[1]]);
+        if let Some(spot) = self.tool_change.finish(xy) {
+            println!(
+                "🔧 Remembering X{:.3} Y{:.3} for this job's tool changes",
+                spot.machine_xy[0], spot.machine_xy[1]
+            );
+        }
+    }
+
+    /// Where this job's tool changes go, once its first one is done
+    pub fn tool_change_spot(&self) -> Option<ToolChangeSpot> {
+        self.tool_change
+            .spot()
+            .filter(|s| Some(s.job_id) == self.console.job_id())
+            .cloned()
+    }
+
+    /// Run a built-in travel sequence, with its rapids as probe moves when the crash guard
+    /// is on. The probe triggering stops the sequence there and is returned as an error.
+    fn run_travel(&mut self, commands: &[String]) -> Result<Vec<String>> {
+        let config = self.machine_profile.crash_guard.clone();
+        if !config.enabled {
+            return self.run_sequence(commands, 2000);
+        }
+        self.get_status()?;
+        let machine_pos = self
+            .last_status
+            .as_ref()
+            .and_then(|r| r.machine_pos.clone())
+            .ok_or_else(|| anyhow!("Crash guard needs the machine position, which isn't known"))?;
+        let work_offset = self
+            .last_work_offset
+            .clone()
+            .ok_or_else(|| anyhow!("Crash guard needs the work offset, which isn't known yet"))?;
+        let guarded = crash_guard::guard_sequence(commands, &machine_pos, &work_offset, &config)?;
+
+        let mut responses = Vec::new();
+        for command in guarded {
+            println!("➡️  {}", command.line);
+            // A probe move isn't acknowledged until it ends, and guarded travel is slow
+            let timeout_ms = if command.probe { 120000 } else { 2000 };
+            let lines = self.send_command_until_ok(&command.line, timeout_ms)?;
+            let hit = lines
+                .iter()
+                .filter_map(|l| grbl_protocol::parse_probe(l))
+                .find(|p| command.probe && p.success);
+            responses.extend(lines);
+            if let Some(hit) = hit {
+                println!("🛑 Crash guard tripped at {:?}", hit.position);
+                self.emit("cnc:crash-guard-tripped", hit.clone());
+                return Err(anyhow!(
+                    "Probe triggered during travel at machine {:?}; the move was stopped",
+                    hit.position
+                ));
             }
         }
+        Ok(responses)
+    }
 
-        Ok(devices)
+    /// Probe the work surface with a touch plate and set Z zero
+    pub fn probe_z_surface(
+        &mut self,
+        max_distance: f32,
+        feed_rate: f32,
+        plate_thickness: f32,
+    ) -> Result<ProbeOutcome> {
+        self.finish_tool_change();
+        let clearance = &self.machine_profile.clearance;
+        let commands =
+            motion_sequences::probe_z(clearance, max_distance, feed_rate, plate_thickness);
+        let retract = motion_sequences::probe_z_retract(clearance);
+        let outcome = self.run_probe_sequence(&commands, &retract)?;
+        if matches!(outcome, ProbeOutcome::Touched { .. }) {
+            self.work_zero_set_at = Some(Instant::now());
+        }
+        Ok(outcome)
     }
 
-    /// Multicast discovery using mDNS multicast group 224.0.0.251
-    fn multicast_discovery(&self, timeout_ms: u64) -> Result<Vec<CncDevice>> {
-        let mut devices = Vec::new();
-
-        // Create UDP socket
-        let socket = UdpSocket::bind("0.0.0.0:1234")?;
-        socket.set_read_timeout(Some(Duration::from_millis(timeout_ms)))?;
-
-        // Join multicast group 224.0.0.251 (mDNS)
-        let multicast_addr = Ipv4Addr::new(224, 0, 0, 251);
-        let interface_addr = Ipv4Addr::UNSPECIFIED;
-        socket.join_multicast_v4(&multicast_addr, &interface_addr)?;
-
-        println!("📡 Joined multicast group 224.0.0.251, listening for CNC devices...");
-
-        let start_time = std::time::Instant::now();
-        let mut buf = [0; 1024];
-
-        while start_time.elapsed() < Duration::from_millis(timeout_ms) {
-            match socket.recv_from(&mut buf) {
-                Ok((size, addr)) => {
-                    let data = &buf[..size];
-                    match std::str::from_utf8(data) {
-                        Ok(json_str) => {
-                            println!("📨 Received multicast from {}: {}", addr, json_str);
-                            match serde_json::from_str::<GenmitsuBroadcast>(json_str) {
-                                Ok(broadcast) => {
-                                    println!("🎯 Parsed Genmitsu device: {}", broadcast.name);
-
-                                    // Convert port string to u16
-                                    let port = broadcast.port.parse::<u16>().unwrap_or(10086);
-
-                                    // Probe the device to verify it's actually a CNC
-                                    if let Ok(mut device) = self.probe_device(&broadcast.ip, port) {
-                                        device.name = broadcast.name;
-                                        device.mac = Some(broadcast.uuid);
-                                        devices.push(device);
-                                        
-                                        // 🚀 SPEED IMPROVEMENT: Return immediately after first valid device
-                                        println!("✅ Found valid CNC device, connecting immediately!");
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("Failed to parse JSON: {}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("Received non-UTF8 data: {:?}", e);
-                        }
+    /// Run a sequence containing a probe move. A probe-fail alarm is cleared and the tool
+    /// backed off with `retract`, and reported as a failed outcome rather than an error.
+    /// Any other failure (other alarms, lost connection) is returned as an error untouched.
+    fn run_probe_sequence(
+        &mut self,
+        commands: &[String],
+        retract: &[String],
+    ) -> Result<ProbeOutcome> {
+        self.last_alarm = None;
+        // G38.2 isn't acknowledged until the probe touches or gives up
+        let result = self.run_sequence(commands, 60000);
+        let alarm = self
+            .last_alarm
+            .filter(|code| PROBE_FAIL_ALARMS.contains(code));
+
+        let (alarm, reason) = match (result, alarm) {
+            (Err(e), None) => {
+                self.restore_absolute_mode();
+                return Err(e);
+            }
+            (Ok(responses), None) => {
+                let probe = responses.iter().find_map(|l| grbl_protocol::parse_probe(l));
+                match probe {
+                    // G38.3 and friends report a miss without alarming
+                    Some(probe) if !probe.success => {
+                        (None, "Probe did not make contact".to_string())
+                    }
+                    probe => {
+                        return Ok(ProbeOutcome::Touched {
+                            position: probe.map(|p| p.position),
+                            responses,
+                        })
                     }
                 }
+            }
+            (_, Some(code)) => (Some(code), grbl_codes::alarm_message(code).to_string()),
+        };
+
+        println!("⚠️  Probe failed: {}", reason);
+        let mut failure = ProbeFailure {
+            alarm,
+            reason,
+            unlocked: false,
+            retracted: false,
+            recovery_error: None,
+        };
+        if alarm.is_some() {
+            match self.send_command_until_ok("$X", 2000) {
+                Ok(_) => failure.unlocked = true,
+                Err(e) => failure.recovery_error = Some(e.to_string()),
+            }
+        }
+        if alarm.is_none() || failure.unlocked {
+            match self.run_sequence(retract, 10000) {
+                Ok(_) => failure.retracted = true,
                 Err(e) => {
-                    if e.kind() == std::io::ErrorKind::TimedOut {
-                        break;
-                    }
-                    println!("Multicast receive error: {}", e);
+                    self.restore_absolute_mode();
+                    failure.recovery_error = Some(e.to_string());
                 }
             }
         }
+        Ok(ProbeOutcome::Failed(failure))
+    }
 
-        // Leave multicast group
-        socket.leave_multicast_v4(&multicast_addr, &interface_addr)?;
+    /// Probe and retract moves are relative; a sequence cut short may have left G91 in
+    /// effect, which would turn the next absolute move into a relative one
+    fn restore_absolute_mode(&mut self) {
+        if let Err(e) = self.send_command_until_ok("G90", 2000) {
+            println!("⚠️  Could not restore absolute mode (G90): {}", e);
+        }
+    }
 
-        Ok(devices)
+    /// Machine Z where a probe at work X/Y touched down
+    fn probe_height_at(&mut self, x: f32, y: f32, max_depth: f32, feed_rate: f32) -> Result<f32> {
+        let clearance = self.machine_profile.clearance.clone();
+        let commands = motion_sequences::probe_point(&clearance, x, y, max_depth, feed_rate);
+        let retract = motion_sequences::probe_z_retract(&clearance);
+        match self.run_probe_sequence(&commands, &retract)? {
+            ProbeOutcome::Touched {
+                position: Some(position),
+                ..
+            } if position.len() > 2 => Ok(position[2]),
+            ProbeOutcome::Touched { .. } => Err(anyhow!("No probe result at X{:.3} Y{:.3}", x, y)),
+            ProbeOutcome::Failed(failure) => Err(anyhow!(
+                "Probe failed at X{:.3} Y{:.3}: {}",
+                x,
+                y,
+                failure.reason
+            )),
+        }
     }
 
-    /// Probe a specific IP and port to see if it's a CNC device
-    fn probe_device(&self, ip: &str, port: u16) -> Result<CncDevice> {
-        let addr = format!("{}:{}", ip, port);
-
-        // Try to connect with a shorter timeout for faster discovery
-        match TcpStream::connect_timeout(&addr.parse()?, Duration::from_millis(1000)) {
-            Ok(mut stream) => {
-                stream.set_read_timeout(Some(Duration::from_millis(1000)))?;
-                stream.set_write_timeout(Some(Duration::from_millis(500)))?;
-
-                // Send Grbl status query
-                let _ = stream.write_all(b"?\n");
-
-                let mut buffer = [0; 512];
-                let mut response = String::new();
-
-                if let Ok(size) = stream.read(&mut buffer) {
-                    response = String::from_utf8_lossy(&buffer[..size]).to_string();
-                }
-
-                // Check if response looks like Grbl
-                if response.contains("Idle")
-                    || response.contains("Alarm")
-                    || response.contains("Run")
-                    || response.contains("MPos")
-                    || response.contains("VER:")
-                {
-                    // Skip firmware version check for faster connection
-                    // We can get this info later if needed
-                    Ok(CncDevice {
-                        name: format!("CNC at {}", ip),
-                        ip: ip.to_string(),
-                        port,
-                        mac: None,
-                        firmware: None, // Skip version check for speed
-                    })
-                } else {
-                    Err(anyhow!(
-                        "Not a CNC device - unexpected response: {}",
-                        response
-                    ))
-                }
-            }
-            Err(e) => Err(anyhow!("Connection failed: {}", e)),
+    /// Probe the spoilboard beside the stock, then the stock top, and save the difference
+    /// as the thickness of the program's stock. The measurement warns when the program
+    /// cuts deeper than that.
+    pub fn measure_stock_thickness(
+        &mut self,
+        program_name: &str,
+        program: &str,
+        spoilboard: [f32; 2],
+        stock_top: [f32; 2],
+        max_depth: f32,
+        feed_rate: f32,
+    ) -> Result<ThicknessMeasurement> {
+        let spoilboard_z =
+            self.probe_height_at(spoilboard[0], spoilboard[1], max_depth, feed_rate)?;
+        let stock_top_z = self.probe_height_at(stock_top[0], stock_top[1], max_depth, feed_rate)?;
+        let thickness = (stock_top_z - spoilboard_z) as f64;
+        println!("📏 Stock is {:.3} mm thick", thickness);
+
+        let stock = stock::with_thickness(self.stock(program_name)?, program, thickness)?;
+        self.set_stock(program_name, &stock)?;
+        let report = stock::analyze(program, &stock);
+        let warning = (report.through_depth > 0.0).then(|| {
+            format!(
+                "The program cuts {:.3} mm deep but the stock is only {:.3} mm thick",
+                report.cut_depth, thickness
+            )
+        });
+        if let Some(warning) = &warning {
+            println!("⚠️  {}", warning);
         }
+        Ok(ThicknessMeasurement {
+            spoilboard_z: spoilboard_z as f64,
+            stock_top_z: stock_top_z as f64,
+            thickness,
+            stock,
+            report,
+            warning,
+        })
     }
 
-    /// Extract firmware information from response
-    /// Connect to a specific CNC device
-    pub fn connect(&mut self, device: &CncDevice) -> Result<()> {
-        let addr = format!("{}:{}", device.ip, device.port);
-        let stream = TcpStream::connect_timeout(&addr.parse()?, Duration::from_millis(5000))?;
+    /// Probe a grid over the stock and save the result as a height map.
+    /// Work Z zero should already be set on the surface.
+    pub fn probe_height_map(
+        &mut self,
+        stock_id: &str,
+        grid: HeightMapGrid,
+        max_depth: f32,
+        feed_rate: f32,
+    ) -> Result<HeightMap> {
+        grid.validate()?;
+        if stock_id.trim().is_empty() {
+            return Err(anyhow!("Height maps need a stock name"));
+        }
 
-        // Set timeouts
-        stream.set_read_timeout(Some(Duration::from_millis(5000)))?;
-        stream.set_write_timeout(Some(Duration::from_millis(1000)))?;
+        let mut heights = vec![0.0; grid.columns * grid.rows];
+        let mut reference = None;
+        for (column, row) in grid.probe_order() {
+            let (x, y) = grid.point(column, row);
+            let z = self.probe_height_at(x, y, max_depth, feed_rate)?;
+            let reference = *reference.get_or_insert(z);
+            heights[row * grid.columns + column] = z - reference;
+        }
 
-        self.current_connection = Some(stream);
-        self.device_info = Some(device.clone());
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let map = HeightMap {
+            id: format!("{}_{}", storage::file_key(stock_id.trim()), created_at),
+            stock_id: stock_id.trim().to_string(),
+            created_at,
+            grid,
+            heights,
+        };
+        if let Some(dir) = &self.data_dir {
+            map.save(dir)?;
+        }
+        println!(
+            "🗺️  Height map {} probed ({} points)",
+            map.id,
+            map.heights.len()
+        );
+        Ok(map)
+    }
 
-        // Initialize connection - send wake up command
-        let _ = self.send_command("?");
+    /// Saved height maps, newest first
+    pub fn height_maps(&self) -> Vec<HeightMap> {
+        self.data_dir
+            .as_deref()
+            .map(HeightMap::list)
+            .unwrap_or_default()
+    }
 
+    /// Apply a saved height map to a program without probing again
+    pub fn apply_height_map(
+        &self,
+        id: &str,
+        program: &str,
+        max_segment_length: f64,
+    ) -> Result<LeveledProgram> {
+        let dir = self
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("No data directory for height maps"))?;
+        HeightMap::load(dir, id)?.apply(program, max_segment_length)
+    }
+
+    pub fn delete_height_map(&self, id: &str) -> Result<()> {
+        let dir = self
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("No data directory for height maps"))?;
+        fs::remove_file(HeightMap::path_for(dir, id))?;
         Ok(())
     }
 
-    /// Send a command to the connected CNC
-    pub fn send_command(&mut self, command: &str) -> Result<String> {
-        if let Some(ref mut stream) = self.current_connection {
-            let cmd_with_newline = format!("{}\n", command);
-            stream.write_all(cmd_with_newline.as_bytes())?;
+    fn bookmarks_path(&self, program_name: &str) -> Result<PathBuf> {
+        let dir = self
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("No data directory for bookmarks"))?;
+        Ok(ProgramBookmarks::path_for(dir, program_name))
+    }
 
-            let mut buffer = [0; 1024];
-            let size = stream.read(&mut buffer)?;
-            let response = String::from_utf8_lossy(&buffer[..size]).to_string();
+    /// Bookmarks of a library program, resolved against its current content
+    pub fn bookmarks(&self, program_name: &str, program: &str) -> Result<Vec<BookmarkLocation>> {
+        let saved: ProgramBookmarks = storage::load_json(&self.bookmarks_path(program_name)?);
+        Ok(saved.locate(program))
+    }
 
-            Ok(response.trim().to_string())
-        } else {
-            Err(anyhow!("Not connected to any device"))
-        }
+    pub fn add_bookmark(
+        &self,
+        program_name: &str,
+        program: &str,
+        line: usize,
+        name: &str,
+        note: Option<String>,
+    ) -> Result<Vec<BookmarkLocation>> {
+        let path = self.bookmarks_path(program_name)?;
+        let mut saved: ProgramBookmarks = storage::load_json(&path);
+        saved.add(program, line, name, note)?;
+        storage::save_json(&path, &saved)?;
+        Ok(saved.locate(program))
     }
 
-    /// Send a command without waiting for response (fire and forget)
-    /// Useful for long-running commands like homing that block the communication
-    pub fn send_command_no_wait(&mut self, command: &str) -> Result<()> {
-        if let Some(ref mut stream) = self.current_connection {
-            let cmd_with_newline = format!("{}\n", command);
-            stream.write_all(cmd_with_newline.as_bytes())?;
-            stream.flush()?; // Ensure data is sent immediately
-            Ok(())
-        } else {
-            Err(anyhow!("Not connected to any device"))
+    pub fn remove_bookmark(
+        &self,
+        program_name: &str,
+        program: &str,
+        line: usize,
+    ) -> Result<Vec<BookmarkLocation>> {
+        let path = self.bookmarks_path(program_name)?;
+        let mut saved: ProgramBookmarks = storage::load_json(&path);
+        if saved.remove(line) {
+            storage::save_json(&path, &saved)?;
         }
+        Ok(saved.locate(program))
     }
 
-    /// Disconnect from current device
-    pub fn disconnect(&mut self) {
-        self.current_connection = None;
-        self.device_info = None;
+    pub fn find_bookmark(
+        &self,
+        program_name: &str,
+        program: &str,
+        name: &str,
+    ) -> Result<BookmarkLocation> {
+        let saved: ProgramBookmarks = storage::load_json(&self.bookmarks_path(program_name)?);
+        saved.find(program, name)
     }
 
-    /// Send jog command
-    pub fn jog(&mut self, axis: &str, distance: f32, feed_rate: u32) -> Result<String> {
-        let command = format!("$J=G91{}{}F{}", axis, distance, feed_rate);
-        self.send_command(&command)
+    fn stock_path(&self, program_name: &str) -> Result<PathBuf> {
+        let dir = self
+            .data_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("No data directory for stock"))?;
+        Ok(Stock::path_for(dir, program_name))
     }
 
-    /// Send jog command (non-blocking)
-    pub fn jog_no_wait(&mut self, axis: &str, distance: f32, feed_rate: u32) -> Result<()> {
-        let command = format!("$J=G91{}{}F{}", axis, distance, feed_rate);
-        self.send_command_no_wait(&command)
+    /// Stock defined for a library program
+    pub fn stock(&self, program_name: &str) -> Result<Option<Stock>> {
+        Ok(storage::load_json(&self.stock_path(program_name)?))
     }
 
-    /// Get machine status
-    pub fn get_status(&mut self) -> Result<String> {
-        self.send_command("?")
+    pub fn set_stock(&self, program_name: &str, stock: &Stock) -> Result<()> {
+        stock.validate()?;
+        storage::save_json(&self.stock_path(program_name)?, stock)
+    }
+
+    pub fn clear_stock(&self, program_name: &str) -> Result<()> {
+        let path = self.stock_path(program_name)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Measure a program against its stock; None when no stock is defined
+    pub fn analyze_stock(&self, program_name: &str, program: &str) -> Result<Option<StockReport>> {
+        Ok(self
+            .stock(program_name)?
+            .map(|stock| stock::analyze(program, &stock)))
+    }
+
+    /// Write a new travel limit for one axis ($130-$135) and update the profile
+    pub fn set_axis_travel(&mut self, axis: char, max_travel: f32) -> Result<MachineProfile> {
+        if !max_travel.is_finite() || max_travel <= 0.0 {
+            return Err(anyhow!("Travel must be a positive number of mm"));
+        }
+        let setting = MachineProfile::travel_setting_number(axis)
+            .ok_or_else(|| anyhow!("Unknown axis: {}", axis))?;
+        self.write_setting(
+            setting,
+            &format!("{:.3}", max_travel),
+            SettingSource::Profile,
+        )?;
+        self.refresh_machine_settings()
     }
 
     /// Home the machine (non-blocking version)
     pub fn home(&mut self) -> Result<()> {
         // Send homing command without waiting for response
         // Status polling will detect when homing is complete
-        self.send_command_no_wait("$H")
+        self.send_command_no_wait("$H")?;
+        self.homing_in_progress = true;
+        Ok(())
     }
 
     /// Reset/unlock the machine
     pub fn reset(&mut self) -> Result<String> {
-        self.send_command("\x18") // Ctrl-X
+        // A reset while moving loses steps; Grbl only keeps position if it was idle
+        let was_idle = self.last_status.as_ref().is_some_and(|s| s.state == "Idle");
+        if !was_idle || self.homing_in_progress {
+            self.homed = Some(false);
+        }
+        self.homing_in_progress = false;
+        self.send_realtime(0x18)?; // Ctrl-X
+
+        // The reset is complete once the welcome banner arrives
+        let start_time = Instant::now();
+        while start_time.elapsed() < Duration::from_millis(RESET_TIMEOUT_MS) {
+            let line = match self.read_line() {
+                Ok(line) => line,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(Routed::Reset) = self.route_line(line) {
+                let banner = self
+                    .pending_banners
+                    .last()
+                    .map(|b| b.raw.clone())
+                    .unwrap_or_default();
+                self.handle_controller_reset();
+                return Ok(banner);
+            }
+        }
+        Err(anyhow!("No welcome banner after reset"))
+    }
+
+    pub fn fixtures(&self) -> &Fixtures {
+        &self.fixtures
+    }
+
+    pub fn save_fixture(&mut self, fixture: Fixture) -> Result<()> {
+        fixture.validate()?;
+        let mut fixtures = self.fixtures.clone();
+        fixtures.upsert(fixture);
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&Fixtures::path_in(dir), &fixtures)?;
+        }
+        self.fixtures = fixtures;
+        Ok(())
+    }
+
+    pub fn delete_fixture(&mut self, name: &str) -> Result<()> {
+        if !self.fixtures.remove(name) {
+            return Err(anyhow!("No fixture named {}", name));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&Fixtures::path_in(dir), &self.fixtures)?;
+        }
+        Ok(())
+    }
+
+    pub fn tool_library(&self) -> &ToolLibrary {
+        &self.tool_library
+    }
+
+    pub fn save_tool(&mut self, tool: Tool) -> Result<()> {
+        tool.validate()?;
+        let mut library = self.tool_library.clone();
+        library.upsert(tool);
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&ToolLibrary::path_in(dir), &library)?;
+        }
+        self.tool_library = library;
+        Ok(())
+    }
+
+    pub fn delete_tool(&mut self, number: u32) -> Result<()> {
+        if !self.tool_library.remove(number) {
+            return Err(anyhow!("No tool T{}", number));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&ToolLibrary::path_in(dir), &self.tool_library)?;
+        }
+        Ok(())
+    }
+
+    /// Saved tunnels and whether each is open
+    pub fn tunnels(&self) -> Vec<TunnelStatus> {
+        self.tunnel_configs
+            .tunnels
+            .iter()
+            .map(
+                |config| match self.tunnels.iter().find(|t| t.name() == config.name) {
+                    Some(tunnel) => tunnel.status(),
+                    None => TunnelStatus {
+                        config: config.clone(),
+                        open: false,
+                        local_port: None,
+                        restarts: 0,
+                        last_error: None,
+                    },
+                },
+            )
+            .collect()
+    }
+
+    pub fn save_tunnel(&mut self, config: TunnelConfig) -> Result<()> {
+        config.validate()?;
+        let mut configs = self.tunnel_configs.clone();
+        configs.upsert(config);
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&Tunnels::path_in(dir), &configs)?;
+        }
+        self.tunnel_configs = configs;
+        Ok(())
+    }
+
+    pub fn delete_tunnel(&mut self, name: &str) -> Result<()> {
+        if !self.tunnel_configs.remove(name) {
+            return Err(anyhow!("No tunnel named {}", name));
+        }
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&Tunnels::path_in(dir), &self.tunnel_configs)?;
+        }
+        if self.tunnels.iter().any(|t| t.name() == name) {
+            self.close_tunnel(name)?;
+        }
+        Ok(())
+    }
+
+    /// The device behind an open tunnel, or the config to open it with (see `Tunnel::open`)
+    pub fn tunnel_to_open(
+        &self,
+        name: &str,
+    ) -> Result<std::result::Result<CncDevice, TunnelConfig>> {
+        if let Some(tunnel) = self.tunnels.iter().find(|t| t.name() == name) {
+            return Ok(Ok(tunnel.device()));
+        }
+        self.tunnel_configs
+            .get(name)
+            .cloned()
+            .map(Err)
+            .ok_or_else(|| anyhow!("No tunnel named {}", name))
+    }
+
+    /// Keep a tunnel opened without the lock held; returns the device to connect to
+    pub fn add_tunnel(&mut self, tunnel: Tunnel) -> CncDevice {
+        // Opened twice at once: the first one stays, the second is dropped and stops
+        if let Some(open) = self.tunnels.iter().find(|t| t.name() == tunnel.name()) {
+            return open.device();
+        }
+        let device = tunnel.device();
+        self.tunnels.push(tunnel);
+        device
+    }
+
+    /// Stop a tunnel, disconnecting first if the machine is connected through it
+    pub fn close_tunnel(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .tunnels
+            .iter()
+            .position(|t| t.name() == name)
+            .ok_or_else(|| anyhow!("Tunnel {} isn't open", name))?;
+        let carries_connection = self
+            .device_info
+            .as_ref()
+            .is_some_and(|d| self.tunnels[index].carries(d));
+        if carries_connection {
+            self.disconnect();
+        }
+        self.tunnels.remove(index);
+        Ok(())
+    }
+
+    /// Restart tunnels whose `ssh` exited. Called regularly by the supervisor thread.
+    pub fn tunnel_tick(&mut self) {
+        let dropped: Vec<_> = self.tunnels.iter_mut().filter_map(|t| t.check()).collect();
+        for dropped in dropped {
+            println!(
+                "🚇 Tunnel {} dropped, restarting: {}",
+                dropped.name, dropped.error
+            );
+            self.emit("cnc:tunnel-dropped", dropped);
+        }
+    }
+
+    /// Probe a fixture's reference corner and set its WCS zero at the fixture's offset
+    pub fn zero_from_fixture(&mut self, name: &str) -> Result<FixtureZero> {
+        let fixture = self
+            .fixtures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("No fixture named {}", name))?;
+        let clearance = self.machine_profile.clearance.clone();
+        let retract = motion_sequences::probe_z_retract(&clearance);
+        let mut positions = Vec::new();
+        for (sequence, face) in fixture
+            .probe_sequences(&clearance)
+            .iter()
+            .zip(["top", "X face", "Y face"])
+        {
+            match self.run_probe_sequence(sequence, &retract)? {
+                ProbeOutcome::Touched {
+                    position: Some(position),
+                    ..
+                } => positions.push(position),
+                ProbeOutcome::Touched { .. } => {
+                    return Err(anyhow!("No probe result for the fixture's {}", face))
+                }
+                ProbeOutcome::Failed(failure) => {
+                    return Err(anyhow!(
+                        "Probing the fixture's {} failed: {}",
+                        face,
+                        failure.reason
+                    ))
+                }
+            }
+        }
+        let corner = fixture.corner_from(&positions[0], &positions[1], &positions[2])?;
+        let work_zero = [
+            corner[0] + fixture.offset[0],
+            corner[1] + fixture.offset[1],
+            corner[2] + fixture.offset[2],
+        ];
+        self.send_command_until_ok(&fixture.set_zero_command(work_zero), 2000)?;
+        self.work_zero_set_at = Some(Instant::now());
+        println!(
+            "📐 {} zero set from fixture {} (corner at {:?})",
+            fixture.wcs, fixture.name, corner
+        );
+        Ok(FixtureZero {
+            fixture: fixture.name,
+            corner,
+            work_zero,
+            wcs: fixture.wcs,
+        })
+    }
+
+    /// Zero `axis` of the active WCS on an edge the tool is touching, allowing for its radius.
+    /// The diameter is `diameter` if given, else that of `tool` or the active `T` in the
+    /// library.
+    pub fn set_edge_zero(
+        &mut self,
+        axis: char,
+        direction: f32,
+        tool: Option<u32>,
+        diameter: Option<f32>,
+    ) -> Result<WorkOffsetChange> {
+        self.check_offset_change_allowed()?;
+        let modal_state = self.refresh_parser_state()?;
+        let (tool, diameter) = match diameter {
+            Some(diameter) => (None, diameter),
+            None => {
+                let number = tool
+                    .or_else(|| tool_library::active_tool(&modal_state))
+                    .ok_or_else(|| anyhow!("No tool selected; give a tool or a diameter"))?;
+                let tool = self.tool_library.get(number).cloned().ok_or_else(|| {
+                    anyhow!(
+                        "T{} isn't in the tool library; add it or give a diameter",
+                        number
+                    )
+                })?;
+                let diameter = tool.diameter;
+                (Some(tool), diameter)
+            }
+        };
+        let command = work_offsets::edge_zero_command(&modal_state, axis, direction, diameter)?;
+        self.send_command_until_ok(&command, 2000)?;
+        self.work_zero_set_at = Some(Instant::now());
+        let wcs = work_offsets::active_wcs(&modal_state).to_string();
+        println!(
+            "📐 {} {} zero set on an edge with a {}mm tool",
+            wcs,
+            axis.to_ascii_uppercase(),
+            diameter
+        );
+        Ok(WorkOffsetChange {
+            wcs,
+            command,
+            tool,
+            tool_diameter: Some(diameter),
+        })
+    }
+
+    /// Move the active WCS origin by `delta` mm, e.g. to start the next part in a row
+    pub fn shift_work_zero(&mut self, delta: [f32; 3]) -> Result<WorkOffsetChange> {
+        self.check_offset_change_allowed()?;
+        let modal_state = self.refresh_parser_state()?;
+        let wcs = work_offsets::active_wcs(&modal_state).to_string();
+        let offsets = self.coordinate_offsets()?;
+        let current = offsets
+            .offsets
+            .get(&wcs)
+            .ok_or_else(|| anyhow!("No {} offset in $#", wcs))?;
+        let command = work_offsets::shift_command(&modal_state, current, delta)?;
+        self.send_command_until_ok(&command, 2000)?;
+        self.work_zero_set_at = Some(Instant::now());
+        println!("📐 {} zero shifted by {:?}", wcs, delta);
+        Ok(WorkOffsetChange {
+            wcs,
+            command,
+            tool: None,
+            tool_diameter: None,
+        })
+    }
+
+    fn check_offset_change_allowed(&self) -> Result<()> {
+        if self.job_monitor.state() != JobState::Idle {
+            return Err(anyhow!("Stop the job before changing work zero"));
+        }
+        Ok(())
     }
 
     /// Set work coordinate system zero
     pub fn set_work_zero(&mut self, axes: &str) -> Result<String> {
         let command = format!("G10L20P1{}", axes);
-        self.send_command(&command)
+        let response = self.send_command(&command)?;
+        self.work_zero_set_at = Some(Instant::now());
+        Ok(response)
+    }
+
+    pub fn checklist_config(&self) -> &ChecklistConfig {
+        &self.checklist_config
+    }
+
+    pub fn set_checklist_config(&mut self, config: ChecklistConfig) -> Result<()> {
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&ChecklistConfig::path_in(dir), &config)?;
+        }
+        self.checklist_config = config;
+        Ok(())
+    }
+
+    /// Record that the operator confirmed a manual checklist step this session
+    pub fn acknowledge_check(&mut self, id: &str) {
+        self.acknowledged_checks.insert(id.to_string());
+    }
+
+    /// Build the playback timeline for a program using this machine's dynamics
+    pub fn load_simulation(&mut self, program: &str) -> SimulationState {
+        let settings = MotionSettings::from_profile(&self.machine_profile);
+        let simulation = self.simulation.insert(Simulation::new(program, &settings));
+        simulation.state()
+    }
+
+    pub fn simulation(&mut self) -> Result<&mut Simulation> {
+        self.simulation
+            .as_mut()
+            .ok_or_else(|| anyhow!("No program loaded for simulation"))
+    }
+
+    pub fn job_queue(&self) -> JobQueue {
+        self.job_queue.clone()
+    }
+
+    pub fn set_job_queue(&mut self, mut queue: JobQueue) -> Result<JobQueue> {
+        queue.validate()?;
+        if let Some(dir) = &self.data_dir {
+            storage::save_json(&JobQueue::path_in(dir), &queue)?;
+        }
+        self.job_queue = queue;
+        Ok(self.job_queue.clone())
+    }
+
+    /// Ready a queued job's program to stream in its assigned work coordinate system,
+    /// warning when that system's offset looks like it was never set
+    pub fn prepare_queued_job(&mut self, index: usize, program: &str) -> Result<PreparedJob> {
+        let job = self
+            .job_queue
+            .jobs
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow!("No job {} in the queue", index))?;
+        let mut prepared = job_queue::prepare(&job, program)?;
+
+        if let (Some(wcs), true) = (&prepared.wcs, self.current_connection.is_some()) {
+            match self.coordinate_offsets() {
+                Ok(offsets) => match offsets.offsets.get(wcs) {
+                    Some(offset) if offset.iter().all(|v| *v == 0.0) => prepared
+                        .warnings
+                        .push(format!("{} offset is all zero; has it been set?", wcs)),
+                    Some(_) => {}
+                    None => prepared
+                        .warnings
+                        .push(format!("Controller didn't report a {} offset", wcs)),
+                },
+                Err(e) => prepared
+                    .warnings
+                    .push(format!("Couldn't read work offsets: {}", e)),
+            }
+        }
+        println!(
+            "📋 Prepared queued job {} ({}) in {}",
+            index,
+            prepared.program_name,
+            prepared.wcs.as_deref().unwrap_or("the active WCS")
+        );
+        Ok(prepared)
+    }
+
+    /// Evaluate the pre-run checklist for a program against the current machine state
+    pub fn run_pre_run_checklist(
+        &mut self,
+        program: &str,
+        program_name: Option<&str>,
+    ) -> ChecklistResult {
+        if self.current_connection.is_some() {
+            let _ = self.get_status();
+        }
+        let stock = program_name.and_then(|name| self.stock(name).ok().flatten());
+        let context = ChecklistContext {
+            connected: self.current_connection.is_some(),
+            machine_state: self.last_status.as_ref().map(|s| s.state.as_str()),
+            homed: self.homed,
+            work_zero_age: self.work_zero_set_at.map(|t| t.elapsed()),
+            work_offset: self.last_work_offset.as_deref(),
+            profile: &self.machine_profile,
+            acknowledged: &self.acknowledged_checks,
+            stock: stock.as_ref(),
+            controller_units: self
+                .parser_state
+                .as_deref()
+                .and_then(Units::from_modal_state),
+        };
+        pre_run_checklist::run_checklist(&self.checklist_config, &context, program)
+    }
+
+    /// Warn about an inch/mm mixup as a program is loaded, against the machine's expected
+    /// units, the controller's current ones and the program's stock
+    pub fn check_program_units(&self, program: &str, program_name: Option<&str>) -> UnitCheck {
+        let stock = program_name.and_then(|name| self.stock(name).ok().flatten());
+        let check = unit_check::check_units(
+            program,
+            self.machine_profile.expected_units,
+            self.parser_state
+                .as_deref()
+                .and_then(Units::from_modal_state),
+            &self.machine_profile.axes,
+            stock.as_ref(),
+        );
+        for warning in &check.warnings {
+            println!("📏 {}", warning);
+        }
+        check
     }
 
     /// Check alarm status on connect - can query current alarm state
     pub fn check_alarm_status(&mut self) -> Result<String> {
         // Send status query to get current machine state
         // The status response will contain alarm codes like <Alarm:9|MPos:...>
-        self.send_command("?")
+        self.get_status()
     }
 }
 
@@ -299,13 +5469,140 @@ impl Drop for CncManager {
     fn drop(&mut self) {
         if self.current_connection.is_some() {
             println!("🔌 Cleaning up CNC connection on drop");
-            // Use a more thorough disconnect that ensures TCP stream is properly closed
-            if let Some(stream) = self.current_connection.take() {
-                // Explicitly drop the stream to ensure it's closed
-                drop(stream);
-            }
+            self.close_connection();
             self.device_info = None;
             println!("✅ CNC connection cleanup completed");
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job_streamer::RX_BUFFER_SIZE;
+    use crate::transport::fake::FakeGrbl;
+
+    fn attached(fake: &FakeGrbl) -> CncManager {
+        let device = CncDevice {
+            name: "Fake".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 23,
+            mac: None,
+            firmware: None,
+            transport: TransportKind::Tcp,
+            baud_rate: None,
+            certificate_pin: None,
+            socket: SocketOptions::default(),
+        };
+        let mut manager = CncManager::new();
+        manager
+            .attach(Box::new(fake.clone()), &device, false)
+            .unwrap();
+        manager
+    }
+
+    /// Stream `program` from a file to the end, passing after pass, as the background task does
+    fn stream(manager: &mut CncManager, name: &str, program: &str) -> StreamFinished {
+        let path = std::env::temp_dir().join(format!("cnc-{}-{}.nc", name, std::process::id()));
+        std::fs::write(&path, program).unwrap();
+        manager.start_job(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for _ in 0..1000 {
+            if let Some(finished) = manager.stream_pass() {
+                return finished;
+            }
+        }
+        panic!("The stream never finished");
+    }
+
+    #[test]
+    fn attach_reads_settings_from_the_controller() {
+        let fake = FakeGrbl::new();
+        let manager = attached(&fake);
+        assert!(manager.current_connection.is_some());
+        assert!(fake.state().received.contains(&"$$".to_string()));
+        assert_eq!(manager.machine_profile().axes.len(), 3);
+    }
+
+    #[test]
+    fn lost_link_records_the_job_as_interrupted_and_keeps_it_recoverable() {
+        let fake = FakeGrbl::new();
+        let mut manager = attached(&fake);
+        let path = std::env::temp_dir().join(format!("cnc-lost-{}.nc", std::process::id()));
+        std::fs::write(&path, "G1 X1 F600\nG1 X2 F600\n").unwrap();
+        manager.start_job(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        manager.drop_link(DisconnectReason::ClosedByPeer, "closed");
+        assert!(manager.job_streamer.is_none());
+        assert_eq!(manager.job_state(), JobState::Idle);
+        let history = manager.job_history(None);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].completed, Some(false));
+        assert!(manager.recoverable_job().is_some());
+    }
+
+    #[test]
+    fn streamed_job_fills_the_receive_buffer_and_gets_every_ack() {
+        let fake = FakeGrbl::new();
+        let mut manager = attached(&fake);
+        let sent_before = fake.state().received.len();
+        let program: Vec<String> = (1..=20)
+            .map(|i| format!("G1 X{}.1234 Y{}.5678 F600", i, i))
+            .collect();
+
+        let finished = stream(
+            &mut manager,
+            "acks",
+            &format!("(part)\n{}\n", program.join("\n")),
+        );
+        assert!(finished.completed, "{:?}", finished.error);
+        assert_eq!(finished.lines_sent, program.len());
+
+        let state = fake.state();
+        assert_eq!(state.received[sent_before..], program[..]);
+        // Several lines went out ahead of their acks, never more than the buffer holds
+        assert!(state.max_unacked > program[0].len() + 1);
+        assert!(state.max_unacked <= RX_BUFFER_SIZE);
+        assert_eq!(manager.job_state(), JobState::Idle);
+    }
+
+    #[test]
+    fn rejected_line_stops_the_stream() {
+        let fake = FakeGrbl::new();
+        let mut manager = attached(&fake);
+        fake.state().reject.push("G1 X2 F600".to_string());
+
+        let finished = stream(
+            &mut manager,
+            "rejected",
+            "G1 X1 F600\nG1 X2 F600\nG1 X3 F600\n",
+        );
+        assert!(!finished.completed);
+        let error = finished.error.unwrap();
+        assert!(
+            error.starts_with("Line 2 (G1 X2 F600) rejected: error:20"),
+            "{}",
+            error
+        );
+        assert!(manager.job_streamer.is_none());
+    }
+
+    #[test]
+    fn unpolled_stream_finishes_on_its_acks() {
+        let fake = FakeGrbl::new();
+        let mut manager = attached(&fake);
+        manager
+            .set_streaming_poll_config(StreamingPollConfig {
+                enabled: false,
+                min_interval_ms: 0,
+            })
+            .unwrap();
+        let queries_before = fake.state().status_queries;
+
+        let finished = stream(&mut manager, "unpolled", "G1 X1 F600\nG1 X2 F600\n");
+        assert!(finished.completed, "{:?}", finished.error);
+        assert_eq!(finished.lines_sent, 2);
+        assert_eq!(fake.state().status_queries, queries_before);
+    }
+}
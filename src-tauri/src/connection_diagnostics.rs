@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Most status queries in one run, so the app isn't tied up for long
+const MAX_SAMPLES: u32 = 500;
+
+/// How a diagnostics run samples the link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsOptions {
+    /// Status queries to send
+    pub samples: u32,
+    /// Time from one query to the next
+    pub interval_ms: u64,
+    /// A report slower than this counts as dropped. A query never answered holds the run
+    /// for the link's read timeout, a few seconds on TCP.
+    pub timeout_ms: u64,
+}
+
+impl Default for DiagnosticsOptions {
+    fn default() -> Self {
+        Self {
+            samples: 50,
+            interval_ms: 100,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+impl DiagnosticsOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.samples == 0 || self.samples > MAX_SAMPLES {
+            return Err(anyhow!("Take 1 to {} samples", MAX_SAMPLES));
+        }
+        if self.timeout_ms == 0 || self.timeout_ms > 10_000 {
+            return Err(anyhow!("The timeout must be up to 10 seconds"));
+        }
+        if self.interval_ms > 5000 {
+            return Err(anyhow!("Samples can't be more than 5 seconds apart"));
+        }
+        Ok(())
+    }
+}
+
+/// Returned by `run_connection_diagnostics`. Status queries bypass the controller's
+/// line queue, so the round trip is the link plus the controller's reply time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub device_name: String,
+    /// e.g. `tcp://192.168.1.20:23`
+    pub transport: String,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub samples: u32,
+    pub answered: u32,
+    /// Queries with no report within the timeout
+    pub dropped: u32,
+    /// Dropped queries whose report did come, after the timeout
+    pub late: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    /// Mean difference between consecutive round trips
+    pub jitter_ms: Option<f64>,
+    /// Round trip of each query in order, None where it was dropped
+    pub round_trips_ms: Vec<Option<f64>>,
+    /// What looks wrong, in plain words; empty when the link looks fine
+    pub findings: Vec<String>,
+}
+
+/// Sample counts and round-trip statistics. `slow_ms` is the round trip the heartbeat
+/// treats as degraded.
+pub fn summarize(
+    device_name: String,
+    transport: String,
+    started_at: u64,
+    round_trips_ms: Vec<Option<f64>>,
+    late: u32,
+    slow_ms: u64,
+) -> DiagnosticsReport {
+    let answered: Vec<f64> = round_trips_ms.iter().flatten().copied().collect();
+    let samples = round_trips_ms.len() as u32;
+    let dropped = samples - answered.len() as u32;
+    let loss_percent = if samples > 0 {
+        f64::from(dropped) * 100.0 / f64::from(samples)
+    } else {
+        0.0
+    };
+
+    let mut sorted = answered.clone();
+    sorted.sort_by(f64::total_cmp);
+    let avg_ms =
+        (!answered.is_empty()).then(|| answered.iter().sum::<f64>() / answered.len() as f64);
+    let p95_ms = (!sorted.is_empty()).then(|| {
+        let rank = (sorted.len() as f64 * 0.95).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    });
+    let jitter_ms = (answered.len() > 1).then(|| {
+        answered
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .sum::<f64>()
+            / (answered.len() - 1) as f64
+    });
+
+    let mut findings = Vec::new();
+    if dropped > 0 {
+        findings.push(format!(
+            "{} of {} status queries went unanswered ({:.1}% loss); WiFi signal or interference",
+            dropped, samples, loss_percent
+        ));
+    }
+    if let Some(avg) = avg_ms.filter(|avg| *avg > slow_ms as f64) {
+        findings.push(format!(
+            "Average round trip {:.0}ms is over {}ms; every jog waits at least this long",
+            avg, slow_ms
+        ));
+    }
+    if let (Some(jitter), Some(avg)) = (jitter_ms, avg_ms) {
+        // Jitter on the order of the round trip itself makes jogs stutter
+        if jitter > 20.0 && jitter > avg / 2.0 {
+            findings.push(format!(
+                "Round trips vary by {:.0}ms from one query to the next; the network is congested or the signal is weak",
+                jitter
+            ));
+        }
+    }
+
+    DiagnosticsReport {
+        device_name,
+        transport,
+        started_at,
+        samples,
+        answered: answered.len() as u32,
+        dropped,
+        late,
+        loss_percent,
+        min_ms: sorted.first().copied(),
+        avg_ms,
+        max_ms: sorted.last().copied(),
+        p95_ms,
+        jitter_ms,
+        round_trips_ms,
+        findings,
+    }
+}
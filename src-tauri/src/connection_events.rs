@@ -0,0 +1,67 @@
+use crate::cnc_comm::CncDevice;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+
+/// Why a connection ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// Disconnected, or replaced by connecting to another machine
+    User,
+    /// The controller stopped answering
+    Timeout,
+    ResetByPeer,
+    /// The controller or its WiFi module closed the connection
+    ClosedByPeer,
+    /// Writes stopped going through
+    BrokenPipe,
+    /// Reconnecting ran out of attempts, or was turned off
+    ReconnectFailed,
+    /// The app restarted the controller and is waiting for it to come back
+    Reboot,
+    Other,
+}
+
+impl DisconnectReason {
+    pub fn of(error: &anyhow::Error) -> Self {
+        let Some(e) = error.downcast_ref::<std::io::Error>() else {
+            return DisconnectReason::Other;
+        };
+        match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => DisconnectReason::Timeout,
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                DisconnectReason::ResetByPeer
+            }
+            ErrorKind::UnexpectedEof | ErrorKind::NotConnected => DisconnectReason::ClosedByPeer,
+            ErrorKind::BrokenPipe => DisconnectReason::BrokenPipe,
+            _ => DisconnectReason::Other,
+        }
+    }
+}
+
+/// Payload of `cnc:connected`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedEvent {
+    pub device: CncDevice,
+    pub read_only: bool,
+    /// Came back by reconnecting after the link dropped
+    pub reconnected: bool,
+}
+
+/// Payload of `cnc:disconnected`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisconnectedEvent {
+    pub device: CncDevice,
+    pub reason: DisconnectReason,
+    pub message: String,
+    /// The link dropped and is being retried; `cnc:connected` follows if that works
+    pub reconnecting: bool,
+}
+
+/// Payload of `cnc:error`: reading or writing the link failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionErrorEvent {
+    pub device_name: String,
+    pub reason: DisconnectReason,
+    pub message: String,
+}
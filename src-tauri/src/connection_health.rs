@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Status queries sent when the link has been quiet, so a dead connection is noticed
+/// before the next command is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+    /// Quiet time before a heartbeat is sent, and the time between heartbeats
+    pub interval_ms: u64,
+    /// A round trip slower than this marks the link degraded
+    pub slow_round_trip_ms: u64,
+    /// Unanswered heartbeats in a row before the link is taken as dead
+    pub dead_after_misses: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 3000,
+            slow_round_trip_ms: 500,
+            dead_after_misses: 3,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("heartbeat.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_ms < 500 {
+            return Err(anyhow!("Heartbeats can't be less than 500ms apart"));
+        }
+        if self.dead_after_misses == 0 {
+            return Err(anyhow!("At least one heartbeat has to be missed"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkHealth {
+    #[default]
+    Disconnected,
+    Healthy,
+    /// Answering slowly, or a heartbeat went unanswered
+    Degraded,
+    /// Stopped answering; the link is being dropped
+    Dead,
+    /// Dropped and being retried
+    Reconnecting,
+}
+
+/// Returned by `get_connection_health` and sent as `cnc:connection-health` on every change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionHealth {
+    pub state: LinkHealth,
+    /// Why it isn't healthy
+    pub reason: Option<String>,
+    /// Time since anything arrived from the controller
+    pub last_heard_ms_ago: Option<u64>,
+    pub last_round_trip_ms: Option<f64>,
+    pub heartbeats_sent: u64,
+    pub heartbeats_answered: u64,
+    pub consecutive_misses: u32,
+}
+
+/// Liveness of the current connection, from heartbeats and any other traffic
+#[derive(Debug, Default)]
+pub struct HealthMonitor {
+    state: LinkHealth,
+    reason: Option<String>,
+    last_heard: Option<Instant>,
+    last_heartbeat: Option<Instant>,
+    last_round_trip: Option<Duration>,
+    sent: u64,
+    answered: u64,
+    consecutive_misses: u32,
+}
+
+impl HealthMonitor {
+    /// Fresh state for a new connection
+    pub fn connected() -> Self {
+        Self {
+            state: LinkHealth::Healthy,
+            last_heard: Some(Instant::now()),
+            ..Default::default()
+        }
+    }
+
+    pub fn state(&self) -> LinkHealth {
+        self.state
+    }
+
+    /// Anything arrived. Returns true if that brought a missing link back.
+    pub fn note_heard(&mut self) -> bool {
+        self.last_heard = Some(Instant::now());
+        if self.consecutive_misses == 0 {
+            return false;
+        }
+        self.consecutive_misses = 0;
+        self.set(LinkHealth::Healthy, None)
+    }
+
+    /// Nothing has been heard, or sent as a heartbeat, for an interval
+    pub fn heartbeat_due(&self, config: &HeartbeatConfig) -> bool {
+        let interval = Duration::from_millis(config.interval_ms);
+        let quiet = |at: Option<Instant>| at.is_none_or(|t| t.elapsed() >= interval);
+        quiet(self.last_heard) && quiet(self.last_heartbeat)
+    }
+
+    /// Outcome of a heartbeat: its round trip, or None if it went unanswered.
+    /// Returns true if the health changed.
+    pub fn record_heartbeat(
+        &mut self,
+        config: &HeartbeatConfig,
+        round_trip: Option<Duration>,
+    ) -> bool {
+        self.sent += 1;
+        self.last_heartbeat = Some(Instant::now());
+        match round_trip {
+            Some(rtt) => {
+                self.answered += 1;
+                self.consecutive_misses = 0;
+                self.last_round_trip = Some(rtt);
+                if rtt > Duration::from_millis(config.slow_round_trip_ms) {
+                    let reason = format!("Slow round trip ({}ms)", rtt.as_millis());
+                    self.set(LinkHealth::Degraded, Some(reason))
+                } else {
+                    self.set(LinkHealth::Healthy, None)
+                }
+            }
+            None => {
+                self.consecutive_misses += 1;
+                let reason = format!("{} heartbeat(s) unanswered", self.consecutive_misses);
+                if self.consecutive_misses >= config.dead_after_misses {
+                    self.set(LinkHealth::Dead, Some(reason))
+                } else {
+                    self.set(LinkHealth::Degraded, Some(reason))
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, state: LinkHealth, reason: Option<String>) -> bool {
+        let changed = self.state != state;
+        self.state = state;
+        self.reason = reason;
+        changed
+    }
+
+    pub fn report(&self) -> ConnectionHealth {
+        ConnectionHealth {
+            state: self.state,
+            reason: self.reason.clone(),
+            last_heard_ms_ago: self.last_heard.map(|t| t.elapsed().as_millis() as u64),
+            last_round_trip_ms: self.last_round_trip.map(|d| d.as_secs_f64() * 1000.0),
+            heartbeats_sent: self.sent,
+            heartbeats_answered: self.answered,
+            consecutive_misses: self.consecutive_misses,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Entries kept in memory by default; about a long job's worth of traffic with status polling
+const DEFAULT_CAPACITY: usize = 200_000;
+
+/// Smallest capacity allowed, so a job's tail is still there to look at
+pub const MIN_CAPACITY: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsoleDirection {
+    Sent,
+    Received,
+    /// Event emitted to the frontend
+    Event,
+}
+
+/// One line of controller traffic or one emitted event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsoleEntry {
+    /// Position in the session's traffic, counting from 1; stays put as old entries drop
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch
+    pub at_ms: u64,
+    pub direction: ConsoleDirection,
+    pub text: String,
+    /// Job running when the line went by
+    pub job_id: Option<u64>,
+    /// Status polling: `?` and `<...>` reports
+    pub status: bool,
+}
+
+/// Which entries to return
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsoleFilter {
+    /// Only lines from this job; None for everything
+    pub job_id: Option<u64>,
+    pub include_status: bool,
+    pub include_events: bool,
+    /// Most recent entries to return
+    pub limit: Option<usize>,
+    /// Only entries with a `seq` after this, e.g. the newest the UI has
+    pub after_seq: Option<u64>,
+    /// Only entries with a `seq` before this, e.g. the oldest the UI has, to page back
+    pub before_seq: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsoleLogConfig {
+    /// Entries kept in memory; the oldest go first
+    pub capacity: usize,
+}
+
+impl Default for ConsoleLogConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+/// What the log holds, for sizing a lazily filled view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsoleBounds {
+    /// Seq of the oldest and newest entries still held, None while empty
+    pub oldest_seq: Option<u64>,
+    pub newest_seq: Option<u64>,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// Recent traffic tagged with the job it belongs to, so a run can be looked at on its own.
+/// A ring buffer: past `capacity` entries the oldest are dropped.
+#[derive(Debug, Default)]
+pub struct ConsoleLog {
+    entries: VecDeque<ConsoleEntry>,
+    job_id: Option<u64>,
+    config: ConsoleLogConfig,
+    last_seq: u64,
+}
+
+impl ConsoleLog {
+    pub fn config(&self) -> &ConsoleLogConfig {
+        &self.config
+    }
+
+    /// Change the capacity, dropping the oldest entries if it shrank
+    pub fn set_config(&mut self, mut config: ConsoleLogConfig) {
+        config.capacity = config.capacity.max(MIN_CAPACITY);
+        self.config = config;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.config.capacity);
+        self.entries.drain(..excess);
+    }
+
+    pub fn bounds(&self) -> ConsoleBounds {
+        ConsoleBounds {
+            oldest_seq: self.entries.front().map(|e| e.seq),
+            newest_seq: self.entries.back().map(|e| e.seq),
+            len: self.entries.len(),
+            capacity: self.config.capacity,
+        }
+    }
+
+    pub fn job_id(&self) -> Option<u64> {
+        self.job_id
+    }
+
+    /// Tag everything recorded from now on with `job_id`
+    pub fn set_job(&mut self, job_id: Option<u64>) {
+        self.job_id = job_id;
+    }
+
+    pub fn record(&mut self, at_ms: u64, direction: ConsoleDirection, text: &str) {
+        let trimmed = text.trim();
+        let status = match direction {
+            ConsoleDirection::Sent => trimmed == "?",
+            ConsoleDirection::Received => trimmed.starts_with('<'),
+            ConsoleDirection::Event => false,
+        };
+        self.last_seq += 1;
+        self.entries.push_back(ConsoleEntry {
+            seq: self.last_seq,
+            at_ms,
+            direction,
+            text: trimmed.to_string(),
+            job_id: self.job_id,
+            status,
+        });
+        self.trim();
+    }
+
+    /// Matching entries, oldest first
+    pub fn entries(&self, filter: &ConsoleFilter) -> Vec<ConsoleEntry> {
+        self.newest(filter, |_| true)
+    }
+
+    /// Entries matching `filter` whose text contains `query`, ignoring case, oldest first
+    pub fn search(&self, filter: &ConsoleFilter, query: &str) -> Vec<ConsoleEntry> {
+        let query = query.to_lowercase();
+        self.newest(filter, |e| e.text.to_lowercase().contains(&query))
+    }
+
+    /// The newest `filter.limit` entries passing `filter` and `keep`, oldest first
+    fn newest(
+        &self,
+        filter: &ConsoleFilter,
+        keep: impl Fn(&ConsoleEntry) -> bool,
+    ) -> Vec<ConsoleEntry> {
+        // Seqs rise from front to back, so the range is a slice of the ring
+        let start = filter
+            .after_seq
+            .map_or(0, |seq| self.entries.partition_point(|e| e.seq <= seq));
+        let end = filter.before_seq.map_or(self.entries.len(), |seq| {
+            self.entries.partition_point(|e| e.seq < seq)
+        });
+        let mut matching: Vec<ConsoleEntry> = self
+            .entries
+            .range(start..end.max(start))
+            .rev()
+            .filter(|e| filter.job_id.is_none() || e.job_id == filter.job_id)
+            .filter(|e| filter.include_status || !e.status)
+            .filter(|e| filter.include_events || e.direction != ConsoleDirection::Event)
+            .filter(|e| keep(e))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        matching.reverse();
+        matching
+    }
+}
+
+/// Entries as text, one per line, timed in seconds from the first
+pub fn export_text(entries: &[ConsoleEntry]) -> String {
+    let start = entries.first().map_or(0, |e| e.at_ms);
+    let mut text = String::new();
+    for entry in entries {
+        let arrow = match entry.direction {
+            ConsoleDirection::Sent => ">>",
+            ConsoleDirection::Received => "<<",
+            ConsoleDirection::Event => "**",
+        };
+        let seconds = entry.at_ms.saturating_sub(start) as f64 / 1000.0;
+        let _ = writeln!(text, "{:>10.3} {} {}", seconds, arrow, entry.text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_with(count: u64) -> ConsoleLog {
+        let mut log = ConsoleLog::default();
+        log.set_config(ConsoleLogConfig {
+            capacity: MIN_CAPACITY,
+        });
+        for i in 1..=count {
+            log.record(i, ConsoleDirection::Sent, &format!("G1 X{}", i));
+        }
+        log
+    }
+
+    fn seqs(entries: &[ConsoleEntry]) -> Vec<u64> {
+        entries.iter().map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn the_ring_drops_the_oldest_entries() {
+        let log = log_with(MIN_CAPACITY as u64 + 5);
+        let bounds = log.bounds();
+        assert_eq!(bounds.len, MIN_CAPACITY);
+        assert_eq!(bounds.oldest_seq, Some(6));
+        assert_eq!(bounds.newest_seq, Some(MIN_CAPACITY as u64 + 5));
+    }
+
+    #[test]
+    fn capacity_has_a_floor() {
+        let mut log = log_with(10);
+        log.set_config(ConsoleLogConfig { capacity: 1 });
+        assert_eq!(log.config().capacity, MIN_CAPACITY);
+        assert_eq!(log.bounds().len, 10);
+    }
+
+    #[test]
+    fn pages_back_and_forward_by_seq() {
+        let log = log_with(20);
+        let page = |after_seq, before_seq, limit| ConsoleFilter {
+            after_seq,
+            before_seq,
+            limit,
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.entries(&page(None, None, Some(3)))), [18, 19, 20]);
+        assert_eq!(
+            seqs(&log.entries(&page(None, Some(18), Some(3)))),
+            [15, 16, 17]
+        );
+        assert_eq!(seqs(&log.entries(&page(Some(18), None, None))), [19, 20]);
+        assert_eq!(seqs(&log.entries(&page(Some(5), Some(8), None))), [6, 7]);
+        assert!(log.entries(&page(Some(8), Some(5), None)).is_empty());
+    }
+
+    #[test]
+    fn search_ignores_case_and_honours_the_filter() {
+        let mut log = ConsoleLog::default();
+        log.record(1, ConsoleDirection::Sent, "?");
+        log.record(2, ConsoleDirection::Received, "<Idle|MPos:0,0,0>");
+        log.record(3, ConsoleDirection::Received, "[MSG:Idle soon]");
+        let everything = ConsoleFilter {
+            include_status: true,
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.search(&everything, "idle")), [2, 3]);
+        assert_eq!(seqs(&log.search(&ConsoleFilter::default(), "idle")), [3]);
+    }
+
+    #[test]
+    fn filters_status_events_and_jobs() {
+        let mut log = ConsoleLog::default();
+        log.record(1, ConsoleDirection::Sent, "?");
+        log.record(2, ConsoleDirection::Received, "<Idle|MPos:0,0,0>");
+        log.set_job(Some(7));
+        log.record(3, ConsoleDirection::Sent, "G1 X1");
+        log.record(4, ConsoleDirection::Event, "cnc:job-state");
+        log.set_job(None);
+        log.record(5, ConsoleDirection::Received, "ok");
+
+        assert_eq!(seqs(&log.entries(&ConsoleFilter::default())), [3, 5]);
+        let everything = ConsoleFilter {
+            include_status: true,
+            include_events: true,
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.entries(&everything)).len(), 5);
+        let job = ConsoleFilter {
+            job_id: Some(7),
+            include_events: true,
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.entries(&job)), [3, 4]);
+    }
+
+    #[test]
+    fn export_times_entries_from_the_first() {
+        let mut log = ConsoleLog::default();
+        log.record(1000, ConsoleDirection::Sent, "G0 X1");
+        log.record(2500, ConsoleDirection::Received, "ok");
+        let text = export_text(&log.entries(&ConsoleFilter::default()));
+        assert_eq!(text, "     0.000 >> G0 X1\n     1.500 << ok\n");
+    }
+}
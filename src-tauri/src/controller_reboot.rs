@@ -0,0 +1,45 @@
+use crate::modal_resync::ModalChange;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Wait after sending the restart before the first reconnect attempt, so the old link
+/// isn't picked up again before the controller has gone down
+pub const REBOOT_SETTLE: Duration = Duration::from_secs(3);
+
+/// Listening time of each rediscovery pass while a restarted controller is missing
+pub const REDISCOVERY_TIMEOUT_MS: u64 = 1000;
+
+/// Command that restarts the controller, from the version in `$I`
+pub fn restart_command(firmware: Option<&str>) -> Option<&'static str> {
+    firmware.filter(|f| f.contains("FluidNC")).map(|_| "$Bye")
+}
+
+/// Session state taken before a restart and put back once the controller answers again
+#[derive(Debug, Clone)]
+pub struct RebootSession {
+    pub command: String,
+    /// `$G` words; the parser comes back with its power-on defaults
+    pub modal_state: Option<Vec<String>>,
+    /// Feed override to set again; overrides come back at 100%
+    pub feed_override: Option<u32>,
+}
+
+/// Payload of `cnc:rebooting`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebootingEvent {
+    pub device_name: String,
+    pub command: String,
+}
+
+/// Payload of `cnc:rebooted`, sent once the restarted controller is connected again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebootedEvent {
+    pub device_name: String,
+    pub command: String,
+    /// From sending the restart until the session was restored
+    pub down_ms: u64,
+    /// Modal words and overrides that were put back
+    pub restored: Vec<String>,
+    /// Modal state the user has to put back, e.g. spindle or coolant
+    pub not_restored: Vec<ModalChange>,
+}
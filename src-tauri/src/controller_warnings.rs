@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningSeverity {
+    Warning,
+    /// The hardware is in trouble now, e.g. a stepper driver shut down mid-cut
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// Driver shut its outputs off from heat
+    Overtemperature,
+    /// Driver getting hot, still running
+    OvertemperaturePrewarning,
+    /// StallGuard saw the motor stall; position is likely lost
+    Stall,
+    ShortCircuit,
+    /// No current through a coil: a loose or broken motor wire
+    OpenLoad,
+    /// The driver failed its test or stopped answering
+    DriverFault,
+    /// Any other `[MSG:WARN:` or `[MSG:ERR:` line
+    General,
+}
+
+/// Payload of `cnc:controller-warning`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerWarning {
+    pub kind: WarningKind,
+    pub severity: WarningSeverity,
+    /// Axis the driver belongs to, when the message names one
+    pub axis: Option<String>,
+    /// Message text without the `[MSG:WARN:` wrapper
+    pub message: String,
+    /// True when the app held the machine because of it
+    pub held: bool,
+}
+
+/// What to do about warnings besides reporting them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerWarningConfig {
+    /// Feed hold a running job on a critical driver fault. Off by default, as the
+    /// controller may already have stopped, and a hold can't save a stalled axis.
+    pub hold_on_critical: bool,
+}
+
+/// Driver fault phrases FluidNC's Trinamic drivers use, matched as whole words, most
+/// specific first
+const DRIVER_FAULTS: [(&str, WarningKind, WarningSeverity); 12] = [
+    (
+        "otpw",
+        WarningKind::OvertemperaturePrewarning,
+        WarningSeverity::Warning,
+    ),
+    (
+        "overtemp warning",
+        WarningKind::OvertemperaturePrewarning,
+        WarningSeverity::Warning,
+    ),
+    (
+        "overtemperature warning",
+        WarningKind::OvertemperaturePrewarning,
+        WarningSeverity::Warning,
+    ),
+    (
+        "overtemp",
+        WarningKind::Overtemperature,
+        WarningSeverity::Critical,
+    ),
+    (
+        "overtemperature",
+        WarningKind::Overtemperature,
+        WarningSeverity::Critical,
+    ),
+    (
+        "over temperature",
+        WarningKind::Overtemperature,
+        WarningSeverity::Critical,
+    ),
+    ("stall", WarningKind::Stall, WarningSeverity::Critical),
+    ("stalled", WarningKind::Stall, WarningSeverity::Critical),
+    (
+        "short",
+        WarningKind::ShortCircuit,
+        WarningSeverity::Critical,
+    ),
+    ("open load", WarningKind::OpenLoad, WarningSeverity::Warning),
+    (
+        "driver test failed",
+        WarningKind::DriverFault,
+        WarningSeverity::Critical,
+    ),
+    (
+        "driver fault",
+        WarningKind::DriverFault,
+        WarningSeverity::Critical,
+    ),
+];
+
+/// Whether `phrase` appears in `text` on word boundaries, so `stall` doesn't match
+/// `install` or `StallGuard`
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + phrase.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Read a `[MSG:...]` line as a warning: FluidNC's `[MSG:WARN: ...]` and `[MSG:ERR: ...]`,
+/// and driver faults at any level (its Trinamic status lines are `INFO`). None for other
+/// messages, such as `[MSG:Pgm End]`.
+pub fn parse_controller_warning(line: &str) -> Option<ControllerWarning> {
+    let body = line.trim().strip_prefix("[MSG:")?.strip_suffix(']')?;
+    let (level, message) = match body.split_once(':') {
+        Some((level @ ("WARN" | "ERR" | "INFO" | "DBG"), message)) => (Some(level), message),
+        _ => (None, body),
+    };
+    let message = message.trim().to_string();
+    let lower = message.to_lowercase();
+
+    let (kind, severity) = match DRIVER_FAULTS
+        .iter()
+        .find(|(phrase, ..)| contains_phrase(&lower, phrase))
+    {
+        Some((_, kind, severity)) => (*kind, *severity),
+        None => match level {
+            Some("WARN" | "ERR") => (WarningKind::General, WarningSeverity::Warning),
+            _ => return None,
+        },
+    };
+    Some(ControllerWarning {
+        kind,
+        severity,
+        axis: axis_name(&message),
+        message,
+        held: false,
+    })
+}
+
+/// The axis in messages like `X Axis driver overtemp` or `Y2 Axis Stall`
+fn axis_name(message: &str) -> Option<String> {
+    let words: Vec<&str> = message.split_whitespace().collect();
+    words.windows(2).find_map(|pair| {
+        let [axis, word] = pair else {
+            return None;
+        };
+        let is_axis = axis.chars().next().is_some_and(|c| "XYZABC".contains(c))
+            && axis.chars().skip(1).all(|c| c.is_ascii_digit());
+        (is_axis && word.eq_ignore_ascii_case("axis")).then(|| axis.to_string())
+    })
+}
@@ -0,0 +1,119 @@
+use crate::gcode;
+use crate::machine_profile::AXIS_LETTERS;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Runs the built-in travel moves (park, go to zero, tool change) as probe moves, so a
+/// touch probe left in the spindle stops the machine when it hits something
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashGuardConfig {
+    pub enabled: bool,
+    /// Feed for guarded travel, mm/min. Probe moves don't run at rapid speed, and the
+    /// machine has to stop within the probe's overtravel once it triggers.
+    pub feed_rate: f32,
+}
+
+impl Default for CrashGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feed_rate: 1000.0,
+        }
+    }
+}
+
+impl CrashGuardConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.feed_rate.is_finite() && self.feed_rate > 0.0) {
+            return Err(anyhow!("Crash guard feed rate must be above zero"));
+        }
+        Ok(())
+    }
+}
+
+/// A line of a guarded sequence
+#[derive(Debug, Clone)]
+pub struct GuardedCommand {
+    pub line: String,
+    /// A `G38.3` move; the probe triggering during it means a collision
+    pub probe: bool,
+}
+
+/// Rewrite a travel sequence so every `G0` is a `G38.3` to the same place, which stops
+/// without alarming when the probe triggers. Targets are given in work coordinates with
+/// G90 since probe moves can't be combined with G53. Moves that go nowhere are dropped
+/// because Grbl rejects a probe move to the current position.
+pub fn guard_sequence(
+    commands: &[String],
+    machine_pos: &[f32],
+    work_offset: &[f32],
+    config: &CrashGuardConfig,
+) -> Result<Vec<GuardedCommand>> {
+    let mut position: Vec<f64> = machine_pos.iter().map(|&v| v as f64).collect();
+    let offset = |axis: usize| work_offset.get(axis).copied().unwrap_or(0.0) as f64;
+    let mut incremental = false;
+    let mut guarded = Vec::new();
+
+    for command in commands {
+        let words = gcode::tokenize_line(command);
+        if gcode::has_code(&words, 'G', 90.0) {
+            incremental = false;
+        }
+        if gcode::has_code(&words, 'G', 91.0) {
+            incremental = true;
+        }
+        if [1.0, 2.0, 3.0, 38.2, 38.3]
+            .iter()
+            .any(|&code| gcode::has_code(&words, 'G', code))
+        {
+            return Err(anyhow!("Crash guard can't follow the move {}", command));
+        }
+        if !gcode::has_code(&words, 'G', 0.0) {
+            guarded.push(GuardedCommand {
+                line: command.clone(),
+                probe: false,
+            });
+            continue;
+        }
+
+        let machine_coords = gcode::has_code(&words, 'G', 53.0);
+        let mut targets = Vec::new();
+        for (axis, &letter) in AXIS_LETTERS.iter().enumerate().take(position.len()) {
+            let Some(value) = gcode::word_value(&words, letter) else {
+                continue;
+            };
+            let target = match (machine_coords, incremental) {
+                (true, _) => value,
+                (false, true) => position[axis] + value,
+                (false, false) => value + offset(axis),
+            };
+            if (target - position[axis]).abs() > 0.0005 {
+                targets.push(format!(
+                    "{}{}",
+                    letter,
+                    gcode::format_value(target - offset(axis), 3)
+                ));
+            }
+            position[axis] = target;
+        }
+        if targets.is_empty() {
+            continue;
+        }
+        guarded.push(GuardedCommand {
+            line: format!(
+                "G90 G38.3 {} F{}",
+                targets.join(" "),
+                gcode::format_value(config.feed_rate as f64, 0)
+            ),
+            probe: true,
+        });
+        if incremental {
+            guarded.push(GuardedCommand {
+                line: "G91".to_string(),
+                probe: false,
+            });
+        }
+    }
+    Ok(guarded)
+}
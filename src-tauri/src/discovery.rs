@@ -0,0 +1,699 @@
+use crate::cnc_comm::CncDevice;
+use crate::telnet_transport;
+use crate::transport::{SocketOptions, TransportKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Port announced by the Genmitsu WiFi module, and assumed when an announcement has none
+pub const DEFAULT_TCP_PORT: u16 = 10086;
+/// How long to keep listening after the first announcement, for other devices on the network
+const ANNOUNCEMENT_GRACE: Duration = Duration::from_millis(300);
+/// Addresses remembered from manual connections
+const MAX_SAVED_ADDRESSES: usize = 20;
+/// How often the discovery datagram is repeated while listening, since UDP can drop it
+const SOLICIT_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// One announcement listener at a time. A second pass binding the same UDP ports would
+/// either fail to bind or split the announcements with the first.
+static LISTENING: Mutex<()> = Mutex::new(());
+
+/// Announcement payloads understood by discovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementFormat {
+    /// Genmitsu WiFi module: `{"ip":"...","port":"10086","name":"...","uuid":"<mac>"}`
+    Genmitsu,
+    /// Any JSON object with `ip` and optionally `port` (number or string), `name` and `mac`
+    Json,
+    /// `ip` or `ip:port`, optionally followed by a name
+    Text,
+}
+
+/// Where discovery listens and what it probes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// UDP ports to listen on for announcements
+    pub listen_ports: Vec<u16>,
+    /// Multicast groups joined on each listen port
+    pub multicast_groups: Vec<Ipv4Addr>,
+    /// Payload formats tried in order on each announcement
+    pub formats: Vec<AnnouncementFormat>,
+    /// Ports the discovery datagram is broadcast to, for modules that only announce
+    /// themselves when asked; empty to only listen
+    pub solicit_ports: Vec<u16>,
+    /// Discovery datagram. Replies in a known format are used as announcements; any other
+    /// reply still makes its sender a candidate on the default port.
+    pub solicit_payload: String,
+    /// TCP ports probed on manual hosts
+    pub probe_ports: Vec<u16>,
+    /// Hosts probed directly when nothing announces itself
+    pub manual_hosts: Vec<String>,
+    pub probe_timeout_ms: u64,
+    /// Probes running at once
+    pub max_parallel_probes: usize,
+    /// Probe every address of the local /24 when nothing else turns up. For networks that
+    /// drop broadcasts, such as many mesh routers.
+    pub subnet_scan: bool,
+    /// Ports tried on each address: the WiFi module, telnet bridges, web UIs
+    pub subnet_scan_ports: Vec<u16>,
+    /// First three octets to scan, e.g. `192.168.1`; None for the local interface's
+    pub subnet: Option<String>,
+    /// Shorter than `probe_timeout_ms`, since most addresses have nothing behind them
+    pub subnet_probe_timeout_ms: u64,
+    pub subnet_parallel_probes: usize,
+    /// Also look for BLE serial bridges, for when the laptop isn't on the machine's network
+    pub ble_scan: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            listen_ports: vec![1234],
+            multicast_groups: vec![Ipv4Addr::new(224, 0, 0, 251)],
+            formats: vec![AnnouncementFormat::Genmitsu, AnnouncementFormat::Json],
+            solicit_ports: vec![1234],
+            solicit_payload: "discover".to_string(),
+            probe_ports: vec![DEFAULT_TCP_PORT],
+            manual_hosts: Vec::new(),
+            probe_timeout_ms: 1000,
+            max_parallel_probes: 16,
+            subnet_scan: false,
+            subnet_scan_ports: vec![DEFAULT_TCP_PORT, 23, 8080],
+            subnet: None,
+            subnet_probe_timeout_ms: 300,
+            subnet_parallel_probes: 64,
+            ble_scan: true,
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("discovery.json")
+    }
+}
+
+/// Payload of the `cnc:subnet-scan-progress` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanProgress {
+    pub probed: usize,
+    pub total: usize,
+    pub found: usize,
+}
+
+/// Reporting and the stop switch for a discovery pass
+#[derive(Clone, Copy, Default)]
+pub struct DiscoveryWatch<'a> {
+    /// Called from the probe threads as each controller answers
+    pub found: Option<&'a (dyn Fn(&CncDevice) + Sync)>,
+    /// Once set, no new probes start and listening stops; what was found is returned
+    pub cancel: Option<&'a AtomicBool>,
+}
+
+impl DiscoveryWatch<'_> {
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_some_and(|c| c.load(Ordering::Relaxed))
+    }
+}
+
+/// Address the user connected to by hand
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedAddress {
+    pub ip: String,
+    pub port: u16,
+    /// Seconds since the Unix epoch
+    pub last_used: u64,
+}
+
+/// Manually entered addresses, most recently used first. Discovery probes them too.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedAddresses {
+    pub addresses: Vec<SavedAddress>,
+}
+
+impl SavedAddresses {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("saved_addresses.json")
+    }
+
+    /// Move an address to the front, adding it if new
+    pub fn remember(&mut self, ip: &str, port: u16, now: u64) {
+        self.forget(ip, port);
+        self.addresses.insert(
+            0,
+            SavedAddress {
+                ip: ip.to_string(),
+                port,
+                last_used: now,
+            },
+        );
+        self.addresses.truncate(MAX_SAVED_ADDRESSES);
+    }
+
+    /// True if the address was saved
+    pub fn forget(&mut self, ip: &str, port: u16) -> bool {
+        let before = self.addresses.len();
+        self.addresses.retain(|a| !(a.ip == ip && a.port == port));
+        self.addresses.len() != before
+    }
+}
+
+/// A device announcing itself over UDP
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub ip: String,
+    pub port: Option<u16>,
+    pub name: Option<String>,
+    pub mac: Option<String>,
+}
+
+// Structure for UDP broadcast response from Genmitsu WiFi module
+#[derive(Debug, Deserialize)]
+struct GenmitsuBroadcast {
+    ip: String,
+    port: String, // Port comes as string from device
+    name: String,
+    uuid: String, // MAC address in uuid field
+}
+
+/// Parse an announcement with the first format that accepts it
+pub fn parse_announcement(payload: &str, formats: &[AnnouncementFormat]) -> Option<Announcement> {
+    let payload = payload.trim();
+    formats.iter().find_map(|format| match format {
+        AnnouncementFormat::Genmitsu => {
+            let broadcast: GenmitsuBroadcast = serde_json::from_str(payload).ok()?;
+            Some(Announcement {
+                ip: broadcast.ip,
+                port: broadcast.port.parse().ok(),
+                name: Some(broadcast.name),
+                mac: Some(broadcast.uuid),
+            })
+        }
+        AnnouncementFormat::Json => {
+            let value: serde_json::Value = serde_json::from_str(payload).ok()?;
+            let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+            let port = value.get("port").and_then(|p| match p {
+                serde_json::Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+                serde_json::Value::String(s) => s.parse().ok(),
+                _ => None,
+            });
+            Some(Announcement {
+                ip: text("ip")?,
+                port,
+                name: text("name"),
+                mac: text("mac"),
+            })
+        }
+        AnnouncementFormat::Text => {
+            let mut parts = payload.splitn(2, char::is_whitespace);
+            let address = parts.next()?;
+            let (ip, port) = match address.split_once(':') {
+                Some((ip, port)) => (ip, Some(port.parse().ok()?)),
+                None => (address, None),
+            };
+            ip.parse::<Ipv4Addr>().ok()?;
+            Some(Announcement {
+                ip: ip.to_string(),
+                port,
+                name: parts
+                    .next()
+                    .map(|n| n.trim().to_string())
+                    .filter(|n| !n.is_empty()),
+                mac: None,
+            })
+        }
+    })
+}
+
+/// Somewhere a controller might be listening, with what its announcement said about it
+#[derive(Debug, Clone)]
+struct Candidate {
+    ip: String,
+    port: u16,
+    name: Option<String>,
+    mac: Option<String>,
+}
+
+/// Whether the candidate is the controller already connected over the network. It isn't
+/// probed: many WiFi bridges take a single client and would drop the live connection.
+fn is_connected(candidate: &Candidate, connected: Option<&CncDevice>) -> bool {
+    connected.is_some_and(|device| {
+        !matches!(device.transport, TransportKind::Serial | TransportKind::Ble)
+            && device.ip == candidate.ip
+            && device.port == candidate.port
+    })
+}
+
+fn skip_connected(candidates: &mut Vec<Candidate>, connected: Option<&CncDevice>) {
+    let before = candidates.len();
+    candidates.retain(|c| !is_connected(c, connected));
+    if let (Some(device), true) = (connected, candidates.len() < before) {
+        println!(
+            "⏭️  Not probing {}:{}: it's connected",
+            device.ip, device.port
+        );
+    }
+}
+
+/// Listen for announcements while probing the manual hosts, then probe whatever announced
+/// itself. Probes run in parallel, so a pass takes about one probe timeout however many
+/// candidates there are.
+pub fn discover(
+    config: &DiscoveryConfig,
+    saved: &SavedAddresses,
+    connected: Option<&CncDevice>,
+    timeout_ms: u64,
+    watch: DiscoveryWatch,
+) -> Result<Vec<CncDevice>> {
+    let mut manual: Vec<Candidate> = config
+        .manual_hosts
+        .iter()
+        .flat_map(|host| {
+            config.probe_ports.iter().map(move |port| Candidate {
+                ip: host.clone(),
+                port: *port,
+                name: Some(format!("CNC at {}:{} (Direct)", host, port)),
+                mac: None,
+            })
+        })
+        .collect();
+    for address in &saved.addresses {
+        if !manual
+            .iter()
+            .any(|c| c.ip == address.ip && c.port == address.port)
+        {
+            manual.push(Candidate {
+                ip: address.ip.clone(),
+                port: address.port,
+                name: Some(format!("CNC at {}:{} (Saved)", address.ip, address.port)),
+                mac: None,
+            });
+        }
+    }
+    skip_connected(&mut manual, connected);
+
+    println!(
+        "📡 Listening for CNC announcements on UDP {:?}, probing {} manual address(es)...",
+        config.listen_ports,
+        manual.len()
+    );
+    let direct_found = AtomicBool::new(false);
+    let (announced, direct) = thread::scope(|scope| {
+        let direct = scope.spawn(|| {
+            let devices = probe_all(&manual, config.max_parallel_probes, config, None, watch);
+            direct_found.store(!devices.is_empty(), Ordering::Relaxed);
+            devices
+        });
+        let announced = listen_for_announcements(config, timeout_ms, &direct_found, watch);
+        (announced, direct.join().unwrap_or_default())
+    });
+
+    let mut announced = announced.unwrap_or_else(|e| {
+        println!("⚠️  Announcement discovery failed: {}", e);
+        Vec::new()
+    });
+    skip_connected(&mut announced, connected);
+    let mut devices = probe_all(&announced, config.max_parallel_probes, config, None, watch);
+    println!(
+        "✅ Found {} device(s) via announcements, {} via direct connection",
+        devices.len(),
+        direct.len()
+    );
+    // Announced devices carry a name and MAC, so they win over a direct hit on the same address
+    for device in direct {
+        if !devices
+            .iter()
+            .any(|d| d.ip == device.ip && d.port == device.port)
+        {
+            devices.push(device);
+        }
+    }
+    Ok(devices)
+}
+
+/// Probe every address of a /24 on the subnet scan ports, reporting progress as probes
+/// finish. Returns every Grbl controller that answered.
+pub fn scan_subnet(
+    config: &DiscoveryConfig,
+    connected: Option<&CncDevice>,
+    progress: &(dyn Fn(ScanProgress) + Sync),
+    watch: DiscoveryWatch,
+) -> Result<Vec<CncDevice>> {
+    let own = local_ipv4();
+    let prefix = match (&config.subnet, &own) {
+        (Some(subnet), _) => subnet.trim().trim_end_matches('.').to_string(),
+        (None, Ok(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}", a, b, c)
+        }
+        (None, Err(e)) => return Err(anyhow!("Could not work out the local subnet: {}", e)),
+    };
+    if format!("{}.1", prefix).parse::<Ipv4Addr>().is_err() {
+        return Err(anyhow!(
+            "{} isn't the first three octets of an IPv4 address",
+            prefix
+        ));
+    }
+    if config.subnet_scan_ports.is_empty() {
+        return Err(anyhow!("No ports to scan"));
+    }
+
+    let scan_config = DiscoveryConfig {
+        probe_timeout_ms: config.subnet_probe_timeout_ms,
+        ..config.clone()
+    };
+    let mut candidates: Vec<Candidate> = (1..=254)
+        .map(|host| format!("{}.{}", prefix, host))
+        .filter(|ip| own.as_ref().map_or(true, |own| own.to_string() != *ip))
+        .flat_map(|ip| {
+            config.subnet_scan_ports.iter().map(move |port| Candidate {
+                name: Some(format!("CNC at {}:{} (Scan)", ip, port)),
+                ip: ip.clone(),
+                port: *port,
+                mac: None,
+            })
+        })
+        .collect();
+    skip_connected(&mut candidates, connected);
+
+    println!(
+        "🔎 Scanning {}.0/24 on ports {:?} ({} probes)...",
+        prefix,
+        config.subnet_scan_ports,
+        candidates.len()
+    );
+    let devices = probe_all(
+        &candidates,
+        config.subnet_parallel_probes,
+        &scan_config,
+        Some(progress),
+        watch,
+    );
+    println!("✅ Subnet scan found {} device(s)", devices.len());
+    Ok(devices)
+}
+
+/// Address of the interface the default route goes out of
+fn local_ipv4() -> Result<Ipv4Addr> {
+    // Connecting a UDP socket sends nothing; it only picks the outgoing interface
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect(("8.8.8.8", 80))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(anyhow!("No IPv4 address on the default route")),
+    }
+}
+
+/// Probe candidates concurrently, at most `parallel` at a time. Devices come back in
+/// candidate order. With a progress callback (a scan), misses aren't logged: nearly
+/// every address is one. Once cancelled, probes already under way finish and no more start.
+fn probe_all(
+    candidates: &[Candidate],
+    parallel: usize,
+    config: &DiscoveryConfig,
+    progress: Option<&(dyn Fn(ScanProgress) + Sync)>,
+    watch: DiscoveryWatch,
+) -> Vec<CncDevice> {
+    let next = AtomicUsize::new(0);
+    let probed = AtomicUsize::new(0);
+    let found = Mutex::new(Vec::new());
+    let workers = parallel.clamp(1, candidates.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if watch.cancelled() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(candidate) = candidates.get(index) else {
+                    break;
+                };
+                match probe_device(&candidate.ip, candidate.port, config.probe_timeout_ms) {
+                    Ok(mut device) => {
+                        if let Some(name) = &candidate.name {
+                            device.name = name.clone();
+                        }
+                        device.mac = candidate.mac.clone();
+                        if let Some(found) = watch.found {
+                            found(&device);
+                        }
+                        if let Ok(mut found) = found.lock() {
+                            found.push((index, device));
+                        }
+                    }
+                    Err(e) if progress.is_none() => {
+                        println!("❌ No CNC at {}:{}: {}", candidate.ip, candidate.port, e)
+                    }
+                    Err(_) => {}
+                }
+                if let Some(progress) = progress {
+                    progress(ScanProgress {
+                        probed: probed.fetch_add(1, Ordering::Relaxed) + 1,
+                        total: candidates.len(),
+                        found: found.lock().map_or(0, |f| f.len()),
+                    });
+                }
+            });
+        }
+    });
+
+    let mut found = found.into_inner().unwrap_or_default();
+    found.sort_by_key(|(index, _)| *index);
+    found.into_iter().map(|(_, device)| device).collect()
+}
+
+/// Bind each listen port, join the multicast groups and collect announcements. Stops shortly
+/// after the first one (others usually follow within moments), or once a manual host answered.
+fn listen_for_announcements(
+    config: &DiscoveryConfig,
+    timeout_ms: u64,
+    direct_found: &AtomicBool,
+    watch: DiscoveryWatch,
+) -> Result<Vec<Candidate>> {
+    let _listening = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+    // The flag marks the socket the discovery datagram goes out on, which gets the replies
+    let mut sockets: Vec<(UdpSocket, bool)> = Vec::new();
+    for port in &config.listen_ports {
+        match UdpSocket::bind(("0.0.0.0", *port)) {
+            Ok(socket) => {
+                for group in &config.multicast_groups {
+                    if let Err(e) = socket.join_multicast_v4(group, &Ipv4Addr::UNSPECIFIED) {
+                        println!("⚠️  Could not join {} on port {}: {}", group, port, e);
+                    }
+                }
+                // Short timeouts so every socket gets a turn
+                socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+                sockets.push((socket, false));
+            }
+            Err(e) => println!("⚠️  Could not listen on UDP {}: {}", port, e),
+        }
+    }
+    if !config.solicit_ports.is_empty() {
+        match solicit_socket() {
+            Ok(socket) => sockets.push((socket, true)),
+            Err(e) => println!(
+                "⚠️  Could not open a socket for the discovery datagram: {}",
+                e
+            ),
+        }
+    }
+    if sockets.is_empty() {
+        return Err(anyhow!("No discovery port could be opened"));
+    }
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let start_time = Instant::now();
+    let mut first_heard: Option<Instant> = None;
+    let mut last_solicit: Option<Instant> = None;
+    let mut buf = [0; 1024];
+
+    while start_time.elapsed() < Duration::from_millis(timeout_ms)
+        && first_heard.is_none_or(|t| t.elapsed() < ANNOUNCEMENT_GRACE)
+        && !(candidates.is_empty() && direct_found.load(Ordering::Relaxed))
+        && !watch.cancelled()
+    {
+        if last_solicit.is_none_or(|t| t.elapsed() >= SOLICIT_INTERVAL) {
+            last_solicit = Some(Instant::now());
+            if let Some((socket, _)) = sockets.iter().find(|(_, solicit)| *solicit) {
+                for port in &config.solicit_ports {
+                    let target = (Ipv4Addr::BROADCAST, *port);
+                    if let Err(e) = socket.send_to(config.solicit_payload.as_bytes(), target) {
+                        println!("⚠️  Could not broadcast to UDP {}: {}", port, e);
+                    }
+                }
+            }
+        }
+        for (socket, solicit) in &sockets {
+            let (size, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    println!("Discovery receive error: {}", e);
+                    continue;
+                }
+            };
+            let payload = String::from_utf8_lossy(&buf[..size]);
+            // Listeners hear our own broadcast
+            if payload == config.solicit_payload {
+                continue;
+            }
+            println!("📨 Received announcement from {}: {}", addr, payload);
+            let announcement = match parse_announcement(&payload, &config.formats) {
+                Some(announcement) => announcement,
+                // It answered the discovery datagram, so it's worth a probe
+                None if *solicit => Announcement {
+                    ip: addr.ip().to_string(),
+                    port: None,
+                    name: None,
+                    mac: None,
+                },
+                None => {
+                    println!("Unrecognised announcement format");
+                    continue;
+                }
+            };
+
+            let port = announcement.port.unwrap_or(DEFAULT_TCP_PORT);
+            first_heard.get_or_insert_with(Instant::now);
+            // Devices repeat their announcement
+            if !candidates
+                .iter()
+                .any(|c| c.ip == announcement.ip && c.port == port)
+            {
+                candidates.push(Candidate {
+                    ip: announcement.ip,
+                    port,
+                    name: announcement.name,
+                    mac: announcement.mac,
+                });
+            }
+        }
+    }
+
+    for (socket, _) in &sockets {
+        for group in &config.multicast_groups {
+            let _ = socket.leave_multicast_v4(group, &Ipv4Addr::UNSPECIFIED);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Socket on an ephemeral port allowed to broadcast; modules answer to its address
+fn solicit_socket() -> Result<UdpSocket> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_broadcast(true)?;
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+    Ok(socket)
+}
+
+/// Probe a specific IP and port to see if it's a CNC device
+pub fn probe_device(ip: &str, port: u16, timeout_ms: u64) -> Result<CncDevice> {
+    let addr = format!("{}:{}", ip, port);
+
+    // Try to connect with a shorter timeout for faster discovery
+    match TcpStream::connect_timeout(&addr.parse()?, Duration::from_millis(timeout_ms)) {
+        Ok(mut stream) => {
+            stream.set_read_timeout(Some(Duration::from_millis(timeout_ms)))?;
+            stream.set_write_timeout(Some(Duration::from_millis(500)))?;
+
+            // Send Grbl status query
+            let _ = stream.write_all(b"?\n");
+
+            let mut buffer = [0; 512];
+            let mut response = String::new();
+            // Telnet servers open with option negotiation
+            let mut telnet = port == telnet_transport::DEFAULT_TELNET_PORT;
+
+            if let Ok(size) = stream.read(&mut buffer) {
+                telnet |= buffer[..size].contains(&telnet_transport::IAC);
+                response = String::from_utf8_lossy(&buffer[..size]).to_string();
+            }
+
+            // Check if response looks like Grbl
+            if response.contains("Idle")
+                || response.contains("Alarm")
+                || response.contains("Run")
+                || response.contains("MPos")
+                || response.contains("VER:")
+            {
+                // Skip firmware version check for faster connection
+                // We can get this info later if needed
+                Ok(CncDevice {
+                    name: format!("CNC at {}", ip),
+                    ip: ip.to_string(),
+                    port,
+                    mac: None,
+                    firmware: None, // Skip version check for speed
+                    transport: if telnet {
+                        TransportKind::Telnet
+                    } else {
+                        TransportKind::Tcp
+                    },
+                    baud_rate: None,
+                    certificate_pin: None,
+                    socket: SocketOptions::default(),
+                })
+            } else {
+                Err(anyhow!(
+                    "Not a CNC device - unexpected response: {}",
+                    response
+                ))
+            }
+        }
+        Err(e) => Err(anyhow!("Connection failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(ip: &str, port: u16) -> Candidate {
+        Candidate {
+            ip: ip.to_string(),
+            port,
+            name: None,
+            mac: None,
+        }
+    }
+
+    #[test]
+    fn only_the_connected_address_and_port_are_skipped() {
+        let device = CncDevice {
+            name: "Bridge".to_string(),
+            ip: "192.168.1.20".to_string(),
+            port: 23,
+            mac: None,
+            firmware: None,
+            transport: TransportKind::Tcp,
+            baud_rate: None,
+            certificate_pin: None,
+            socket: SocketOptions::default(),
+        };
+        let mut candidates = vec![
+            candidate("192.168.1.20", 23),
+            candidate("192.168.1.20", 8080),
+            candidate("192.168.1.21", 23),
+        ];
+        skip_connected(&mut candidates, Some(&device));
+        let left: Vec<_> = candidates.iter().map(|c| (c.ip.as_str(), c.port)).collect();
+        assert_eq!(left, [("192.168.1.20", 8080), ("192.168.1.21", 23)]);
+    }
+}
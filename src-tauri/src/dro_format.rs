@@ -0,0 +1,130 @@
+use crate::axis_mapping::AxisMapping;
+use crate::machine_profile::{self, AXIS_LETTERS};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const MM_PER_INCH: f64 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DroUnits {
+    Mm,
+    Inch,
+}
+
+/// How rotary axis positions are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotaryDisplay {
+    /// The position as reported, counting whole turns: 370° stays 370°
+    Continuous,
+    /// Within one turn, 0 up to 360
+    Wrapped,
+}
+
+/// How positions are shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DroFormat {
+    pub units: DroUnits,
+    pub decimals: usize,
+    pub show_unit_suffix: bool,
+    /// Prefix positive values with `+` so columns line up
+    pub always_show_sign: bool,
+    /// Lathe-style: X is shown as a diameter (twice the radius)
+    pub diameter_mode: bool,
+    pub rotary_display: RotaryDisplay,
+}
+
+impl Default for DroFormat {
+    fn default() -> Self {
+        Self {
+            units: DroUnits::Mm,
+            decimals: 3,
+            show_unit_suffix: false,
+            always_show_sign: false,
+            diameter_mode: false,
+            rotary_display: RotaryDisplay::Continuous,
+        }
+    }
+}
+
+/// One axis value ready for display
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormattedAxis {
+    pub axis: char,
+    /// Name shown for the axis, the letter unless the axis mapping relabels it
+    pub label: String,
+    /// In display units, after diameter doubling
+    pub value: f64,
+    pub text: String,
+}
+
+impl DroFormat {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("dro_format.json")
+    }
+
+    /// Format a position given in mm (degrees for rotary axes)
+    pub fn format_axis(&self, axis: char, value: f32) -> FormattedAxis {
+        let rotary = machine_profile::is_rotary(axis);
+        let mut value = value as f64;
+        if self.diameter_mode && axis == 'X' {
+            value *= 2.0;
+        }
+        if !rotary && self.units == DroUnits::Inch {
+            value /= MM_PER_INCH;
+        }
+        let decimals = self.decimals.min(6);
+        if rotary && self.rotary_display == RotaryDisplay::Wrapped {
+            value = value.rem_euclid(360.0);
+            // Just under a turn would round up to 360, which is 0 again
+            if format!("{:.*}", decimals, value).parse::<f64>() == Ok(360.0) {
+                value = 0.0;
+            }
+        }
+
+        let mut text = format!("{:.*}", decimals, value);
+        // "-0.000" is noise from values like -0.0001
+        if text.starts_with('-') && text[1..].chars().all(|c| c == '0' || c == '.') {
+            text.remove(0);
+        }
+        if self.always_show_sign && !text.starts_with('-') {
+            text.insert(0, '+');
+        }
+        if self.show_unit_suffix {
+            text.push_str(match (rotary, self.units) {
+                (true, _) => "°",
+                (false, DroUnits::Mm) => " mm",
+                (false, DroUnits::Inch) => " in",
+            });
+        }
+        FormattedAxis {
+            axis,
+            label: axis.to_string(),
+            value,
+            text,
+        }
+    }
+
+    /// Format a position report's values, which are in axis order
+    pub fn format_position(&self, values: &[f32]) -> Vec<FormattedAxis> {
+        values
+            .iter()
+            .zip(AXIS_LETTERS)
+            .map(|(value, axis)| self.format_axis(axis, *value))
+            .collect()
+    }
+
+    /// Format a machine position as the UI's axes: swapped, inverted and relabelled
+    pub fn format_mapped(&self, mapping: &AxisMapping, values: &[f32]) -> Vec<FormattedAxis> {
+        mapping
+            .to_display(values)
+            .into_iter()
+            .map(|(axis, value)| FormattedAxis {
+                label: mapping.label(axis),
+                ..self.format_axis(axis, value)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const MQTT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the dust collector's relay is switched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RelayControl {
+    /// Lines sent to the controller, e.g. M8/M9 on a board with the coolant pin wired to a relay
+    Gcode { on: String, off: String },
+    /// URLs fetched with GET, e.g. a Shelly or Tasmota smart plug
+    Webhook { on_url: String, off_url: String },
+    /// Message published to a broker, e.g. for a Home Assistant switch
+    Mqtt {
+        host: String,
+        port: u16,
+        topic: String,
+        on_payload: String,
+        off_payload: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+impl RelayControl {
+    /// Switch a webhook or MQTT relay; G-code lines go through the controller instead
+    pub fn switch_remote(&self, on: bool) -> Result<()> {
+        match self {
+            RelayControl::Gcode { .. } => {
+                Err(anyhow!("G-code relays are switched by the controller"))
+            }
+            RelayControl::Webhook { on_url, off_url } => {
+                fetch_url(if on { on_url } else { off_url })
+            }
+            RelayControl::Mqtt {
+                host,
+                port,
+                topic,
+                on_payload,
+                off_payload,
+                username,
+                password,
+            } => mqtt_publish(
+                host,
+                *port,
+                username.as_deref(),
+                password.as_deref(),
+                topic,
+                if on { on_payload } else { off_payload },
+            ),
+        }
+    }
+}
+
+/// Dust collection switched on when a job starts and off a while after it ends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DustCollectionConfig {
+    pub enabled: bool,
+    pub relay: RelayControl,
+    /// Keeps running after the job to clear the last chips from the hose
+    pub off_delay_seconds: f32,
+}
+
+impl Default for DustCollectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            relay: RelayControl::Gcode {
+                on: "M8".to_string(),
+                off: "M9".to_string(),
+            },
+            off_delay_seconds: 10.0,
+        }
+    }
+}
+
+impl DustCollectionConfig {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("dust_collection.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.off_delay_seconds.is_finite() || self.off_delay_seconds < 0.0 {
+            return Err(anyhow!("Off delay can't be negative"));
+        }
+        let empty = match &self.relay {
+            RelayControl::Gcode { on, off } => on.trim().is_empty() || off.trim().is_empty(),
+            RelayControl::Webhook { on_url, off_url } => {
+                on_url.trim().is_empty() || off_url.trim().is_empty()
+            }
+            RelayControl::Mqtt { host, topic, .. } => {
+                host.trim().is_empty() || topic.trim().is_empty()
+            }
+        };
+        if empty {
+            return Err(anyhow!("Fill in how to switch the relay on and off"));
+        }
+        Ok(())
+    }
+}
+
+/// Payload of the `cnc:dust-collection` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DustCollectionSwitch {
+    pub on: bool,
+    /// Why switching failed, if it did
+    pub error: Option<String>,
+}
+
+impl DustCollectionSwitch {
+    /// The outcome of switching, noting the relay's new state in `relay_on` if it worked
+    pub fn record(on: bool, result: Result<()>, relay_on: &AtomicBool) -> Self {
+        let error = match result {
+            Ok(()) => {
+                println!("🌪️  Dust collection {}", if on { "on" } else { "off" });
+                relay_on.store(on, Ordering::Relaxed);
+                None
+            }
+            Err(e) => {
+                println!("⚠️  Could not switch dust collection: {}", e);
+                Some(e.to_string())
+            }
+        };
+        Self { on, error }
+    }
+}
+
+pub fn fetch_url(url: &str) -> Result<()> {
+    ureq::get(url).timeout(HTTP_TIMEOUT).call()?;
+    Ok(())
+}
+
+/// Publish one QoS 0 message with a throwaway MQTT 3.1.1 session
+pub fn mqtt_publish(
+    host: &str,
+    port: u16,
+    username: Option<&str>,
+    password: Option<&str>,
+    topic: &str,
+    payload: &str,
+) -> Result<()> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, MQTT_TIMEOUT)?;
+    stream.set_read_timeout(Some(MQTT_TIMEOUT))?;
+    stream.set_write_timeout(Some(MQTT_TIMEOUT))?;
+
+    let mut connect = Vec::new();
+    push_string(&mut connect, "MQTT");
+    let flags = 0x02 // clean session
+        | if username.is_some() { 0x80 } else { 0 }
+        | if password.is_some() { 0x40 } else { 0 };
+    connect.extend_from_slice(&[4, flags, 0, 30]);
+    push_string(&mut connect, &format!("cnc-{}", std::process::id()));
+    for field in [username, password].into_iter().flatten() {
+        push_string(&mut connect, field);
+    }
+    stream.write_all(&packet(0x10, &connect))?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(anyhow!(
+            "MQTT broker refused the connection (code {})",
+            connack[3]
+        ));
+    }
+
+    let mut publish = Vec::new();
+    push_string(&mut publish, topic);
+    publish.extend_from_slice(payload.as_bytes());
+    stream.write_all(&packet(0x30, &publish))?;
+    stream.write_all(&packet(0xE0, &[]))?;
+    Ok(())
+}
+
+/// Fixed header with the variable-length remaining length, then the body
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+fn push_string(out: &mut Vec<u8>, text: &str) {
+    out.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
@@ -0,0 +1,187 @@
+use crate::grbl_protocol::StatusReport;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Lines listed in `ExecutionHeatmap::hottest_lines`
+const HOTTEST_LINES: usize = 10;
+
+/// A gap between status reports longer than this is a stall in polling, not cutting time
+const MAX_SAMPLE_GAP_SECONDS: f64 = 2.0;
+
+/// Where the tool spent its time on one job line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeatmapLine {
+    /// 1-based job line
+    pub line: usize,
+    /// Time spent executing the line, with holds and door openings left out
+    pub seconds: f64,
+    /// `seconds` relative to the slowest line, 0 to 1, for colouring
+    pub intensity: f64,
+    /// Actual feed from the `FS:` field, averaged over the time spent on the line
+    pub average_feed: Option<f32>,
+    pub peak_feed: Option<f32>,
+    /// Lowest feed override seen on the line
+    pub min_feed_override: Option<u32>,
+    /// Time on the line with the feed override below 100%
+    pub reduced_override_seconds: f64,
+}
+
+/// Per-line time and feed of the last finished job, for drawing over the toolpath.
+/// Lines are placed by the planner estimate in `JobLineMap::executing`, so time on
+/// very short moves can land on a neighbouring line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionHeatmap {
+    pub program_name: Option<String>,
+    /// Tag on the job's console lines, matching the history entry
+    pub job_id: Option<u64>,
+    /// Seconds since the Unix epoch
+    pub finished_at: u64,
+    pub completed: Option<bool>,
+    /// Time attributed to lines; a little under the job's active time
+    pub total_seconds: f64,
+    /// In line order, only lines that were seen executing
+    pub lines: Vec<HeatmapLine>,
+    /// Lines with the most time, slowest first
+    pub hottest_lines: Vec<usize>,
+    /// Lines where the feed override was lowered, in line order
+    pub reduced_override_lines: Vec<usize>,
+}
+
+impl ExecutionHeatmap {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("execution_heatmap.json")
+    }
+}
+
+#[derive(Debug, Default)]
+struct LineTotals {
+    seconds: f64,
+    /// Feed multiplied by time, for the time-weighted average
+    feed_seconds: f64,
+    feed_sampled_seconds: f64,
+    peak_feed: Option<f32>,
+    min_feed_override: Option<u32>,
+    reduced_override_seconds: f64,
+}
+
+/// The previous status report, whose values hold until the next one arrives
+struct Sample {
+    at: Instant,
+    line: usize,
+    running: bool,
+    feed: Option<f32>,
+}
+
+/// Builds the heatmap from status reports while a job streams
+#[derive(Default)]
+pub struct HeatmapRecorder {
+    lines: BTreeMap<usize, LineTotals>,
+    last: Option<Sample>,
+    /// `Ov:` only comes every few reports, so the last one seen stays in effect
+    feed_override: Option<u32>,
+}
+
+impl HeatmapRecorder {
+    /// Forget the previous job
+    pub fn start(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Feed a status report taken during the job, with the line the planner is executing
+    pub fn observe(&mut self, report: &StatusReport, executing: Option<usize>) {
+        let now = Instant::now();
+        if let Some(last) = self.last.take() {
+            let seconds = now.duration_since(last.at).as_secs_f64();
+            if last.running && seconds <= MAX_SAMPLE_GAP_SECONDS {
+                let totals = self.lines.entry(last.line).or_default();
+                totals.seconds += seconds;
+                if let Some(feed) = last.feed {
+                    totals.feed_seconds += f64::from(feed) * seconds;
+                    totals.feed_sampled_seconds += seconds;
+                }
+                if self.feed_override.is_some_and(|o| o < 100) {
+                    totals.reduced_override_seconds += seconds;
+                }
+            }
+        }
+        // After charging the last interval, which ran at the override in effect before
+        if let Some(overrides) = report.overrides {
+            self.feed_override = Some(overrides.feed);
+        }
+        let Some(line) = executing else {
+            return;
+        };
+        let running = report.state == "Run";
+        if running {
+            let totals = self.lines.entry(line).or_default();
+            if let Some(feed) = report.feed_rate {
+                totals.peak_feed = Some(totals.peak_feed.map_or(feed, |p| p.max(feed)));
+            }
+            if let Some(ov) = self.feed_override {
+                totals.min_feed_override = Some(totals.min_feed_override.map_or(ov, |m| m.min(ov)));
+            }
+        }
+        self.last = Some(Sample {
+            at: now,
+            line,
+            running,
+            feed: report.feed_rate,
+        });
+    }
+
+    /// The heatmap of the job so far, None if no line was seen executing
+    pub fn finish(
+        &mut self,
+        program_name: Option<String>,
+        job_id: Option<u64>,
+        finished_at: u64,
+        completed: Option<bool>,
+    ) -> Option<ExecutionHeatmap> {
+        let recorded = std::mem::take(self);
+        if recorded.lines.is_empty() {
+            return None;
+        }
+        let slowest = recorded
+            .lines
+            .values()
+            .map(|t| t.seconds)
+            .fold(0.0, f64::max);
+        let lines: Vec<HeatmapLine> = recorded
+            .lines
+            .into_iter()
+            .map(|(line, t)| HeatmapLine {
+                line,
+                seconds: t.seconds,
+                intensity: if slowest > 0.0 {
+                    t.seconds / slowest
+                } else {
+                    0.0
+                },
+                average_feed: (t.feed_sampled_seconds > 0.0)
+                    .then(|| (t.feed_seconds / t.feed_sampled_seconds) as f32),
+                peak_feed: t.peak_feed,
+                min_feed_override: t.min_feed_override,
+                reduced_override_seconds: t.reduced_override_seconds,
+            })
+            .collect();
+
+        let mut by_time: Vec<&HeatmapLine> = lines.iter().filter(|l| l.seconds > 0.0).collect();
+        by_time.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+        Some(ExecutionHeatmap {
+            program_name,
+            job_id,
+            finished_at,
+            completed,
+            total_seconds: lines.iter().map(|l| l.seconds).sum(),
+            hottest_lines: by_time.iter().take(HOTTEST_LINES).map(|l| l.line).collect(),
+            reduced_override_lines: lines
+                .iter()
+                .filter(|l| l.min_feed_override.is_some_and(|o| o < 100))
+                .map(|l| l.line)
+                .collect(),
+            lines,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use crate::transport::CncTransport;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Faults to inject into the active transport (developer mode only)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultConfig {
+    /// Delay added to every read and write, in ms
+    pub latency_ms: u64,
+    /// Chance (0-1) that each received byte is dropped. Outgoing bytes are never
+    /// corrupted, so a real machine can't be sent a mangled command.
+    pub drop_byte_probability: f64,
+    /// Deliver received data in random pieces of at most this many bytes
+    pub split_max_bytes: Option<usize>,
+    /// Chance (0-1) per read or write that the link goes dead until reconnect
+    pub disconnect_probability: f64,
+    /// Seed for reproducible runs; time-based when unset
+    pub seed: Option<u64>,
+}
+
+/// Config shared between the manager and the wrapped connection, so it can change live
+pub type SharedFaultConfig = Arc<Mutex<Option<FaultConfig>>>;
+
+/// Wraps a transport and misbehaves according to the shared config; passes through when it is None
+pub struct FaultInjector {
+    inner: Box<dyn CncTransport>,
+    config: SharedFaultConfig,
+    rng: u64,
+    seeded_from: Option<u64>,
+    disconnected: bool,
+}
+
+impl FaultInjector {
+    pub fn new(inner: Box<dyn CncTransport>, config: SharedFaultConfig) -> Self {
+        Self {
+            inner,
+            config,
+            rng: 0,
+            seeded_from: None,
+            disconnected: false,
+        }
+    }
+
+    /// Snapshot the config, reseeding the generator when the seed changes
+    fn current(&mut self) -> Option<FaultConfig> {
+        let config = self.config.lock().ok()?.clone()?;
+        if self.rng == 0 || config.seed.is_some() && config.seed != self.seeded_from {
+            let seed = config.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(1, |d| d.as_nanos() as u64)
+            });
+            self.rng = seed.max(1);
+            self.seeded_from = config.seed;
+        }
+        Some(config)
+    }
+
+    /// xorshift64*, good enough for fault rolls
+    fn roll(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Apply latency and maybe kill the link; returns the config to use for this call
+    fn before_io(&mut self) -> Option<FaultConfig> {
+        let config = self.current()?;
+        if config.latency_ms > 0 {
+            thread::sleep(Duration::from_millis(config.latency_ms));
+        }
+        if !self.disconnected
+            && config.disconnect_probability > 0.0
+            && self.roll() < config.disconnect_probability
+        {
+            println!("💥 Fault injection: simulated disconnect");
+            self.disconnected = true;
+        }
+        Some(config)
+    }
+}
+
+impl Read for FaultInjector {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with(buf, |inner, buf| inner.read(buf))
+    }
+}
+
+impl FaultInjector {
+    /// Read through `read`, then mangle what came back
+    fn read_with(
+        &mut self,
+        buf: &mut [u8],
+        read: impl FnOnce(&mut dyn CncTransport, &mut [u8]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let Some(config) = self.before_io() else {
+            return read(self.inner.as_mut(), buf);
+        };
+        if self.disconnected {
+            // What a closed socket looks like to the reader
+            return Ok(0);
+        }
+
+        let mut limit = buf.len();
+        if let Some(max) = config.split_max_bytes.filter(|m| *m > 0) {
+            let piece = 1 + (self.roll() * max as f64) as usize;
+            limit = limit.min(piece.min(max));
+        }
+        let size = read(self.inner.as_mut(), &mut buf[..limit])?;
+
+        if config.drop_byte_probability <= 0.0 || size == 0 {
+            return Ok(size);
+        }
+        let mut kept = 0;
+        for i in 0..size {
+            if self.roll() >= config.drop_byte_probability {
+                buf[kept] = buf[i];
+                kept += 1;
+            }
+        }
+        if kept == 0 {
+            // Returning 0 would look like a closed connection
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "fault injection dropped every byte",
+            ));
+        }
+        Ok(kept)
+    }
+}
+
+impl Write for FaultInjector {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.before_io().is_some() && self.disconnected {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "fault injection: link down",
+            ));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl CncTransport for FaultInjector {
+    fn describe(&self) -> String {
+        format!("{} (fault injection)", self.inner.describe())
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.inner.shutdown()
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with(buf, |inner, buf| inner.read_available(buf))
+    }
+}
@@ -0,0 +1,328 @@
+use crate::motion_model::{MotionModel, MoveKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Grbl's feed override range
+const MIN_FEED_PERCENT: u32 = 10;
+const MAX_FEED_PERCENT: u32 = 200;
+
+/// Real-time feed override commands (Grbl 1.1)
+const FEED_OVERRIDE_RESET: u8 = 0x90;
+const FEED_OVERRIDE_PLUS_10: u8 = 0x91;
+const FEED_OVERRIDE_MINUS_10: u8 = 0x92;
+const FEED_OVERRIDE_PLUS_1: u8 = 0x93;
+const FEED_OVERRIDE_MINUS_1: u8 = 0x94;
+const RAPID_OVERRIDE_RESET: u8 = 0x95;
+const RAPID_OVERRIDE_MEDIUM: u8 = 0x96;
+const RAPID_OVERRIDE_LOW: u8 = 0x97;
+const SPINDLE_OVERRIDE_RESET: u8 = 0x99;
+const SPINDLE_OVERRIDE_PLUS_10: u8 = 0x9A;
+const SPINDLE_OVERRIDE_MINUS_10: u8 = 0x9B;
+const SPINDLE_OVERRIDE_PLUS_1: u8 = 0x9C;
+const SPINDLE_OVERRIDE_MINUS_1: u8 = 0x9D;
+
+/// Part of a program where the feed should be reduced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ZoneArea {
+    /// 1-based line numbers, inclusive
+    Lines { first: usize, last: usize },
+    /// Work XY rectangle in mm; cutting moves that start or end inside count
+    Region {
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedZone {
+    pub name: String,
+    pub area: ZoneArea,
+    /// Feed override inside the zone, 10-100%
+    pub feed_percent: u32,
+}
+
+/// A zone resolved to the program lines it covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneSpan {
+    pub name: String,
+    pub first: usize,
+    pub last: usize,
+    pub feed_percent: u32,
+}
+
+/// Resolve zones against a program. Regions become one span per run of consecutive
+/// cutting moves inside the rectangle.
+pub fn resolve_zones(zones: &[FeedZone], program: &str) -> Result<Vec<ZoneSpan>> {
+    let mut spans = Vec::new();
+    let mut model = None;
+    for zone in zones {
+        if !(MIN_FEED_PERCENT..=100).contains(&zone.feed_percent) {
+            return Err(anyhow!(
+                "Zone {}: feed must be between {}% and 100%",
+                zone.name,
+                MIN_FEED_PERCENT
+            ));
+        }
+        let span = |first: usize, last: usize| ZoneSpan {
+            name: zone.name.clone(),
+            first,
+            last,
+            feed_percent: zone.feed_percent,
+        };
+        match zone.area {
+            ZoneArea::Lines { first, last } => {
+                if first == 0 || last < first {
+                    return Err(anyhow!("Zone {}: invalid line range", zone.name));
+                }
+                spans.push(span(first, last));
+            }
+            ZoneArea::Region {
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+            } => {
+                let model = model.get_or_insert_with(|| MotionModel::from_program(program));
+                let inside = |p: &[f64; 3]| {
+                    (x_min..=x_max).contains(&p[0]) && (y_min..=y_max).contains(&p[1])
+                };
+                let mut run: Option<(usize, usize)> = None;
+                for m in &model.moves {
+                    let cutting = matches!(m.kind, MoveKind::Linear | MoveKind::Arc);
+                    if cutting && (inside(&m.start) || inside(&m.end)) {
+                        run = Some(run.map_or((m.line, m.line), |(first, _)| (first, m.line)));
+                    } else if m.kind != MoveKind::Dwell {
+                        if let Some((first, last)) = run.take() {
+                            spans.push(span(first, last));
+                        }
+                    }
+                }
+                if let Some((first, last)) = run {
+                    spans.push(span(first, last));
+                }
+            }
+        }
+    }
+    Ok(spans)
+}
+
+/// Real-time bytes that set the feed override to `percent`: reset to 100%, then step
+pub fn feed_override_bytes(percent: u32) -> Vec<u8> {
+    stepped_override_bytes(
+        percent,
+        [
+            FEED_OVERRIDE_RESET,
+            FEED_OVERRIDE_PLUS_10,
+            FEED_OVERRIDE_MINUS_10,
+            FEED_OVERRIDE_PLUS_1,
+            FEED_OVERRIDE_MINUS_1,
+        ],
+    )
+}
+
+/// Real-time bytes that set the spindle override to `percent`, the same way as the feed
+pub fn spindle_override_bytes(percent: u32) -> Vec<u8> {
+    stepped_override_bytes(
+        percent,
+        [
+            SPINDLE_OVERRIDE_RESET,
+            SPINDLE_OVERRIDE_PLUS_10,
+            SPINDLE_OVERRIDE_MINUS_10,
+            SPINDLE_OVERRIDE_PLUS_1,
+            SPINDLE_OVERRIDE_MINUS_1,
+        ],
+    )
+}
+
+/// The rapid override has three levels only
+pub fn rapid_override_byte(percent: u32) -> Result<u8> {
+    match percent {
+        100 => Ok(RAPID_OVERRIDE_RESET),
+        50 => Ok(RAPID_OVERRIDE_MEDIUM),
+        25 => Ok(RAPID_OVERRIDE_LOW),
+        _ => Err(anyhow!(
+            "Rapid override is 25, 50 or 100%, not {}%",
+            percent
+        )),
+    }
+}
+
+/// `codes` are reset, +10, -10, +1 and -1; the feed and spindle ranges are the same
+fn stepped_override_bytes(percent: u32, codes: [u8; 5]) -> Vec<u8> {
+    let [reset, plus_10, minus_10, plus_1, minus_1] = codes;
+    let percent = percent.clamp(MIN_FEED_PERCENT, MAX_FEED_PERCENT);
+    let mut bytes = vec![reset];
+    let (tens, ones) = if percent >= 100 {
+        (plus_10, plus_1)
+    } else {
+        (minus_10, minus_1)
+    };
+    let difference = percent.abs_diff(100);
+    bytes.extend(std::iter::repeat_n(tens, (difference / 10) as usize));
+    bytes.extend(std::iter::repeat_n(ones, (difference % 10) as usize));
+    bytes
+}
+
+/// Payload of the `cnc:feed-zone` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedZoneChange {
+    pub line: usize,
+    /// Zone entered, None when leaving zones
+    pub zone: Option<String>,
+    pub feed_percent: u32,
+}
+
+/// Switches the feed override as the job enters and leaves zones
+pub struct FeedZoneController {
+    spans: Vec<ZoneSpan>,
+    /// Override set by the current zone, None while outside every zone
+    active: Option<u32>,
+    /// The user's own override, put back when leaving a zone
+    user_feed: u32,
+}
+
+impl FeedZoneController {
+    pub fn new(spans: Vec<ZoneSpan>) -> Self {
+        Self {
+            spans,
+            active: None,
+            user_feed: 100,
+        }
+    }
+
+    /// Given the line being cut and the reported override, the override to switch to, if any.
+    /// Zones never speed the job up: a user override already below the zone's wins.
+    pub fn update(&mut self, line: usize, reported_feed: Option<u32>) -> Option<FeedZoneChange> {
+        if self.active.is_none() {
+            if let Some(feed) = reported_feed {
+                self.user_feed = feed;
+            }
+        }
+        let zone = self
+            .spans
+            .iter()
+            .filter(|s| (s.first..=s.last).contains(&line))
+            .min_by_key(|s| s.feed_percent);
+        let wanted = zone.map(|s| s.feed_percent.min(self.user_feed));
+        if wanted == self.active {
+            return None;
+        }
+        self.active = wanted;
+        Some(FeedZoneChange {
+            line,
+            zone: zone.map(|s| s.name.clone()),
+            feed_percent: wanted.unwrap_or(self.user_feed),
+        })
+    }
+
+    /// Override to put back if the job ended inside a zone
+    pub fn finish(&mut self) -> Option<u32> {
+        self.active.take().map(|_| self.user_feed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(name: &str, area: ZoneArea, feed_percent: u32) -> FeedZone {
+        FeedZone {
+            name: name.to_string(),
+            area,
+            feed_percent,
+        }
+    }
+
+    fn span(first: usize, last: usize, feed_percent: u32) -> ZoneSpan {
+        ZoneSpan {
+            name: format!("{}-{}", first, last),
+            first,
+            last,
+            feed_percent,
+        }
+    }
+
+    #[test]
+    fn regions_resolve_to_runs_of_cutting_moves() {
+        let program = "G0 X0 Y0\nG1 X5 F500\nX15\nX20\nG0 X6\nG1 X7\n";
+        let region = ZoneArea::Region {
+            x_min: 4.0,
+            y_min: -1.0,
+            x_max: 8.0,
+            y_max: 1.0,
+        };
+        let spans = resolve_zones(&[zone("clamp", region, 50)], program).unwrap();
+        let lines: Vec<_> = spans.iter().map(|s| (s.first, s.last)).collect();
+        assert_eq!(lines, [(2, 3), (6, 6)]);
+    }
+
+    #[test]
+    fn invalid_zones_are_refused() {
+        let lines = |first, last| ZoneArea::Lines { first, last };
+        assert!(resolve_zones(&[zone("a", lines(5, 4), 50)], "").is_err());
+        assert!(resolve_zones(&[zone("a", lines(0, 4), 50)], "").is_err());
+        assert!(resolve_zones(&[zone("a", lines(1, 4), 5)], "").is_err());
+        assert!(resolve_zones(&[zone("a", lines(1, 4), 110)], "").is_err());
+        assert_eq!(
+            resolve_zones(&[zone("a", lines(1, 4), 40)], "").unwrap()[0].last,
+            4
+        );
+    }
+
+    #[test]
+    fn override_bytes_step_from_a_reset() {
+        assert_eq!(feed_override_bytes(100), [FEED_OVERRIDE_RESET]);
+        assert_eq!(
+            feed_override_bytes(73),
+            [
+                FEED_OVERRIDE_RESET,
+                FEED_OVERRIDE_MINUS_10,
+                FEED_OVERRIDE_MINUS_10,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1,
+                FEED_OVERRIDE_MINUS_1
+            ]
+        );
+        assert_eq!(
+            spindle_override_bytes(111),
+            [
+                SPINDLE_OVERRIDE_RESET,
+                SPINDLE_OVERRIDE_PLUS_10,
+                SPINDLE_OVERRIDE_PLUS_1
+            ]
+        );
+        // Clamped to Grbl's 10-200%
+        assert_eq!(feed_override_bytes(500).len(), 11);
+        assert_eq!(rapid_override_byte(25).unwrap(), RAPID_OVERRIDE_LOW);
+        assert!(rapid_override_byte(75).is_err());
+    }
+
+    #[test]
+    fn controller_enters_and_leaves_zones() {
+        let mut controller = FeedZoneController::new(vec![span(10, 20, 50), span(15, 16, 30)]);
+        assert!(controller.update(5, Some(100)).is_none());
+        assert_eq!(controller.update(10, Some(100)).unwrap().feed_percent, 50);
+        assert!(controller.update(12, Some(50)).is_none());
+        // The slowest of overlapping zones wins
+        assert_eq!(controller.update(15, Some(50)).unwrap().feed_percent, 30);
+        let left = controller.update(21, Some(30)).unwrap();
+        assert_eq!((left.zone, left.feed_percent), (None, 100));
+        assert!(controller.finish().is_none());
+    }
+
+    #[test]
+    fn zones_never_speed_up_a_slower_user_override() {
+        let mut controller = FeedZoneController::new(vec![span(10, 20, 50)]);
+        controller.update(1, Some(40));
+        let entered = controller.update(10, Some(40)).unwrap();
+        assert_eq!(entered.feed_percent, 40);
+        assert_eq!(controller.finish(), Some(40));
+    }
+}
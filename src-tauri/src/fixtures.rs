@@ -0,0 +1,173 @@
+use crate::job_queue::WORK_COORDINATE_SYSTEMS;
+use crate::machine_profile::ClearanceHeights;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A reference corner fixed to the table, such as the end of a fence, with work zero at a
+/// known offset from it. Stock pushed against the fence sits in the same place every time,
+/// so probing the corner once sets work zero for the whole batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub name: String,
+    /// Machine X/Y of the reference's outside corner, close enough to start probing from
+    pub corner: [f32; 2],
+    /// Machine Z of the reference's top, roughly
+    pub top_z: f32,
+    /// Which way the reference extends from the corner along X and Y: 1 or -1.
+    /// `[1, 1]` is a front-left corner.
+    pub sides: [f32; 2],
+    /// How far from each face probing starts, and twice the most it travels
+    pub approach: f32,
+    /// How far below the top the faces are probed
+    pub face_depth: f32,
+    pub probe_diameter: f32,
+    pub feed_rate: f32,
+    /// Work zero relative to the probed corner (X face, Y face, top)
+    pub offset: [f32; 3],
+    /// Work coordinate system to set, e.g. "G54"
+    pub wcs: String,
+}
+
+impl Fixture {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Give the fixture a name"));
+        }
+        if self.sides.iter().any(|s| s.abs() != 1.0) {
+            return Err(anyhow!("Fixture sides must be 1 or -1"));
+        }
+        let positive = [self.approach, self.face_depth, self.feed_rate];
+        if positive.iter().any(|v| !v.is_finite() || *v <= 0.0) {
+            return Err(anyhow!(
+                "Approach, face depth and feed rate must be above zero"
+            ));
+        }
+        if !self.probe_diameter.is_finite() || self.probe_diameter < 0.0 {
+            return Err(anyhow!("Probe diameter can't be negative"));
+        }
+        if !WORK_COORDINATE_SYSTEMS.contains(&self.wcs.as_str()) {
+            return Err(anyhow!("{} isn't a work coordinate system", self.wcs));
+        }
+        Ok(())
+    }
+
+    /// `P` number of the WCS for G10
+    fn wcs_number(&self) -> usize {
+        WORK_COORDINATE_SYSTEMS
+            .iter()
+            .position(|w| *w == self.wcs)
+            .map_or(1, |i| i + 1)
+    }
+
+    /// Probe moves for the top, the X face and the Y face, each starting from and ending at
+    /// a safe height. The probe move is the only G38.2 in each.
+    pub fn probe_sequences(&self, clearance: &ClearanceHeights) -> [Vec<String>; 3] {
+        let [cx, cy] = self.corner;
+        let [sx, sy] = self.sides;
+        let (a, clear) = (self.approach, clearance.probe_clearance);
+        let above = self.top_z + clear;
+        let face_z = self.top_z - self.face_depth;
+        let raise = format!("G53 G0 Z{:.3}", clearance.safe_z);
+        let travel = |x: f32, y: f32| format!("G53 G0 X{:.3} Y{:.3}", x, y);
+        let down = |z: f32| format!("G53 G0 Z{:.3}", z);
+        let probe = |axis: char, distance: f32| {
+            format!("G91 G38.2 {}{:.3} F{:.0}", axis, distance, self.feed_rate)
+        };
+        let back_off = |axis: char, distance: f32| format!("G0 {}{:.3}", axis, distance);
+        [
+            vec![
+                raise.clone(),
+                travel(cx + sx * a, cy + sy * a),
+                down(above),
+                probe('Z', -(clear + a)),
+                back_off('Z', clear),
+                "G90".to_string(),
+                raise.clone(),
+            ],
+            vec![
+                travel(cx - sx * a, cy + sy * a),
+                down(face_z),
+                probe('X', sx * 2.0 * a),
+                back_off('X', -sx * clear),
+                "G90".to_string(),
+                raise.clone(),
+            ],
+            vec![
+                travel(cx + sx * a, cy - sy * a),
+                down(face_z),
+                probe('Y', sy * 2.0 * a),
+                back_off('Y', -sy * clear),
+                "G90".to_string(),
+                raise,
+            ],
+        ]
+    }
+
+    /// Machine position of the corner from the three probe positions; the tip touched
+    /// each face a radius short of it
+    pub fn corner_from(&self, top: &[f32], x_face: &[f32], y_face: &[f32]) -> Result<[f32; 3]> {
+        if top.len() < 3 || x_face.is_empty() || y_face.len() < 2 {
+            return Err(anyhow!("Probe reports were missing axes"));
+        }
+        let radius = self.probe_diameter / 2.0;
+        Ok([
+            x_face[0] + self.sides[0] * radius,
+            y_face[1] + self.sides[1] * radius,
+            top[2],
+        ])
+    }
+
+    /// Sets the WCS origin to machine position `zero`
+    pub fn set_zero_command(&self, zero: [f32; 3]) -> String {
+        format!(
+            "G10 L2 P{} X{:.3} Y{:.3} Z{:.3}",
+            self.wcs_number(),
+            zero[0],
+            zero[1],
+            zero[2]
+        )
+    }
+}
+
+/// Saved fixtures, by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fixtures {
+    pub fixtures: Vec<Fixture>,
+}
+
+impl Fixtures {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("fixtures.json")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Fixture> {
+        self.fixtures.iter().find(|f| f.name == name)
+    }
+
+    /// Add the fixture, replacing one with the same name
+    pub fn upsert(&mut self, fixture: Fixture) {
+        match self.fixtures.iter_mut().find(|f| f.name == fixture.name) {
+            Some(existing) => *existing = fixture,
+            None => self.fixtures.push(fixture),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.fixtures.len();
+        self.fixtures.retain(|f| f.name != name);
+        self.fixtures.len() != before
+    }
+}
+
+/// Result of zeroing from a fixture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureZero {
+    pub fixture: String,
+    /// Machine position of the probed corner
+    pub corner: [f32; 3],
+    /// Machine position now set as work zero
+    pub work_zero: [f32; 3],
+    pub wcs: String,
+}
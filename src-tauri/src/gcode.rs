@@ -0,0 +1,201 @@
+/// A single G-code word such as `G1`, `X12.5` or `S12000`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Word {
+    pub letter: char,
+    pub value: f64,
+}
+
+/// Remove `( ... )` and `; ...` comments from a line
+pub fn strip_comments(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut in_paren = false;
+
+    for c in line.chars() {
+        match c {
+            '(' => in_paren = true,
+            ')' if in_paren => in_paren = false,
+            ';' if !in_paren => break,
+            _ if !in_paren => result.push(c),
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Text of the `( ... )` and `; ...` comments on a line, joined with spaces
+pub fn comment_text(line: &str) -> String {
+    let mut comments: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+
+    for (i, c) in line.char_indices() {
+        match (c, current.as_mut()) {
+            ('(', None) => current = Some(String::new()),
+            (')', Some(_)) => comments.extend(current.take()),
+            (';', None) => {
+                comments.push(line[i + 1..].to_string());
+                break;
+            }
+            (_, Some(text)) => text.push(c),
+            _ => {}
+        }
+    }
+    // An unclosed paren runs to the end of the line
+    comments.extend(current);
+
+    comments
+        .iter()
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a line into G-code words. Comments, `$` system commands and
+/// malformed words are ignored.
+pub fn tokenize_line(line: &str) -> Vec<Word> {
+    let code = strip_comments(line);
+    let code = code.trim();
+    if code.starts_with('$') || code.starts_with('%') {
+        return Vec::new();
+    }
+
+    let mut words = Vec::new();
+    let mut chars = code.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_alphabetic() {
+            continue;
+        }
+
+        let mut number = String::new();
+        while let Some(&next) = chars.peek() {
+            if next.is_ascii_digit() || next == '.' || next == '-' || next == '+' {
+                number.push(next);
+                chars.next();
+            } else if next == ' ' || next == '\t' {
+                // Grbl ignores whitespace inside numbers
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if let Ok(value) = number.parse::<f64>() {
+            words.push(Word {
+                letter: c.to_ascii_uppercase(),
+                value,
+            });
+        }
+    }
+
+    words
+}
+
+/// Value of the first word with the given letter
+pub fn word_value(words: &[Word], letter: char) -> Option<f64> {
+    words.iter().find(|w| w.letter == letter).map(|w| w.value)
+}
+
+/// True if the line contains the given G or M code, e.g. `has_code(&words, 'M', 3.0)`
+pub fn has_code(words: &[Word], letter: char, code: f64) -> bool {
+    words
+        .iter()
+        .any(|w| w.letter == letter && (w.value - code).abs() < 1e-6)
+}
+
+/// Render words as a G-code line, e.g. `G1 X10.5 Y-2`, with at most 4 decimals
+pub fn format_words(words: &[Word]) -> String {
+    words
+        .iter()
+        .map(|w| format!("{}{}", w.letter, format_value(w.value, 4)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A word value rounded to `decimals` places without trailing zeros, e.g. `10.5`, `-2`
+pub fn format_value(value: f64, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn words_strategy() -> impl Strategy<Value = Vec<(char, f64)>> {
+        prop::collection::vec(
+            (
+                prop::sample::select(vec!['G', 'M', 'X', 'Y', 'Z', 'F', 'S', 'I', 'J', 'P']),
+                -10000.0f64..10000.0,
+            ),
+            0..10,
+        )
+    }
+
+    fn render(words: &[(char, f64)]) -> String {
+        words
+            .iter()
+            .map(|(letter, value)| format!("{}{:.3}", letter, value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    proptest! {
+        #[test]
+        fn tokenizer_never_panics(line in "\\PC*") {
+            let _ = tokenize_line(&line);
+            let _ = strip_comments(&line);
+            let _ = comment_text(&line);
+        }
+
+        #[test]
+        fn tokenizer_round_trips(words in words_strategy()) {
+            let parsed = tokenize_line(&render(&words));
+            prop_assert_eq!(parsed.len(), words.len());
+            for (word, (letter, value)) in parsed.iter().zip(&words) {
+                prop_assert_eq!(word.letter, *letter);
+                prop_assert!((word.value - value).abs() < 0.001);
+            }
+        }
+
+        #[test]
+        fn formatted_words_round_trip(words in words_strategy()) {
+            let words: Vec<Word> = words
+                .into_iter()
+                .map(|(letter, value)| Word { letter, value })
+                .collect();
+            let parsed = tokenize_line(&format_words(&words));
+            prop_assert_eq!(parsed.len(), words.len());
+            for (parsed, word) in parsed.iter().zip(&words) {
+                prop_assert_eq!(parsed.letter, word.letter);
+                prop_assert!((parsed.value - word.value).abs() < 0.0001);
+            }
+        }
+
+        #[test]
+        fn comments_do_not_change_words(
+            words in words_strategy(),
+            comment in prop::collection::vec(
+                prop::sample::select(vec!['a', 'X', '1', ' ', ';', 'G', '.', '-']),
+                0..20,
+            ),
+        ) {
+            let comment: String = comment.into_iter().collect();
+            let line = render(&words);
+            let expected = tokenize_line(&line);
+            prop_assert_eq!(tokenize_line(&format!("({}){}", comment, line)), expected.clone());
+            prop_assert_eq!(tokenize_line(&format!("{} ; {}", line, comment)), expected);
+        }
+    }
+}
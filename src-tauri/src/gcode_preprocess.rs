@@ -0,0 +1,1090 @@
+use crate::gcode::{
+    comment_text, format_value, format_words, has_code, strip_comments, tokenize_line, word_value,
+    Word,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Options for rewriting a program before it is sent to the controller
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreprocessOptions {
+    /// Rewrite G81/G82/G83 drilling cycles, which Grbl rejects, as plain G0/G1/G4 moves
+    #[serde(default)]
+    pub expand_canned_cycles: bool,
+    /// Seconds to dwell after the spindle is started or its speed changed with M3/M4.
+    /// For spindles that take a few seconds to reach speed when the CAM post omits dwells.
+    pub spindle_dwell_seconds: Option<f32>,
+    /// Software backlash compensation for controllers without it. Off unless set.
+    pub backlash: Option<BacklashCompensation>,
+    /// Shrink the program for slow links. Runs last, after every other pass.
+    pub minify: Option<MinifyOptions>,
+}
+
+impl PreprocessOptions {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(decimals) = self.minify.as_ref().and_then(|m| m.decimals) {
+            if decimals < MIN_MINIFY_DECIMALS {
+                return Err(anyhow!(
+                    "Rounding to fewer than {} decimals would move the tool",
+                    MIN_MINIFY_DECIMALS
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the minify pass may remove
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinifyOptions {
+    pub strip_comments: bool,
+    /// Round coordinates, feeds and speeds to this many decimals; None keeps them
+    pub decimals: Option<usize>,
+    /// Drop G, F and S words that repeat the modal state, and unchanged coordinates
+    pub drop_redundant_modals: bool,
+}
+
+/// Bytes saved by each step of the minify pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MinifyStats {
+    pub lines_before: usize,
+    pub lines_after: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    pub comment_bytes_saved: usize,
+    /// Rounding plus dropping spaces between words
+    pub precision_bytes_saved: usize,
+    pub modal_bytes_saved: usize,
+}
+
+/// Measured lost motion for software backlash compensation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BacklashCompensation {
+    /// mm of backlash on X, Y and Z; 0 leaves an axis alone
+    pub axes: [f32; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreprocessResult {
+    pub program: String,
+    /// Holes drilled by expanded canned cycles
+    pub cycles_expanded: usize,
+    pub dwells_inserted: usize,
+    pub backlash_moves_inserted: usize,
+    /// Caveats about the rewritten program the user should see before running it
+    pub warnings: Vec<String>,
+    pub minify: Option<MinifyStats>,
+}
+
+/// Apply all enabled preprocessing passes to a program
+pub fn preprocess(program: &str, options: &PreprocessOptions) -> PreprocessResult {
+    let mut result = PreprocessResult {
+        program: program.to_string(),
+        cycles_expanded: 0,
+        dwells_inserted: 0,
+        backlash_moves_inserted: 0,
+        warnings: Vec::new(),
+        minify: None,
+    };
+
+    // First, so the passes below see the moves the cycles expand to
+    if options.expand_canned_cycles {
+        let (program, expanded, warnings) = expand_canned_cycles(&result.program);
+        result.program = program;
+        result.cycles_expanded = expanded;
+        result.warnings.extend(warnings);
+    }
+
+    if let Some(seconds) = options.spindle_dwell_seconds.filter(|s| *s > 0.0) {
+        let (program, inserted) = insert_spindle_dwells(&result.program, seconds);
+        result.program = program;
+        result.dwells_inserted = inserted;
+    }
+
+    if let Some(backlash) = options
+        .backlash
+        .as_ref()
+        .filter(|b| b.axes.iter().any(|a| *a > 0.0))
+    {
+        let (program, inserted, warnings) = insert_backlash_moves(&result.program, backlash);
+        result.program = program;
+        result.backlash_moves_inserted = inserted;
+        result.warnings.extend(warnings);
+    }
+
+    if let Some(minify_options) = &options.minify {
+        let (program, stats) = minify(&result.program, minify_options);
+        result.program = program;
+        result.minify = Some(stats);
+    }
+
+    result
+}
+
+/// Insert `G4 P<seconds>` after every M3/M4 that starts the spindle or changes its speed.
+/// A move on the same line would run before the dwell, so the spindle words go on a line
+/// of their own, then the dwell, then the rest of the line.
+pub fn insert_spindle_dwells(program: &str, seconds: f32) -> (String, usize) {
+    let mut output = String::with_capacity(program.len());
+    let mut inserted = 0;
+
+    // Speed and direction the spindle is currently spinning at, None when stopped
+    let mut running: Option<(f64, bool)> = None;
+    let mut programmed_speed = 0.0;
+
+    for line in program.lines() {
+        let words = tokenize_line(line);
+        if let Some(speed) = word_value(&words, 'S') {
+            programmed_speed = speed;
+        }
+
+        // Program ends stop the spindle too
+        if [5.0, 2.0, 30.0].iter().any(|m| has_code(&words, 'M', *m)) {
+            running = None;
+        }
+        let clockwise = has_code(&words, 'M', 3.0);
+        let mut starts = false;
+        if clockwise || has_code(&words, 'M', 4.0) {
+            let spin = (programmed_speed, clockwise);
+            starts = running != Some(spin) && programmed_speed > 0.0;
+            running = Some(spin);
+        }
+        if !starts {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let (spindle, rest): (Vec<Word>, Vec<Word>) = words
+            .iter()
+            .partition(|w| w.letter == 'S' || (w.letter == 'M' && [3.0, 4.0].contains(&w.value)));
+        if rest.is_empty() {
+            output.push_str(line);
+        } else {
+            output.push_str(&format_words(&spindle));
+        }
+        output.push_str(&format!("\nG4 P{}\n", seconds));
+        inserted += 1;
+        if !rest.is_empty() {
+            output.push_str(&format_words(&rest));
+            let comment = comment_text(line);
+            if !comment.is_empty() {
+                output.push_str(&format!(" ({})", comment));
+            }
+            output.push('\n');
+        }
+    }
+
+    (output, inserted)
+}
+
+/// G codes whose moves or coordinates the backlash pass can't follow: machine coordinate
+/// moves, homing returns, offset changes and probing
+const BACKLASH_RESETS: [f64; 9] = [10.0, 28.0, 30.0, 53.0, 92.0, 38.2, 38.3, 38.4, 38.5];
+
+/// Insert a short move on every axis that reverses direction, taking up the backlash before
+/// the reversing line runs, and shift later absolute coordinates by the slack taken up.
+/// This is approximate: the backlash is assumed constant and already taken up in the
+/// direction of each axis's first move, arcs are judged by their end points only, and the
+/// DRO shows work positions offset by up to the backlash.
+pub fn insert_backlash_moves(
+    program: &str,
+    backlash: &BacklashCompensation,
+) -> (String, usize, Vec<String>) {
+    const AXES: [char; 3] = ['X', 'Y', 'Z'];
+    let mut output = String::with_capacity(program.len());
+    let mut inserted = 0;
+    let mut warnings =
+        vec!["Backlash compensation is approximate; check the first part carefully".to_string()];
+
+    // Programmed position in program units
+    let mut position = [0.0f64; 3];
+    // Direction each axis last moved in (-1, 0 unknown, 1)
+    let mut direction = [0i8; 3];
+    // Slack taken up so far, added to absolute coordinates, in program units
+    let mut offset = [0.0f64; 3];
+    let mut absolute = true;
+    let mut mm_per_unit = 1.0;
+    let mut motion: Option<u32> = None;
+    let mut warned_arcs = false;
+
+    for (index, line) in program.lines().enumerate() {
+        let words = tokenize_line(line);
+        let mut line_motion = None;
+        for word in words.iter().filter(|w| w.letter == 'G') {
+            match (word.value * 10.0).round() as u32 {
+                code @ (0 | 10 | 20 | 30) => line_motion = Some(code),
+                800 => motion = None,
+                200 => mm_per_unit = 25.4,
+                210 => mm_per_unit = 1.0,
+                900 => absolute = true,
+                910 => absolute = false,
+                _ => {}
+            }
+        }
+        if line_motion.is_some() {
+            motion = line_motion;
+        }
+
+        if BACKLASH_RESETS
+            .iter()
+            .any(|code| has_code(&words, 'G', *code))
+        {
+            if direction.iter().any(|d| *d != 0) {
+                warnings.push(format!(
+                    "Line {}: backlash tracking restarts after this line",
+                    index + 1
+                ));
+            }
+            direction = [0; 3];
+            offset = [0.0; 3];
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let Some(mode) = motion.filter(|_| AXES.iter().any(|a| word_value(&words, *a).is_some()))
+        else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+        if matches!(mode, 20 | 30) && !warned_arcs {
+            warnings.push("Reversals in the middle of arcs are not compensated".to_string());
+            warned_arcs = true;
+        }
+
+        let mut target = position;
+        for (axis, letter) in AXES.iter().enumerate() {
+            if let Some(value) = word_value(&words, *letter) {
+                target[axis] = if absolute {
+                    value
+                } else {
+                    position[axis] + value
+                };
+            }
+        }
+
+        let mut correction = [0.0f64; 3];
+        for axis in 0..3 {
+            let delta = target[axis] - position[axis];
+            if delta.abs() < 1e-9 {
+                continue;
+            }
+            let moving = if delta > 0.0 { 1 } else { -1 };
+            if direction[axis] != 0 && direction[axis] != moving && backlash.axes[axis] > 0.0 {
+                correction[axis] = moving as f64 * backlash.axes[axis] as f64 / mm_per_unit;
+                offset[axis] += correction[axis];
+            }
+            direction[axis] = moving;
+        }
+
+        let corrected = correction.iter().any(|c| *c != 0.0);
+        if corrected {
+            // Take up the slack on the reversing axes alone, rapid for rapids and at the
+            // modal feed otherwise
+            let mut take_up = vec![Word {
+                letter: 'G',
+                value: if mode == 0 { 0.0 } else { 1.0 },
+            }];
+            for (axis, letter) in AXES.iter().enumerate() {
+                if correction[axis] != 0.0 {
+                    take_up.push(Word {
+                        letter: *letter,
+                        value: if absolute {
+                            position[axis] + offset[axis]
+                        } else {
+                            correction[axis]
+                        },
+                    });
+                }
+            }
+            output.push_str(&format_words(&take_up));
+            output.push('\n');
+            inserted += 1;
+        }
+
+        let shifted = absolute
+            && words.iter().any(|w| {
+                AXES.iter()
+                    .position(|a| *a == w.letter)
+                    .is_some_and(|axis| offset[axis] != 0.0)
+            });
+        if shifted || (corrected && line_motion.is_none()) {
+            let mut rewritten: Vec<Word> = words
+                .iter()
+                .map(|w| match AXES.iter().position(|a| *a == w.letter) {
+                    Some(axis) if absolute => Word {
+                        letter: w.letter,
+                        value: w.value + offset[axis],
+                    },
+                    _ => *w,
+                })
+                .collect();
+            // The take-up move changed the motion mode, so the line has to restate it
+            if corrected && line_motion.is_none() {
+                rewritten.insert(
+                    0,
+                    Word {
+                        letter: 'G',
+                        value: mode as f64 / 10.0,
+                    },
+                );
+            }
+            output.push_str(&format_words(&rewritten));
+            let comment = comment_text(line);
+            if !comment.is_empty() {
+                output.push_str(&format!(" ({})", comment));
+            }
+        } else {
+            output.push_str(line);
+        }
+        output.push('\n');
+        position = target;
+    }
+
+    (output, inserted, warnings)
+}
+
+/// Gap left above the previous peck when rapiding back down in G83, in mm
+const PECK_CLEARANCE_MM: f64 = 0.254;
+
+/// Drilling cycle parameters, which stay modal until G80 or another motion mode
+#[derive(Debug, Clone, Copy)]
+struct DrillCycle {
+    /// 810, 820 or 830
+    code: u32,
+    /// Bottom of the hole (absolute)
+    z: f64,
+    /// Retract plane (absolute)
+    r: f64,
+    /// Peck depth for G83
+    q: f64,
+    /// Dwell at the bottom for G82, in seconds
+    p: f64,
+}
+
+/// Expand G81 (drill), G82 (drill with dwell) and G83 (peck drill) into G0/G1/G4 moves,
+/// following LinuxCNC's semantics: G98 retracts to the Z the cycle started from and G99
+/// to the R plane, a line with only X/Y repeats the cycle there, and in G91 mode R is
+/// relative to the starting Z, Z to R, and L repeats the hole that many times.
+pub fn expand_canned_cycles(program: &str) -> (String, usize, Vec<String>) {
+    const CYCLE_WORDS: [char; 8] = ['X', 'Y', 'Z', 'R', 'Q', 'P', 'L', 'F'];
+    let mut output = String::with_capacity(program.len());
+    let mut holes = 0;
+    let mut warnings = Vec::new();
+
+    let mut position = [0.0f64; 3];
+    let mut z_known = false;
+    let mut absolute = true;
+    let mut mm_per_unit = 1.0;
+    let mut retract_to_r = false;
+    let mut cycle: Option<DrillCycle> = None;
+
+    for (index, line) in program.lines().enumerate() {
+        let words = tokenize_line(line);
+        let mut cycle_code = None;
+        let mut other_motion = false;
+        for word in words.iter().filter(|w| w.letter == 'G') {
+            match (word.value * 10.0).round() as u32 {
+                code @ (810 | 820 | 830) => cycle_code = Some(code),
+                800 | 0 | 10 | 20 | 30 | 382 | 383 | 384 | 385 => other_motion = true,
+                980 => retract_to_r = false,
+                990 => retract_to_r = true,
+                200 => mm_per_unit = 25.4,
+                210 => mm_per_unit = 1.0,
+                900 => absolute = true,
+                910 => absolute = false,
+                _ => {}
+            }
+        }
+        if other_motion {
+            cycle = None;
+        }
+        if words
+            .iter()
+            .any(|w| w.letter == 'G' && matches!((w.value * 10.0).round() as u32, 280 | 300 | 530))
+        {
+            // Homing returns and machine coordinate moves leave the work position unknown
+            z_known = false;
+        }
+
+        let has_axes = ['X', 'Y', 'Z']
+            .iter()
+            .any(|a| word_value(&words, *a).is_some());
+        let repeat = cycle.is_some() && cycle_code.is_none() && !other_motion && has_axes;
+        if cycle_code.is_none() && !repeat {
+            // Track where plain moves leave the tool
+            if has_axes {
+                for (axis, letter) in ['X', 'Y', 'Z'].iter().enumerate() {
+                    if let Some(value) = word_value(&words, *letter) {
+                        position[axis] = if absolute {
+                            value
+                        } else {
+                            position[axis] + value
+                        };
+                        z_known |= axis == 2;
+                    }
+                }
+            }
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        // Start Z: where the tool is, or the R plane if that isn't known
+        let start_z = position[2];
+        let previous = cycle;
+        let r = match word_value(&words, 'R') {
+            Some(r) if absolute => r,
+            Some(r) => start_z + r,
+            None => match previous {
+                Some(c) => c.r,
+                None => {
+                    warnings.push(format!("Line {}: drilling cycle without R", index + 1));
+                    output.push_str(line);
+                    output.push('\n');
+                    continue;
+                }
+            },
+        };
+        let z = match word_value(&words, 'Z') {
+            Some(z) if absolute => z,
+            Some(z) => r + z,
+            None => match previous {
+                Some(c) => c.z,
+                None => {
+                    warnings.push(format!("Line {}: drilling cycle without Z", index + 1));
+                    output.push_str(line);
+                    output.push('\n');
+                    continue;
+                }
+            },
+        };
+        let drill = DrillCycle {
+            code: cycle_code.or(previous.map(|c| c.code)).unwrap_or(810),
+            z,
+            r,
+            q: word_value(&words, 'Q')
+                .map(f64::abs)
+                .or(previous.map(|c| c.q))
+                .unwrap_or(0.0),
+            p: word_value(&words, 'P')
+                .or(previous.map(|c| c.p))
+                .unwrap_or(0.0),
+        };
+        if drill.code == 830 && drill.q <= 0.0 {
+            warnings.push(format!(
+                "Line {}: G83 needs a positive Q peck depth",
+                index + 1
+            ));
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+        cycle = Some(drill);
+        if !z_known {
+            warnings.push(format!(
+                "Line {}: Z before the drilling cycle is unknown; starting from R",
+                index + 1
+            ));
+        }
+        let initial_z = if z_known { start_z } else { drill.r };
+
+        let comment = comment_text(line);
+        if !comment.is_empty() {
+            output.push_str(&format!("({})\n", comment));
+        }
+        // Keep anything else on the line, e.g. M8 or S words, ahead of the moves
+        let others: Vec<Word> = words
+            .iter()
+            .filter(|w| !CYCLE_WORDS.contains(&w.letter))
+            .filter(|w| {
+                w.letter != 'G'
+                    || !matches!(
+                        (w.value * 10.0).round() as u32,
+                        810 | 820 | 830 | 900 | 910 | 980 | 990
+                    )
+            })
+            .copied()
+            .collect();
+        if !others.is_empty() {
+            output.push_str(&format_words(&others));
+            output.push('\n');
+        }
+
+        let mut moves: Vec<Vec<Word>> = Vec::new();
+        let mut push = |words: &[(char, f64)]| {
+            moves.push(
+                words
+                    .iter()
+                    .map(|(letter, value)| Word {
+                        letter: *letter,
+                        value: *value,
+                    })
+                    .collect(),
+            )
+        };
+        if let Some(feed) = word_value(&words, 'F') {
+            push(&[('F', feed)]);
+        }
+
+        let repeats = if absolute {
+            1
+        } else {
+            word_value(&words, 'L').map_or(1, |l| l.max(1.0) as usize)
+        };
+        let mut z_now = initial_z;
+        let clear_z = if retract_to_r {
+            drill.r
+        } else {
+            initial_z.max(drill.r)
+        };
+        for _ in 0..repeats {
+            for (axis, letter) in ['X', 'Y'].iter().enumerate() {
+                if let Some(value) = word_value(&words, *letter) {
+                    position[axis] = if absolute {
+                        value
+                    } else {
+                        position[axis] + value
+                    };
+                }
+            }
+            // Never cross the work below R
+            if z_now < drill.r {
+                push(&[('G', 0.0), ('Z', drill.r)]);
+            }
+            push(&[('G', 0.0), ('X', position[0]), ('Y', position[1])]);
+            push(&[('G', 0.0), ('Z', drill.r)]);
+
+            if drill.code == 830 {
+                let clearance = PECK_CLEARANCE_MM / mm_per_unit;
+                let mut depth = drill.r;
+                while depth > drill.z {
+                    let next = (depth - drill.q).max(drill.z);
+                    if depth < drill.r {
+                        push(&[('G', 0.0), ('Z', depth + clearance)]);
+                    }
+                    push(&[('G', 1.0), ('Z', next)]);
+                    push(&[('G', 0.0), ('Z', drill.r)]);
+                    depth = next;
+                }
+            } else {
+                push(&[('G', 1.0), ('Z', drill.z)]);
+                if drill.code == 820 && drill.p > 0.0 {
+                    push(&[('G', 4.0), ('P', drill.p)]);
+                }
+            }
+            push(&[('G', 0.0), ('Z', clear_z)]);
+            z_now = clear_z;
+            holes += 1;
+        }
+
+        if !absolute {
+            output.push_str("G90\n");
+        }
+        for words in &moves {
+            output.push_str(&format_words(words));
+            output.push('\n');
+        }
+        if !absolute {
+            output.push_str("G91\n");
+        }
+        position[2] = clear_z;
+        z_known = true;
+    }
+
+    (output, holes, warnings)
+}
+
+/// Modal G code groups whose repeats can be dropped: plane, units, distance mode,
+/// feed rate mode and work coordinate system
+const DROPPABLE_GROUPS: [&[u32]; 5] = [
+    &[170, 180, 190],
+    &[200, 210],
+    &[900, 910],
+    &[930, 940],
+    &[540, 550, 560, 570, 580, 590],
+];
+
+/// Motion group codes, times ten: G0-G3 and the G38.2-G38.5 probes. G80 cancels the mode.
+const MOTION_CODES: [u32; 8] = [0, 10, 20, 30, 382, 383, 384, 385];
+
+/// G codes after which coordinates mean something other than a plain move target
+const NON_MODAL_CODES: [u32; 11] = [40, 100, 280, 300, 431, 530, 920, 382, 383, 384, 385];
+
+/// Fewest decimals minify rounds to: 0.001 mm is about Grbl's own resolution
+const MIN_MINIFY_DECIMALS: usize = 3;
+
+/// Strip comments, round values and drop redundant words, counting what each step saves.
+/// Minified lines have no spaces between words, which Grbl accepts.
+pub fn minify(program: &str, options: &MinifyOptions) -> (String, MinifyStats) {
+    let mut stats = MinifyStats::default();
+    let mut output = String::with_capacity(program.len());
+
+    let mut motion: Option<u32> = None;
+    let mut groups: [Option<u32>; 5] = [None; 5];
+    let mut feed: Option<String> = None;
+    let mut speed: Option<String> = None;
+    let mut position: [Option<String>; 3] = [None, None, None];
+    let mut absolute = true;
+
+    for line in program.lines() {
+        stats.lines_before += 1;
+        stats.bytes_before += line.len() + 1;
+
+        let stripped = if options.strip_comments {
+            strip_comments(line).trim().to_string()
+        } else {
+            line.to_string()
+        };
+        stats.comment_bytes_saved += line.len() - stripped.len();
+        if stripped.is_empty() && options.strip_comments {
+            // Comment-only and blank lines go entirely, newline included
+            stats.comment_bytes_saved += 1;
+            continue;
+        }
+
+        let words = tokenize_line(&stripped);
+        // System commands, `%` and lines with comments kept can't be re-rendered
+        let rerender = !words.is_empty()
+            && (options.strip_comments || comment_text(&stripped).is_empty())
+            && (options.decimals.is_some() || options.drop_redundant_modals);
+        if !rerender {
+            output.push_str(&stripped);
+            output.push('\n');
+            stats.lines_after += 1;
+            stats.bytes_after += stripped.len() + 1;
+            continue;
+        }
+
+        let render = |words: &[(char, String)]| -> String {
+            words.iter().map(|(l, v)| format!("{}{}", l, v)).collect()
+        };
+        // Dwells, arc centres and radii, line numbers and L counts keep their precision
+        let rendered: Vec<(char, String)> = words
+            .iter()
+            .map(|w| {
+                let places = match w.letter {
+                    'G' | 'M' => Some(1),
+                    'X' | 'Y' | 'Z' | 'A' | 'B' | 'C' | 'F' | 'S' => options.decimals,
+                    _ => None,
+                };
+                let value = match places {
+                    Some(places) => format_value(w.value, places),
+                    None => w.value.to_string(),
+                };
+                (w.letter, value)
+            })
+            .collect();
+        let compact = render(&rendered);
+        stats.precision_bytes_saved += stripped.len().saturating_sub(compact.len());
+
+        let mut kept = rendered.clone();
+        if options.drop_redundant_modals {
+            let codes: Vec<u32> = words
+                .iter()
+                .filter(|w| w.letter == 'G')
+                .map(|w| (w.value * 10.0).round() as u32)
+                .collect();
+            let special = codes.iter().any(|c| NON_MODAL_CODES.contains(c));
+            let line_motion = codes.iter().copied().find(|c| MOTION_CODES.contains(c));
+            if codes.contains(&800) {
+                motion = None;
+            }
+            let arc = matches!(line_motion.or(motion), Some(20 | 30));
+            // Inverse time needs F on every move
+            let inverse_time =
+                (groups[3] == Some(930) || codes.contains(&930)) && !codes.contains(&940);
+            // The same number is somewhere else in another WCS or unit. Grbl keeps the feed
+            // in mm/min and wants it again on leaving G93, so F isn't redundant after a unit
+            // or feed mode change either.
+            let changes = |group: usize| {
+                codes
+                    .iter()
+                    .any(|c| DROPPABLE_GROUPS[group].contains(c) && groups[group] != Some(*c))
+            };
+            if changes(1) || changes(4) {
+                position = [None, None, None];
+            }
+            if changes(1) || changes(3) {
+                feed = None;
+            }
+
+            kept = rendered
+                .iter()
+                .zip(&words)
+                .filter(|((letter, value), word)| match letter {
+                    'G' => {
+                        let code = (word.value * 10.0).round() as u32;
+                        if MOTION_CODES.contains(&code) {
+                            return motion != Some(code) || special;
+                        }
+                        match DROPPABLE_GROUPS.iter().position(|g| g.contains(&code)) {
+                            Some(group) => groups[group] != Some(code),
+                            None => true,
+                        }
+                    }
+                    'F' => inverse_time || feed.as_deref() != Some(value.as_str()),
+                    'S' => speed.as_deref() != Some(value.as_str()),
+                    'X' | 'Y' | 'Z' if absolute && !special && !arc => {
+                        let axis = ['X', 'Y', 'Z']
+                            .iter()
+                            .position(|a| a == letter)
+                            .unwrap_or(0);
+                        position[axis].as_deref() != Some(value.as_str())
+                    }
+                    _ => true,
+                })
+                .map(|(word, _)| word.clone())
+                .collect();
+
+            // Update the modal state from the full line
+            for code in &codes {
+                if let Some(group) = DROPPABLE_GROUPS.iter().position(|g| g.contains(code)) {
+                    groups[group] = Some(*code);
+                }
+                match code {
+                    900 => absolute = true,
+                    910 => absolute = false,
+                    _ => {}
+                }
+            }
+            if let Some(code) = line_motion {
+                motion = Some(code);
+            }
+            for (letter, value) in &rendered {
+                match letter {
+                    'F' => feed = Some(value.clone()),
+                    'S' => speed = Some(value.clone()),
+                    'X' | 'Y' | 'Z' => {
+                        let axis = ['X', 'Y', 'Z']
+                            .iter()
+                            .position(|a| a == letter)
+                            .unwrap_or(0);
+                        position[axis] = if absolute && !special {
+                            Some(value.clone())
+                        } else {
+                            // Relative and machine coordinate moves leave the work position unknown here
+                            None
+                        };
+                    }
+                    _ => {}
+                }
+            }
+            if special || !absolute {
+                position = [None, None, None];
+            }
+        }
+
+        let minified = render(&kept);
+        stats.modal_bytes_saved += compact.len() - minified.len();
+        if minified.is_empty() {
+            stats.modal_bytes_saved += 1;
+            continue;
+        }
+        output.push_str(&minified);
+        output.push('\n');
+        stats.lines_after += 1;
+        stats.bytes_after += minified.len() + 1;
+    }
+
+    (output, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minified(program: &str) -> Vec<String> {
+        let options = MinifyOptions {
+            strip_comments: true,
+            decimals: None,
+            drop_redundant_modals: true,
+        };
+        minify(program, &options)
+            .0
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn minify_drops_repeated_modals() {
+        assert_eq!(
+            minified("G21 G90 (setup)\nG1 X1 Y2 F300\nG1 X3 Y2 F300\nG21 X4\n"),
+            ["G21G90", "G1X1Y2F300", "X3", "X4"]
+        );
+    }
+
+    #[test]
+    fn minify_keeps_motion_word_after_probe() {
+        assert_eq!(
+            minified("G1 X0 F100\nG38.2 Z-10 F50\nG1 X5\n"),
+            ["G1X0F100", "G38.2Z-10F50", "G1X5"]
+        );
+    }
+
+    #[test]
+    fn minify_keeps_motion_word_after_g80() {
+        assert_eq!(minified("G0 X0\nG80\nG0 X5\n"), ["G0X0", "G80", "G0X5"]);
+    }
+
+    #[test]
+    fn minify_keeps_feed_in_inverse_time() {
+        assert_eq!(
+            minified("G93 G1 X1 F2\nX2 F2\nG94 G1 X3 F2\nX4 F2\n"),
+            ["G93G1X1F2", "X2F2", "G94X3F2", "X4"]
+        );
+    }
+
+    #[test]
+    fn minify_keeps_coordinates_after_wcs_change() {
+        assert_eq!(
+            minified("G54 G0 X10 Y10\nG55 X10 Y10\nX10 Y10\n"),
+            ["G54G0X10Y10", "G55X10Y10"]
+        );
+    }
+
+    #[test]
+    fn minify_keeps_coordinates_and_feed_after_unit_change() {
+        assert_eq!(
+            minified("G21 G1 X1 F10\nG20 X1 F10\n"),
+            ["G21G1X1F10", "G20X1F10"]
+        );
+    }
+
+    #[test]
+    fn minify_keeps_g43_1_offsets() {
+        assert_eq!(
+            minified("G0 Z5\nG43.1 Z5\nG0 Z5\n"),
+            ["G0Z5", "G43.1Z5", "Z5"]
+        );
+    }
+
+    #[test]
+    fn minify_rounds_only_coordinates_feeds_and_speeds() {
+        let options = MinifyOptions {
+            strip_comments: true,
+            decimals: Some(3),
+            drop_redundant_modals: false,
+        };
+        let (program, _) = minify(
+            "G2 X1.23456 Y2 I0.12345 J-0.54321 F100.0004\nG4 P0.12345\n",
+            &options,
+        );
+        assert_eq!(
+            program.lines().collect::<Vec<_>>(),
+            ["G2X1.235Y2I0.12345J-0.54321F100", "G4P0.12345"]
+        );
+    }
+
+    #[test]
+    fn minify_without_decimals_keeps_precision() {
+        assert_eq!(minified("G1 X1.1234567 F100\n"), ["G1X1.1234567F100"]);
+    }
+
+    #[test]
+    fn minify_refuses_to_round_below_three_decimals() {
+        let mut options = PreprocessOptions {
+            minify: Some(MinifyOptions {
+                strip_comments: false,
+                decimals: Some(2),
+                drop_redundant_modals: false,
+            }),
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+        options.minify.as_mut().unwrap().decimals = Some(3);
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn spindle_dwell_goes_before_a_move_on_the_same_line() {
+        let (program, inserted) = insert_spindle_dwells("G0 X1 M3 S12000 (start)\nG1 X2\n", 2.0);
+        assert_eq!(inserted, 1);
+        assert_eq!(
+            program.lines().collect::<Vec<_>>(),
+            ["M3 S12000", "G4 P2", "G0 X1 (start)", "G1 X2"]
+        );
+    }
+
+    #[test]
+    fn spindle_dwell_follows_a_spindle_only_line() {
+        let (program, inserted) = insert_spindle_dwells("S8000 M3\nS8000 M3\nS9000 M3\n", 1.5);
+        assert_eq!(inserted, 2);
+        assert_eq!(
+            program.lines().collect::<Vec<_>>(),
+            ["S8000 M3", "G4 P1.5", "S8000 M3", "S9000 M3", "G4 P1.5"]
+        );
+    }
+
+    #[test]
+    fn program_end_stops_the_spindle_for_dwells() {
+        for end in ["M2", "M30", "M5"] {
+            let program = format!("M3 S8000\n{}\nM3 S8000\n", end);
+            let (_, inserted) = insert_spindle_dwells(&program, 1.0);
+            assert_eq!(inserted, 2, "after {}", end);
+        }
+    }
+
+    fn expanded(program: &str) -> Vec<String> {
+        let (output, _, warnings) = expand_canned_cycles(program);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn drill_cycle_repeats_at_each_xy() {
+        assert_eq!(
+            expanded("G90 G0 X0 Y0 Z5\nG81 X1 Y1 Z-2 R1 F100\nX2\nG80\n"),
+            [
+                "G90 G0 X0 Y0 Z5",
+                "F100",
+                "G0 X1 Y1",
+                "G0 Z1",
+                "G1 Z-2",
+                "G0 Z5",
+                "G0 X2 Y1",
+                "G0 Z1",
+                "G1 Z-2",
+                "G0 Z5",
+                "G80"
+            ]
+        );
+    }
+
+    #[test]
+    fn dwell_cycle_retracts_to_r_in_g99() {
+        assert_eq!(
+            expanded("G90 G0 X0 Y0 Z5\nG99 G82 X1 Y1 Z-2 R1 P0.5 F100\nG80\n"),
+            [
+                "G90 G0 X0 Y0 Z5",
+                "F100",
+                "G0 X1 Y1",
+                "G0 Z1",
+                "G1 Z-2",
+                "G4 P0.5",
+                "G0 Z1",
+                "G80"
+            ]
+        );
+    }
+
+    #[test]
+    fn peck_cycle_clears_chips_between_pecks() {
+        assert_eq!(
+            expanded("G90 G0 X0 Y0 Z5\nG98 G83 X1 Y1 Z-3 R1 Q1.2 F100\nG80\n"),
+            [
+                "G90 G0 X0 Y0 Z5",
+                "F100",
+                "G0 X1 Y1",
+                "G0 Z1",
+                "G1 Z-0.2",
+                "G0 Z1",
+                "G0 Z0.054",
+                "G1 Z-1.4",
+                "G0 Z1",
+                "G0 Z-1.146",
+                "G1 Z-2.6",
+                "G0 Z1",
+                "G0 Z-2.346",
+                "G1 Z-3",
+                "G0 Z1",
+                "G0 Z5",
+                "G80"
+            ]
+        );
+    }
+
+    #[test]
+    fn incremental_cycle_repeats_l_times() {
+        assert_eq!(
+            expanded("G90 G0 X1 Y1 Z5\nG91 G99 G81 X2 Z-2 R-3 L2 F100\nG80\n"),
+            [
+                "G90 G0 X1 Y1 Z5",
+                "G90",
+                "F100",
+                "G0 X3 Y1",
+                "G0 Z2",
+                "G1 Z0",
+                "G0 Z2",
+                "G0 X5 Y1",
+                "G0 Z2",
+                "G1 Z0",
+                "G0 Z2",
+                "G91",
+                "G80"
+            ]
+        );
+    }
+
+    #[test]
+    fn drill_cycle_from_unknown_z_starts_at_r() {
+        let (_, holes, warnings) = expand_canned_cycles("G81 X1 Y1 Z-2 R1 F100\nG80\n");
+        assert_eq!(holes, 1);
+        assert_eq!(
+            warnings,
+            ["Line 1: Z before the drilling cycle is unknown; starting from R"]
+        );
+    }
+
+    fn compensated(program: &str) -> Vec<String> {
+        let backlash = BacklashCompensation {
+            axes: [0.1, 0.2, 0.0],
+        };
+        let (output, _, _) = insert_backlash_moves(program, &backlash);
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn backlash_is_taken_up_on_each_reversal() {
+        assert_eq!(
+            compensated("G90 G1 X10 Y10 F500\nX5\nY5\nX8 Y2\n"),
+            [
+                "G90 G1 X10 Y10 F500",
+                "G1 X9.9",
+                "G1 X4.9",
+                "G1 Y9.8",
+                "G1 Y4.8",
+                "G1 X5",
+                "G1 X8 Y1.8"
+            ]
+        );
+    }
+
+    #[test]
+    fn incremental_backlash_moves_by_the_slack_alone() {
+        assert_eq!(
+            compensated("G91 G1 X10 F500\nX-5\nX-5\n"),
+            ["G91 G1 X10 F500", "G1 X-0.1", "G1 X-5", "X-5"]
+        );
+    }
+
+    #[test]
+    fn backlash_is_converted_to_inches() {
+        assert_eq!(
+            compensated("G20 G90 G0 X1\nX0.5\n"),
+            ["G20 G90 G0 X1", "G0 X0.9961", "G0 X0.4961"]
+        );
+    }
+
+    #[test]
+    fn backlash_tracking_restarts_after_an_offset_change() {
+        let backlash = BacklashCompensation {
+            axes: [0.1, 0.0, 0.0],
+        };
+        let (output, inserted, warnings) =
+            insert_backlash_moves("G90 G1 X10 F500\nX5\nG92 X0\nX-3\n", &backlash);
+        assert_eq!(inserted, 1);
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            ["G90 G1 X10 F500", "G1 X9.9", "G1 X4.9", "G92 X0", "X-3"]
+        );
+        assert_eq!(
+            warnings[1],
+            "Line 3: backlash tracking restarts after this line"
+        );
+    }
+
+}
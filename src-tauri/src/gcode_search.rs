@@ -0,0 +1,133 @@
+use crate::gcode::{self, has_code, tokenize_line, word_value};
+use crate::motion_model::{MotionModel, MoveKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// What to look for in a program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SearchQuery {
+    /// A G or M code such as `M6`, `G38.2` or `M3`
+    Code { code: String },
+    /// Tool selections: a given `T` number, or any `T` word or `M6` when unset
+    Tool { number: Option<u32> },
+    /// Comment text, case-insensitive
+    Comment { text: String },
+    /// Moves ending below this work Z
+    ZBelow { z: f64 },
+}
+
+/// A matching program line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+    /// Work Z at the end of the line's move, for Z searches
+    pub z: Option<f64>,
+}
+
+/// Every line matching the query, in program order
+pub fn search(program: &str, query: &SearchQuery) -> Result<Vec<SearchMatch>> {
+    let lines: Vec<&str> = program.lines().collect();
+    let found = |line: usize, z: Option<f64>| SearchMatch {
+        line,
+        text: lines[line - 1].to_string(),
+        z,
+    };
+
+    let matches = match query {
+        SearchQuery::Code { code } => {
+            let (letter, value) = parse_code(code)?;
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, text)| has_code(&tokenize_line(text), letter, value))
+                .map(|(index, _)| found(index + 1, None))
+                .collect()
+        }
+        SearchQuery::Tool { number } => lines
+            .iter()
+            .enumerate()
+            .filter(|(_, text)| {
+                let words = tokenize_line(text);
+                match number {
+                    Some(n) => word_value(&words, 'T') == Some(*n as f64),
+                    None => word_value(&words, 'T').is_some() || has_code(&words, 'M', 6.0),
+                }
+            })
+            .map(|(index, _)| found(index + 1, None))
+            .collect(),
+        SearchQuery::Comment { text } => {
+            let needle = text.to_lowercase();
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| gcode::comment_text(line).to_lowercase().contains(&needle))
+                .map(|(index, _)| found(index + 1, None))
+                .collect()
+        }
+        SearchQuery::ZBelow { z } => MotionModel::from_program(program)
+            .moves
+            .iter()
+            .filter(|m| m.kind != MoveKind::Dwell && m.end[2] < *z)
+            .map(|m| found(m.line, Some(m.end[2])))
+            .collect(),
+    };
+    Ok(matches)
+}
+
+/// First match after `from_line` (or before it when searching backwards), wrapping around
+pub fn find_next(
+    program: &str,
+    query: &SearchQuery,
+    from_line: usize,
+    backwards: bool,
+) -> Result<Option<SearchMatch>> {
+    let matches = search(program, query)?;
+    let next = if backwards {
+        matches
+            .iter()
+            .rev()
+            .find(|m| m.line < from_line)
+            .or(matches.last())
+    } else {
+        matches
+            .iter()
+            .find(|m| m.line > from_line)
+            .or(matches.first())
+    };
+    Ok(next.cloned())
+}
+
+/// The cutting move (feed or arc) that goes deepest; the first one when several tie
+pub fn deepest_cut(program: &str) -> Option<SearchMatch> {
+    let model = MotionModel::from_program(program);
+    let deepest = model
+        .moves
+        .iter()
+        .filter(|m| matches!(m.kind, MoveKind::Linear | MoveKind::Arc))
+        .min_by(|a, b| a.end[2].total_cmp(&b.end[2]))?;
+    Some(SearchMatch {
+        line: deepest.line,
+        text: program.lines().nth(deepest.line - 1)?.to_string(),
+        z: Some(deepest.end[2]),
+    })
+}
+
+/// `M6` -> ('M', 6.0)
+fn parse_code(code: &str) -> Result<(char, f64)> {
+    let code = code.trim();
+    let mut chars = code.chars();
+    let letter = chars
+        .next()
+        .map(|c| c.to_ascii_uppercase())
+        .filter(|c| matches!(c, 'G' | 'M'))
+        .ok_or_else(|| anyhow!("Search for a G or M code, e.g. M6 (got {:?})", code))?;
+    let value = chars
+        .as_str()
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| anyhow!("Not a valid code number: {}", code))?;
+    Ok((letter, value))
+}
@@ -0,0 +1,350 @@
+use crate::gcode::format_value;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+
+/// Height above the last peck's depth the drill rapids back down to
+const PECK_CLEARANCE: f32 = 0.5;
+
+/// One value a template asks for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateParameter {
+    pub key: String,
+    pub label: String,
+    /// e.g. "mm", "mm/min", "°"
+    pub unit: Option<String>,
+    pub default: f32,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    /// Whole numbers only, e.g. a hole count
+    pub integer: bool,
+}
+
+/// A parameterized program and the schema of what it needs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcodeTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub parameters: Vec<TemplateParameter>,
+}
+
+fn param(key: &str, label: &str, unit: &str, default: f32, min: f32) -> TemplateParameter {
+    TemplateParameter {
+        unit: (!unit.is_empty()).then(|| unit.to_string()),
+        min: Some(min),
+        ..position(key, label, default)
+    }
+}
+
+/// Millimetres, any sign
+fn position(key: &str, label: &str, default: f32) -> TemplateParameter {
+    TemplateParameter {
+        key: key.to_string(),
+        label: label.to_string(),
+        unit: Some("mm".to_string()),
+        default,
+        min: None,
+        max: None,
+        integer: false,
+    }
+}
+
+/// A template with the tool, feed and height parameters every program shares after its own.
+/// `depth` is None when the template works the depth out itself.
+fn template(
+    id: &str,
+    name: &str,
+    description: &str,
+    own: Vec<TemplateParameter>,
+    depth: Option<f32>,
+) -> GcodeTemplate {
+    let mut parameters = own;
+    if let Some(depth) = depth {
+        parameters.push(param("depth", "Depth", "mm", depth, 0.01));
+    }
+    parameters.extend([
+        param("step_down", "Step down", "mm", 1.0, 0.01),
+        param("feed_rate", "Feed rate", "mm/min", 600.0, 1.0),
+        param("plunge_rate", "Plunge rate", "mm/min", 200.0, 1.0),
+        param("spindle_rpm", "Spindle speed", "rpm", 10000.0, 0.0),
+        param("safe_z", "Safe Z", "mm", 5.0, 0.1),
+    ]);
+    GcodeTemplate {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        parameters,
+    }
+}
+
+/// Every template, for the frontend to build its prompts from. Positions are in work
+/// coordinates with Z0 on the stock surface.
+pub fn library() -> Vec<GcodeTemplate> {
+    vec![
+        template(
+            "circular_pocket",
+            "Circular pocket",
+            "Round pocket cleared in rings from the center out",
+            vec![
+                position("center_x", "Center X", 0.0),
+                position("center_y", "Center Y", 0.0),
+                param("diameter", "Pocket diameter", "mm", 20.0, 0.1),
+                param("tool_diameter", "Tool diameter", "mm", 3.175, 0.1),
+                TemplateParameter {
+                    max: Some(100.0),
+                    ..param("stepover_percent", "Stepover", "%", 40.0, 5.0)
+                },
+            ],
+            Some(3.0),
+        ),
+        template(
+            "bolt_circle",
+            "Bolt circle",
+            "Holes drilled evenly around a circle, pecking to clear chips",
+            vec![
+                position("center_x", "Center X", 0.0),
+                position("center_y", "Center Y", 0.0),
+                param("circle_diameter", "Circle diameter", "mm", 40.0, 0.1),
+                TemplateParameter {
+                    integer: true,
+                    max: Some(360.0),
+                    ..param("hole_count", "Holes", "", 6.0, 1.0)
+                },
+                TemplateParameter {
+                    unit: Some("°".to_string()),
+                    ..position("start_angle", "First hole angle", 0.0)
+                },
+                param("peck_depth", "Peck depth (0 for none)", "mm", 2.0, 0.0),
+            ],
+            Some(6.0),
+        ),
+        template(
+            "slot",
+            "Slot",
+            "Straight slot the width of the tool, cut back and forth",
+            vec![
+                position("start_x", "Start X", 0.0),
+                position("start_y", "Start Y", 0.0),
+                position("end_x", "End X", 30.0),
+                position("end_y", "End Y", 0.0),
+            ],
+            Some(3.0),
+        ),
+        template(
+            "chamfer_pass",
+            "Chamfer pass",
+            "V-bit pass around the outside edge of a rectangle",
+            vec![
+                position("x", "Left edge X", 0.0),
+                position("y", "Bottom edge Y", 0.0),
+                param("width", "Width", "mm", 50.0, 0.1),
+                param("height", "Height", "mm", 30.0, 0.1),
+                param("chamfer_width", "Chamfer width", "mm", 1.0, 0.01),
+                TemplateParameter {
+                    max: Some(179.0),
+                    ..param("v_angle", "V-bit angle", "°", 90.0, 1.0)
+                },
+            ],
+            // Follows from the chamfer width and the bit's angle
+            None,
+        ),
+    ]
+}
+
+/// Program for a template, with parameters left out taking their defaults
+pub fn generate(id: &str, values: &BTreeMap<String, f32>) -> Result<String> {
+    let template = library()
+        .into_iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| anyhow!("No template called {}", id))?;
+    let mut p = BTreeMap::new();
+    for parameter in &template.parameters {
+        let value = values
+            .get(&parameter.key)
+            .copied()
+            .unwrap_or(parameter.default);
+        let out_of_range = parameter.min.is_some_and(|min| value < min)
+            || parameter.max.is_some_and(|max| value > max);
+        if !value.is_finite() || out_of_range || (parameter.integer && value.fract() != 0.0) {
+            return Err(anyhow!("{} can't be {}", parameter.label, value));
+        }
+        p.insert(parameter.key.as_str(), value);
+    }
+    let get = |key: &str| p[key];
+
+    let mut writer = ProgramWriter::new(&template.name, &p);
+    match id {
+        "circular_pocket" => {
+            let (cx, cy) = (get("center_x"), get("center_y"));
+            let tool_radius = get("tool_diameter") / 2.0;
+            let outer = get("diameter") / 2.0 - tool_radius;
+            if outer <= 0.0 {
+                return Err(anyhow!("The pocket must be wider than the tool"));
+            }
+            let stepover = get("tool_diameter") * get("stepover_percent") / 100.0;
+            let rings = (outer / stepover).ceil() as usize;
+            for z in writer.passes(get("depth")) {
+                writer.plunge(cx, cy, z);
+                for ring in 1..=rings {
+                    let radius = outer * ring as f32 / rings as f32;
+                    writer.feed(&format!("G1 X{} Y{}", num(cx + radius), num(cy)));
+                    // Counterclockwise climbs the wall with the spindle turning clockwise
+                    writer.feed(&format!(
+                        "G3 X{} Y{} I{} J0",
+                        num(cx + radius),
+                        num(cy),
+                        num(-radius)
+                    ));
+                }
+                writer.feed(&format!("G1 X{} Y{}", num(cx), num(cy)));
+            }
+            writer.retract();
+        }
+        "bolt_circle" => {
+            let radius = get("circle_diameter") / 2.0;
+            let count = get("hole_count") as usize;
+            let peck = get("peck_depth");
+            let depth = get("depth");
+            for hole in 0..count {
+                let angle = (get("start_angle") + 360.0 * hole as f32 / count as f32) * PI / 180.0;
+                let x = get("center_x") + radius * angle.cos();
+                let y = get("center_y") + radius * angle.sin();
+                writer.comment(&format!("Hole {}", hole + 1));
+                writer.rapid(&format!("G0 X{} Y{}", num(x), num(y)));
+                let mut reached = 0.0;
+                while reached < depth {
+                    if reached > 0.0 {
+                        // Back down the cleared hole quickly, stopping short of the bottom
+                        writer.rapid(&format!("G0 Z{}", num(-reached + PECK_CLEARANCE)));
+                    }
+                    reached = if peck > 0.0 {
+                        (reached + peck).min(depth)
+                    } else {
+                        depth
+                    };
+                    writer.plunge_here(-reached);
+                    writer.retract();
+                }
+            }
+        }
+        "slot" => {
+            let ends = [
+                (get("start_x"), get("start_y")),
+                (get("end_x"), get("end_y")),
+            ];
+            if ends[0] == ends[1] {
+                return Err(anyhow!("The slot's start and end are the same point"));
+            }
+            for (i, z) in writer.passes(get("depth")).into_iter().enumerate() {
+                let (from, to) = (ends[i % 2], ends[(i + 1) % 2]);
+                if i == 0 {
+                    writer.plunge(from.0, from.1, z);
+                } else {
+                    writer.plunge_here(z);
+                }
+                writer.feed(&format!("G1 X{} Y{}", num(to.0), num(to.1)));
+            }
+            writer.retract();
+        }
+        "chamfer_pass" => {
+            // The tip runs along the edge, deep enough for the cone to reach the width
+            let half_angle = get("v_angle") / 2.0 * PI / 180.0;
+            let depth = get("chamfer_width") / half_angle.tan();
+            writer.comment(&format!("Chamfer depth {}mm", num(depth)));
+            let (x, y) = (get("x"), get("y"));
+            let (right, top) = (x + get("width"), y + get("height"));
+            // Clockwise climbs an outside edge
+            let corners = [(x, top), (right, top), (right, y), (x, y)];
+            for z in writer.passes(depth) {
+                writer.plunge(x, y, z);
+                for (cx, cy) in corners {
+                    writer.feed(&format!("G1 X{} Y{}", num(cx), num(cy)));
+                }
+            }
+            writer.retract();
+        }
+        _ => return Err(anyhow!("No template called {}", id)),
+    }
+    Ok(writer.finish())
+}
+
+struct ProgramWriter<'a> {
+    p: &'a BTreeMap<&'a str, f32>,
+    lines: Vec<String>,
+    /// The first cutting move after a plunge carries the feed rate
+    feed_pending: bool,
+}
+
+impl<'a> ProgramWriter<'a> {
+    fn new(name: &str, p: &'a BTreeMap<&'a str, f32>) -> Self {
+        let lines = vec![
+            format!("({})", name),
+            "G21 G90 G17".to_string(),
+            format!("G0 Z{}", num(p["safe_z"])),
+            format!("M3 S{}", format_value(p["spindle_rpm"] as f64, 0)),
+        ];
+        Self {
+            p,
+            lines,
+            feed_pending: false,
+        }
+    }
+
+    fn comment(&mut self, text: &str) {
+        self.lines.push(format!("({})", text));
+    }
+
+    /// Depths of each pass down to `depth`
+    fn passes(&self, depth: f32) -> Vec<f32> {
+        let count = (depth / self.p["step_down"]).ceil().max(1.0) as usize;
+        (1..=count)
+            .map(|i| -(depth * i as f32 / count as f32))
+            .collect()
+    }
+
+    fn rapid(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+    }
+
+    fn plunge(&mut self, x: f32, y: f32, z: f32) {
+        self.lines.push(format!("G0 X{} Y{}", num(x), num(y)));
+        self.plunge_here(z);
+    }
+
+    fn plunge_here(&mut self, z: f32) {
+        self.lines.push(format!(
+            "G1 Z{} F{}",
+            num(z),
+            format_value(self.p["plunge_rate"] as f64, 0)
+        ));
+        self.feed_pending = true;
+    }
+
+    fn feed(&mut self, line: &str) {
+        if std::mem::take(&mut self.feed_pending) {
+            self.lines.push(format!(
+                "{} F{}",
+                line,
+                format_value(self.p["feed_rate"] as f64, 0)
+            ));
+        } else {
+            self.lines.push(line.to_string());
+        }
+    }
+
+    fn retract(&mut self) {
+        self.lines.push(format!("G0 Z{}", num(self.p["safe_z"])));
+    }
+
+    fn finish(mut self) -> String {
+        self.lines.push("M5".to_string());
+        self.lines.push("M30".to_string());
+        self.lines.join("\n") + "\n"
+    }
+}
+
+fn num(value: f32) -> String {
+    format_value(value as f64, 3)
+}
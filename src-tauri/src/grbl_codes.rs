@@ -0,0 +1,66 @@
+/// Grbl 1.1 alarm descriptions, from the Grbl wiki
+pub fn alarm_message(code: u32) -> &'static str {
+    match code {
+        1 => "Hard limit triggered. Position is likely lost; re-homing is highly recommended.",
+        2 => "Motion target exceeds machine travel. Position retained; alarm may be unlocked.",
+        3 => "Reset while in motion. Position is likely lost; re-homing is highly recommended.",
+        4 => "Probe fail. The probe was not in the expected initial state before the cycle.",
+        5 => "Probe fail. The probe did not contact the workpiece within the programmed travel.",
+        6 => "Homing fail. Reset during active homing cycle.",
+        7 => "Homing fail. Safety door was opened during homing.",
+        8 => "Homing fail. Pull-off failed to clear the limit switch.",
+        9 => "Homing fail. Could not find the limit switch within the search distance.",
+        10 => "Homing fail. Second dual-axis limit switch failed to trigger.",
+        _ => "Unknown alarm",
+    }
+}
+
+/// Grbl 1.1 error descriptions, from the Grbl wiki
+pub fn error_message(code: u32) -> &'static str {
+    match code {
+        1 => "G-code word is missing its letter.",
+        2 => "Numeric value format is not valid or missing an expected value.",
+        3 => "'$' system command was not recognized or supported.",
+        4 => "Negative value received for an expected positive value.",
+        5 => "Homing cycle is not enabled via settings.",
+        6 => "Minimum step pulse time must be greater than 3usec.",
+        7 => "EEPROM read failed. Reset and restored to default values.",
+        8 => "'$' command cannot be used unless Grbl is idle.",
+        9 => "G-code locked out during alarm or jog state.",
+        10 => "Soft limits cannot be enabled without homing also enabled.",
+        11 => "Max characters per line exceeded. Line was not executed.",
+        12 => "'$' setting value exceeds the maximum step rate supported.",
+        13 => "Safety door detected as opened.",
+        14 => "Build info or startup line exceeds the EEPROM line length limit.",
+        15 => "Jog target exceeds machine travel. Command ignored.",
+        16 => "Jog command has no '=' or contains prohibited G-code.",
+        17 => "Laser mode requires PWM output.",
+        20 => "Unsupported or invalid G-code command in block.",
+        21 => "More than one G-code command from the same modal group in block.",
+        22 => "Feed rate has not yet been set or is undefined.",
+        23 => "G-code command in block requires an integer value.",
+        24 => "More than one G-code command that requires axis words in block.",
+        25 => "Repeated G-code word in block.",
+        26 => "No axis words found in block for a command that requires them.",
+        27 => "Line number value is invalid.",
+        28 => "G-code command is missing a required value word.",
+        29 => "G59.x work coordinate systems are not supported.",
+        30 => "G53 only allowed with G0 and G1 motion modes.",
+        31 => "Axis words found in block when no command or current modal state uses them.",
+        32 => "G2 and G3 arcs require at least one in-plane axis word.",
+        33 => "Motion command target is invalid.",
+        34 => "Arc radius value is invalid.",
+        35 => "G2 and G3 arcs require at least one in-plane offset word.",
+        36 => "Unused value words found in block.",
+        37 => {
+            "G43.1 dynamic tool length offset is not assigned to the configured tool length axis."
+        }
+        38 => "Tool number greater than max supported value.",
+        _ => "Unknown error",
+    }
+}
+
+/// Numeric code from a line such as `ALARM:2` or `error:22`
+pub fn parse_code(line: &str, prefix: &str) -> Option<u32> {
+    line.trim().strip_prefix(prefix)?.trim().parse().ok()
+}
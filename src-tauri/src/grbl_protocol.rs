@@ -0,0 +1,529 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Real-time command that stops a jog and discards queued jog motions
+pub const JOG_CANCEL: u8 = 0x85;
+
+/// Planner blocks and serial RX bytes on a Grbl 0.9 Uno, which reports usage rather than free space
+const LEGACY_PLANNER_BLOCKS: u32 = 18;
+const LEGACY_RX_BUFFER_BYTES: u32 = 128;
+
+/// A parsed Grbl real-time status report, e.g. `<Idle|MPos:0.000,0.000,0.000|FS:0,0>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusReport {
+    /// Machine state including any sub-state, e.g. "Idle", "Hold:0", "Alarm"
+    pub state: String,
+    pub machine_pos: Option<Vec<f32>>,
+    pub work_pos: Option<Vec<f32>>,
+    pub work_offset: Option<Vec<f32>>,
+    pub feed_rate: Option<f32>,
+    pub spindle_speed: Option<f32>,
+    pub buffer: Option<BufferState>,
+    pub overrides: Option<Overrides>,
+    /// Spindle load in percent, from grblHAL's `SL:` field on builds whose spindle reports it
+    pub spindle_load: Option<f32>,
+}
+
+/// Override percentages from the `Ov:` field (only sent every few reports)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Overrides {
+    pub feed: u32,
+    pub rapid: u32,
+    pub spindle: u32,
+}
+
+/// Free space in the controller's buffers, from the `Bf:` field
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BufferState {
+    pub planner_blocks_free: u32,
+    pub rx_bytes_free: u32,
+}
+
+/// Parse the first status report found in a response. Handles both the Grbl 1.1
+/// format (`<Idle|MPos:...|FS:...>`) and the 0.9 one (`<Idle,MPos:...,WPos:...,Buf:0,RX:0>`).
+pub fn parse_status_report(response: &str) -> Option<StatusReport> {
+    let start = response.find('<')?;
+    let end = start + response[start..].find('>')?;
+    let body = &response[start + 1..end];
+
+    let (state, fields) = if is_legacy_status(body) {
+        legacy_fields(body)
+    } else {
+        let mut parts = body.split('|');
+        let state = parts.next()?;
+        let fields = parts
+            .filter_map(|field| field.split_once(':'))
+            .map(|(key, value)| (key, value.to_string()))
+            .collect();
+        (state, fields)
+    };
+    let mut report = StatusReport {
+        state: state.to_string(),
+        ..Default::default()
+    };
+    let mut legacy_buffer = (None, None);
+
+    for (key, value) in &fields {
+        let value = value.as_str();
+        match *key {
+            "MPos" => report.machine_pos = parse_axis_values(value),
+            "WPos" => report.work_pos = parse_axis_values(value),
+            "WCO" => report.work_offset = parse_axis_values(value),
+            "F" => report.feed_rate = value.parse().ok(),
+            "SL" => report.spindle_load = value.trim().parse().ok(),
+            "FS" => {
+                let mut parts = value.split(',');
+                report.feed_rate = parts.next().and_then(|v| v.parse().ok());
+                report.spindle_speed = parts.next().and_then(|v| v.parse().ok());
+            }
+            "Ov" => {
+                let values: Vec<u32> = value
+                    .split(',')
+                    .filter_map(|v| v.trim().parse().ok())
+                    .collect();
+                if let [feed, rapid, spindle] = values[..] {
+                    report.overrides = Some(Overrides {
+                        feed,
+                        rapid,
+                        spindle,
+                    });
+                }
+            }
+            "Bf" => {
+                let mut parts = value.split(',').map(|v| v.trim().parse::<u32>().ok());
+                if let (Some(Some(blocks)), Some(Some(bytes))) = (parts.next(), parts.next()) {
+                    report.buffer = Some(BufferState {
+                        planner_blocks_free: blocks,
+                        rx_bytes_free: bytes,
+                    });
+                }
+            }
+            // 0.9 reports how much of each buffer is in use
+            "Buf" => legacy_buffer.0 = value.trim().parse::<u32>().ok(),
+            "RX" => legacy_buffer.1 = value.trim().parse::<u32>().ok(),
+            _ => {}
+        }
+    }
+    if let (None, (Some(blocks), Some(bytes))) = (report.buffer, legacy_buffer) {
+        report.buffer = Some(BufferState {
+            planner_blocks_free: LEGACY_PLANNER_BLOCKS.saturating_sub(blocks),
+            rx_bytes_free: LEGACY_RX_BUFFER_BYTES.saturating_sub(bytes),
+        });
+    }
+
+    Some(report)
+}
+
+/// True for a 0.9-style report body, where fields are separated by commas instead of `|`
+pub fn is_legacy_status(body: &str) -> bool {
+    !body.contains('|') && body.contains(',')
+}
+
+/// Split a 0.9 body: a new field starts at every comma-separated piece with a `:`,
+/// the pieces without one are more values of the previous field
+fn legacy_fields(body: &str) -> (&str, Vec<(&str, String)>) {
+    let mut pieces = body.split(',');
+    let state = pieces.next().unwrap_or("");
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for piece in pieces {
+        match piece.split_once(':') {
+            Some((key, value)) => fields.push((key, value.to_string())),
+            None => {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push(',');
+                    value.push_str(piece);
+                }
+            }
+        }
+    }
+    (state, fields)
+}
+
+/// True when a firmware version string (`0.9j`, `1.1h.20190825`) is older than Grbl 1.1,
+/// which has no `$J=` jogging, real-time overrides or `|`-separated status reports
+pub fn is_legacy_version(version: &str) -> bool {
+    let version = version.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let mut parts = version.split('.');
+    let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+    let minor = parts
+        .next()
+        .map(|p| p.trim_end_matches(|c: char| !c.is_ascii_digit()))
+        .and_then(|p| p.parse::<u32>().ok());
+    matches!((major, minor), (Some(0), _) | (Some(1), Some(0)))
+}
+
+fn parse_axis_values(value: &str) -> Option<Vec<f32>> {
+    value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().ok())
+        .collect()
+}
+
+/// Parse a `$$` settings dump into a map of setting number to raw value.
+/// Tolerates the trailing `(description)` comments printed by older Grbl builds.
+pub fn parse_settings(response: &str) -> BTreeMap<u16, String> {
+    let mut settings = BTreeMap::new();
+
+    for line in response.lines() {
+        let line = line.trim();
+        let Some(rest) = line.strip_prefix('$') else {
+            continue;
+        };
+        let Some((key, value)) = rest.split_once('=') else {
+            continue;
+        };
+        let Ok(number) = key.trim().parse::<u16>() else {
+            continue;
+        };
+        let value = value.split(['(', ' ']).next().unwrap_or("").trim();
+        settings.insert(number, value.to_string());
+    }
+
+    settings
+}
+
+/// Greeting printed by the controller on power-up and after every soft reset,
+/// e.g. `Grbl 1.1h ['$' for help]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WelcomeBanner {
+    /// "Grbl", "GrblHAL", ...
+    pub firmware: String,
+    pub version: String,
+    pub raw: String,
+}
+
+pub fn parse_welcome_banner(line: &str) -> Option<WelcomeBanner> {
+    let line = line.trim();
+    if !line.contains("for help") {
+        return None;
+    }
+    let mut parts = line.split_whitespace();
+    let firmware = parts.next()?;
+    if !firmware.to_ascii_lowercase().starts_with("grbl") {
+        return None;
+    }
+    Some(WelcomeBanner {
+        firmware: firmware.to_string(),
+        version: parts.next().unwrap_or("").to_string(),
+        raw: line.to_string(),
+    })
+}
+
+/// Build information from `$I`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: Option<String>,
+    pub build_string: Option<String>,
+    /// Compile-time option letters from `[OPT:...]`
+    pub options: Option<String>,
+    pub planner_blocks: Option<u32>,
+    pub rx_buffer_bytes: Option<u32>,
+}
+
+/// Parse `[VER:1.1h.20190825:]` and `[OPT:V,15,128]` lines
+pub fn parse_build_info(response: &str) -> BuildInfo {
+    let mut info = BuildInfo::default();
+
+    for line in response.lines() {
+        let line = line.trim().trim_start_matches('[').trim_end_matches(']');
+        if let Some(ver) = line.strip_prefix("VER:") {
+            let mut parts = ver.splitn(2, ':');
+            info.version = parts.next().map(|v| v.to_string());
+            info.build_string = parts
+                .next()
+                .filter(|b| !b.is_empty())
+                .map(|b| b.to_string());
+        } else if let Some(opt) = line.strip_prefix("OPT:") {
+            let mut parts = opt.split(',');
+            info.options = parts.next().map(|o| o.to_string());
+            info.planner_blocks = parts.next().and_then(|v| v.trim().parse().ok());
+            info.rx_buffer_bytes = parts.next().and_then(|v| v.trim().parse().ok());
+        } else if line.starts_with(|c: char| c.is_ascii_digit()) && info.version.is_none() {
+            // Grbl 0.9 prints `[0.9j.20160316:]` without the VER: prefix
+            if let Some((version, build)) = line.split_once(':') {
+                info.version = Some(version.to_string());
+                info.build_string = (!build.is_empty()).then(|| build.to_string());
+            }
+        }
+    }
+
+    info
+}
+
+/// Parse the active modal words from a `$G` response, e.g. `[GC:G0 G54 G17 G21 G90 G94 M5 M9 T0 F0 S0]`
+pub fn parse_parser_state(response: &str) -> Option<Vec<String>> {
+    let rest = match response.find("[GC:") {
+        Some(start) => &response[start + 4..],
+        // Grbl 0.9 leaves out the GC: prefix, e.g. `[G0 G54 G17 G21 G90 G94 M0 M5 M9 T0 F0. S0.]`
+        None => {
+            let start = response
+                .lines()
+                .map(str::trim)
+                .find(|l| l.starts_with("[G") && !l.contains(':'))?;
+            &start[1..]
+        }
+    };
+    let end = rest.find(']')?;
+    Some(
+        rest[..end]
+            .split_whitespace()
+            .map(|w| w.to_string())
+            .collect(),
+    )
+}
+
+/// Coordinate system data from `$#`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoordinateOffsets {
+    /// G54-G59, G28, G30 and G92 keyed by name
+    pub offsets: BTreeMap<String, Vec<f32>>,
+    pub tool_length_offset: Option<f32>,
+    pub probe: Option<ProbeResult>,
+}
+
+/// Last probe cycle result from `[PRB:x,y,z:success]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub position: Vec<f32>,
+    pub success: bool,
+}
+
+/// Parse the bracketed lines of a `$#` response, e.g. `[G54:0.000,0.000,0.000]` and `[TLO:0.000]`
+pub fn parse_offsets(response: &str) -> CoordinateOffsets {
+    let mut result = CoordinateOffsets::default();
+
+    for line in response.lines() {
+        let Some(body) = line
+            .trim()
+            .strip_prefix('[')
+            .and_then(|l| l.strip_suffix(']'))
+        else {
+            continue;
+        };
+        let Some((key, value)) = body.split_once(':') else {
+            continue;
+        };
+        match key {
+            "TLO" => result.tool_length_offset = value.trim().parse().ok(),
+            "PRB" => result.probe = parse_probe_value(value),
+            "G54" | "G55" | "G56" | "G57" | "G58" | "G59" | "G28" | "G30" | "G92" => {
+                if let Some(values) = parse_axis_values(value) {
+                    result.offsets.insert(key.to_string(), values);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Parse a `[PRB:x,y,z:success]` line, sent after every probe cycle
+pub fn parse_probe(line: &str) -> Option<ProbeResult> {
+    let body = line.trim().strip_prefix("[PRB:")?.strip_suffix(']')?;
+    parse_probe_value(body)
+}
+
+fn parse_probe_value(value: &str) -> Option<ProbeResult> {
+    let (position, success) = value.rsplit_once(':').unwrap_or((value, "0"));
+    Some(ProbeResult {
+        position: parse_axis_values(position)?,
+        success: success.trim() == "1",
+    })
+}
+
+/// What kind of line the controller sent, so it can be routed to the right consumer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// `<...>` real-time status report
+    Status,
+    Ok,
+    /// `error:N`
+    Error,
+    /// `ALARM:N`
+    Alarm,
+    /// `[MSG:...]`
+    Message,
+    /// `[PRB:...]`
+    Probe,
+    /// Welcome banner after power-up or a reset
+    Banner,
+    /// Output belonging to the current command (`$$` lines, `[GC:...]`, ...)
+    Data,
+}
+
+pub fn classify_line(line: &str) -> LineKind {
+    let line = line.trim();
+    if line.starts_with('<') && line.ends_with('>') {
+        LineKind::Status
+    } else if line == "ok" || line.starts_with("ok N") {
+        // Line-numbering controllers may echo the line number, e.g. `ok N12`
+        LineKind::Ok
+    } else if line.starts_with("error:") {
+        LineKind::Error
+    } else if line.starts_with("ALARM:") {
+        LineKind::Alarm
+    } else if line.starts_with("[MSG:") {
+        LineKind::Message
+    } else if line.starts_with("[PRB:") {
+        LineKind::Probe
+    } else if parse_welcome_banner(line).is_some() {
+        LineKind::Banner
+    } else {
+        LineKind::Data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const STATES: [&str; 7] = ["Idle", "Run", "Hold:0", "Jog", "Alarm", "Door:1", "Home"];
+    const NOISE: [&str; 5] = [
+        "",
+        "ok\r\n",
+        "error:9\n",
+        "[MSG:Pgm End]\n",
+        "\u{fffd}\u{fffd}",
+    ];
+
+    fn parse_everything(input: &str) {
+        let _ = parse_status_report(input);
+        let _ = parse_settings(input);
+        let _ = parse_welcome_banner(input);
+        let _ = parse_build_info(input);
+        let _ = parse_parser_state(input);
+        let _ = parse_offsets(input);
+        let _ = parse_probe(input);
+        for line in input.lines() {
+            let _ = classify_line(line);
+        }
+    }
+
+    fn rounded(value: f32) -> f32 {
+        format!("{:.3}", value).parse().unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn parsers_never_panic_on_text(input in "\\PC*") {
+            parse_everything(&input);
+        }
+
+        #[test]
+        fn parsers_never_panic_on_bytes(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            parse_everything(&String::from_utf8_lossy(&bytes));
+        }
+
+        #[test]
+        fn status_report_round_trips(
+            state in prop::sample::select(STATES.to_vec()),
+            pos in prop::array::uniform3(-1000.0f32..1000.0),
+            feed in 0u32..10000,
+            speed in 0u32..30000,
+            blocks in 0u32..16,
+            bytes in 0u32..128,
+            noise in prop::sample::select(NOISE.to_vec()),
+            load in 0u32..200,
+            has_load in any::<bool>(),
+        ) {
+            let load = has_load.then_some(load);
+            let load_field = load.map(|l| format!("|SL:{}", l)).unwrap_or_default();
+            let response = format!(
+                "{}<{}|MPos:{:.3},{:.3},{:.3}|Bf:{},{}|FS:{},{}{}>\r\nok",
+                noise, state, pos[0], pos[1], pos[2], blocks, bytes, feed, speed, load_field
+            );
+            let report = parse_status_report(&response).unwrap();
+            prop_assert_eq!(report.state.as_str(), state);
+            let expected: Vec<f32> = pos.iter().map(|p| rounded(*p)).collect();
+            prop_assert_eq!(report.machine_pos, Some(expected));
+            prop_assert_eq!(report.feed_rate, Some(feed as f32));
+            prop_assert_eq!(report.spindle_speed, Some(speed as f32));
+            prop_assert_eq!(report.spindle_load, load.map(|l| l as f32));
+            let buffer = report.buffer.unwrap();
+            prop_assert_eq!(buffer.planner_blocks_free, blocks);
+            prop_assert_eq!(buffer.rx_bytes_free, bytes);
+        }
+
+        #[test]
+        fn legacy_status_report_round_trips(
+            state in prop::sample::select(vec!["Idle", "Run", "Hold", "Alarm", "Door", "Home"]),
+            mpos in prop::array::uniform3(-1000.0f32..1000.0),
+            wpos in prop::array::uniform3(-1000.0f32..1000.0),
+            blocks in 0u32..18,
+            bytes in 0u32..128,
+        ) {
+            let response = format!(
+                "<{},MPos:{:.3},{:.3},{:.3},WPos:{:.3},{:.3},{:.3},Buf:{},RX:{}>\r\nok",
+                state, mpos[0], mpos[1], mpos[2], wpos[0], wpos[1], wpos[2], blocks, bytes
+            );
+            let report = parse_status_report(&response).unwrap();
+            prop_assert_eq!(report.state.as_str(), state);
+            let round = |v: [f32; 3]| v.iter().map(|p| rounded(*p)).collect::<Vec<_>>();
+            prop_assert_eq!(report.machine_pos, Some(round(mpos)));
+            prop_assert_eq!(report.work_pos, Some(round(wpos)));
+            let buffer = report.buffer.unwrap();
+            prop_assert_eq!(buffer.planner_blocks_free, LEGACY_PLANNER_BLOCKS - blocks);
+            prop_assert_eq!(buffer.rx_bytes_free, LEGACY_RX_BUFFER_BYTES - bytes);
+        }
+
+        #[test]
+        fn settings_round_trip(
+            settings in prop::collection::btree_map(0u16..200, 0.0f32..10000.0, 0..30),
+            with_descriptions in any::<bool>(),
+        ) {
+            let mut response = String::new();
+            for (number, value) in &settings {
+                response.push_str(&format!("${}={:.3}", number, value));
+                if with_descriptions {
+                    response.push_str(" (some setting, mm)");
+                }
+                response.push_str("\r\n");
+            }
+            response.push_str("ok\r\n");
+
+            let parsed = parse_settings(&response);
+            prop_assert_eq!(parsed.len(), settings.len());
+            for (number, value) in &settings {
+                prop_assert_eq!(parsed.get(number), Some(&format!("{:.3}", value)));
+            }
+        }
+
+        #[test]
+        fn parser_state_round_trips(
+            words in prop::collection::vec(
+                prop::sample::select(vec!["G0", "G1", "G54", "G17", "G21", "G90", "G91.1", "G94", "M5", "M9", "T0", "F0", "S0"]),
+                1..12,
+            ),
+            noise in prop::sample::select(NOISE.to_vec()),
+        ) {
+            let response = format!("{}[GC:{}]\r\nok", noise, words.join(" "));
+            let parsed = parse_parser_state(&response).unwrap();
+            prop_assert_eq!(parsed, words.iter().map(|w| w.to_string()).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn offsets_round_trip(
+            g54 in prop::array::uniform3(-1000.0f32..1000.0),
+            g92 in prop::array::uniform3(-1000.0f32..1000.0),
+            tlo in -100.0f32..100.0,
+            probe in prop::array::uniform3(-1000.0f32..1000.0),
+            success in any::<bool>(),
+        ) {
+            let response = format!(
+                "[G54:{:.3},{:.3},{:.3}]\n[G92:{:.3},{:.3},{:.3}]\n[TLO:{:.3}]\n[PRB:{:.3},{:.3},{:.3}:{}]\nok",
+                g54[0], g54[1], g54[2], g92[0], g92[1], g92[2], tlo,
+                probe[0], probe[1], probe[2], if success { 1 } else { 0 }
+            );
+            let parsed = parse_offsets(&response);
+            let round = |v: [f32; 3]| v.iter().map(|p| rounded(*p)).collect::<Vec<_>>();
+            prop_assert_eq!(parsed.offsets.get("G54"), Some(&round(g54)));
+            prop_assert_eq!(parsed.offsets.get("G92"), Some(&round(g92)));
+            prop_assert_eq!(parsed.tool_length_offset, Some(rounded(tlo)));
+            let result = parsed.probe.unwrap();
+            prop_assert_eq!(result.position, round(probe));
+            prop_assert_eq!(result.success, success);
+        }
+    }
+}
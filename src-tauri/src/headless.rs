@@ -0,0 +1,208 @@
+use crate::cnc_comm::CncManager;
+use crate::discovery::DEFAULT_TCP_PORT;
+use crate::job_streamer::STREAM_INTERVAL;
+use crate::min_z_guard::{MinZAction, MinZGuard};
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str =
+    "Usage: cnc stream HOST[:PORT] [FILE|-] [--name NAME] [--data-dir DIR] [--min-z Z]";
+
+/// Time between progress lines on stderr
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Input lines read ahead of the stream; the rest waits in the pipe until they're wanted
+const READ_AHEAD_LINES: usize = 256;
+
+struct StreamArgs {
+    host: String,
+    port: u16,
+    /// None reads standard input
+    file: Option<PathBuf>,
+    name: Option<String>,
+    data_dir: Option<PathBuf>,
+    /// Lowest work Z the job may go to
+    min_z: Option<f64>,
+}
+
+fn parse_args(args: &[String]) -> Result<StreamArgs> {
+    let mut positional = Vec::new();
+    let mut name = None;
+    let mut data_dir = None;
+    let mut min_z = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = args.next().cloned(),
+            "--data-dir" => data_dir = args.next().map(PathBuf::from),
+            "--min-z" => {
+                let value = args.next().ok_or_else(|| anyhow!("{}", USAGE))?;
+                min_z = Some(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("{} isn't a Z height", value))?,
+                );
+            }
+            "-h" | "--help" => return Err(anyhow!("{}", USAGE)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let (address, file) = match positional.as_slice() {
+        [address] => (address, None),
+        [address, file] if file == "-" => (address, None),
+        [address, file] => (address, Some(PathBuf::from(file))),
+        _ => return Err(anyhow!("{}", USAGE)),
+    };
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host.to_string(),
+            port.parse()
+                .map_err(|_| anyhow!("{} isn't a port number", port))?,
+        ),
+        None => (address.clone(), DEFAULT_TCP_PORT),
+    };
+    Ok(StreamArgs {
+        host,
+        port,
+        name: name.or_else(|| {
+            file.as_ref()
+                .and_then(|f| f.file_name())
+                .map(|n| n.to_string_lossy().to_string())
+        }),
+        file,
+        data_dir,
+        min_z,
+    })
+}
+
+/// Run a command line subcommand instead of the window
+pub fn run(args: &[String]) -> Result<()> {
+    match args.split_first() {
+        Some((command, rest)) if command == "stream" => stream(rest),
+        _ => Err(anyhow!("{}", USAGE)),
+    }
+}
+
+/// `cnc stream`: send G-code to a machine without the window, from a file or piped from
+/// another process such as a CAM post or a generator. Lines are streamed as they arrive
+/// with character counting, like a file streamed from the app, and the job is tracked the
+/// same way (history, end-of-job actions with `--data-dir`). Progress goes to stderr.
+fn stream(args: &[String]) -> Result<()> {
+    let args = parse_args(args)?;
+    let input: Box<dyn BufRead + Send> = match &args.file {
+        Some(path) => {
+            Box::new(BufReader::new(File::open(path).map_err(|e| {
+                anyhow!("Could not open {}: {}", path.display(), e)
+            })?))
+        }
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut manager = CncManager::new();
+    if let Some(dir) = args.data_dir.clone() {
+        manager.set_data_dir(dir);
+    }
+    manager.connect_to_address(&args.host, args.port, false)?;
+    // Stopping with a reset loses position; a hold leaves the machine for the operator
+    let guard = args.min_z.map(|min_z| MinZGuard {
+        min_z,
+        action: MinZAction::Hold,
+    });
+    let result = manager
+        .set_min_z_guard(guard)
+        .and_then(|_| manager.start_open_job(args.name.unwrap_or_else(|| "stdin".to_string())))
+        .and_then(|_| stream_lines(&mut manager, read_lines(input)));
+    manager.disconnect();
+    result
+}
+
+/// Read the input on its own thread, so a slow producer doesn't hold up the stream. The
+/// channel is bounded, which leaves the rest of a fast input waiting in the pipe.
+fn read_lines(input: Box<dyn BufRead + Send>) -> Receiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::sync_channel(READ_AHEAD_LINES);
+    thread::spawn(move || {
+        for line in input.lines() {
+            let failed = line.is_err();
+            if sender.send(line).is_err() || failed {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+fn stream_lines(manager: &mut CncManager, input: Receiver<io::Result<String>>) -> Result<()> {
+    let started = Instant::now();
+    let mut lines_read = 0;
+    let mut input_open = true;
+    let mut last_progress = Instant::now();
+    let mut announced_pause = false;
+    loop {
+        while input_open && manager.job_lines_waiting() < READ_AHEAD_LINES {
+            match input.try_recv() {
+                Ok(Ok(line)) => {
+                    lines_read += 1;
+                    manager.queue_job_line(lines_read, &line)?;
+                }
+                Ok(Err(e)) => {
+                    manager.close_job_input(Some(format!("Could not read the input: {}", e)));
+                    input_open = false;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    manager.close_job_input(None);
+                    input_open = false;
+                    eprintln!(
+                        "📊 All {} lines read; waiting for the machine to finish",
+                        lines_read
+                    );
+                }
+            }
+        }
+
+        if let Some(finished) = manager.stream_pass() {
+            if let Some(timing) = finished.completion.and_then(|c| c.timing) {
+                eprintln!(
+                    "🏁 Job {} after {:.0}s ({:.0}s paused)",
+                    if finished.completed {
+                        "finished"
+                    } else {
+                        "stopped"
+                    },
+                    timing.elapsed_seconds,
+                    timing.paused_seconds
+                );
+            }
+            return match finished.error {
+                Some(error) => Err(anyhow!("{}", error)),
+                None => Ok(()),
+            };
+        }
+
+        // Held or the door is open; the stream carries on once the machine resumes
+        let paused = manager.job_paused();
+        if paused && !announced_pause {
+            eprintln!(
+                "⏸️  Job {:?}; waiting for it to resume",
+                manager.job_state()
+            );
+        }
+        announced_pause = paused;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            let cutting = manager.job_line_map().and_then(|map| map.executing);
+            eprintln!(
+                "📊 {} input lines read in {:.0}s, cutting line {}",
+                lines_read,
+                started.elapsed().as_secs_f32(),
+                cutting.map_or("-".to_string(), |line| line.to_string())
+            );
+        }
+        thread::sleep(STREAM_INTERVAL);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::gcode::{tokenize_line, Word};
+use crate::storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Longest straight cut after splitting, in mm, unless the caller asks otherwise
+pub const DEFAULT_SEGMENT_LENGTH: f64 = 5.0;
+
+/// Rectangular probe grid in work coordinates (mm)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeightMapGrid {
+    pub origin_x: f32,
+    pub origin_y: f32,
+    pub spacing_x: f32,
+    pub spacing_y: f32,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl HeightMapGrid {
+    pub fn validate(&self) -> Result<()> {
+        if self.columns < 2 || self.rows < 2 {
+            return Err(anyhow!("A height map needs at least 2 x 2 points"));
+        }
+        if [self.spacing_x, self.spacing_y]
+            .iter()
+            .any(|s| !s.is_finite() || *s <= 0.0)
+        {
+            return Err(anyhow!("Grid spacing must be positive"));
+        }
+        if !self.origin_x.is_finite() || !self.origin_y.is_finite() {
+            return Err(anyhow!("Grid origin must be a number"));
+        }
+        Ok(())
+    }
+
+    /// Work XY of a grid point
+    pub fn point(&self, column: usize, row: usize) -> (f32, f32) {
+        (
+            self.origin_x + column as f32 * self.spacing_x,
+            self.origin_y + row as f32 * self.spacing_y,
+        )
+    }
+
+    /// Grid points in probing order, snaking row by row to keep travel short
+    pub fn probe_order(&self) -> Vec<(usize, usize)> {
+        let mut order = Vec::with_capacity(self.columns * self.rows);
+        for row in 0..self.rows {
+            if row % 2 == 0 {
+                order.extend((0..self.columns).map(|column| (column, row)));
+            } else {
+                order.extend((0..self.columns).rev().map(|column| (column, row)));
+            }
+        }
+        order
+    }
+}
+
+/// Probed surface of a piece of stock, kept so later files for the same stock
+/// (e.g. the drill file after the isolation file) can reuse it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeightMap {
+    pub id: String,
+    /// What was probed, e.g. a board name
+    pub stock_id: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub grid: HeightMapGrid,
+    /// Surface height at each grid point relative to the first probed point, row-major, mm
+    pub heights: Vec<f32>,
+}
+
+/// A program with height map compensation applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeveledProgram {
+    pub program: String,
+    pub lines_adjusted: usize,
+    /// Extra lines from splitting long cuts so they follow the surface
+    pub segments_added: usize,
+}
+
+impl HeightMap {
+    pub fn dir_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("height_maps")
+    }
+
+    pub fn path_for(data_dir: &Path, id: &str) -> PathBuf {
+        Self::dir_in(data_dir).join(format!("{}.json", storage::file_key(id)))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        storage::save_json(&Self::path_for(data_dir, &self.id), self)
+    }
+
+    pub fn load(data_dir: &Path, id: &str) -> Result<Self> {
+        let path = Self::path_for(data_dir, id);
+        let contents =
+            fs::read_to_string(&path).map_err(|e| anyhow!("No height map {}: {}", id, e))?;
+        let map: Self = serde_json::from_str(&contents)?;
+        map.check()?;
+        Ok(map)
+    }
+
+    /// Saved maps, newest first; unreadable files are skipped
+    pub fn list(data_dir: &Path) -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(Self::dir_in(data_dir)) else {
+            return Vec::new();
+        };
+        let mut maps: Vec<Self> = entries
+            .flatten()
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|contents| serde_json::from_str(&contents).ok())
+            .collect();
+        maps.sort_by_key(|m: &Self| std::cmp::Reverse(m.created_at));
+        maps
+    }
+
+    fn check(&self) -> Result<()> {
+        self.grid.validate()?;
+        if self.heights.len() != self.grid.columns * self.grid.rows {
+            return Err(anyhow!(
+                "Height map {} has {} heights for a {} x {} grid",
+                self.id,
+                self.heights.len(),
+                self.grid.columns,
+                self.grid.rows
+            ));
+        }
+        Ok(())
+    }
+
+    /// Surface height at a work XY by bilinear interpolation; outside the grid the
+    /// nearest edge is used
+    pub fn height_at(&self, x: f64, y: f64) -> f64 {
+        let grid = &self.grid;
+        let fx = ((x - grid.origin_x as f64) / grid.spacing_x as f64)
+            .clamp(0.0, (grid.columns - 1) as f64);
+        let fy =
+            ((y - grid.origin_y as f64) / grid.spacing_y as f64).clamp(0.0, (grid.rows - 1) as f64);
+        let column = (fx as usize).min(grid.columns - 2);
+        let row = (fy as usize).min(grid.rows - 2);
+        let (tx, ty) = (fx - column as f64, fy - row as f64);
+
+        let h = |c: usize, r: usize| self.heights[r * grid.columns + c] as f64;
+        let bottom = h(column, row) * (1.0 - tx) + h(column + 1, row) * tx;
+        let top = h(column, row + 1) * (1.0 - tx) + h(column + 1, row + 1) * tx;
+        bottom * (1.0 - ty) + top * ty
+    }
+
+    /// Offset the Z of every G0/G1/G2/G3 move by the surface height under it. Straight
+    /// cuts are split into pieces no longer than `max_segment_length` mm so they follow
+    /// the surface; arcs only have their end point adjusted.
+    pub fn apply(&self, program: &str, max_segment_length: f64) -> Result<LeveledProgram> {
+        self.check()?;
+        if !max_segment_length.is_finite() || max_segment_length <= 0.0 {
+            return Err(anyhow!("Segment length must be positive"));
+        }
+
+        let mut output = String::with_capacity(program.len() * 2);
+        let mut lines_adjusted = 0;
+        let mut segments_added = 0;
+        // Programmed position in mm; None until the program has set that axis
+        let mut position: [Option<f64>; 3] = [None; 3];
+        let mut motion_mode = 0u32;
+        let mut absolute = true;
+        let mut scale = 1.0;
+
+        for (index, line) in program.lines().enumerate() {
+            let words = tokenize_line(line);
+            let mut non_modal_move = false;
+            for word in words.iter().filter(|w| w.letter == 'G') {
+                match (word.value * 10.0).round() as u32 {
+                    code @ (0 | 10 | 20 | 30) => motion_mode = code / 10,
+                    // Probing moves are left alone
+                    382..=385 => motion_mode = 38,
+                    200 => scale = MM_PER_INCH,
+                    210 => scale = 1.0,
+                    // Machine-coordinate moves aren't on the work surface
+                    280 | 300 | 530 => non_modal_move = true,
+                    800 => motion_mode = 80,
+                    900 => absolute = true,
+                    910 => absolute = false,
+                    _ => {}
+                }
+            }
+
+            let has_axis = words.iter().any(|w| matches!(w.letter, 'X' | 'Y' | 'Z'));
+            if !has_axis || non_modal_move || motion_mode > 3 {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+            if !absolute {
+                return Err(anyhow!(
+                    "Line {}: relative (G91) moves can't be height mapped",
+                    index + 1
+                ));
+            }
+
+            let mut target = position;
+            for (axis, letter) in ['X', 'Y', 'Z'].iter().enumerate() {
+                if let Some(word) = words.iter().find(|w| w.letter == *letter) {
+                    target[axis] = Some(word.value * scale);
+                }
+            }
+            let (Some(start_x), Some(start_y), Some(x), Some(y)) =
+                (position[0], position[1], target[0], target[1])
+            else {
+                // Nothing to look the surface up at until the program has set X and Y
+                output.push_str(line);
+                output.push('\n');
+                position = target;
+                continue;
+            };
+
+            let pieces = if motion_mode == 1 {
+                let length = (x - start_x).hypot(y - start_y);
+                ((length / max_segment_length).ceil() as usize).max(1)
+            } else {
+                1
+            };
+            let start_z = position[2].or(target[2]);
+            for piece in 1..=pieces {
+                let t = piece as f64 / pieces as f64;
+                let px = start_x + (x - start_x) * t;
+                let py = start_y + (y - start_y) * t;
+
+                let mut text: Vec<String> = Vec::new();
+                if piece == 1 {
+                    // Modal words, feed, arc centres... stay on the first piece
+                    text.extend(
+                        words
+                            .iter()
+                            .filter(|w| !matches!(w.letter, 'X' | 'Y' | 'Z'))
+                            .map(format_word),
+                    );
+                }
+                text.push(format!("X{}", format_number(px / scale)));
+                text.push(format!("Y{}", format_number(py / scale)));
+                if let (Some(z0), Some(z1)) = (start_z, target[2]) {
+                    let z = z0 + (z1 - z0) * t + self.height_at(px, py);
+                    text.push(format!("Z{}", format_number(z / scale)));
+                }
+                output.push_str(&text.join(" "));
+                output.push('\n');
+            }
+            lines_adjusted += 1;
+            segments_added += pieces - 1;
+            position = target;
+        }
+
+        Ok(LeveledProgram {
+            program: output,
+            lines_adjusted,
+            segments_added,
+        })
+    }
+}
+
+fn format_word(word: &Word) -> String {
+    format!("{}{}", word.letter, format_number(word.value))
+}
+
+/// Up to 4 decimals without trailing zeros
+fn format_number(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
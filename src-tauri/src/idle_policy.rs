@@ -0,0 +1,73 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What happens once the machine has sat idle for a while, so it isn't left energized
+/// all evening. Any command afterwards wakes it again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdlePolicy {
+    pub enabled: bool,
+    pub idle_minutes: f32,
+    pub spindle_off: bool,
+    /// Raise and move to the machine XY origin
+    pub park: bool,
+    /// Name of a machine profile macro to run instead of parking
+    pub macro_name: Option<String>,
+    /// `$SLP`: Grbl de-energizes the steppers and needs a reset to wake
+    pub sleep: bool,
+    /// Stop querying status, letting the WiFi module idle
+    pub stop_polling: bool,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_minutes: 30.0,
+            spindle_off: true,
+            park: false,
+            macro_name: None,
+            sleep: false,
+            stop_polling: true,
+        }
+    }
+}
+
+impl IdlePolicy {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("idle_policy.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.idle_minutes.is_finite() || self.idle_minutes < 1.0 {
+            return Err(anyhow!("Idle time must be at least a minute"));
+        }
+        Ok(())
+    }
+}
+
+/// Payload of the `cnc:idle-power-down` event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdlePowerDown {
+    /// Steps that ran, e.g. "spindle off", "sleep"
+    pub actions: Vec<String>,
+    pub errors: Vec<String>,
+    pub polling_stopped: bool,
+}
+
+impl IdlePowerDown {
+    pub fn record<T>(&mut self, action: &str, result: Result<T>) -> bool {
+        match result {
+            Ok(_) => {
+                self.actions.push(action.to_string());
+                true
+            }
+            Err(e) => {
+                println!("⚠️  Idle {} failed: {}", action, e);
+                self.errors.push(format!("{}: {}", action, e));
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::motion_model::{MotionModel, MotionSettings, MoveKind};
+use serde::{Deserialize, Serialize};
+
+/// Grbl's planner holds 16 blocks, one of which is always executing
+const DEFAULT_PLANNER_BLOCKS: usize = 15;
+
+/// Regions are only reported when the sustainable feed drops below this fraction of the programmed feed
+const STUTTER_THRESHOLD: f64 = 0.9;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StutterOptions {
+    /// Planner blocks available for lookahead (defaults to Grbl's 15)
+    pub planner_blocks: Option<usize>,
+    /// Measured link throughput; when set, segments that execute faster than
+    /// their line can be transmitted are flagged too
+    pub link_bytes_per_sec: Option<f64>,
+}
+
+/// A run of consecutive short segments that can't be executed at the programmed feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StutterRegion {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub segment_count: usize,
+    /// mm/min
+    pub programmed_feed: f64,
+    /// Highest feed the machine can hold through the region, in mm/min
+    pub sustainable_feed: f64,
+    /// mm
+    pub average_segment_length: f64,
+    /// Segment length needed to hold the programmed feed, in mm
+    pub required_segment_length: f64,
+    pub suggestion: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StutterReport {
+    pub regions: Vec<StutterRegion>,
+    pub analyzed_segments: usize,
+    pub stuttering_segments: usize,
+}
+
+/// Find regions whose segments are too short for the planner (or the link) to sustain the programmed feed.
+/// The planner must always be able to stop within the distance it has buffered, so the speed it can
+/// hold is limited to sqrt(2 * accel * lookahead distance).
+pub fn analyze_stutter(
+    program: &str,
+    settings: &MotionSettings,
+    options: &StutterOptions,
+) -> StutterReport {
+    let model = MotionModel::from_program(program);
+    let line_bytes: Vec<usize> = program.lines().map(|l| l.trim().len() + 1).collect();
+    let blocks = options
+        .planner_blocks
+        .unwrap_or(DEFAULT_PLANNER_BLOCKS)
+        .max(1);
+    let accel = settings.acceleration.max(1.0);
+
+    let mut regions: Vec<StutterRegion> = Vec::new();
+    let mut current: Option<(StutterRegion, f64)> = None;
+    let mut analyzed = 0;
+    let mut stuttering = 0;
+
+    for (i, m) in model.moves.iter().enumerate() {
+        let cutting = matches!(m.kind, MoveKind::Linear | MoveKind::Arc);
+        let mut flagged = None;
+
+        if cutting && m.length > 0.0 && m.feed_rate > 0.0 {
+            analyzed += 1;
+            let programmed = m.feed_rate.min(settings.rapid_rate);
+
+            let lookahead: f64 = model.moves[i..]
+                .iter()
+                .take(blocks)
+                .take_while(|n| n.kind != MoveKind::Dwell)
+                .map(|n| n.length)
+                .sum();
+            let mut sustainable = (2.0 * accel * lookahead).sqrt() * 60.0;
+
+            if let Some(bytes_per_sec) = options.link_bytes_per_sec.filter(|b| *b > 0.0) {
+                let bytes = line_bytes.get(m.line - 1).copied().unwrap_or(1) as f64;
+                sustainable = sustainable.min(m.length / (bytes / bytes_per_sec) * 60.0);
+            }
+
+            if sustainable < programmed * STUTTER_THRESHOLD {
+                flagged = Some((programmed, sustainable));
+            }
+        }
+
+        match flagged {
+            Some((programmed, sustainable)) => {
+                stuttering += 1;
+                let (region, total_length) = current.get_or_insert_with(|| {
+                    (
+                        StutterRegion {
+                            start_line: m.line,
+                            end_line: m.line,
+                            segment_count: 0,
+                            programmed_feed: programmed,
+                            sustainable_feed: sustainable,
+                            average_segment_length: 0.0,
+                            required_segment_length: 0.0,
+                            suggestion: String::new(),
+                        },
+                        0.0,
+                    )
+                });
+                region.end_line = m.line;
+                region.segment_count += 1;
+                region.programmed_feed = region.programmed_feed.max(programmed);
+                region.sustainable_feed = region.sustainable_feed.min(sustainable);
+                *total_length += m.length;
+            }
+            None if cutting => {
+                if let Some(region) = current.take() {
+                    regions.push(finish_region(region, accel, blocks, options));
+                }
+            }
+            None => {}
+        }
+    }
+    if let Some(region) = current.take() {
+        regions.push(finish_region(region, accel, blocks, options));
+    }
+
+    StutterReport {
+        regions,
+        analyzed_segments: analyzed,
+        stuttering_segments: stuttering,
+    }
+}
+
+fn finish_region(
+    (mut region, total_length): (StutterRegion, f64),
+    accel: f64,
+    blocks: usize,
+    options: &StutterOptions,
+) -> StutterRegion {
+    region.average_segment_length = total_length / region.segment_count as f64;
+
+    // Average segment length at which the lookahead could hold the programmed feed
+    let speed = region.programmed_feed / 60.0;
+    let mut required = speed * speed / (2.0 * accel) / blocks as f64;
+    if let Some(bytes_per_sec) = options.link_bytes_per_sec.filter(|b| *b > 0.0) {
+        // Assume a typical ~30 byte line when sizing for the link
+        required = required.max(speed * 30.0 / bytes_per_sec);
+    }
+    region.required_segment_length = required;
+
+    region.suggestion = format!(
+        "Lines {}-{}: reduce feed to about {:.0} mm/min, or loosen the CAM tolerance so segments average at least {:.3} mm (currently {:.3} mm)",
+        region.start_line,
+        region.end_line,
+        region.sustainable_feed,
+        region.required_segment_length,
+        region.average_segment_length
+    );
+    region
+}
+
+/// Predicted run time at one feed override level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverrideEstimate {
+    pub override_percent: u32,
+    pub seconds: f64,
+    /// Difference from the 100% estimate (negative is faster)
+    pub delta_seconds: f64,
+}
+
+/// Grbl accepts feed overrides between 10% and 200%
+pub const DEFAULT_OVERRIDE_LEVELS: [u32; 6] = [80, 90, 100, 110, 120, 150];
+
+/// Predict run time at several feed override levels. Rapids aren't affected by feed
+/// override and acceleration-limited short segments barely speed up, so the gain is
+/// usually well below the override percentage.
+pub fn estimate_override_times(
+    program: &str,
+    settings: &MotionSettings,
+    levels: &[u32],
+) -> Vec<OverrideEstimate> {
+    let model = MotionModel::from_program(program);
+    let baseline = model.estimate_seconds(settings, 1.0);
+
+    levels
+        .iter()
+        .map(|level| {
+            let percent = (*level).clamp(10, 200);
+            let seconds = model.estimate_seconds(settings, percent as f64 / 100.0);
+            OverrideEstimate {
+                override_percent: percent,
+                seconds,
+                delta_seconds: seconds - baseline,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> MotionSettings {
+        MotionSettings {
+            rapid_rate: 5000.0,
+            ..Default::default()
+        }
+    }
+
+    /// 50 segments of 0.05 mm at F3000, then a long move
+    fn short_segments() -> String {
+        let mut program = "G90 G0 X0 Y0\nG1 F3000\n".to_string();
+        for i in 1..=50 {
+            program.push_str(&format!("X{:.2}\n", i as f64 * 0.05));
+        }
+        program.push_str("X100\n");
+        program
+    }
+
+    #[test]
+    fn short_segments_are_flagged_until_the_lookahead_reaches_a_long_move() {
+        let report = analyze_stutter(&short_segments(), &settings(), &StutterOptions::default());
+        assert_eq!(report.analyzed_segments, 51);
+        assert_eq!(report.regions.len(), 1);
+        let region = &report.regions[0];
+        // The last 14 short segments see the long move in their lookahead
+        assert_eq!((region.start_line, region.end_line), (3, 38));
+        assert_eq!(region.segment_count, 36);
+        assert_eq!(report.stuttering_segments, 36);
+        assert!((region.average_segment_length - 0.05).abs() < 1e-6);
+        assert!(region.sustainable_feed < region.programmed_feed * STUTTER_THRESHOLD);
+        assert!(region.required_segment_length > region.average_segment_length);
+    }
+
+    #[test]
+    fn long_segments_do_not_stutter() {
+        let report = analyze_stutter(
+            "G1 X50 F1000\nY50\nX0\n",
+            &settings(),
+            &StutterOptions::default(),
+        );
+        assert_eq!(report.analyzed_segments, 3);
+        assert!(report.regions.is_empty());
+    }
+
+    #[test]
+    fn a_slow_link_flags_segments_the_planner_could_hold() {
+        let options = StutterOptions {
+            planner_blocks: None,
+            link_bytes_per_sec: Some(100.0),
+        };
+        let report = analyze_stutter("G1 X50 F1000\nX50.5\nX51\nX100\n", &settings(), &options);
+        let lines: Vec<_> = report
+            .regions
+            .iter()
+            .map(|r| (r.start_line, r.end_line))
+            .collect();
+        assert_eq!(lines, [(2, 3)]);
+    }
+
+    #[test]
+    fn override_estimates_are_relative_to_100_percent() {
+        let estimates =
+            estimate_override_times("G1 X100 F600\nG0 X0\n", &settings(), &[50, 100, 300]);
+        let percents: Vec<_> = estimates.iter().map(|e| e.override_percent).collect();
+        assert_eq!(percents, [50, 100, 200]);
+        assert!(estimates[0].delta_seconds > 0.0);
+        assert_eq!(estimates[1].delta_seconds, 0.0);
+        assert!(estimates[2].delta_seconds < 0.0);
+    }
+}
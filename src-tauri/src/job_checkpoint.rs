@@ -0,0 +1,35 @@
+use crate::job_control::JobState;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often a running job's checkpoint is rewritten
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where a streamed job had got to, kept on disk while it runs. A checkpoint still there
+/// when the app starts means the last session ended (crash, power loss) mid-job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCheckpoint {
+    /// Library program being run, if the streamer said
+    pub program_name: Option<String>,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub state: JobState,
+    /// Last line of the file the controller accepted; `start_job_from_line` picks up from here
+    pub last_acked_line: Option<usize>,
+    /// Line estimated to be cutting at the last update
+    pub executing_line: Option<usize>,
+    /// Machine the job ran on (see `CncDevice::machine_key`)
+    pub machine: Option<String>,
+    pub machine_pos: Option<Vec<f32>>,
+    pub work_offset: Option<Vec<f32>>,
+    /// `$G` modal words when the job started: coordinate system, units and so on
+    pub modal_state: Option<Vec<String>>,
+}
+
+impl JobCheckpoint {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("job_checkpoint.json")
+    }
+}
@@ -0,0 +1,253 @@
+use crate::alarm_history::AlarmKind;
+use crate::grbl_protocol::Overrides;
+use crate::job_control::{JobState, JobStateChange};
+use crate::job_history::JobHistoryEntry;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Time differences smaller than this, in seconds, aren't worth a line
+const MIN_TIME_DELTA: f64 = 30.0;
+
+/// Nor are override averages closer than this, in percent
+const MIN_OVERRIDE_DELTA: f64 = 5.0;
+
+/// How an override was used over a job, weighted by time
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverrideUsage {
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    /// Time spent away from 100%
+    pub seconds_changed: f64,
+}
+
+/// What happened during a job besides its timing, kept with its history entry
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobRunStats {
+    /// Feed holds and door openings
+    pub pauses: usize,
+    /// Of those, the ones the app didn't ask for (hold button, door, stall auto-pause...)
+    pub machine_pauses: usize,
+    pub errors: usize,
+    pub alarms: usize,
+    pub feed_override: Option<OverrideUsage>,
+    pub rapid_override: Option<OverrideUsage>,
+    pub spindle_override: Option<OverrideUsage>,
+}
+
+#[derive(Default)]
+struct UsageTotals {
+    min: u32,
+    max: u32,
+    weighted: f64,
+    seconds: f64,
+    seconds_changed: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, percent: u32, seconds: f64) {
+        if self.seconds == 0.0 {
+            self.min = percent;
+            self.max = percent;
+        }
+        self.min = self.min.min(percent);
+        self.max = self.max.max(percent);
+        self.weighted += percent as f64 * seconds;
+        self.seconds += seconds;
+        if percent != 100 {
+            self.seconds_changed += seconds;
+        }
+    }
+
+    fn usage(&self) -> Option<OverrideUsage> {
+        (self.seconds > 0.0).then(|| OverrideUsage {
+            min: self.min,
+            max: self.max,
+            mean: self.weighted / self.seconds,
+            seconds_changed: self.seconds_changed,
+        })
+    }
+}
+
+/// Collects `JobRunStats` while a job runs
+#[derive(Default)]
+pub struct JobRunRecorder {
+    stats: JobRunStats,
+    /// Overrides in force since the last report, and when that was
+    last: Option<(Overrides, Instant)>,
+    feed: UsageTotals,
+    rapid: UsageTotals,
+    spindle: UsageTotals,
+}
+
+impl JobRunRecorder {
+    pub fn start(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Overrides as of a status report; the time since the last one is counted at the old values
+    pub fn observe_overrides(&mut self, overrides: Option<Overrides>, now: Instant) {
+        if let Some((previous, since)) = self.last {
+            let seconds = now.duration_since(since).as_secs_f64();
+            self.feed.add(previous.feed, seconds);
+            self.rapid.add(previous.rapid, seconds);
+            self.spindle.add(previous.spindle, seconds);
+        }
+        self.last = overrides.map(|o| (o, now));
+    }
+
+    pub fn note_state_change(&mut self, change: &JobStateChange) {
+        // Moving between held and door open is still the one pause
+        if change.previous == JobState::Running {
+            self.stats.pauses += 1;
+            if change.machine_initiated {
+                self.stats.machine_pauses += 1;
+            }
+        }
+    }
+
+    pub fn note_alarm(&mut self, kind: AlarmKind) {
+        match kind {
+            AlarmKind::Alarm => self.stats.alarms += 1,
+            AlarmKind::Error => self.stats.errors += 1,
+        }
+    }
+
+    pub fn finish(&mut self) -> JobRunStats {
+        self.observe_overrides(None, Instant::now());
+        let mut stats = std::mem::take(&mut self.stats);
+        stats.feed_override = self.feed.usage();
+        stats.rapid_override = self.rapid.usage();
+        stats.spindle_override = self.spindle.usage();
+        *self = Self::default();
+        stats
+    }
+}
+
+/// Returned by `compare_job_runs`: how a run differed from an earlier one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobComparison {
+    pub baseline: JobHistoryEntry,
+    pub run: JobHistoryEntry,
+    /// Run minus baseline, in seconds; positive when the run took longer
+    pub elapsed_delta_seconds: f64,
+    pub active_delta_seconds: f64,
+    pub paused_delta_seconds: f64,
+    /// What differed, in plain words
+    pub differences: Vec<String>,
+}
+
+fn minutes(seconds: f64) -> String {
+    if seconds.abs() < 90.0 {
+        format!("{:.0}s", seconds.abs())
+    } else {
+        format!("{:.1} min", seconds.abs() / 60.0)
+    }
+}
+
+fn longer_or_shorter(delta: f64) -> &'static str {
+    if delta > 0.0 {
+        "longer"
+    } else {
+        "shorter"
+    }
+}
+
+/// Compare a run with a baseline, normally earlier runs of the same file
+pub fn compare_runs(baseline: &JobHistoryEntry, run: &JobHistoryEntry) -> JobComparison {
+    let elapsed = run.timing.elapsed_seconds - baseline.timing.elapsed_seconds;
+    let active = run.timing.active_seconds - baseline.timing.active_seconds;
+    let paused = run.timing.paused_seconds - baseline.timing.paused_seconds;
+    let mut differences = Vec::new();
+
+    if baseline.program_name != run.program_name {
+        differences.push(format!(
+            "Different programs: {} and {}",
+            baseline.program_name.as_deref().unwrap_or("unnamed"),
+            run.program_name.as_deref().unwrap_or("unnamed")
+        ));
+    }
+    if baseline.completed != run.completed || baseline.lines != run.lines {
+        let describe = |entry: &JobHistoryEntry| match (entry.completed, entry.lines) {
+            (Some(true), Some(lines)) => format!("completed to line {}", lines),
+            (Some(false), Some(lines)) => format!("stopped at line {}", lines),
+            (Some(false), None) => "stopped".to_string(),
+            _ => "ended without saying how".to_string(),
+        };
+        differences.push(format!(
+            "Baseline {}; this run {}",
+            describe(baseline),
+            describe(run)
+        ));
+    }
+    if elapsed.abs() >= MIN_TIME_DELTA {
+        differences.push(format!(
+            "Took {} {} ({} cutting, {} paused)",
+            minutes(elapsed),
+            longer_or_shorter(elapsed),
+            signed(active),
+            signed(paused)
+        ));
+    }
+
+    match (&baseline.stats, &run.stats) {
+        (Some(before), Some(after)) => compare_stats(before, after, &mut differences),
+        _ => differences
+            .push("Pauses, errors and overrides weren't recorded for both runs".to_string()),
+    }
+
+    JobComparison {
+        baseline: baseline.clone(),
+        run: run.clone(),
+        elapsed_delta_seconds: elapsed,
+        active_delta_seconds: active,
+        paused_delta_seconds: paused,
+        differences,
+    }
+}
+
+fn signed(seconds: f64) -> String {
+    format!(
+        "{}{}",
+        if seconds < 0.0 { "-" } else { "+" },
+        minutes(seconds)
+    )
+}
+
+fn compare_stats(before: &JobRunStats, after: &JobRunStats, differences: &mut Vec<String>) {
+    if before.pauses != after.pauses {
+        differences.push(format!(
+            "Paused {} times ({} from the machine), against {} ({})",
+            after.pauses, after.machine_pauses, before.pauses, before.machine_pauses
+        ));
+    }
+    for (name, before, after) in [
+        ("Feed", before.feed_override, after.feed_override),
+        ("Rapid", before.rapid_override, after.rapid_override),
+        ("Spindle", before.spindle_override, after.spindle_override),
+    ] {
+        let mean = |usage: Option<OverrideUsage>| usage.map_or(100.0, |u| u.mean);
+        if (mean(after) - mean(before)).abs() >= MIN_OVERRIDE_DELTA {
+            differences.push(format!(
+                "{} override averaged {:.0}% ({}-{}%), against {:.0}%",
+                name,
+                mean(after),
+                after.map_or(100, |u| u.min),
+                after.map_or(100, |u| u.max),
+                mean(before)
+            ));
+        }
+    }
+    if before.errors != after.errors {
+        differences.push(format!(
+            "{} error responses, against {}",
+            after.errors, before.errors
+        ));
+    }
+    if before.alarms != after.alarms {
+        differences.push(format!(
+            "{} alarms, against {}",
+            after.alarms, before.alarms
+        ));
+    }
+}
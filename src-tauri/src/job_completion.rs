@@ -0,0 +1,135 @@
+use crate::job_control::JobTiming;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the backend does when a job ends, so unattended jobs finish safely
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionActions {
+    pub enabled: bool,
+    /// M5 M9 once the last line is done
+    pub spindle_off: bool,
+    /// Name of a machine profile macro to run
+    pub macro_name: Option<String>,
+    pub park: bool,
+    /// Line sent last, e.g. an M-code wired to the dust extractor relay
+    pub peripherals_off_gcode: Option<String>,
+    /// HTTP URL of a camera still image to save
+    pub snapshot_url: Option<String>,
+    /// Emit `cnc:job-complete` so the UI can show a notification
+    pub notify: bool,
+    /// HTTP URL that gets a JSON POST, e.g. a home automation hook for a smart plug
+    pub webhook_url: Option<String>,
+}
+
+impl Default for CompletionActions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spindle_off: true,
+            macro_name: None,
+            park: true,
+            peripherals_off_gcode: None,
+            snapshot_url: None,
+            notify: true,
+            webhook_url: None,
+        }
+    }
+}
+
+impl CompletionActions {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("job_completion.json")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionResult {
+    pub action: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Result of the end-of-job actions, also the `cnc:job-complete` payload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobCompletion {
+    /// False when the job was stopped early; machine actions only run after a finished job
+    pub completed: bool,
+    /// Cutting and paused time of the job
+    pub timing: Option<JobTiming>,
+    pub actions: Vec<ActionResult>,
+    pub snapshot_path: Option<PathBuf>,
+}
+
+impl JobCompletion {
+    pub fn record(&mut self, action: &str, result: Result<String>) -> bool {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => {
+                println!("⚠️  End-of-job {} failed: {}", action, e);
+                (false, e.to_string())
+            }
+        };
+        self.actions.push(ActionResult {
+            action: action.to_string(),
+            ok,
+            detail,
+        });
+        ok
+    }
+}
+
+/// Save a camera still from an HTTP snapshot URL
+pub fn fetch_snapshot(url: &str, path: &Path) -> Result<()> {
+    let response = ureq::get(url).timeout(HTTP_TIMEOUT).call()?;
+    let mut image = Vec::new();
+    response.into_reader().read_to_end(&mut image)?;
+    if image.is_empty() {
+        return Err(anyhow!("Camera returned an empty image"));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, image)?;
+    Ok(())
+}
+
+/// Take the snapshot and post the webhook from a background thread, so a slow camera or
+/// server never holds up the machine. `done` gets the completion with their results.
+pub fn spawn_network_actions(
+    mut completion: JobCompletion,
+    snapshot: Option<(String, PathBuf)>,
+    webhook_url: Option<String>,
+    done: impl FnOnce(JobCompletion) + Send + 'static,
+) {
+    thread::spawn(move || {
+        if let Some((url, path)) = snapshot {
+            let result = fetch_snapshot(&url, &path).map(|_| path.display().to_string());
+            if result.is_ok() {
+                completion.snapshot_path = Some(path);
+            }
+            completion.record("snapshot", result);
+        }
+        if let Some(url) = webhook_url {
+            let result = post_webhook(&url, &completion).map(|_| url.clone());
+            completion.record("webhook", result);
+        }
+        done(completion);
+    });
+}
+
+pub fn post_webhook(url: &str, completion: &JobCompletion) -> Result<()> {
+    let body = serde_json::to_string(completion)?;
+    ureq::post(url)
+        .timeout(HTTP_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body)?;
+    Ok(())
+}
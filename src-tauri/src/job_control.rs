@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Acknowledged lines remembered for placing the executing line; more than a planner holds
+const RECENT_ACKED: usize = 64;
+
+/// Backend view of the job being streamed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Idle,
+    Running,
+    /// Feed hold: from the app, or the machine's hold button
+    Held,
+    /// Safety door opened
+    DoorOpen,
+}
+
+/// Payload of the `cnc:job-state` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStateChange {
+    pub previous: JobState,
+    pub state: JobState,
+    /// Grbl state that caused the change, e.g. "Hold:0" or "Door:1"
+    pub machine_state: String,
+    /// True when the machine paused on its own (physical hold button, door switch)
+    /// rather than because the app sent a feed hold
+    pub machine_initiated: bool,
+    pub timing: JobTiming,
+}
+
+/// Wall-clock time of a job split into cutting and paused time, in seconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobTiming {
+    pub elapsed_seconds: f64,
+    /// Time not spent in a hold or with the door open
+    pub active_seconds: f64,
+    pub paused_seconds: f64,
+}
+
+/// Where the job's lines are between the socket and the tool, as lines of the file
+/// (1-based, inclusive ranges)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobLineMap {
+    /// Last line written to the controller
+    pub last_sent: usize,
+    /// Last line the controller answered; everything up to here is in the planner or done
+    pub last_acked: usize,
+    /// Written but not yet answered, still in the controller's serial buffer
+    pub unacked: Option<(usize, usize)>,
+    /// Accepted lines still queued in the planner behind the executing one
+    pub planned: Option<(usize, usize)>,
+    /// Best guess at the line the tool is cutting: the oldest block still in the planner
+    pub executing: Option<usize>,
+}
+
+/// Payload of the `cnc:job-progress` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub state: JobState,
+    pub lines: Option<JobLineMap>,
+    pub timing: JobTiming,
+    /// Best guess at the line of the file being cut (1-based), as in `lines.executing`
+    pub current_line: Option<usize>,
+    /// Bytes of the job written to the controller, newlines included
+    pub bytes_sent: usize,
+    /// None when whoever streams the job didn't say how long it is
+    pub total_lines: Option<usize>,
+    pub percent: Option<f64>,
+    /// Active time per line so far applied to the lines left; pauses don't count
+    pub remaining_seconds: Option<f64>,
+}
+
+/// Tracks the job through holds and door openings reported in status reports,
+/// so lines aren't pushed into a controller that has paused
+pub struct JobMonitor {
+    state: JobState,
+    /// The app asked for the current hold, so it isn't reported as machine initiated
+    app_hold_requested: bool,
+    /// File line of each line written and not yet acknowledged, oldest first; None for
+    /// lines that aren't in the file, such as the setup before resuming part way through
+    in_flight: VecDeque<Option<usize>>,
+    /// Latest file lines acknowledged, oldest first
+    recent_acked: VecDeque<usize>,
+    /// File line of the first and the last line written
+    first_sent: Option<usize>,
+    last_sent: Option<usize>,
+    bytes_sent: usize,
+    /// Length of the job in lines of the file, when known
+    total_lines: Option<usize>,
+    started_at: Option<Instant>,
+    /// Start of the current hold or door opening
+    paused_since: Option<Instant>,
+    /// Finished pauses so far
+    paused_total: Duration,
+}
+
+impl JobMonitor {
+    pub fn new() -> Self {
+        Self {
+            state: JobState::Idle,
+            app_hold_requested: false,
+            in_flight: VecDeque::new(),
+            recent_acked: VecDeque::new(),
+            first_sent: None,
+            last_sent: None,
+            bytes_sent: 0,
+            total_lines: None,
+            started_at: None,
+            paused_since: None,
+            paused_total: Duration::ZERO,
+        }
+    }
+
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    /// False while the machine is paused, or a hold has been sent and no status report
+    /// has shown it yet; the streamer should wait
+    pub fn accepts_lines(&self) -> bool {
+        match self.state {
+            JobState::Idle => true,
+            JobState::Running => !self.app_hold_requested,
+            JobState::Held | JobState::DoorOpen => false,
+        }
+    }
+
+    /// Held, door open, or an app hold on its way
+    pub fn paused(&self) -> bool {
+        self.state != JobState::Idle && !self.accepts_lines()
+    }
+
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.state = if streaming {
+            JobState::Running
+        } else {
+            JobState::Idle
+        };
+        self.app_hold_requested = false;
+        self.in_flight.clear();
+        self.recent_acked.clear();
+        self.first_sent = None;
+        self.last_sent = None;
+        self.bytes_sent = 0;
+        self.total_lines = None;
+        self.started_at = streaming.then(Instant::now);
+        self.paused_since = None;
+        self.paused_total = Duration::ZERO;
+    }
+
+    /// Time split of the running job, None when no job is running
+    pub fn timing(&self) -> Option<JobTiming> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.started_at?);
+        let paused = self.paused_total
+            + self
+                .paused_since
+                .map_or(Duration::ZERO, |since| now.duration_since(since));
+        Some(JobTiming {
+            elapsed_seconds: elapsed.as_secs_f64(),
+            active_seconds: elapsed.saturating_sub(paused).as_secs_f64(),
+            paused_seconds: paused.as_secs_f64(),
+        })
+    }
+
+    /// A line of the job was written to the controller, `bytes` long with its newline.
+    /// `line` is where it is in the file, None for a line that isn't in it.
+    pub fn note_sent(&mut self, line: Option<usize>, bytes: usize) {
+        if self.state == JobState::Idle {
+            return;
+        }
+        self.in_flight.push_back(line);
+        self.bytes_sent += bytes;
+        if let Some(line) = line {
+            self.first_sent.get_or_insert(line);
+            self.last_sent = Some(line);
+        }
+    }
+
+    /// File line to give the next line written, for senders that don't say: they're
+    /// taken to send the file from the top with nothing left out
+    pub fn next_line(&self) -> usize {
+        self.last_sent.map_or(1, |line| line + 1)
+    }
+
+    /// Lines of the running job not written yet, when its length is known
+    pub fn lines_waiting(&self) -> Option<usize> {
+        self.total_lines
+            .map(|total| total.saturating_sub(self.last_sent.unwrap_or(0)))
+    }
+
+    /// How many lines the running job's file has, for percent complete and time remaining
+    pub fn set_total_lines(&mut self, total_lines: Option<usize>) {
+        if self.state != JobState::Idle {
+            self.total_lines = total_lines.filter(|total| *total > 0);
+        }
+    }
+
+    /// Progress of the running job given the line map, None when no job is running
+    pub fn progress(&self, lines: Option<JobLineMap>) -> Option<JobProgress> {
+        let timing = self.timing()?;
+        let current_line = lines.and_then(|l| l.executing);
+        // The line being cut isn't done yet
+        let done = current_line.map_or(0, |line| line - 1);
+        // A job started part way through has only cut from its first line
+        let done_this_run = done.saturating_sub(self.first_sent.map_or(0, |line| line - 1));
+        let percent = self
+            .total_lines
+            .map(|total| done.min(total) as f64 / total as f64 * 100.0);
+        let remaining_seconds = self.total_lines.filter(|_| done_this_run > 0).map(|total| {
+            timing.active_seconds / done_this_run as f64 * total.saturating_sub(done) as f64
+        });
+        Some(JobProgress {
+            state: self.state,
+            lines,
+            timing,
+            current_line,
+            bytes_sent: self.bytes_sent,
+            total_lines: self.total_lines,
+            percent,
+            remaining_seconds,
+        })
+    }
+
+    /// The controller answered the oldest job line in flight with `ok` or `error:`.
+    /// Returns its file line; the caller only passes on acks that belong to the job.
+    pub fn note_ack(&mut self) -> Option<usize> {
+        let line = self.in_flight.pop_front()??;
+        self.recent_acked.push_back(line);
+        if self.recent_acked.len() > RECENT_ACKED {
+            self.recent_acked.pop_front();
+        }
+        Some(line)
+    }
+
+    /// Last file line the controller accepted (1-based), None when no job is running.
+    /// Grbl plans ahead, so the line being cut may be a few lines earlier.
+    pub fn current_line(&self) -> Option<usize> {
+        self.recent_acked
+            .back()
+            .copied()
+            .filter(|_| self.state != JobState::Idle)
+    }
+
+    /// Map the job's lines given how many blocks the planner holds (from the `Bf:` field).
+    /// Grbl plans one block per motion line, so this is an estimate for arcs and non-motion lines.
+    pub fn line_map(&self, planner_queued: usize) -> Option<JobLineMap> {
+        let last_sent = self.last_sent.filter(|_| self.state != JobState::Idle)?;
+        let last_acked = self.current_line().unwrap_or(0);
+        // The oldest block in the planner, or the oldest line remembered
+        let executing = self
+            .recent_acked
+            .len()
+            .checked_sub(1)
+            .map(|newest| self.recent_acked[newest.saturating_sub(planner_queued)]);
+        let planned = executing
+            .filter(|line| *line < last_acked)
+            .and_then(|line| self.recent_acked.iter().find(|l| **l > line))
+            .map(|first| (*first, last_acked));
+        let first_unacked = self.in_flight.iter().flatten().next();
+        Some(JobLineMap {
+            last_sent,
+            last_acked,
+            unacked: first_unacked.map(|first| (*first, last_sent)),
+            planned,
+            executing,
+        })
+    }
+
+    /// The app sent a feed hold (`!`) or cycle start (`~`)
+    pub fn note_app_command(&mut self, byte: u8) {
+        match byte {
+            b'!' => self.app_hold_requested = true,
+            b'~' => self.app_hold_requested = false,
+            _ => {}
+        }
+    }
+
+    /// Feed the state from a status report; returns the change if the job state moved
+    pub fn observe(&mut self, machine_state: &str) -> Option<JobStateChange> {
+        if self.state == JobState::Idle {
+            return None;
+        }
+
+        let next = if machine_state.starts_with("Door") {
+            JobState::DoorOpen
+        } else if machine_state.starts_with("Hold") {
+            JobState::Held
+        } else if machine_state == "Run" {
+            JobState::Running
+        } else {
+            // Idle, Alarm, ... don't say anything about whether the job is paused
+            self.state
+        };
+        if next == self.state {
+            return None;
+        }
+
+        // Holding and door open both count as paused; moving between them keeps the clock
+        if next == JobState::Running {
+            if let Some(since) = self.paused_since.take() {
+                self.paused_total += since.elapsed();
+            }
+        } else if self.paused_since.is_none() {
+            self.paused_since = Some(Instant::now());
+        }
+
+        let change = JobStateChange {
+            previous: self.state,
+            state: next,
+            machine_state: machine_state.to_string(),
+            machine_initiated: !self.app_hold_requested,
+            timing: self.timing().unwrap_or_default(),
+        };
+        self.state = next;
+        if next == JobState::Running {
+            self.app_hold_requested = false;
+        }
+        Some(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running() -> JobMonitor {
+        let mut monitor = JobMonitor::new();
+        monitor.set_streaming(true);
+        monitor
+    }
+
+    #[test]
+    fn lines_are_tracked_by_file_line() {
+        let mut monitor = running();
+        // Lines 1-2 were comments, 5 was blank
+        for line in [3, 4, 6, 7] {
+            monitor.note_sent(Some(line), 10);
+        }
+        assert_eq!(monitor.note_ack(), Some(3));
+        assert_eq!(monitor.note_ack(), Some(4));
+        assert_eq!(monitor.note_ack(), Some(6));
+        assert_eq!(monitor.current_line(), Some(6));
+
+        let map = monitor.line_map(1).unwrap();
+        assert_eq!(map.last_sent, 7);
+        assert_eq!(map.last_acked, 6);
+        assert_eq!(map.unacked, Some((7, 7)));
+        assert_eq!(map.executing, Some(4));
+        assert_eq!(map.planned, Some((6, 6)));
+    }
+
+    #[test]
+    fn setup_lines_and_stray_acks_move_nothing() {
+        let mut monitor = running();
+        monitor.note_sent(None, 20);
+        monitor.note_sent(Some(120), 10);
+        assert_eq!(monitor.note_ack(), None);
+        assert_eq!(monitor.current_line(), None);
+        assert_eq!(monitor.note_ack(), Some(120));
+        // Nothing in flight: an ack for something else
+        assert_eq!(monitor.note_ack(), None);
+        assert_eq!(monitor.current_line(), Some(120));
+        assert_eq!(monitor.next_line(), 121);
+    }
+
+    #[test]
+    fn progress_of_a_resumed_job_counts_from_its_first_line() {
+        let mut monitor = running();
+        monitor.set_total_lines(Some(200));
+        for line in 101..=151 {
+            monitor.note_sent(Some(line), 10);
+            monitor.note_ack();
+        }
+        let progress = monitor.progress(monitor.line_map(0)).unwrap();
+        assert_eq!(progress.current_line, Some(151));
+        assert_eq!(progress.percent, Some(75.0));
+        assert_eq!(monitor.lines_waiting(), Some(49));
+    }
+}
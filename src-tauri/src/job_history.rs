@@ -0,0 +1,76 @@
+use crate::job_comparison::JobRunStats;
+use crate::job_control::JobTiming;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Oldest jobs are dropped beyond this
+const MAX_JOBS: usize = 500;
+
+/// One streamed job and how its time was spent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobHistoryEntry {
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+    /// Tag on the job's console lines (see `ConsoleLog`)
+    #[serde(default)]
+    pub job_id: Option<u64>,
+    #[serde(default)]
+    pub program_name: Option<String>,
+    /// Some(false) when stopped early; None when the streamer didn't say
+    pub completed: Option<bool>,
+    /// Last line of the file the controller acknowledged
+    pub lines: Option<usize>,
+    pub timing: JobTiming,
+    /// None for jobs recorded before these were kept
+    #[serde(default)]
+    pub stats: Option<JobRunStats>,
+}
+
+/// Persistent list of finished jobs, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobHistory {
+    pub jobs: Vec<JobHistoryEntry>,
+}
+
+impl JobHistory {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("job_history.json")
+    }
+
+    pub fn push(&mut self, entry: JobHistoryEntry) {
+        self.jobs.push(entry);
+        if self.jobs.len() > MAX_JOBS {
+            let excess = self.jobs.len() - MAX_JOBS;
+            self.jobs.drain(..excess);
+        }
+    }
+
+    pub fn get(&self, job_id: u64) -> Option<&JobHistoryEntry> {
+        self.jobs.iter().find(|j| j.job_id == Some(job_id))
+    }
+
+    /// The last two runs of a program, earlier first
+    pub fn last_two_runs(
+        &self,
+        program_name: &str,
+    ) -> Option<(&JobHistoryEntry, &JobHistoryEntry)> {
+        let mut runs = self
+            .jobs
+            .iter()
+            .rev()
+            .filter(|j| j.program_name.as_deref() == Some(program_name));
+        let run = runs.next()?;
+        Some((runs.next()?, run))
+    }
+
+    /// Newest first
+    pub fn recent(&self, limit: Option<usize>) -> Vec<JobHistoryEntry> {
+        self.jobs
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,95 @@
+use crate::gcode::{has_code, tokenize_line};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Work coordinate systems a queued job can be assigned to
+pub const WORK_COORDINATE_SYSTEMS: [&str; 6] = ["G54", "G55", "G56", "G57", "G58", "G59"];
+
+/// One library program waiting to run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub program_name: String,
+    /// G54-G59 to select before the job starts, e.g. one per fixture on the table.
+    /// None runs in whatever coordinate system is active.
+    pub wcs: Option<String>,
+}
+
+/// Programs to run one after another in an unattended session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobQueue {
+    pub jobs: Vec<QueuedJob>,
+}
+
+impl JobQueue {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("job_queue.json")
+    }
+
+    /// Normalize WCS names to upper case and reject anything that isn't G54-G59
+    pub fn validate(&mut self) -> Result<()> {
+        for job in &mut self.jobs {
+            if job.program_name.trim().is_empty() {
+                return Err(anyhow!("Queued jobs need a program name"));
+            }
+            if let Some(wcs) = &mut job.wcs {
+                *wcs = wcs.trim().to_ascii_uppercase();
+                if !WORK_COORDINATE_SYSTEMS.contains(&wcs.as_str()) {
+                    return Err(anyhow!(
+                        "{}: {} is not a work coordinate system (G54-G59)",
+                        job.program_name,
+                        wcs
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A queued job's program ready to stream in its coordinate system
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedJob {
+    pub program_name: String,
+    pub wcs: Option<String>,
+    /// The program with the WCS selected on its first line
+    pub content: String,
+    pub warnings: Vec<String>,
+}
+
+/// Select the job's WCS at the top of its program. A program that switches to another
+/// coordinate system itself is refused, since it would cut on the wrong fixture.
+pub fn prepare(job: &QueuedJob, program: &str) -> Result<PreparedJob> {
+    let Some(wcs) = &job.wcs else {
+        return Ok(PreparedJob {
+            program_name: job.program_name.clone(),
+            wcs: None,
+            content: program.to_string(),
+            warnings: Vec::new(),
+        });
+    };
+
+    for (index, line) in program.lines().enumerate() {
+        let words = tokenize_line(line);
+        let other = WORK_COORDINATE_SYSTEMS
+            .iter()
+            .filter(|name| *name != wcs)
+            .find(|name| has_code(&words, 'G', name[1..].parse().unwrap_or(0.0)));
+        if let Some(other) = other {
+            return Err(anyhow!(
+                "{} selects {} on line {}, but it is queued to run in {}",
+                job.program_name,
+                other,
+                index + 1,
+                wcs
+            ));
+        }
+    }
+
+    Ok(PreparedJob {
+        program_name: job.program_name.clone(),
+        wcs: Some(wcs.clone()),
+        content: format!("{}\n{}", wcs, program),
+        warnings: Vec::new(),
+    })
+}
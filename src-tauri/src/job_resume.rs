@@ -0,0 +1,343 @@
+use crate::gcode::{self, Word};
+use crate::job_queue::WORK_COORDINATE_SYSTEMS;
+use crate::motion_model::MotionTracker;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Time for the spindle to come up to speed before the tool goes back down when no
+/// spindle dwell is configured, in seconds
+const SPINDLE_SPIN_UP_SECONDS: f64 = 3.0;
+
+/// Setup that puts the machine back where a program was at a line, so the job can pick up
+/// there instead of from the top. Returned by `start_job_from_line` to show what was sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumePlan {
+    /// Line of the file streaming restarts from, counting from 1
+    pub start_line: usize,
+    /// Sent before that line: the modal state, then the approach from above
+    pub preamble: Vec<String>,
+    /// Work position the tool goes back to, in program units
+    pub position: [f64; 3],
+    /// Height of the approach, in program units
+    pub safe_z: f64,
+    /// `G0` to `G3`, the motion in effect at the line; None after G80
+    pub motion: Option<String>,
+    /// What the replay can't restore, for the operator to check first
+    pub warnings: Vec<String>,
+}
+
+fn axis_words(words: &[Word]) -> Vec<usize> {
+    ['X', 'Y', 'Z']
+        .iter()
+        .enumerate()
+        .filter(|(_, letter)| words.iter().any(|w| w.letter == **letter))
+        .map(|(axis, _)| axis)
+        .collect()
+}
+
+/// Replay the program up to `start_line` and plan the way back to it. The approach rises
+/// to `safe_z` (by default the highest Z the program reached before the line), starts the
+/// spindle and coolant, waiting `spindle_dwell_seconds` for it, moves over the point and
+/// feeds down to it.
+pub fn plan_resume(
+    program: &str,
+    start_line: usize,
+    safe_z: Option<f64>,
+    spindle_dwell_seconds: Option<f32>,
+) -> Result<ResumePlan> {
+    let lines: Vec<&str> = program.lines().collect();
+    let line = start_line
+        .checked_sub(1)
+        .and_then(|index| lines.get(index))
+        .ok_or_else(|| anyhow!("The program has {} lines", lines.len()))?;
+    if gcode::strip_comments(line).trim().is_empty() {
+        return Err(anyhow!("Line {} has no G-code to start from", start_line));
+    }
+
+    let mut tracker = MotionTracker::default();
+    let mut units = "G21";
+    let mut plane = "G17";
+    let mut wcs = "G54";
+    let mut inverse_time = false;
+    let mut feed = None;
+    let mut spindle = None;
+    let mut speed = None;
+    let (mut mist, mut flood) = (false, false);
+    let mut tool = None;
+    let mut tool_changed = false;
+    let mut offsets_changed = None;
+    // Where the job starts is unknown until it moves each axis to an absolute position
+    let mut known = [false; 3];
+    let mut highest_z = None::<f64>;
+
+    for (index, line) in lines[..start_line - 1].iter().enumerate() {
+        let words = gcode::tokenize_line(line);
+        let has = |letter: char, code: f64| gcode::has_code(&words, letter, code);
+        let axes = axis_words(&words);
+        let machine_move = has('G', 28.0) || has('G', 30.0) || has('G', 53.0);
+        if has('G', 10.0) || has('G', 92.0) {
+            offsets_changed.get_or_insert(index + 1);
+        }
+        if machine_move {
+            // G28 or G30 alone sends every axis to its stored position
+            let moved = if axes.is_empty() && !has('G', 53.0) {
+                vec![0, 1, 2]
+            } else {
+                axes.clone()
+            };
+            for axis in moved {
+                known[axis] = false;
+            }
+        }
+
+        units = if has('G', 20.0) {
+            "G20"
+        } else if has('G', 21.0) {
+            "G21"
+        } else {
+            units
+        };
+        for (code, name) in [(17.0, "G17"), (18.0, "G18"), (19.0, "G19")] {
+            if has('G', code) {
+                plane = name;
+            }
+        }
+        if let Some(selected) = WORK_COORDINATE_SYSTEMS
+            .iter()
+            .find(|w| has('G', w[1..].parse().unwrap_or_default()))
+        {
+            wcs = selected;
+        }
+        inverse_time = (inverse_time || has('G', 93.0)) && !has('G', 94.0);
+        feed = gcode::word_value(&words, 'F').or(feed);
+        speed = gcode::word_value(&words, 'S').or(speed);
+        if has('M', 3.0) {
+            spindle = Some("M3");
+        } else if has('M', 4.0) {
+            spindle = Some("M4");
+        } else if has('M', 5.0) || has('M', 2.0) || has('M', 30.0) {
+            spindle = None;
+        }
+        mist = (mist || has('M', 7.0)) && !has('M', 9.0);
+        flood = (flood || has('M', 8.0)) && !has('M', 9.0);
+        if let Some(t) = gcode::word_value(&words, 'T') {
+            tool = Some(t as u32);
+        }
+        tool_changed |= has('M', 6.0);
+
+        let step = tracker.next_move(index + 1, line);
+        if step.is_some() && !machine_move && tracker.is_absolute() {
+            for axis in &axes {
+                known[*axis] = true;
+            }
+        }
+        if known[2] {
+            let z = tracker.position[2];
+            highest_z = Some(highest_z.map_or(z, |h| h.max(z)));
+        }
+    }
+
+    if let Some(axis) = known.iter().position(|k| !k) {
+        return Err(anyhow!(
+            "Where {} is before line {} isn't known from the program; start from a later line",
+            ['X', 'Y', 'Z'][axis],
+            start_line
+        ));
+    }
+    // A line that leaves G93 itself is fine, as the approach is in G94 anyway, but the
+    // feeds before it aren't rates, so the approach takes the line's own
+    let start_words = gcode::tokenize_line(line);
+    if inverse_time {
+        if !gcode::has_code(&start_words, 'G', 94.0) {
+            return Err(anyhow!(
+                "Line {} is in inverse time feed (G93); start outside that section",
+                start_line
+            ));
+        }
+        feed = gcode::word_value(&start_words, 'F');
+    }
+    let feed = feed.ok_or_else(|| anyhow!("No feed rate is set before line {}", start_line))?;
+
+    let scale = if units == "G20" { MM_PER_INCH } else { 1.0 };
+    let position = tracker.position.map(|v| v / scale);
+    let safe_z = match (safe_z, highest_z) {
+        (Some(z), _) => z,
+        (None, Some(z)) => z / scale,
+        (None, None) => return Err(anyhow!("Give a safe Z for the approach")),
+    };
+    if safe_z < position[2] {
+        return Err(anyhow!(
+            "Safe Z {} is below the height to resume at ({})",
+            safe_z,
+            position[2]
+        ));
+    }
+
+    let value = |v: f64| gcode::format_value(v, 4);
+    let mut preamble = vec![
+        format!("{} {} {} G90 G94", units, plane, wcs),
+        format!("G0 Z{}", value(safe_z)),
+    ];
+    if let Some(direction) = spindle {
+        preamble.push(format!("{} S{}", direction, value(speed.unwrap_or(0.0))));
+        let dwell = spindle_dwell_seconds
+            .filter(|s| *s > 0.0)
+            .map_or(SPINDLE_SPIN_UP_SECONDS, f64::from);
+        preamble.push(format!("G4 P{}", gcode::format_value(dwell, 3)));
+    }
+    if mist {
+        preamble.push("M7".to_string());
+    }
+    if flood {
+        preamble.push("M8".to_string());
+    }
+    preamble.push(format!(
+        "G0 X{} Y{}",
+        value(position[0]),
+        value(position[1])
+    ));
+    preamble.push(format!("G1 Z{} F{}", value(position[2]), value(feed)));
+    if !tracker.is_absolute() {
+        preamble.push("G91".to_string());
+    }
+
+    let mut warnings = Vec::new();
+    if let Some(line) = offsets_changed {
+        warnings.push(format!(
+            "Line {} changes work offsets (G10/G92); check they are as the job left them",
+            line
+        ));
+    }
+    if tool_changed {
+        warnings.push(match tool {
+            Some(t) => format!("The job changed tools; T{} should be in the spindle", t),
+            None => "The job changed tools; check the right one is in the spindle".to_string(),
+        });
+    }
+    if spindle.is_some() && speed.is_none() {
+        warnings.push("No spindle speed was set before the line".to_string());
+    }
+
+    Ok(ResumePlan {
+        start_line,
+        preamble,
+        position,
+        safe_z,
+        motion: match tracker.motion_mode() {
+            mode @ 0..=3 => Some(format!("G{}", mode)),
+            _ => None,
+        },
+        warnings,
+    })
+}
+
+/// The approach leaves G1 in effect, so put the program's motion word on the first line
+/// that relies on it. Stops at a line that sets its own.
+pub fn restore_motion_mode<'a>(lines: impl Iterator<Item = &'a mut String>, motion: &str) {
+    for line in lines {
+        let words = gcode::tokenize_line(line);
+        if [0.0, 1.0, 2.0, 3.0, 80.0]
+            .iter()
+            .any(|code| gcode::has_code(&words, 'G', *code))
+        {
+            return;
+        }
+        let non_modal = [10.0, 28.0, 30.0, 53.0, 92.0]
+            .iter()
+            .any(|code| gcode::has_code(&words, 'G', *code));
+        if !non_modal && !axis_words(&words).is_empty() {
+            *line = format!("{} {}", motion, line);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn refuses_a_line_before_every_axis_is_known() {
+        let program = "G21 G90\nG0 Z5\nG0 X1\nG1 Z-1 F100\nG1 X2\n";
+        let error = plan_resume(program, 5, None, None).unwrap_err().to_string();
+        assert!(error.starts_with("Where Y is before line 5"), "{}", error);
+    }
+
+    #[test]
+    fn inch_programs_resume_in_inches() {
+        let program = "G20 G90\nG0 X1 Y2 Z0.5\nG1 Z-0.1 F10\nG1 X2\n";
+        let plan = plan_resume(program, 4, None, None).unwrap();
+        assert_near(plan.position, [1.0, 2.0, -0.1]);
+        assert!((plan.safe_z - 0.5).abs() < 1e-6);
+        assert_eq!(
+            plan.preamble,
+            ["G20 G17 G54 G90 G94", "G0 Z0.5", "G0 X1 Y2", "G1 Z-0.1 F10"]
+        );
+    }
+
+    #[test]
+    fn relative_mode_is_restored_after_the_approach() {
+        let program = "G90\nG0 X0 Y0 Z5\nG1 Z0 F100\nG91\nG1 X1\nG1 X1\n";
+        let plan = plan_resume(program, 6, None, None).unwrap();
+        assert_near(plan.position, [1.0, 0.0, 0.0]);
+        assert_eq!(plan.preamble.last().unwrap(), "G91");
+        assert_eq!(plan.motion.as_deref(), Some("G1"));
+    }
+
+    #[test]
+    fn refuses_to_resume_in_inverse_time() {
+        let program = "G90 G0 X0 Y0 Z1\nG93 G1 X1 F2\nG1 X2 F2\nG94 G1 X3 F100\n";
+        let error = plan_resume(program, 3, None, None).unwrap_err().to_string();
+        assert!(error.contains("inverse time"), "{}", error);
+        // The line leaving G93 can be started from, with its own feed for the approach
+        let plan = plan_resume(program, 4, Some(5.0), None).unwrap();
+        assert_eq!(plan.preamble.last().map(String::as_str), Some("G1 Z1 F100"));
+    }
+
+    #[test]
+    fn spindle_and_coolant_start_before_the_plunge() {
+        let program = "G90 G0 X0 Y0 Z5\nM3 S10000\nM8\nG1 Z-1 F200\nG1 X5\n";
+        let plan = plan_resume(program, 5, Some(10.0), None).unwrap();
+        assert_eq!(
+            plan.preamble,
+            [
+                "G21 G17 G54 G90 G94",
+                "G0 Z10",
+                "M3 S10000",
+                "G4 P3",
+                "M8",
+                "G0 X0 Y0",
+                "G1 Z-1 F200"
+            ]
+        );
+        assert!(plan.warnings.is_empty());
+
+        // The configured spindle dwell replaces the default wait
+        let plan = plan_resume(program, 5, Some(10.0), Some(1.5)).unwrap();
+        assert_eq!(plan.preamble[3], "G4 P1.5");
+    }
+
+    #[test]
+    fn motion_word_goes_on_the_first_line_that_moves() {
+        let mut lines = vec![
+            "G10 L20 P0 X0".to_string(),
+            "M8".to_string(),
+            "X1 Y2".to_string(),
+            "X3".to_string(),
+        ];
+        restore_motion_mode(lines.iter_mut(), "G2");
+        assert_eq!(lines, ["G10 L20 P0 X0", "M8", "G2 X1 Y2", "X3"]);
+
+        let mut lines = vec!["G0 X1".to_string(), "X2".to_string()];
+        restore_motion_mode(lines.iter_mut(), "G2");
+        assert_eq!(lines, ["G0 X1", "X2"]);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::gcode;
+use crate::grbl_codes;
+use crate::job_completion::JobCompletion;
+use crate::job_resume::{self, ResumePlan};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Grbl's serial receive buffer. Character counting keeps the bytes sent but not yet
+/// acknowledged within it, so the planner is fed without waiting on each `ok`.
+pub const RX_BUFFER_SIZE: usize = 128;
+
+/// Time between streaming passes. Each pass tops up the receive buffer and polls status,
+/// which collects the acks.
+pub const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Idle reports in a row after the last ack before the job counts as finished, since the
+/// cycle may not have started when the last line was acked
+const IDLE_REPORTS_TO_FINISH: u32 = 2;
+
+struct JobLine {
+    /// 1-based line in the file, 0 for the setup sent before resuming
+    source_line: usize,
+    text: String,
+}
+
+/// Payload of `cnc:stream-finished`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamFinished {
+    pub program_name: String,
+    pub completed: bool,
+    pub lines_sent: usize,
+    pub total_lines: usize,
+    /// Why the stream stopped early
+    pub error: Option<String>,
+    pub completion: Option<JobCompletion>,
+}
+
+/// A G-code file being streamed with character counting: which lines went out, and how
+/// many bytes of them the controller hasn't acknowledged yet
+pub struct JobStreamer {
+    pub program_name: String,
+    lines: Vec<JobLine>,
+    next: usize,
+    /// Index and bytes of each line sent and not yet acknowledged, oldest first
+    in_flight: VecDeque<(usize, usize)>,
+    /// Reason to stop, once the machine has been held
+    stopping: Option<String>,
+    hold_sent: bool,
+    /// The stream can't go on at all, e.g. the controller reset
+    failed: Option<String>,
+    idle_reports: u32,
+    /// More lines may still be pushed, e.g. while they're piped in
+    input_open: bool,
+}
+
+impl JobStreamer {
+    pub fn load(path: &Path) -> Result<Self> {
+        let program = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().to_string(),
+        );
+        Self::from_program(name, &program)
+    }
+
+    /// Comments and blank lines are dropped; a line too long for the receive buffer can
+    /// never be sent, so it's refused up front
+    pub fn from_program(program_name: String, program: &str) -> Result<Self> {
+        let mut streamer = Self::open(program_name);
+        for (index, line) in program.lines().enumerate() {
+            streamer.push_line(index + 1, line)?;
+        }
+        streamer.close_input();
+        if streamer.lines.is_empty() {
+            return Err(anyhow!("{} has no G-code to send", streamer.program_name));
+        }
+        Ok(streamer)
+    }
+
+    /// A stream whose lines arrive while it runs, through `push_line` until `close_input`
+    pub fn open(program_name: String) -> Self {
+        Self {
+            program_name,
+            lines: Vec::new(),
+            next: 0,
+            in_flight: VecDeque::new(),
+            stopping: None,
+            hold_sent: false,
+            failed: None,
+            idle_reports: 0,
+            input_open: true,
+        }
+    }
+
+    /// Queue `source_line` of the input, skipping it if it's only a comment
+    pub fn push_line(&mut self, source_line: usize, line: &str) -> Result<()> {
+        let text = gcode::strip_comments(line).trim().to_string();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if text.len() + 1 > RX_BUFFER_SIZE {
+            return Err(anyhow!(
+                "Line {} is longer than the controller's {} byte buffer",
+                source_line,
+                RX_BUFFER_SIZE
+            ));
+        }
+        self.lines.push(JobLine { source_line, text });
+        Ok(())
+    }
+
+    /// No more lines will be pushed; the job can finish once the last one has run
+    pub fn close_input(&mut self) {
+        self.input_open = false;
+    }
+
+    /// Start at `plan.start_line` instead of the top, after the plan's setup lines
+    pub fn resume_at(mut self, plan: &ResumePlan) -> Result<Self> {
+        self.lines
+            .retain(|line| line.source_line >= plan.start_line);
+        if let Some(motion) = &plan.motion {
+            job_resume::restore_motion_mode(self.lines.iter_mut().map(|l| &mut l.text), motion);
+        }
+        if let Some(line) = self
+            .lines
+            .iter()
+            .find(|l| l.text.len() + 1 > RX_BUFFER_SIZE)
+        {
+            return Err(anyhow!(
+                "Line {} is too long once its motion word is restored",
+                line.source_line
+            ));
+        }
+        let setup = plan.preamble.iter().map(|text| JobLine {
+            source_line: 0,
+            text: text.clone(),
+        });
+        self.lines.splice(0..0, setup);
+        Ok(self)
+    }
+
+    pub fn total_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Line of the file the job ends on
+    pub fn last_source_line(&self) -> usize {
+        self.lines.last().map_or(0, |line| line.source_line)
+    }
+
+    pub fn lines_sent(&self) -> usize {
+        self.next
+    }
+
+    /// Lines not written yet
+    pub fn lines_waiting(&self) -> usize {
+        self.lines.len() - self.next
+    }
+
+    /// Bytes in the controller's receive buffer, as far as the acks tell
+    pub fn buffered(&self) -> usize {
+        self.in_flight.iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// The next line, if it fits in the receive buffer now and the stream isn't stopping
+    pub fn next_line(&self) -> Option<&str> {
+        if self.stopping.is_some() || self.failed.is_some() {
+            return None;
+        }
+        let line = self.lines.get(self.next)?;
+        // The newline takes a byte of the buffer too
+        let bytes = line.text.len() + 1;
+        (self.buffered() + bytes <= RX_BUFFER_SIZE).then_some(line.text.as_str())
+    }
+
+    /// Line of the file the next line comes from, None for a setup line
+    pub fn next_source_line(&self) -> Option<usize> {
+        self.lines
+            .get(self.next)
+            .map(|line| line.source_line)
+            .filter(|line| *line > 0)
+    }
+
+    /// The next line went out as `bytes` bytes, newline included. Returns its line in the
+    /// file, None for a setup line sent before resuming.
+    pub fn note_sent(&mut self, bytes: usize) -> Option<usize> {
+        self.in_flight.push_back((self.next, bytes));
+        self.next += 1;
+        Some(self.lines[self.next - 1].source_line).filter(|line| *line > 0)
+    }
+
+    /// Line the oldest unacknowledged bytes belong to, for naming the culprit of an error
+    pub fn oldest_unacked(&self) -> Option<&str> {
+        let (index, _) = self.in_flight.front()?;
+        Some(self.lines[*index].text.as_str())
+    }
+
+    /// The controller acknowledged the oldest line. An `error:` stops the stream: Grbl
+    /// skips the line and carries on with the rest, which is rarely safe.
+    pub fn note_ack(&mut self, ack: &str) {
+        let Some((index, _)) = self.in_flight.pop_front() else {
+            return;
+        };
+        if ack.starts_with("error:") {
+            let line = &self.lines[index];
+            let reason = grbl_codes::parse_code(ack, "error:")
+                .map_or("Unrecognised code", grbl_codes::error_message);
+            let which = match line.source_line {
+                0 => "Resume setup line".to_string(),
+                n => format!("Line {}", n),
+            };
+            self.stop(format!(
+                "{} ({}) rejected: {} {}",
+                which, line.text, ack, reason
+            ));
+        }
+    }
+
+    /// Stop sending; the manager holds the machine, then resets it once at rest
+    pub fn stop(&mut self, reason: String) {
+        self.stopping.get_or_insert(reason);
+    }
+
+    pub fn stopping(&self) -> Option<&str> {
+        self.stopping.as_deref()
+    }
+
+    /// Whether the feed hold for stopping still has to be sent; true only once
+    pub fn take_hold(&mut self) -> bool {
+        let due = self.stopping.is_some() && !self.hold_sent;
+        self.hold_sent |= due;
+        due
+    }
+
+    /// A reset threw away whatever the controller had buffered
+    pub fn controller_reset(&mut self) {
+        self.in_flight.clear();
+        self.failed
+            .get_or_insert_with(|| "Controller reset".to_string());
+    }
+
+    pub fn failed(&self) -> Option<&str> {
+        self.failed.as_deref()
+    }
+
+    /// Every line is acked and `idle` says whether the machine reported Idle; true once
+    /// the job has finished moving
+    pub fn note_idle(&mut self, idle: bool) -> bool {
+        if self.input_open || self.next < self.lines.len() || !self.in_flight.is_empty() {
+            return false;
+        }
+        self.idle_reports = if idle { self.idle_reports + 1 } else { 0 };
+        self.idle_reports >= IDLE_REPORTS_TO_FINISH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_stream_only_finishes_once_input_is_closed() {
+        let mut streamer = JobStreamer::open("stdin".to_string());
+        streamer.push_line(1, "(setup)").unwrap();
+        streamer.push_line(2, "G0 X1").unwrap();
+        assert_eq!(streamer.next_source_line(), Some(2));
+        let bytes = streamer.next_line().unwrap().len() + 1;
+        assert_eq!(streamer.note_sent(bytes), Some(2));
+        streamer.note_ack("ok");
+        assert!(!streamer.note_idle(true));
+        assert!(!streamer.note_idle(true));
+
+        streamer.close_input();
+        assert!(!streamer.note_idle(true));
+        assert!(streamer.note_idle(true));
+        assert_eq!(streamer.last_source_line(), 2);
+    }
+
+    #[test]
+    fn character_counting_keeps_the_receive_buffer_from_overflowing() {
+        let line = format!("G1 X{}", "1".repeat(50));
+        let mut streamer = JobStreamer::open("stdin".to_string());
+        for source_line in 1..=3 {
+            streamer.push_line(source_line, &line).unwrap();
+        }
+        while let Some(next) = streamer.next_line() {
+            let bytes = next.len() + 1;
+            streamer.note_sent(bytes);
+        }
+        assert_eq!(streamer.in_flight(), 2);
+        assert!(streamer.buffered() <= RX_BUFFER_SIZE);
+        streamer.note_ack("ok");
+        assert!(streamer.next_line().is_some());
+    }
+
+    #[test]
+    fn lines_too_long_for_the_buffer_are_refused() {
+        let mut streamer = JobStreamer::open("stdin".to_string());
+        assert!(streamer
+            .push_line(7, &format!("G1 X{}", "1".repeat(RX_BUFFER_SIZE)))
+            .is_err());
+        assert_eq!(streamer.lines_waiting(), 0);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::grbl_codes;
+use crate::machine_profile::{self, MachineProfile, AXIS_LETTERS};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Smallest jog that survives formatting to 4 decimal places
+const MIN_JOG_DISTANCE: f32 = 0.0001;
+
+/// Axes assumed when the machine's settings haven't been read yet
+const DEFAULT_AXES: [char; 3] = ['X', 'Y', 'Z'];
+
+/// Why a jog was refused before anything was sent to the controller
+#[derive(Debug, Clone, PartialEq)]
+pub enum JogError {
+    /// Not a single axis letter, e.g. "" or "XY"
+    InvalidAxis(String),
+    /// A valid letter this machine doesn't have
+    UnsupportedAxis(char),
+    /// NaN, infinite or zero
+    InvalidDistance(f32),
+    InvalidFeedRate(u32),
+}
+
+impl fmt::Display for JogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JogError::InvalidAxis(axis) => write!(f, "Invalid jog axis {:?}", axis),
+            JogError::UnsupportedAxis(axis) => write!(f, "This machine has no {} axis", axis),
+            JogError::InvalidDistance(distance) => {
+                write!(f, "Invalid jog distance {}", distance)
+            }
+            JogError::InvalidFeedRate(feed_rate) => {
+                write!(f, "Invalid jog feed rate {}", feed_rate)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JogError {}
+
+/// A jog that has been checked against the machine profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct JogRequest {
+    pub axis: char,
+    /// mm, or degrees on a rotary axis
+    pub distance: f32,
+    /// mm/min (degrees/min on a rotary axis), clamped to the axis max rate when known
+    pub feed_rate: f32,
+}
+
+impl JogRequest {
+    pub fn validate(
+        profile: &MachineProfile,
+        axis: &str,
+        distance: f32,
+        feed_rate: u32,
+    ) -> Result<Self, JogError> {
+        let mut letters = axis.trim().chars();
+        let letter = match (letters.next(), letters.next()) {
+            (Some(letter), None) => letter.to_ascii_uppercase(),
+            _ => return Err(JogError::InvalidAxis(axis.to_string())),
+        };
+        if !AXIS_LETTERS.contains(&letter) {
+            return Err(JogError::InvalidAxis(axis.to_string()));
+        }
+        let limits = profile.axis(letter);
+        let supported = if profile.axes.is_empty() {
+            DEFAULT_AXES.contains(&letter)
+        } else {
+            limits.is_some()
+        };
+        if !supported {
+            return Err(JogError::UnsupportedAxis(letter));
+        }
+
+        if !distance.is_finite() || distance.abs() < MIN_JOG_DISTANCE {
+            return Err(JogError::InvalidDistance(distance));
+        }
+        if feed_rate == 0 {
+            return Err(JogError::InvalidFeedRate(feed_rate));
+        }
+
+        let mut feed = feed_rate as f32;
+        if let Some(max_rate) = limits.and_then(|l| l.max_rate).filter(|r| *r > 0.0) {
+            feed = feed.min(max_rate);
+        }
+
+        Ok(Self {
+            axis: letter,
+            distance,
+            feed_rate: feed,
+        })
+    }
+
+    pub fn rotary(&self) -> bool {
+        machine_profile::is_rotary(self.axis)
+    }
+
+    /// Grbl scales every axis by 25.4 in G20, rotary ones included, so a rotary jog says
+    /// G21 to keep its degrees as degrees
+    fn units(&self) -> &'static str {
+        if self.rotary() {
+            "G21"
+        } else {
+            ""
+        }
+    }
+
+    /// The `$J=` line for this jog
+    pub fn command(&self) -> String {
+        format!(
+            "$J={}G91{}{:.4}F{:.0}",
+            self.units(),
+            self.axis,
+            self.distance,
+            self.feed_rate
+        )
+    }
+
+    /// Grbl 0.9 has no `$J=`: an incremental rapid, then back to absolute mode.
+    /// The rapid runs at the axis max rate, so the feed is ignored. G21 is modal here,
+    /// so a rotary jog leaves the machine in mm.
+    pub fn legacy_commands(&self) -> [String; 2] {
+        let units = if self.rotary() { "G21 " } else { "" };
+        [
+            format!("{}G91 G0 {}{:.4}", units, self.axis, self.distance),
+            "G90".to_string(),
+        ]
+    }
+}
+
+/// A jog this short after being queued may have finished before any report showed it moving
+const JOG_SETTLE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JogOutcome {
+    /// Accepted into the planner; the machine may still be moving
+    Queued,
+    /// Answered with an error, so the machine never moved
+    Rejected,
+    /// The machine went back to Idle afterwards
+    Completed,
+    /// Motion ended some other way: an alarm, a door, a jog cancel into Hold
+    Interrupted,
+}
+
+/// What became of a jog. `jog_cnc` returns it queued or rejected; completion follows as
+/// a `cnc:jog-feedback` event with the same id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JogFeedback {
+    pub id: u64,
+    pub outcome: JogOutcome,
+    pub command: String,
+    pub error_code: Option<u32>,
+    /// Why it was rejected or interrupted, e.g. Grbl's description of error:15 when the
+    /// jog would pass the soft limits
+    pub message: Option<String>,
+}
+
+impl JogFeedback {
+    /// From the controller's answer to the jog line
+    pub fn from_response(id: u64, command: &str, response: &str) -> Self {
+        let error_code = response
+            .lines()
+            .find_map(|line| grbl_codes::parse_code(line, "error:"));
+        Self {
+            id,
+            outcome: if error_code.is_some() {
+                JogOutcome::Rejected
+            } else {
+                JogOutcome::Queued
+            },
+            command: command.to_string(),
+            error_code,
+            message: error_code.map(|code| grbl_codes::error_message(code).to_string()),
+        }
+    }
+}
+
+/// Follows the latest queued jog through status reports to see how it ended. Jogs run in
+/// order, so an earlier one still in flight is taken to end with the latest.
+#[derive(Debug, Default)]
+pub struct JogTracker {
+    next_id: u64,
+    pending: Option<(JogFeedback, Instant, bool)>,
+}
+
+impl JogTracker {
+    pub fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn track(&mut self, feedback: &JogFeedback) {
+        if feedback.outcome == JogOutcome::Queued {
+            self.pending = Some((feedback.clone(), Instant::now(), false));
+        }
+    }
+
+    /// Feed a status report's state; returns the jog's final feedback once it has ended
+    pub fn observe(&mut self, state: &str) -> Option<JogFeedback> {
+        let (_, queued_at, seen_moving) = self.pending.as_mut()?;
+        let base = state.split(':').next().unwrap_or(state);
+        let outcome = match base {
+            "Jog" | "Run" => {
+                *seen_moving = true;
+                return None;
+            }
+            "Idle" if *seen_moving || queued_at.elapsed() >= JOG_SETTLE => JogOutcome::Completed,
+            "Idle" => return None,
+            _ => JogOutcome::Interrupted,
+        };
+        let (mut feedback, _, _) = self.pending.take()?;
+        feedback.outcome = outcome;
+        if outcome == JogOutcome::Interrupted {
+            feedback.message = Some(format!("Jog ended in {}", state));
+        }
+        Some(feedback)
+    }
+
+    /// The connection went away with the jog's fate unknown
+    pub fn clear(&mut self) {
+        self.pending = None;
+    }
+}
+
+/// Time each continuous-jog segment takes at the jog feed
+const SEGMENT_DURATION: Duration = Duration::from_millis(50);
+/// Furthest the machine may keep moving if the UI stops topping up the queue
+const MAX_OVERRUN_MM: f32 = 5.0;
+/// The same for a rotary axis, in degrees
+const MAX_OVERRUN_DEGREES: f32 = 10.0;
+const MAX_QUEUED_SEGMENTS: usize = 8;
+/// Fewest segments kept queued; fewer would leave gaps between them
+const MIN_QUEUED_SEGMENTS: usize = 2;
+/// Used when the axis acceleration hasn't been read from the controller
+const DEFAULT_ACCELERATION: f32 = 200.0;
+/// Feed change per scroll notch or gesture step. Scaling keeps the steps fine at a creep
+/// and coarse at traverse speeds.
+const FEED_NUDGE_FACTOR: f32 = 1.25;
+
+/// Hold-to-jog as a stream of short `$J` segments. Enough segments are kept queued for
+/// smooth motion, but never more than `MAX_OVERRUN_MM` (or `MAX_OVERRUN_DEGREES`) worth,
+/// so the machine stops
+/// within a bounded distance even if the cancel never arrives.
+#[derive(Debug, Clone)]
+pub struct ContinuousJog {
+    /// One segment; every queued segment is identical
+    pub segment: JogRequest,
+    pub max_queued: usize,
+    /// When the last queued segment is expected to finish
+    queued_until: Option<Instant>,
+}
+
+impl ContinuousJog {
+    pub fn plan(
+        profile: &MachineProfile,
+        axis: &str,
+        direction: f32,
+        feed_rate: u32,
+    ) -> Result<Self, JogError> {
+        if !direction.is_finite() || direction == 0.0 {
+            return Err(JogError::InvalidDistance(direction));
+        }
+        let mut segment = JogRequest::validate(profile, axis, direction.signum(), feed_rate)?;
+
+        // The planner must be able to stop within what is queued, so the feed it can
+        // hold is limited by the overrun budget: v = sqrt(2 * a * d). The shortest queue
+        // has to fit in the budget as well.
+        let acceleration = profile
+            .axis(segment.axis)
+            .and_then(|a| a.acceleration)
+            .filter(|a| *a > 0.0)
+            .unwrap_or(DEFAULT_ACCELERATION);
+        let overrun = if segment.rotary() {
+            MAX_OVERRUN_DEGREES
+        } else {
+            MAX_OVERRUN_MM
+        };
+        let min_queue_seconds = MIN_QUEUED_SEGMENTS as f32 * SEGMENT_DURATION.as_secs_f32();
+        let max_feed = (2.0 * acceleration * overrun)
+            .sqrt()
+            .min(overrun / min_queue_seconds)
+            * 60.0;
+        segment.feed_rate = segment.feed_rate.min(max_feed);
+
+        let length = segment.feed_rate / 60.0 * SEGMENT_DURATION.as_secs_f32();
+        let max_queued =
+            ((overrun / length) as usize).clamp(MIN_QUEUED_SEGMENTS, MAX_QUEUED_SEGMENTS);
+        segment.distance = direction.signum() * length.max(MIN_JOG_DISTANCE);
+
+        Ok(Self {
+            segment,
+            max_queued,
+            queued_until: None,
+        })
+    }
+
+    /// Same jog at a different feed, keeping track of what is already queued
+    pub fn with_feed(&self, profile: &MachineProfile, feed_rate: u32) -> Result<Self, JogError> {
+        let mut jog = Self::plan(
+            profile,
+            &self.segment.axis.to_string(),
+            self.segment.distance.signum(),
+            feed_rate,
+        )?;
+        jog.queued_until = self.queued_until;
+        Ok(jog)
+    }
+
+    /// Feed `steps` notches faster, or slower when negative. Each notch moves it by at
+    /// least 1 mm/min, since feeds are sent as whole numbers, and never below 1 mm/min.
+    pub fn nudged_feed(&self, steps: i32) -> u32 {
+        let current = self.segment.feed_rate.round();
+        let scaled = (current * FEED_NUDGE_FACTOR.powi(steps)).round();
+        let moved = if steps > 0 {
+            scaled.max(current + steps as f32)
+        } else {
+            scaled.min(current + steps as f32)
+        };
+        moved.max(1.0) as u32
+    }
+
+    /// Segments to send now to keep the queue topped up
+    pub fn segments_due(&self, now: Instant) -> usize {
+        let queued = match self.queued_until {
+            Some(until) if until > now => {
+                let remaining = until - now;
+                remaining.as_millis().div_ceil(SEGMENT_DURATION.as_millis()) as usize
+            }
+            _ => 0,
+        };
+        self.max_queued.saturating_sub(queued)
+    }
+
+    pub fn record_sent(&mut self, now: Instant) {
+        let start = self.queued_until.filter(|t| *t > now).unwrap_or(now);
+        self.queued_until = Some(start + SEGMENT_DURATION);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine_profile::AxisLimits;
+
+    fn profile(max_rate: Option<f32>, acceleration: Option<f32>) -> MachineProfile {
+        let axes = DEFAULT_AXES
+            .iter()
+            .map(|axis| AxisLimits {
+                axis: *axis,
+                max_travel: 300.0,
+                max_rate,
+                acceleration,
+                steps_per_mm: None,
+            })
+            .collect();
+        MachineProfile {
+            axes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn axis_must_be_a_single_letter() {
+        let profile = profile(None, None);
+        for axis in ["", " ", "XY", "x1", "Q"] {
+            assert_eq!(
+                JogRequest::validate(&profile, axis, 1.0, 100),
+                Err(JogError::InvalidAxis(axis.to_string()))
+            );
+        }
+        assert_eq!(
+            JogRequest::validate(&profile, " y ", 1.0, 100)
+                .unwrap()
+                .axis,
+            'Y'
+        );
+    }
+
+    #[test]
+    fn axis_must_be_on_the_machine() {
+        assert_eq!(
+            JogRequest::validate(&profile(None, None), "A", 1.0, 100),
+            Err(JogError::UnsupportedAxis('A'))
+        );
+        // Before the settings are read only X, Y and Z are assumed
+        let unknown = MachineProfile::default();
+        assert!(JogRequest::validate(&unknown, "Z", 1.0, 100).is_ok());
+        assert_eq!(
+            JogRequest::validate(&unknown, "B", 1.0, 100),
+            Err(JogError::UnsupportedAxis('B'))
+        );
+    }
+
+    #[test]
+    fn distance_must_be_a_usable_number() {
+        let profile = profile(None, None);
+        for distance in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 0.0, 0.00005] {
+            assert!(matches!(
+                JogRequest::validate(&profile, "X", distance, 100),
+                Err(JogError::InvalidDistance(_))
+            ));
+        }
+        assert!(JogRequest::validate(&profile, "X", -MIN_JOG_DISTANCE, 100).is_ok());
+    }
+
+    #[test]
+    fn feed_must_be_positive() {
+        assert_eq!(
+            JogRequest::validate(&profile(None, None), "X", 1.0, 0),
+            Err(JogError::InvalidFeedRate(0))
+        );
+    }
+
+    #[test]
+    fn feed_is_clamped_to_the_axis_max_rate() {
+        let clamped = JogRequest::validate(&profile(Some(1000.0), None), "X", 1.0, 5000).unwrap();
+        assert_eq!(clamped.feed_rate, 1000.0);
+        let unknown = JogRequest::validate(&profile(None, None), "X", 1.0, 5000).unwrap();
+        assert_eq!(unknown.feed_rate, 5000.0);
+    }
+
+    #[test]
+    fn queued_segments_stay_within_the_overrun() {
+        for acceleration in [None, Some(10.0), Some(200.0), Some(2000.0), Some(20000.0)] {
+            let profile = profile(None, acceleration);
+            for feed_rate in [1, 100, 1000, 5000, 20000, 100000] {
+                let jog = ContinuousJog::plan(&profile, "X", -1.0, feed_rate).unwrap();
+                let queued = jog.segment.distance.abs() * jog.max_queued as f32;
+                assert!(
+                    queued <= MAX_OVERRUN_MM + 1e-4,
+                    "{} mm queued at F{} and {:?} mm/s^2",
+                    queued,
+                    feed_rate,
+                    acceleration
+                );
+                assert!(jog.segment.distance < 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn segments_due_never_exceed_the_queue() {
+        let mut jog = ContinuousJog::plan(&profile(None, None), "Y", 1.0, 1000).unwrap();
+        let start = Instant::now();
+        assert_eq!(jog.segments_due(start), jog.max_queued);
+        for sent in 1..=jog.max_queued + 3 {
+            jog.record_sent(start);
+            assert_eq!(jog.segments_due(start), jog.max_queued.saturating_sub(sent));
+        }
+        for elapsed in [0, 10, 50, 120, 400, 5000] {
+            let due = jog.segments_due(start + Duration::from_millis(elapsed));
+            assert!(due <= jog.max_queued, "{} due after {}ms", due, elapsed);
+        }
+        assert_eq!(
+            jog.segments_due(start + Duration::from_secs(60)),
+            jog.max_queued
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How a held key moves the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyJogMode {
+    /// One fixed step per key press
+    Step,
+    /// Move while the key is held
+    Continuous,
+    /// Step on press, switch to continuous when held past the threshold
+    Auto,
+}
+
+/// Key (as reported by the browser's `KeyboardEvent.key`) that jogs an axis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: String,
+    pub axis: String,
+    /// +1 or -1
+    pub direction: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyboardJogConfig {
+    pub mode: KeyJogMode,
+    pub bindings: Vec<KeyBinding>,
+    /// mm per press in step mode
+    pub step_distance: f32,
+    pub step_feed_rate: u32,
+    /// How long a key must be held before auto mode switches to continuous
+    pub hold_threshold_ms: u64,
+    /// Continuous jogs start at this feed and ramp up to the max
+    pub start_feed_rate: u32,
+    pub max_feed_rate: u32,
+    /// Time to ramp from the start feed to the max feed
+    pub ramp_ms: u64,
+}
+
+impl Default for KeyboardJogConfig {
+    fn default() -> Self {
+        let bind = |key: &str, axis: &str, direction: f32| KeyBinding {
+            key: key.to_string(),
+            axis: axis.to_string(),
+            direction,
+        };
+        Self {
+            mode: KeyJogMode::Auto,
+            bindings: vec![
+                bind("ArrowLeft", "X", -1.0),
+                bind("ArrowRight", "X", 1.0),
+                bind("ArrowUp", "Y", 1.0),
+                bind("ArrowDown", "Y", -1.0),
+                bind("PageUp", "Z", 1.0),
+                bind("PageDown", "Z", -1.0),
+            ],
+            step_distance: 1.0,
+            step_feed_rate: 1000,
+            hold_threshold_ms: 300,
+            start_feed_rate: 200,
+            max_feed_rate: 2000,
+            ramp_ms: 1500,
+        }
+    }
+}
+
+impl KeyboardJogConfig {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("keyboard_jog.json")
+    }
+}
+
+/// What the manager should send in response to key events
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyJogAction {
+    Step {
+        axis: String,
+        distance: f32,
+        feed_rate: u32,
+    },
+    StartContinuous {
+        axis: String,
+        direction: f32,
+        feed_rate: u32,
+    },
+    /// Ramp the running continuous jog to a new feed
+    SetFeed(u32),
+    StopContinuous,
+}
+
+struct HeldKey {
+    key: String,
+    axis: String,
+    direction: f32,
+    pressed_at: Instant,
+    /// When continuous motion started, None while still a step
+    continuous_since: Option<Instant>,
+    feed_rate: u32,
+}
+
+/// Turns key-down/key-up events into jog actions, so every view jogs the same way.
+/// Only one key jogs at a time; pressing another replaces it.
+pub struct KeyboardJog {
+    config: KeyboardJogConfig,
+    held: Option<HeldKey>,
+}
+
+impl KeyboardJog {
+    pub fn new(config: KeyboardJogConfig) -> Self {
+        Self { config, held: None }
+    }
+
+    pub fn config(&self) -> &KeyboardJogConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: KeyboardJogConfig) -> Vec<KeyJogAction> {
+        let actions = self.release_all();
+        self.config = config;
+        actions
+    }
+
+    pub fn key_down(&mut self, key: &str, now: Instant) -> Vec<KeyJogAction> {
+        if self.held.as_ref().is_some_and(|h| h.key == key) {
+            // Keyboard auto-repeat
+            return self.tick(now);
+        }
+        let Some(binding) = self.config.bindings.iter().find(|b| b.key == key).cloned() else {
+            return Vec::new();
+        };
+
+        let mut actions = self.release_all();
+        let mut held = HeldKey {
+            key: binding.key,
+            axis: binding.axis,
+            direction: binding.direction.signum(),
+            pressed_at: now,
+            continuous_since: None,
+            feed_rate: self.config.start_feed_rate,
+        };
+        match self.config.mode {
+            KeyJogMode::Step | KeyJogMode::Auto => actions.push(KeyJogAction::Step {
+                axis: held.axis.clone(),
+                distance: held.direction * self.config.step_distance,
+                feed_rate: self.config.step_feed_rate,
+            }),
+            KeyJogMode::Continuous => {
+                held.continuous_since = Some(now);
+                actions.push(KeyJogAction::StartContinuous {
+                    axis: held.axis.clone(),
+                    direction: held.direction,
+                    feed_rate: held.feed_rate,
+                });
+            }
+        }
+        self.held = Some(held);
+        actions
+    }
+
+    pub fn key_up(&mut self, key: &str) -> Vec<KeyJogAction> {
+        if self.held.as_ref().is_some_and(|h| h.key == key) {
+            self.release_all()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Stop whatever is moving, e.g. when the window loses focus
+    pub fn release_all(&mut self) -> Vec<KeyJogAction> {
+        match self.held.take() {
+            Some(held) if held.continuous_since.is_some() => vec![KeyJogAction::StopContinuous],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Advance mode switching and feed ramping for the held key
+    pub fn tick(&mut self, now: Instant) -> Vec<KeyJogAction> {
+        let config = &self.config;
+        let Some(held) = self.held.as_mut() else {
+            return Vec::new();
+        };
+
+        let Some(since) = held.continuous_since else {
+            let threshold = Duration::from_millis(config.hold_threshold_ms);
+            if config.mode != KeyJogMode::Auto || now.duration_since(held.pressed_at) < threshold {
+                return Vec::new();
+            }
+            held.continuous_since = Some(now);
+            held.feed_rate = config.start_feed_rate;
+            return vec![KeyJogAction::StartContinuous {
+                axis: held.axis.clone(),
+                direction: held.direction,
+                feed_rate: held.feed_rate,
+            }];
+        };
+
+        let feed_rate = ramped_feed(config, now.duration_since(since));
+        if feed_rate == held.feed_rate {
+            return Vec::new();
+        }
+        held.feed_rate = feed_rate;
+        vec![KeyJogAction::SetFeed(feed_rate)]
+    }
+}
+
+/// Linear ramp from the start feed to the max feed, in 10% steps so the
+/// continuous jog isn't replanned on every tick
+fn ramped_feed(config: &KeyboardJogConfig, held_for: Duration) -> u32 {
+    let start = config.start_feed_rate.min(config.max_feed_rate);
+    if config.ramp_ms == 0 {
+        return config.max_feed_rate;
+    }
+    let progress = (held_for.as_millis() as f32 / config.ramp_ms as f32).min(1.0);
+    let progress = (progress * 10.0).floor() / 10.0;
+    start + ((config.max_feed_rate - start) as f32 * progress) as u32
+}
@@ -1,7 +1,136 @@
+mod alarm_history;
+mod alerts;
+mod axis_mapping;
+mod bandwidth;
+mod ble_transport;
+mod bookmarks;
+mod calibration;
 mod cnc_comm;
+mod connection_diagnostics;
+mod connection_events;
+mod connection_health;
+mod console_log;
+mod controller_reboot;
+mod controller_warnings;
+mod crash_guard;
+mod discovery;
+mod dro_format;
+mod dust_collection;
+mod execution_heatmap;
+mod fault_injection;
+mod feed_zones;
+mod fixtures;
+pub mod gcode;
+mod gcode_preprocess;
+mod gcode_search;
+mod gcode_templates;
+mod grbl_codes;
+pub mod grbl_protocol;
+mod headless;
+mod height_map;
+mod idle_policy;
+mod job_analysis;
+mod job_checkpoint;
+mod job_comparison;
+mod job_completion;
+mod job_control;
+mod job_history;
+mod job_queue;
+mod job_resume;
+mod job_streamer;
+mod jog;
+mod keyboard_jog;
+mod line_numbering;
+mod machine_profile;
+mod maintenance;
+mod min_z_guard;
+mod modal_resync;
+mod motion_model;
+mod motion_sequences;
+mod pre_run_checklist;
+mod probing;
+mod reconnect;
+mod saved_devices;
+mod serial_ports;
+mod session_log;
+mod settings_audit;
+mod simulation;
+mod spindle_load;
+mod ssh_tunnel;
+mod status_mask;
+mod stock;
+mod storage;
+mod stream_monitor;
+mod telnet_transport;
+mod tls_transport;
+mod tool_change;
+mod tool_library;
+mod transport;
+mod unit_check;
+mod websocket_transport;
+mod wifi_provisioning;
+mod work_offsets;
 
-use cnc_comm::{CncDevice, CncManager};
+use alarm_history::{AlarmKind, AlarmRecord};
+use alerts::{AlertContext, AlertTemplate, AlertWebhooks};
+use axis_mapping::AxisMapping;
+use bandwidth::BandwidthReport;
+use bookmarks::{BookmarkLocation, ProgramLine};
+use calibration::{AxisMeasurement, CalibrationCut, StepsPerMmSuggestion};
+use cnc_comm::{CncDevice, CncManager, CommMetrics, DeviceInfoRefresh, FullState, MachineStatus};
+use connection_diagnostics::{DiagnosticsOptions, DiagnosticsReport};
+use connection_health::{ConnectionHealth, HeartbeatConfig};
+use console_log::{ConsoleBounds, ConsoleEntry, ConsoleFilter, ConsoleLogConfig};
+use controller_warnings::ControllerWarningConfig;
+use crash_guard::CrashGuardConfig;
+use discovery::{DiscoveryConfig, SavedAddress, DEFAULT_TCP_PORT};
+use dro_format::{DroFormat, FormattedAxis};
+use dust_collection::DustCollectionConfig;
+use execution_heatmap::ExecutionHeatmap;
+use fault_injection::FaultConfig;
+use feed_zones::{FeedZone, ZoneSpan};
+use fixtures::{Fixture, FixtureZero};
+use gcode_preprocess::{BacklashCompensation, PreprocessOptions, PreprocessResult};
+use gcode_search::{SearchMatch, SearchQuery};
+use gcode_templates::GcodeTemplate;
+use grbl_protocol::{CoordinateOffsets, Overrides};
+use height_map::{HeightMap, HeightMapGrid, LeveledProgram};
+use idle_policy::IdlePolicy;
+use job_analysis::{OverrideEstimate, StutterOptions, StutterReport};
+use job_checkpoint::JobCheckpoint;
+use job_comparison::JobComparison;
+use job_completion::{CompletionActions, JobCompletion};
+use job_control::{JobLineMap, JobState, JobTiming};
+use job_history::JobHistoryEntry;
+use job_queue::{JobQueue, PreparedJob};
+use job_resume::ResumePlan;
+use jog::JogFeedback;
+use keyboard_jog::KeyboardJogConfig;
+use machine_profile::{AxisRange, ClearanceHeights, GcodeMacro, MachineProfile, SettingsApplyPlan};
+use min_z_guard::MinZGuard;
+use modal_resync::ModalResyncPolicy;
+use motion_model::MotionSettings;
+use pre_run_checklist::{ChecklistConfig, ChecklistResult};
+use probing::ProbeOutcome;
+use reconnect::ReconnectPolicy;
+use saved_devices::SavedDevices;
+use serial_ports::SerialPortEntry;
+use session_log::{MachineAction, SessionAction};
+use settings_audit::SettingAuditEntry;
+use simulation::SimulationState;
+use spindle_load::SpindleLoadConfig;
+use ssh_tunnel::{Tunnel, TunnelConfig, TunnelStatus};
+use status_mask::StatusReportMask;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use stock::{Stock, StockReport, ThicknessMeasurement};
+use stream_monitor::{StallConfig, StreamingPollConfig};
+use tauri::{Emitter, Manager};
+use tool_change::ToolChangeSpot;
+use tool_library::Tool;
+use unit_check::{UnitCheck, Units};
+use wifi_provisioning::{WifiProvisioning, WifiStatus};
+use work_offsets::WorkOffsetChange;
 
 // App state for sharing CNC manager across commands
 struct AppState {
@@ -14,17 +143,163 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Starts a discovery pass and returns at once: devices come as `cnc:device-found`
+/// events, then `cnc:discovery-finished` with the whole list
 #[tauri::command]
-fn discover_cnc_devices(state: tauri::State<AppState>) -> Result<Vec<CncDevice>, String> {
-    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+fn discover_cnc_devices(state: tauri::State<AppState>) -> Result<(), String> {
+    let session = state
+        .cnc_manager
+        .lock()
+        .map_err(|e| e.to_string())?
+        .discovery_session();
+    // The manager isn't held while discovering, so a running job isn't held up
     // Reduced timeout since we connect to first device found
-    manager.discover_devices(3000).map_err(|e| e.to_string())
+    session.spawn(3000);
+    Ok(())
+}
+
+#[tauri::command]
+fn cancel_discovery(state: tauri::State<AppState>) -> Result<(), String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.cancel_discovery();
+    Ok(())
+}
+
+#[tauri::command]
+fn scan_subnet(state: tauri::State<AppState>) -> Result<Vec<CncDevice>, String> {
+    let session = state
+        .cnc_manager
+        .lock()
+        .map_err(|e| e.to_string())?
+        .discovery_session();
+    session.scan_subnet().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_discovery_config(state: tauri::State<AppState>) -> Result<DiscoveryConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.discovery_config())
+}
+
+#[tauri::command]
+fn set_discovery_config(
+    state: tauri::State<AppState>,
+    config: DiscoveryConfig,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_discovery_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn discover_ble_devices(timeout_ms: Option<u64>) -> Result<Vec<CncDevice>, String> {
+    ble_transport::scan(timeout_ms.unwrap_or(5000)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_serial_ports() -> Result<Vec<SerialPortEntry>, String> {
+    serial_ports::list_ports().map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn detect_serial_baud(port_name: String) -> Result<u32, String> {
+    serial_ports::detect_baud_rate(&port_name).map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn connect_to_cnc(
+    device: CncDevice,
+    read_only: Option<bool>,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .connect(&device, read_only.unwrap_or(false))
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit(
+        "cnc:machine-profile-changed",
+        manager.machine_profile().clone(),
+    );
+    Ok(())
+}
+
+/// Connect to a typed-in address; the port defaults to the WiFi module's
+#[tauri::command(rename_all = "snake_case")]
+fn connect_to_address(
+    ip: String,
+    port: Option<u16>,
+    read_only: Option<bool>,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .connect_to_address(
+            &ip,
+            port.unwrap_or(DEFAULT_TCP_PORT),
+            read_only.unwrap_or(false),
+        )
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit(
+        "cnc:machine-profile-changed",
+        manager.machine_profile().clone(),
+    );
+    Ok(())
+}
+
+#[tauri::command]
+fn get_saved_addresses(state: tauri::State<AppState>) -> Result<Vec<SavedAddress>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.saved_addresses().to_vec())
+}
+
+/// Keep a device for connecting to without discovery
+#[tauri::command]
+fn save_device(device: CncDevice, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.save_device(device).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_saved_devices(state: tauri::State<AppState>) -> Result<SavedDevices, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.saved_devices().clone())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn forget_device(machine_key: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .forget_device(&machine_key)
+        .map_err(|e| e.to_string())
+}
+
+/// Drop a TLS device's pinned certificate so the next connection pins the new one
+#[tauri::command(rename_all = "snake_case")]
+fn clear_certificate_pin(machine_key: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .clear_certificate_pin(&machine_key)
+        .map_err(|e| e.to_string())
+}
+
+/// Connect to the last-used saved device when the app starts
+#[tauri::command]
+fn set_auto_connect(enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_auto_connect(enabled).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn connect_to_cnc(device: CncDevice, state: tauri::State<AppState>) -> Result<(), String> {
+fn forget_saved_address(
+    ip: String,
+    port: u16,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
     let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
-    manager.connect(&device).map_err(|e| e.to_string())
+    manager.forget_address(&ip, port).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -40,13 +315,66 @@ fn send_cnc_command(command: String, state: tauri::State<AppState>) -> Result<St
     manager.send_command(&command).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn send_mdi_command(command: String, state: tauri::State<AppState>) -> Result<String, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .send_mdi_command(&command)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_maintenance_mode(state: tauri::State<AppState>) -> Result<bool, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.maintenance_mode())
+}
+
+#[tauri::command]
+fn set_maintenance_mode(enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_maintenance_mode(enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_line_numbering(state: tauri::State<AppState>) -> Result<bool, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.line_numbering())
+}
+
+#[tauri::command]
+fn set_line_numbering(enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_line_numbering(enabled)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_modal_resync_policy(state: tauri::State<AppState>) -> Result<ModalResyncPolicy, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.modal_resync_policy())
+}
+
+#[tauri::command]
+fn set_modal_resync_policy(
+    policy: ModalResyncPolicy,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_modal_resync_policy(policy)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command(rename_all = "snake_case")]
 fn jog_cnc(
     axis: String,
     distance: f32,
     feed_rate: u32,
     state: tauri::State<AppState>,
-) -> Result<String, String> {
+) -> Result<JogFeedback, String> {
     let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
     manager
         .jog(&axis, distance, feed_rate)
@@ -66,12 +394,131 @@ fn jog_cnc_no_wait(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command(rename_all = "snake_case")]
+fn start_continuous_jog(
+    axis: String,
+    direction: f32,
+    feed_rate: u32,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .start_continuous_jog(&axis, direction, feed_rate)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn continue_continuous_jog(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.continue_jog().map_err(|e| e.to_string())
+}
+
+/// Scroll or gesture steps on the running continuous jog's feed; returns the new feed
+#[tauri::command]
+fn nudge_jog_feed(steps: i32, state: tauri::State<AppState>) -> Result<Option<f32>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.nudge_jog_feed(steps).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_continuous_jog(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.stop_continuous_jog().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_keyboard_jog_config(state: tauri::State<AppState>) -> Result<KeyboardJogConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.keyboard_jog_config().clone())
+}
+
+#[tauri::command]
+fn set_keyboard_jog_config(
+    config: KeyboardJogConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_keyboard_jog_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn keyboard_jog_key(
+    key: String,
+    pressed: bool,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .keyboard_jog_key(&key, pressed)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn keyboard_jog_tick(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.keyboard_jog_tick().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn keyboard_jog_release(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.keyboard_jog_release().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn get_cnc_status(state: tauri::State<AppState>) -> Result<String, String> {
     let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
     manager.get_status().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_machine_status(state: tauri::State<AppState>) -> Result<MachineStatus, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.get_machine_status().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_full_state(state: tauri::State<AppState>) -> Result<FullState, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.full_state())
+}
+
+#[tauri::command]
+fn get_dro_format(state: tauri::State<AppState>) -> Result<DroFormat, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.dro_format().clone())
+}
+
+#[tauri::command]
+fn set_dro_format(format: DroFormat, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_dro_format(format).map_err(|e| e.to_string())
+}
+
+/// Format any position (offsets, probe results...) the same way as the DRO
+#[tauri::command]
+fn format_dro_position(
+    values: Vec<f32>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<FormattedAxis>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.dro_format().format_position(&values))
+}
+
+#[tauri::command]
+fn get_parser_state(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.parser_state().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_coordinate_offsets(state: tauri::State<AppState>) -> Result<CoordinateOffsets, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.coordinate_offsets().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn home_cnc(state: tauri::State<AppState>) -> Result<(), String> {
     let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
@@ -97,34 +544,1382 @@ fn check_cnc_alarm_status(state: tauri::State<AppState>) -> Result<String, Strin
 }
 
 #[tauri::command]
-fn write_performance_log(message: String) -> Result<(), String> {
-    use std::fs::OpenOptions;
-    use std::io::Write;
-    use std::time::{SystemTime, UNIX_EPOCH};
-    
-    let log_path = "cnc_performance.log";
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
-    let log_entry = format!("[{}] {}\n", now, message);
-    
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_path)
+fn get_machine_profile(state: tauri::State<AppState>) -> Result<MachineProfile, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.machine_profile().clone())
+}
+
+#[tauri::command]
+fn get_machine_envelope(state: tauri::State<AppState>) -> Result<Vec<AxisRange>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.machine_profile().envelope())
+}
+
+#[tauri::command]
+fn refresh_machine_settings(
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .refresh_machine_settings()
         .map_err(|e| e.to_string())?;
-    
-    file.write_all(log_entry.as_bytes())
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn set_axis_travel(
+    axis: char,
+    max_travel: f32,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .set_axis_travel(axis, max_travel)
         .map_err(|e| e.to_string())?;
-    
-    Ok(())
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
 }
 
 #[tauri::command]
-fn delete_file(path: String) -> Result<(), String> {
-    std::fs::remove_file(&path)
-        .map_err(|e| format!("Failed to delete file {}: {}", path, e))
+fn set_expected_units(
+    units: Units,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .set_expected_units(units)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command]
+fn set_axis_mapping(
+    mapping: AxisMapping,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .set_axis_mapping(mapping)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command]
+fn set_clearance_heights(
+    clearance: ClearanceHeights,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .set_clearance_heights(clearance)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command]
+fn set_crash_guard(
+    config: CrashGuardConfig,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager.set_crash_guard(config).map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command]
+fn set_machine_macros(
+    macros: Vec<GcodeMacro>,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager.set_macros(macros).map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+#[tauri::command]
+fn export_machine_profile(
+    path: String,
+    name: String,
+    description: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .export_profile_bundle(std::path::Path::new(&path), &name, description)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn import_machine_profile(
+    path: String,
+    write_settings: bool,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .import_profile_bundle(std::path::Path::new(&path), write_settings)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+/// The `$` writes importing a profile with `write_settings` would issue, without writing
+#[tauri::command]
+fn preview_machine_profile_import(
+    path: String,
+    state: tauri::State<AppState>,
+) -> Result<SettingsApplyPlan, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .preview_profile_bundle_import(std::path::Path::new(&path))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_fixtures(state: tauri::State<AppState>) -> Result<Vec<Fixture>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.fixtures().fixtures.clone())
+}
+
+/// Add a fixture, or replace the one with the same name
+#[tauri::command]
+fn save_fixture(fixture: Fixture, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.save_fixture(fixture).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_fixture(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.delete_fixture(&name).map_err(|e| e.to_string())
+}
+
+/// Probe a fixture's reference corner and set work zero from it
+#[tauri::command]
+fn zero_from_fixture(name: String, state: tauri::State<AppState>) -> Result<FixtureZero, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.zero_from_fixture(&name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_tool_library(state: tauri::State<AppState>) -> Result<Vec<Tool>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.tool_library().tools.clone())
+}
+
+/// Add a tool, or replace the one with the same number
+#[tauri::command]
+fn save_tool(tool: Tool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.save_tool(tool).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_tool(number: u32, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.delete_tool(number).map_err(|e| e.to_string())
+}
+
+/// Zero an axis on the edge the tool is touching; `direction` is the side the edge is on
+/// (1 or -1), and the diameter comes from the tool library unless given
+#[tauri::command]
+fn set_edge_zero(
+    axis: char,
+    direction: f32,
+    tool: Option<u32>,
+    diameter: Option<f32>,
+    state: tauri::State<AppState>,
+) -> Result<WorkOffsetChange, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_edge_zero(axis, direction, tool, diameter)
+        .map_err(|e| e.to_string())
+}
+
+/// Move the active work zero by (dx, dy, dz) mm
+#[tauri::command]
+fn shift_work_zero(
+    delta: [f32; 3],
+    state: tauri::State<AppState>,
+) -> Result<WorkOffsetChange, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.shift_work_zero(delta).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_tunnels(state: tauri::State<AppState>) -> Result<Vec<TunnelStatus>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.tunnels())
+}
+
+#[tauri::command]
+fn save_tunnel(tunnel: TunnelConfig, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.save_tunnel(tunnel).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_tunnel(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.delete_tunnel(&name).map_err(|e| e.to_string())
+}
+
+/// Open a saved SSH tunnel and return the device to pass to `connect_to_cnc`
+#[tauri::command]
+fn open_tunnel(name: String, state: tauri::State<AppState>) -> Result<CncDevice, String> {
+    let config = match state
+        .cnc_manager
+        .lock()
+        .map_err(|e| e.to_string())?
+        .tunnel_to_open(&name)
+        .map_err(|e| e.to_string())?
+    {
+        Ok(device) => return Ok(device),
+        Err(config) => config,
+    };
+    // Logging in can take a while; the manager isn't held meanwhile
+    let tunnel = Tunnel::open(config).map_err(|e| e.to_string())?;
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.add_tunnel(tunnel))
+}
+
+#[tauri::command]
+fn close_tunnel(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.close_tunnel(&name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn park_cnc(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.park().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn return_to_work_zero(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.return_to_work_zero().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn move_to_tool_change(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.move_to_tool_change().map_err(|e| e.to_string())
+}
+
+/// XY this job's tool changes go to, once its first one is done
+#[tauri::command]
+fn get_tool_change_spot(state: tauri::State<AppState>) -> Result<Option<ToolChangeSpot>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.tool_change_spot())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn probe_z_surface(
+    max_distance: f32,
+    feed_rate: f32,
+    plate_thickness: f32,
+    state: tauri::State<AppState>,
+) -> Result<ProbeOutcome, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .probe_z_surface(max_distance, feed_rate, plate_thickness)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn probe_height_map(
+    stock_id: String,
+    grid: HeightMapGrid,
+    max_depth: f32,
+    feed_rate: f32,
+    state: tauri::State<AppState>,
+) -> Result<HeightMap, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .probe_height_map(&stock_id, grid, max_depth, feed_rate)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_height_maps(state: tauri::State<AppState>) -> Result<Vec<HeightMap>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.height_maps())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn apply_height_map(
+    id: String,
+    program: String,
+    max_segment_length: Option<f64>,
+    state: tauri::State<AppState>,
+) -> Result<LeveledProgram, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .apply_height_map(
+            &id,
+            &program,
+            max_segment_length.unwrap_or(height_map::DEFAULT_SEGMENT_LENGTH),
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_height_map(id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.delete_height_map(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn list_bookmarks(
+    program_name: String,
+    content: String,
+    state: tauri::State<AppState>,
+) -> Result<Vec<BookmarkLocation>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .bookmarks(&program_name, &content)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn add_bookmark(
+    program_name: String,
+    content: String,
+    line: usize,
+    name: String,
+    note: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<BookmarkLocation>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .add_bookmark(&program_name, &content, line, &name, note)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn remove_bookmark(
+    program_name: String,
+    content: String,
+    line: usize,
+    state: tauri::State<AppState>,
+) -> Result<Vec<BookmarkLocation>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .remove_bookmark(&program_name, &content, line)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn jump_to_bookmark(
+    program_name: String,
+    content: String,
+    name: String,
+    state: tauri::State<AppState>,
+) -> Result<BookmarkLocation, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .find_bookmark(&program_name, &content, &name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn get_stock(program_name: String, state: tauri::State<AppState>) -> Result<Option<Stock>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.stock(&program_name).map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn set_stock(
+    program_name: String,
+    stock: Stock,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_stock(&program_name, &stock)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn clear_stock(program_name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .clear_stock(&program_name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn analyze_stock(
+    program_name: String,
+    content: String,
+    state: tauri::State<AppState>,
+) -> Result<Option<StockReport>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .analyze_stock(&program_name, &content)
+        .map_err(|e| e.to_string())
+}
+
+/// Probe the spoilboard at `spoilboard` and the stock top at `stock_top` (work X/Y), and
+/// store the thickness on the program's stock
+#[tauri::command(rename_all = "snake_case")]
+fn measure_stock_thickness(
+    program_name: String,
+    content: String,
+    spoilboard: [f32; 2],
+    stock_top: [f32; 2],
+    max_depth: f32,
+    feed_rate: f32,
+    state: tauri::State<AppState>,
+) -> Result<ThicknessMeasurement, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .measure_stock_thickness(
+            &program_name,
+            &content,
+            spoilboard,
+            stock_top,
+            max_depth,
+            feed_rate,
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn peek_program_lines(content: String, line: usize, context: Option<usize>) -> Vec<ProgramLine> {
+    bookmarks::peek(&content, line, context.unwrap_or(3))
+}
+
+#[tauri::command]
+fn search_gcode(content: String, query: SearchQuery) -> Result<Vec<SearchMatch>, String> {
+    gcode_search::search(&content, &query).map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn find_next_gcode_match(
+    content: String,
+    query: SearchQuery,
+    from_line: usize,
+    backwards: Option<bool>,
+) -> Result<Option<SearchMatch>, String> {
+    gcode_search::find_next(&content, &query, from_line, backwards.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn find_deepest_cut(content: String) -> Option<SearchMatch> {
+    gcode_search::deepest_cut(&content)
+}
+
+#[tauri::command]
+fn get_job_queue(state: tauri::State<AppState>) -> Result<JobQueue, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.job_queue())
+}
+
+#[tauri::command]
+fn set_job_queue(queue: JobQueue, state: tauri::State<AppState>) -> Result<JobQueue, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_job_queue(queue).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn prepare_queued_job(
+    index: usize,
+    content: String,
+    state: tauri::State<AppState>,
+) -> Result<PreparedJob, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .prepare_queued_job(index, &content)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn run_pre_run_checklist(
+    content: String,
+    program_name: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<ChecklistResult, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.run_pre_run_checklist(&content, program_name.as_deref()))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn check_program_units(
+    content: String,
+    program_name: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<UnitCheck, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.check_program_units(&content, program_name.as_deref()))
+}
+
+#[tauri::command]
+fn get_checklist_config(state: tauri::State<AppState>) -> Result<ChecklistConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.checklist_config().clone())
+}
+
+#[tauri::command]
+fn set_checklist_config(
+    config: ChecklistConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_checklist_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn acknowledge_checklist_item(id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.acknowledge_check(&id);
+    Ok(())
+}
+
+/// For jobs the frontend streams; with `total_lines` the backend can also give percent
+/// complete and tell a starved planner from the job's last moves
+#[tauri::command(rename_all = "snake_case")]
+fn set_job_streaming(
+    active: bool,
+    program_name: Option<String>,
+    total_lines: Option<usize>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_job_streaming(active, program_name);
+    manager.set_job_total_lines(total_lines);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_recoverable_job(state: tauri::State<AppState>) -> Result<Option<JobCheckpoint>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.recoverable_job())
+}
+
+#[tauri::command]
+fn dismiss_recoverable_job(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.dismiss_recoverable_job().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_feed_zones(
+    content: String,
+    zones: Vec<FeedZone>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<ZoneSpan>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_feed_zones(&content, &zones)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_min_z_guard(state: tauri::State<AppState>) -> Result<Option<MinZGuard>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.min_z_guard())
+}
+
+#[tauri::command]
+fn set_min_z_guard(guard: Option<MinZGuard>, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_min_z_guard(guard).map_err(|e| e.to_string())
+}
+
+/// Stream a G-code file from a background task; the job ends with `cnc:stream-finished`
+#[tauri::command]
+fn start_job(path: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state
+        .cnc_manager
+        .lock()
+        .map_err(|e| e.to_string())?
+        .start_job(std::path::Path::new(&path))
+        .map_err(|e| e.to_string())?;
+    spawn_stream_task(state.cnc_manager.clone());
+    Ok(())
+}
+
+/// Stream a file from a line part way through, after replaying the state before it.
+/// `spindle_dwell_seconds` is the preprocess setting, if the program is run with one.
+#[tauri::command(rename_all = "snake_case")]
+fn start_job_from_line(
+    path: String,
+    line: usize,
+    safe_z: Option<f64>,
+    spindle_dwell_seconds: Option<f32>,
+    state: tauri::State<AppState>,
+) -> Result<ResumePlan, String> {
+    let plan = state
+        .cnc_manager
+        .lock()
+        .map_err(|e| e.to_string())?
+        .start_job_from_line(
+            std::path::Path::new(&path),
+            line,
+            safe_z,
+            spindle_dwell_seconds,
+        )
+        .map_err(|e| e.to_string())?;
+    spawn_stream_task(state.cnc_manager.clone());
+    Ok(plan)
+}
+
+fn spawn_stream_task(manager: Arc<Mutex<CncManager>>) {
+    // The lock is only held for each pass, so status polls, holds and overrides get through
+    thread::spawn(move || loop {
+        thread::sleep(job_streamer::STREAM_INTERVAL);
+        let Ok(mut manager) = manager.lock() else {
+            break;
+        };
+        if !manager.stream_tick() {
+            break;
+        }
+    });
+}
+
+#[tauri::command]
+fn pause_job(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.pause_job().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resume_job(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.resume_job().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_job(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.stop_job().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn finish_job(completed: bool, state: tauri::State<AppState>) -> Result<JobCompletion, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.finish_job(completed).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_alert_webhooks(state: tauri::State<AppState>) -> Result<AlertWebhooks, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.alert_webhooks().clone())
+}
+
+#[tauri::command]
+fn set_alert_webhooks(
+    webhooks: AlertWebhooks,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_alert_webhooks(webhooks)
+        .map_err(|e| e.to_string())
+}
+
+/// Post a template filled with sample values and return the body that was sent
+#[tauri::command]
+fn send_test_alert(template: AlertTemplate) -> Result<String, String> {
+    AlertWebhooks {
+        templates: vec![template.clone()],
+    }
+    .validate()
+    .map_err(|e| e.to_string())?;
+    let context = AlertContext {
+        machine: Some("Test machine".to_string()),
+        job_name: Some("test.nc".to_string()),
+        duration_seconds: Some(754.0),
+        error: Some("ALARM:1: Hard limit triggered".to_string()),
+    };
+    let body = alerts::render(&template, template.event, &context);
+    alerts::post(&template, &body).map_err(|e| e.to_string())?;
+    Ok(body)
+}
+
+#[tauri::command]
+fn get_idle_policy(state: tauri::State<AppState>) -> Result<IdlePolicy, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.idle_policy().clone())
+}
+
+#[tauri::command]
+fn set_idle_policy(policy: IdlePolicy, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_idle_policy(policy).map_err(|e| e.to_string())
+}
+
+/// Whether the link is actually answering, from heartbeats and other traffic
+#[tauri::command]
+fn get_connection_health(state: tauri::State<AppState>) -> Result<ConnectionHealth, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.connection_health())
+}
+
+#[tauri::command]
+fn get_heartbeat_config(state: tauri::State<AppState>) -> Result<HeartbeatConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.heartbeat_config())
+}
+
+#[tauri::command]
+fn set_heartbeat_config(
+    config: HeartbeatConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_heartbeat_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_reconnect_policy(state: tauri::State<AppState>) -> Result<ReconnectPolicy, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.reconnect_policy())
+}
+
+#[tauri::command]
+fn set_reconnect_policy(
+    policy: ReconnectPolicy,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_reconnect_policy(policy)
+        .map_err(|e| e.to_string())
+}
+
+/// Restart the controller and reconnect; `command` overrides the one picked from the firmware
+#[tauri::command]
+fn reboot_controller(state: tauri::State<AppState>, command: Option<String>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .reboot_controller(command)
+        .map_err(|e| e.to_string())
+}
+
+/// Network and address the controller's WiFi module is on
+#[tauri::command]
+fn get_wifi_config(state: tauri::State<AppState>) -> Result<WifiStatus, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.wifi_status().map_err(|e| e.to_string())
+}
+
+/// Move the controller's WiFi module to another network; the connection closes
+#[tauri::command]
+fn provision_wifi(config: WifiProvisioning, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.provision_wifi(config).map_err(|e| e.to_string())
+}
+
+/// The user is at the machine (pointer, keys): restart the idle clock and wake it if asleep
+#[tauri::command]
+fn wake_machine(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.note_activity();
+    Ok(())
+}
+
+#[tauri::command]
+fn get_dust_collection_config(
+    state: tauri::State<AppState>,
+) -> Result<DustCollectionConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.dust_collection_config().clone())
+}
+
+#[tauri::command]
+fn set_dust_collection_config(
+    config: DustCollectionConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_dust_collection_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_dust_collection(on: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_dust_collection(on).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_completion_actions(state: tauri::State<AppState>) -> Result<CompletionActions, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.completion_actions().clone())
+}
+
+#[tauri::command]
+fn set_completion_actions(
+    actions: CompletionActions,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_completion_actions(actions)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_machine_macro(name: String, state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.run_macro(&name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn refresh_device_info(state: tauri::State<AppState>) -> Result<DeviceInfoRefresh, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.refresh_device_info().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn is_read_only(state: tauri::State<AppState>) -> Result<bool, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.read_only())
+}
+
+#[tauri::command]
+fn is_legacy_grbl(state: tauri::State<AppState>) -> Result<bool, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.legacy_grbl())
+}
+
+#[tauri::command]
+fn get_job_state(state: tauri::State<AppState>) -> Result<JobState, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.job_state())
+}
+
+#[tauri::command]
+fn get_job_timing(state: tauri::State<AppState>) -> Result<Option<JobTiming>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.job_timing())
+}
+
+#[tauri::command]
+fn get_job_line_map(state: tauri::State<AppState>) -> Result<Option<JobLineMap>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.job_line_map())
+}
+
+#[tauri::command]
+fn get_console_lines(
+    filter: ConsoleFilter,
+    state: tauri::State<AppState>,
+) -> Result<Vec<ConsoleEntry>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.console_entries(&filter))
+}
+
+/// Console lines containing `query`, ignoring case; `filter` narrows and pages them
+#[tauri::command]
+fn search_console(
+    query: String,
+    filter: ConsoleFilter,
+    state: tauri::State<AppState>,
+) -> Result<Vec<ConsoleEntry>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.search_console(&filter, &query))
+}
+
+/// Range of `seq` the console holds, to size a view filled with `get_console_lines`
+#[tauri::command]
+fn get_console_bounds(state: tauri::State<AppState>) -> Result<ConsoleBounds, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.console_bounds())
+}
+
+#[tauri::command]
+fn get_console_log_config(state: tauri::State<AppState>) -> Result<ConsoleLogConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.console_log_config().clone())
+}
+
+#[tauri::command]
+fn set_console_log_config(
+    config: ConsoleLogConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_console_log_config(config)
+        .map_err(|e| e.to_string())
+}
+
+/// Write the console lines matching `filter` to a text file, e.g. one job's traffic
+#[tauri::command]
+fn export_console(
+    filter: ConsoleFilter,
+    path: String,
+    state: tauri::State<AppState>,
+) -> Result<usize, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let entries = manager.console_entries(&filter);
+    std::fs::write(&path, console_log::export_text(&entries)).map_err(|e| e.to_string())?;
+    println!("💾 {} console lines written to {}", entries.len(), path);
+    Ok(entries.len())
+}
+
+#[tauri::command]
+fn get_job_history(
+    state: tauri::State<AppState>,
+    limit: Option<usize>,
+) -> Result<Vec<JobHistoryEntry>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.job_history(limit))
+}
+
+/// Why one run of a job differed from another: two job ids, or a program's last two runs
+#[tauri::command(rename_all = "snake_case")]
+fn compare_job_runs(
+    baseline_job_id: Option<u64>,
+    job_id: Option<u64>,
+    program_name: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<JobComparison, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .compare_job_runs(baseline_job_id, job_id, program_name.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_execution_heatmap(
+    state: tauri::State<AppState>,
+) -> Result<Option<ExecutionHeatmap>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.execution_heatmap())
+}
+
+#[tauri::command]
+fn get_alarm_history(
+    state: tauri::State<AppState>,
+    kind: Option<AlarmKind>,
+    code: Option<u32>,
+    limit: Option<usize>,
+) -> Result<Vec<AlarmRecord>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.alarm_history(kind, code, limit))
+}
+
+#[tauri::command]
+fn get_settings_audit(
+    state: tauri::State<AppState>,
+    number: Option<u16>,
+    limit: Option<usize>,
+) -> Result<Vec<SettingAuditEntry>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.settings_audit(number, limit))
+}
+
+#[tauri::command]
+fn revert_setting_change(
+    id: u64,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<MachineProfile, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let profile = manager
+        .revert_setting_change(id)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("cnc:machine-profile-changed", profile.clone());
+    Ok(profile)
+}
+
+/// Zero, WCS, setting and override changes made this session, newest first
+#[tauri::command]
+fn get_session_log(
+    limit: Option<usize>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<SessionAction>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.session_log(limit))
+}
+
+#[tauri::command]
+fn revert_session_action(
+    id: u64,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<SessionAction, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let entry = manager
+        .revert_session_action(id)
+        .map_err(|e| e.to_string())?;
+    if matches!(entry.action, MachineAction::Setting { .. }) {
+        let _ = app.emit(
+            "cnc:machine-profile-changed",
+            manager.machine_profile().clone(),
+        );
+    }
+    Ok(entry)
+}
+
+#[tauri::command]
+fn set_overrides(
+    feed: Option<u32>,
+    rapid: Option<u32>,
+    spindle: Option<u32>,
+    state: tauri::State<AppState>,
+) -> Result<Overrides, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_overrides(feed, rapid, spindle)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_alarm_history(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.clear_alarm_history().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_status_report_mask(state: tauri::State<AppState>) -> Result<StatusReportMask, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.status_report_mask().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_status_report_mask(
+    mask: StatusReportMask,
+    state: tauri::State<AppState>,
+) -> Result<StatusReportMask, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_status_report_mask(mask)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_streaming_poll_config(state: tauri::State<AppState>) -> Result<StreamingPollConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.streaming_poll_config().clone())
+}
+
+#[tauri::command]
+fn set_streaming_poll_config(
+    config: StreamingPollConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_streaming_poll_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_controller_warning_config(
+    state: tauri::State<AppState>,
+) -> Result<ControllerWarningConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.controller_warning_config().clone())
+}
+
+#[tauri::command]
+fn set_controller_warning_config(
+    config: ControllerWarningConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_controller_warning_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_spindle_load_config(state: tauri::State<AppState>) -> Result<SpindleLoadConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.spindle_load_config().clone())
+}
+
+#[tauri::command]
+fn set_spindle_load_config(
+    config: SpindleLoadConfig,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_spindle_load_config(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_stall_config(state: tauri::State<AppState>) -> Result<StallConfig, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.stall_config().clone())
+}
+
+#[tauri::command]
+fn set_stall_config(config: StallConfig, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager.set_stall_config(config).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_fault_injection(state: tauri::State<AppState>) -> Result<Option<FaultConfig>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.fault_injection())
+}
+
+#[tauri::command]
+fn set_fault_injection(
+    config: Option<FaultConfig>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .set_fault_injection(config)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn preprocess_gcode(
+    content: String,
+    options: PreprocessOptions,
+) -> Result<PreprocessResult, String> {
+    options.validate().map_err(|e| e.to_string())?;
+    Ok(gcode_preprocess::preprocess(&content, &options))
+}
+
+/// Preprocess a program and write the result, e.g. a minified copy for a slow link
+#[tauri::command]
+fn export_preprocessed_gcode(
+    content: String,
+    options: PreprocessOptions,
+    path: String,
+) -> Result<PreprocessResult, String> {
+    options.validate().map_err(|e| e.to_string())?;
+    let result = gcode_preprocess::preprocess(&content, &options);
+    std::fs::write(&path, &result.program).map_err(|e| e.to_string())?;
+    println!("💾 Preprocessed program written to {}", path);
+    Ok(result)
+}
+
+/// Built-in parameterized programs and the parameters each one asks for
+#[tauri::command]
+fn get_gcode_templates() -> Vec<GcodeTemplate> {
+    gcode_templates::library()
+}
+
+#[tauri::command]
+fn generate_from_template(
+    id: String,
+    values: std::collections::BTreeMap<String, f32>,
+) -> Result<String, String> {
+    gcode_templates::generate(&id, &values).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn generate_calibration_cut(cut: CalibrationCut) -> Result<String, String> {
+    calibration::generate(&cut).map_err(|e| e.to_string())
+}
+
+/// Steps/mm that would have cut the measured test to size, from the machine's current `$100`-`$102`
+#[tauri::command]
+fn suggest_steps_per_mm(
+    measurements: Vec<AxisMeasurement>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<StepsPerMmSuggestion>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    calibration::suggest_steps_per_mm(&measurements, &manager.machine_profile().firmware_settings)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn suggest_backlash(measurements: Vec<AxisMeasurement>) -> Result<BacklashCompensation, String> {
+    calibration::suggest_backlash(&measurements).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn load_simulation(
+    content: String,
+    state: tauri::State<AppState>,
+) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.load_simulation(&content))
+}
+
+#[tauri::command]
+fn get_simulation_state(state: tauri::State<AppState>) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.simulation().map_err(|e| e.to_string())?.state())
+}
+
+#[tauri::command]
+fn play_simulation(state: tauri::State<AppState>) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.simulation().map_err(|e| e.to_string())?.play())
+}
+
+#[tauri::command]
+fn pause_simulation(state: tauri::State<AppState>) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.simulation().map_err(|e| e.to_string())?.pause())
+}
+
+#[tauri::command]
+fn seek_simulation(seconds: f64, state: tauri::State<AppState>) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .simulation()
+        .and_then(|s| s.seek(seconds))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn seek_simulation_line(
+    line: usize,
+    state: tauri::State<AppState>,
+) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .simulation()
+        .and_then(|s| s.seek_line(line))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_simulation_time_scale(
+    scale: f64,
+    state: tauri::State<AppState>,
+) -> Result<SimulationState, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .simulation()
+        .and_then(|s| s.set_time_scale(scale))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn analyze_feed_stutter(
+    content: String,
+    options: StutterOptions,
+    state: tauri::State<AppState>,
+) -> Result<StutterReport, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let settings = MotionSettings::from_profile(manager.machine_profile());
+    Ok(job_analysis::analyze_stutter(&content, &settings, &options))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+fn estimate_override_times(
+    content: String,
+    override_percents: Option<Vec<u32>>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<OverrideEstimate>, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    let settings = MotionSettings::from_profile(manager.machine_profile());
+    let levels =
+        override_percents.unwrap_or_else(|| job_analysis::DEFAULT_OVERRIDE_LEVELS.to_vec());
+    Ok(job_analysis::estimate_override_times(
+        &content, &settings, &levels,
+    ))
+}
+
+const PERFORMANCE_LOG_PATH: &str = "cnc_performance.log";
+
+/// Traffic per bucket of `bucket_seconds` (default 1) and last-minute rates
+#[tauri::command(rename_all = "snake_case")]
+fn get_bandwidth_report(
+    bucket_seconds: Option<u64>,
+    limit: Option<usize>,
+    state: tauri::State<AppState>,
+) -> Result<BandwidthReport, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.bandwidth_report(bucket_seconds.unwrap_or(1), limit))
+}
+
+/// Time status-query round trips and count unanswered ones; options default to 50
+/// samples 100ms apart
+#[tauri::command]
+fn run_connection_diagnostics(
+    options: Option<DiagnosticsOptions>,
+    state: tauri::State<AppState>,
+) -> Result<DiagnosticsReport, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .run_connection_diagnostics(options.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_comm_metrics(state: tauri::State<AppState>) -> Result<CommMetrics, String> {
+    let manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    Ok(manager.comm_metrics())
+}
+
+#[tauri::command]
+fn export_diagnostics(path: String, state: tauri::State<AppState>) -> Result<String, String> {
+    let mut manager = state.cnc_manager.lock().map_err(|e| e.to_string())?;
+    manager
+        .export_diagnostics(
+            std::path::Path::new(&path),
+            std::path::Path::new(PERFORMANCE_LOG_PATH),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// `timestamp_ms` is the wire time carried by an event (`received_at_ms`); defaults to now
+#[tauri::command(rename_all = "snake_case")]
+fn write_performance_log(message: String, timestamp_ms: Option<u64>) -> Result<(), String> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    
+    let log_path = PERFORMANCE_LOG_PATH;
+    let now = timestamp_ms.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    });
+    let log_entry = format!("[{}] {}\n", now, message);
+    
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .map_err(|e| e.to_string())?;
+    
+    file.write_all(log_entry.as_bytes())
+        .map_err(|e| e.to_string())?;
+    
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_file(path: String) -> Result<(), String> {
+    std::fs::remove_file(&path)
+        .map_err(|e| format!("Failed to delete file {}: {}", path, e))
+}
+
+/// Run a subcommand without the window, e.g. `cnc stream HOST [FILE]`
+pub fn run_headless(args: &[String]) -> anyhow::Result<()> {
+    headless::run(args)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -141,19 +1936,226 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .manage(app_state)
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            let state = app.state::<AppState>();
+            if let Ok(mut manager) = state.cnc_manager.lock() {
+                manager.set_data_dir(data_dir);
+                manager.set_app_handle(app.handle().clone());
+            }
+            // Connecting can take seconds; the window shouldn't wait for it
+            let manager = state.cnc_manager.clone();
+            thread::spawn(move || {
+                if let Ok(mut manager) = manager.lock() {
+                    manager.auto_connect();
+                }
+            });
+            // Sends heartbeats and retries dropped connections, sleeping between them
+            // without the lock held
+            let manager = state.cnc_manager.clone();
+            thread::spawn(move || loop {
+                thread::sleep(reconnect::SUPERVISOR_INTERVAL);
+                if let Ok(mut manager) = manager.lock() {
+                    manager.tunnel_tick();
+                    manager.heartbeat_tick();
+                    manager.reconnect_tick();
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             discover_cnc_devices,
+            cancel_discovery,
+            discover_ble_devices,
+            scan_subnet,
+            get_discovery_config,
+            set_discovery_config,
+            list_serial_ports,
+            detect_serial_baud,
             connect_to_cnc,
+            connect_to_address,
+            get_saved_addresses,
+            forget_saved_address,
+            save_device,
+            list_saved_devices,
+            forget_device,
+            clear_certificate_pin,
+            set_auto_connect,
             disconnect_cnc,
             send_cnc_command,
+            send_mdi_command,
+            get_maintenance_mode,
+            set_maintenance_mode,
+            get_line_numbering,
+            set_line_numbering,
+            get_modal_resync_policy,
+            set_modal_resync_policy,
             jog_cnc,
             jog_cnc_no_wait,
+            start_continuous_jog,
+            continue_continuous_jog,
+            nudge_jog_feed,
+            stop_continuous_jog,
+            get_keyboard_jog_config,
+            set_keyboard_jog_config,
+            keyboard_jog_key,
+            keyboard_jog_tick,
+            keyboard_jog_release,
             get_cnc_status,
+            get_machine_status,
+            get_full_state,
+            get_dro_format,
+            set_dro_format,
+            format_dro_position,
+            get_parser_state,
+            get_coordinate_offsets,
             home_cnc,
             reset_cnc,
             set_cnc_work_zero,
             check_cnc_alarm_status,
+            get_machine_profile,
+            get_machine_envelope,
+            refresh_machine_settings,
+            set_axis_travel,
+            set_clearance_heights,
+            set_crash_guard,
+            set_expected_units,
+            set_axis_mapping,
+            set_machine_macros,
+            export_machine_profile,
+            import_machine_profile,
+            preview_machine_profile_import,
+            get_fixtures,
+            save_fixture,
+            delete_fixture,
+            zero_from_fixture,
+            get_tool_library,
+            save_tool,
+            delete_tool,
+            set_edge_zero,
+            shift_work_zero,
+            get_tunnels,
+            save_tunnel,
+            delete_tunnel,
+            open_tunnel,
+            close_tunnel,
+            park_cnc,
+            return_to_work_zero,
+            move_to_tool_change,
+            get_tool_change_spot,
+            probe_z_surface,
+            probe_height_map,
+            list_height_maps,
+            apply_height_map,
+            delete_height_map,
+            list_bookmarks,
+            add_bookmark,
+            remove_bookmark,
+            jump_to_bookmark,
+            peek_program_lines,
+            search_gcode,
+            find_next_gcode_match,
+            find_deepest_cut,
+            get_stock,
+            set_stock,
+            clear_stock,
+            analyze_stock,
+            measure_stock_thickness,
+            get_job_queue,
+            set_job_queue,
+            prepare_queued_job,
+            run_pre_run_checklist,
+            check_program_units,
+            get_checklist_config,
+            set_checklist_config,
+            acknowledge_checklist_item,
+            set_job_streaming,
+            get_recoverable_job,
+            dismiss_recoverable_job,
+            set_feed_zones,
+            get_min_z_guard,
+            set_min_z_guard,
+            start_job,
+            start_job_from_line,
+            pause_job,
+            resume_job,
+            stop_job,
+            finish_job,
+            get_alert_webhooks,
+            set_alert_webhooks,
+            send_test_alert,
+            get_idle_policy,
+            set_idle_policy,
+            get_connection_health,
+            get_heartbeat_config,
+            set_heartbeat_config,
+            get_reconnect_policy,
+            set_reconnect_policy,
+            reboot_controller,
+            get_wifi_config,
+            provision_wifi,
+            wake_machine,
+            get_dust_collection_config,
+            set_dust_collection_config,
+            set_dust_collection,
+            get_completion_actions,
+            set_completion_actions,
+            run_machine_macro,
+            get_job_state,
+            is_read_only,
+            is_legacy_grbl,
+            refresh_device_info,
+            get_job_timing,
+            get_job_line_map,
+            get_job_history,
+            compare_job_runs,
+            get_execution_heatmap,
+            get_console_lines,
+            export_console,
+            search_console,
+            get_console_bounds,
+            get_console_log_config,
+            set_console_log_config,
+            get_alarm_history,
+            clear_alarm_history,
+            get_settings_audit,
+            revert_setting_change,
+            get_session_log,
+            revert_session_action,
+            set_overrides,
+            get_status_report_mask,
+            set_status_report_mask,
+            get_streaming_poll_config,
+            set_streaming_poll_config,
+            get_spindle_load_config,
+            set_spindle_load_config,
+            get_controller_warning_config,
+            set_controller_warning_config,
+            get_stall_config,
+            set_stall_config,
+            get_fault_injection,
+            set_fault_injection,
+            preprocess_gcode,
+            export_preprocessed_gcode,
+            get_gcode_templates,
+            generate_from_template,
+            generate_calibration_cut,
+            suggest_steps_per_mm,
+            suggest_backlash,
+            load_simulation,
+            get_simulation_state,
+            play_simulation,
+            pause_simulation,
+            seek_simulation,
+            seek_simulation_line,
+            set_simulation_time_scale,
+            analyze_feed_stutter,
+            estimate_override_times,
+            get_comm_metrics,
+            get_bandwidth_report,
+            run_connection_diagnostics,
+            export_diagnostics,
             write_performance_log,
             delete_file
         ])
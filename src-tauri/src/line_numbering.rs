@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+
+/// Numbered lines kept for resending; a resend request further back than this fails
+const HISTORY_LINES: usize = 256;
+
+/// Tells the controller the next line number is 1
+pub const RESET_COMMAND: &str = "M110 N0";
+
+/// Marlin-style line numbering: every line is sent as `N<n> <line>*<checksum>` so the
+/// controller can spot corrupted or dropped lines and ask for them again with `Resend: <n>`
+#[derive(Debug, Clone)]
+pub struct LineNumbering {
+    next: u32,
+    sent: VecDeque<(u32, String)>,
+    /// Line being resent from and how many more requests for it to ignore. The controller
+    /// rejects every line that was in flight behind the bad one, asking for the same line
+    /// each time; only the first request should trigger a resend.
+    swallow: Option<(u32, usize)>,
+}
+
+impl Default for LineNumbering {
+    fn default() -> Self {
+        Self {
+            next: 1,
+            sent: VecDeque::new(),
+            swallow: None,
+        }
+    }
+}
+
+impl LineNumbering {
+    /// Frame a line with the next number and its checksum, remembering it for resends
+    pub fn number(&mut self, line: &str) -> String {
+        let framed = frame(self.next, line);
+        self.sent.push_back((self.next, framed.clone()));
+        if self.sent.len() > HISTORY_LINES {
+            self.sent.pop_front();
+        }
+        self.next += 1;
+        framed
+    }
+
+    /// Lines to send again for a `Resend: <n>` request; empty for a repeat request
+    /// that an earlier resend already covers
+    pub fn resend_from(&mut self, number: u32) -> Result<Vec<String>> {
+        if let Some((pending, remaining)) = self.swallow {
+            if pending == number && remaining > 0 {
+                self.swallow = Some((pending, remaining - 1));
+                return Ok(Vec::new());
+            }
+        }
+        let start = self
+            .sent
+            .iter()
+            .position(|(n, _)| *n == number)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Controller asked to resend line {}, which is no longer kept",
+                    number
+                )
+            })?;
+        let lines: Vec<String> = self
+            .sent
+            .iter()
+            .skip(start)
+            .map(|(_, l)| l.clone())
+            .collect();
+        self.swallow = Some((number, lines.len().saturating_sub(1)));
+        Ok(lines)
+    }
+}
+
+/// `N<n> <line>*<checksum>`, where the checksum XORs every byte before the `*`
+pub fn frame(number: u32, line: &str) -> String {
+    let body = format!("N{} {}", number, line.trim());
+    format!("{}*{}", body, checksum(&body))
+}
+
+pub fn checksum(text: &str) -> u8 {
+    text.bytes().fold(0, |sum, b| sum ^ b)
+}
+
+/// Line number from a resend request, e.g. `Resend: 12`, `Resend:12` or `rs N12`
+pub fn parse_resend(line: &str) -> Option<u32> {
+    let line = line.trim();
+    let rest = line
+        .strip_prefix("Resend:")
+        .or_else(|| line.strip_prefix("rs "))?;
+    rest.trim().trim_start_matches('N').trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_framed_with_number_and_checksum() {
+        let mut numbering = LineNumbering::default();
+        let first = numbering.number(" G1 X1 ");
+        assert_eq!(first, format!("N1 G1 X1*{}", checksum("N1 G1 X1")));
+        assert!(numbering.number("G1 X2").starts_with("N2 G1 X2*"));
+        assert_eq!(checksum("N0 M110"), b"N0 M110".iter().fold(0, |s, b| s ^ b));
+    }
+
+    #[test]
+    fn resend_requests_are_parsed() {
+        assert_eq!(parse_resend("Resend: 12"), Some(12));
+        assert_eq!(parse_resend("Resend:12"), Some(12));
+        assert_eq!(parse_resend("rs N7"), Some(7));
+        assert_eq!(parse_resend("ok"), None);
+    }
+
+    #[test]
+    fn a_resend_covers_the_repeats_for_lines_in_flight() {
+        let mut numbering = LineNumbering::default();
+        let sent: Vec<_> = ["G1 X1", "G1 X2", "G1 X3"]
+            .iter()
+            .map(|l| numbering.number(l))
+            .collect();
+        assert_eq!(numbering.resend_from(2).unwrap(), sent[1..]);
+        // The controller asks again for each line that was behind the bad one
+        assert!(numbering.resend_from(2).unwrap().is_empty());
+        assert_eq!(numbering.resend_from(2).unwrap(), sent[1..]);
+    }
+
+    #[test]
+    fn lines_no_longer_kept_cannot_be_resent() {
+        let mut numbering = LineNumbering::default();
+        for _ in 0..HISTORY_LINES + 1 {
+            numbering.number("G1 X1");
+        }
+        assert!(numbering.resend_from(1).is_err());
+        assert_eq!(numbering.resend_from(2).unwrap().len(), HISTORY_LINES);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::axis_mapping::AxisMapping;
+use crate::crash_guard::CrashGuardConfig;
+use crate::grbl_protocol::BuildInfo;
+use crate::storage;
+use crate::unit_check::Units;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Axis letters in Grbl/grblHAL setting order ($130 = X, $131 = Y, ... $135 = C)
+pub const AXIS_LETTERS: [char; 6] = ['X', 'Y', 'Z', 'A', 'B', 'C'];
+
+/// A, B and C turn rather than slide: positions are degrees and feeds degrees/min
+pub fn is_rotary(axis: char) -> bool {
+    matches!(axis, 'A' | 'B' | 'C')
+}
+
+const STEPS_PER_MM_BASE: u16 = 100;
+const MAX_RATE_BASE: u16 = 110;
+const ACCELERATION_BASE: u16 = 120;
+const MAX_TRAVEL_BASE: u16 = 130;
+
+/// Per-axis limits read from the controller's `$1xx` settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxisLimits {
+    pub axis: char,
+    /// Maximum travel in mm ($130-$135)
+    pub max_travel: f32,
+    /// Maximum rate in mm/min, degrees/min for rotary axes ($110-$115)
+    pub max_rate: Option<f32>,
+    /// Acceleration in mm/sec^2 ($120-$125)
+    pub acceleration: Option<f32>,
+    /// Steps per mm ($100-$105)
+    pub steps_per_mm: Option<f32>,
+}
+
+/// Allowed machine-coordinate range for one axis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxisRange {
+    pub axis: char,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Heights used by the built-in motion sequences instead of hardcoded retracts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearanceHeights {
+    /// Machine Z (G53) to raise to before any XY travel
+    pub safe_z: f32,
+    /// Distance to back off after the probe touches
+    pub probe_clearance: f32,
+    /// Machine Z (G53) for manual tool changes
+    pub tool_change_z: f32,
+}
+
+impl Default for ClearanceHeights {
+    fn default() -> Self {
+        // Just below the Z home switch so the pull-off doesn't trigger it again
+        Self {
+            safe_z: -1.0,
+            probe_clearance: 2.0,
+            tool_change_z: -1.0,
+        }
+    }
+}
+
+/// Description of the connected machine. Axis limits and flags come from the
+/// firmware settings; the rest is user configuration saved per machine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MachineProfile {
+    #[serde(default)]
+    pub axes: Vec<AxisLimits>,
+    #[serde(default)]
+    pub soft_limits_enabled: bool,
+    #[serde(default)]
+    pub homing_enabled: bool,
+    /// Cornering tolerance in mm ($11)
+    #[serde(default)]
+    pub junction_deviation: Option<f32>,
+    #[serde(default)]
+    pub clearance: ClearanceHeights,
+    /// Raw values from the last `$$` dump
+    #[serde(default)]
+    pub firmware_settings: BTreeMap<u16, String>,
+    #[serde(default)]
+    pub macros: Vec<GcodeMacro>,
+    /// `$I` build info of the firmware the profile was last checked against
+    #[serde(default)]
+    pub build_info: Option<BuildInfo>,
+    /// How the UI shows and jogs the axes of this machine
+    #[serde(default)]
+    pub axis_mapping: AxisMapping,
+    /// Travel moves as probe moves, for machines that carry a touch probe
+    #[serde(default)]
+    pub crash_guard: CrashGuardConfig,
+    /// Units programs for this machine are meant to be in; others get a warning before the run
+    #[serde(default)]
+    pub expected_units: Units,
+}
+
+/// A `$$` setting whose value differs between two dumps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingChange {
+    pub number: u16,
+    pub previous: Option<String>,
+    pub current: Option<String>,
+}
+
+/// Settings added, removed or changed between two dumps
+pub fn setting_changes(
+    previous: &BTreeMap<u16, String>,
+    current: &BTreeMap<u16, String>,
+) -> Vec<SettingChange> {
+    let mut numbers: Vec<u16> = previous.keys().chain(current.keys()).copied().collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+        .into_iter()
+        .filter(|n| previous.get(n) != current.get(n))
+        .map(|number| SettingChange {
+            number,
+            previous: previous.get(&number).cloned(),
+            current: current.get(&number).cloned(),
+        })
+        .collect()
+}
+
+/// A `$` write that applying a settings file would issue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingWrite {
+    pub number: u16,
+    /// Value on the controller now, None if it doesn't report the setting
+    pub previous: Option<String>,
+    pub value: String,
+    /// Line sent to the controller, e.g. `$110=5000`
+    pub command: String,
+}
+
+/// What applying a settings file would do, worked out before anything is written
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SettingsApplyPlan {
+    pub writes: Vec<SettingWrite>,
+    /// Settings skipped because the controller already has the value
+    pub unchanged: Vec<u16>,
+}
+
+/// Writes needed to bring `current` to `target`. Values that only differ in how they are
+/// written, like `1000` and `1000.000`, count as identical.
+pub fn plan_setting_writes(
+    current: &BTreeMap<u16, String>,
+    target: &BTreeMap<u16, String>,
+) -> SettingsApplyPlan {
+    let mut plan = SettingsApplyPlan::default();
+    for (number, value) in target {
+        let value = value.trim();
+        let previous = current.get(number);
+        if previous.is_some_and(|p| same_setting_value(p, value)) {
+            plan.unchanged.push(*number);
+            continue;
+        }
+        plan.writes.push(SettingWrite {
+            number: *number,
+            previous: previous.cloned(),
+            value: value.to_string(),
+            command: format!("${}={}", number, value),
+        });
+    }
+    plan
+}
+
+fn same_setting_value(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// A named snippet of G-code the operator can run from the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcodeMacro {
+    pub name: String,
+    pub gcode: String,
+}
+
+/// Current version of the shareable profile file
+pub const PROFILE_BUNDLE_VERSION: u32 = 1;
+
+/// A machine profile with its tuned `$$` settings and macros, packaged as one
+/// file that can be shared for a given machine model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileBundle {
+    pub format_version: u32,
+    /// e.g. "Genmitsu 3018-PRO, stock spindle"
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Firmware the settings were tuned on
+    #[serde(default)]
+    pub firmware: Option<String>,
+    pub profile: MachineProfile,
+}
+
+impl ProfileBundle {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bundle: ProfileBundle = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if bundle.format_version > PROFILE_BUNDLE_VERSION {
+            return Err(anyhow!(
+                "{} was made by a newer version of this app (format {})",
+                path.display(),
+                bundle.format_version
+            ));
+        }
+        MachineProfile::validate_clearance(&bundle.profile.clearance)?;
+        Ok(bundle)
+    }
+}
+
+impl MachineProfile {
+    /// Update the firmware-derived fields from a parsed `$$` dump
+    pub fn apply_settings(&mut self, settings: &BTreeMap<u16, String>) {
+        let number = |key: u16| settings.get(&key).and_then(|v| v.parse::<f32>().ok());
+        let flag = |key: u16| settings.get(&key).map(|v| v != "0");
+
+        self.firmware_settings = settings.clone();
+
+        let mut axes = Vec::new();
+        for (index, axis) in AXIS_LETTERS.iter().enumerate() {
+            let offset = index as u16;
+            // An axis exists if the controller reports a travel setting for it
+            if let Some(max_travel) = number(MAX_TRAVEL_BASE + offset) {
+                axes.push(AxisLimits {
+                    axis: *axis,
+                    max_travel,
+                    max_rate: number(MAX_RATE_BASE + offset),
+                    acceleration: number(ACCELERATION_BASE + offset),
+                    steps_per_mm: number(STEPS_PER_MM_BASE + offset),
+                });
+            }
+        }
+        if !axes.is_empty() {
+            self.axes = axes;
+        }
+
+        if let Some(deviation) = number(11) {
+            self.junction_deviation = Some(deviation);
+        }
+        if let Some(enabled) = flag(20) {
+            self.soft_limits_enabled = enabled;
+        }
+        if let Some(enabled) = flag(22) {
+            self.homing_enabled = enabled;
+        }
+    }
+
+    /// Where the profile for a machine is saved inside the app data directory
+    pub fn path_for(data_dir: &Path, machine_key: &str) -> PathBuf {
+        data_dir
+            .join("profiles")
+            .join(format!("{}.json", storage::file_key(machine_key)))
+    }
+
+    /// Check clearance heights are usable machine Z positions
+    pub fn validate_clearance(clearance: &ClearanceHeights) -> Result<()> {
+        if !clearance.safe_z.is_finite() || !clearance.tool_change_z.is_finite() {
+            return Err(anyhow!("Safe and tool change heights must be numbers"));
+        }
+        if clearance.safe_z > 0.0 || clearance.tool_change_z > 0.0 {
+            return Err(anyhow!(
+                "Safe and tool change heights are machine Z positions and must be 0 or below"
+            ));
+        }
+        if !clearance.probe_clearance.is_finite() || clearance.probe_clearance <= 0.0 {
+            return Err(anyhow!("Probe clearance must be greater than 0"));
+        }
+        Ok(())
+    }
+
+    pub fn axis(&self, axis: char) -> Option<&AxisLimits> {
+        let axis = axis.to_ascii_uppercase();
+        self.axes.iter().find(|a| a.axis == axis)
+    }
+
+    /// Grbl setting number holding the travel limit for an axis
+    pub fn travel_setting_number(axis: char) -> Option<u16> {
+        let axis = axis.to_ascii_uppercase();
+        AXIS_LETTERS
+            .iter()
+            .position(|a| *a == axis)
+            .map(|index| MAX_TRAVEL_BASE + index as u16)
+    }
+
+    /// Machine-coordinate envelope. Grbl homes to the top of each axis and treats
+    /// machine space as running from -max_travel up to zero.
+    pub fn envelope(&self) -> Vec<AxisRange> {
+        self.axes
+            .iter()
+            .map(|a| AxisRange {
+                axis: a.axis,
+                min: -a.max_travel,
+                max: 0.0,
+            })
+            .collect()
+    }
+
+    /// Check a machine-coordinate target against the soft limits, if enabled
+    pub fn check_soft_limits(&self, axis: char, machine_pos: f32) -> Result<()> {
+        if !self.soft_limits_enabled {
+            return Ok(());
+        }
+        let Some(limits) = self.axis(axis) else {
+            return Ok(());
+        };
+        if machine_pos > 0.0 || machine_pos < -limits.max_travel {
+            return Err(anyhow!(
+                "{} target {:.3} is outside machine travel (-{:.3} to 0)",
+                limits.axis,
+                machine_pos,
+                limits.max_travel
+            ));
+        }
+        Ok(())
+    }
+}
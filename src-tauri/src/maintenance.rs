@@ -0,0 +1,42 @@
+use crate::gcode::{has_code, tokenize_line};
+
+/// Why a line can't be sent in maintenance mode, or None if it doesn't move anything.
+/// Status, settings, offsets and unlock are still allowed.
+pub fn blocked_reason(line: &str) -> Option<&'static str> {
+    for line in line.lines() {
+        let trimmed = line.trim().to_ascii_uppercase();
+        if trimmed == "~" {
+            return Some("cycle start");
+        }
+        if trimmed.starts_with("$H") {
+            return Some("homing");
+        }
+        if trimmed.starts_with("$J=") {
+            return Some("jogging");
+        }
+
+        let words = tokenize_line(line);
+        if [3.0, 4.0].iter().any(|code| has_code(&words, 'M', *code)) {
+            return Some("spindle start");
+        }
+        if [7.0, 8.0].iter().any(|code| has_code(&words, 'M', *code)) {
+            return Some("coolant");
+        }
+        // G0-G3, probing, and G28/G30 travel to their stored positions
+        let motion = [0.0, 1.0, 2.0, 3.0, 38.2, 38.3, 38.4, 38.5, 28.0, 30.0];
+        if motion.iter().any(|code| has_code(&words, 'G', *code)) {
+            return Some("motion");
+        }
+        // Bare axis words continue the modal motion, unless they set offsets
+        let sets_offsets = [10.0, 92.0, 43.1, 28.1, 30.1]
+            .iter()
+            .any(|code| has_code(&words, 'G', *code));
+        let has_axis = words
+            .iter()
+            .any(|w| matches!(w.letter, 'X' | 'Y' | 'Z' | 'A' | 'B' | 'C'));
+        if has_axis && !sets_offsets {
+            return Some("motion");
+        }
+    }
+    None
+}
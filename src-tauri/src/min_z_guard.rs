@@ -0,0 +1,181 @@
+use crate::gcode;
+use crate::motion_model::MotionTracker;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Allowance for rounding in the program's coordinates, in mm
+const TOLERANCE: f64 = 0.0005;
+
+/// What the streamer does when a line would take the tool below the floor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MinZAction {
+    /// Feed hold with the line unsent, so the operator can look before stopping the job.
+    /// A streamed job that's resumed holds again at the same line.
+    #[default]
+    Hold,
+    /// Soft reset, emptying the planner at once. Grbl loses position if it was moving.
+    Abort,
+}
+
+/// "Never go below Z = `min_z` in work coordinates" for one job, as a last resort against
+/// a CAM setup with the wrong origin. Lines are checked before they're sent, so the
+/// offending line never reaches the controller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinZGuard {
+    /// Lowest work Z allowed, in mm
+    pub min_z: f64,
+    #[serde(default)]
+    pub action: MinZAction,
+}
+
+impl MinZGuard {
+    pub fn validate(&self) -> Result<()> {
+        if !self.min_z.is_finite() {
+            return Err(anyhow!("Minimum Z must be a number"));
+        }
+        Ok(())
+    }
+}
+
+/// Payload of `cnc:min-z-violation`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinZViolation {
+    /// Line of the file, counting from 1; None for a line that isn't in it, such as MDI
+    /// during a pause or the setup before resuming part way through
+    pub job_line: Option<usize>,
+    pub command: String,
+    /// Work Z the line moves to, in mm
+    pub z: f64,
+    pub min_z: f64,
+    pub action: MinZAction,
+}
+
+/// Follows the job's lines through the motion model. Z is only known once the job has
+/// moved it to an absolute height, or the job started from a known work position:
+/// G53, G28 and G30 leave it unknown until then, as they move in machine coordinates,
+/// and so do G10 and G92, which move the work origin, and G43.1 and G49, which change
+/// the tool length offset.
+pub struct MinZTracker {
+    guard: MinZGuard,
+    motion: MotionTracker,
+    z_known: bool,
+}
+
+impl MinZTracker {
+    /// `work_pos` is where the machine is as the job starts, if a status report said
+    pub fn new(guard: MinZGuard, work_pos: Option<&[f32]>) -> Self {
+        let start = work_pos
+            .filter(|p| p.len() >= 3)
+            .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64]);
+        Self {
+            guard,
+            motion: MotionTracker::starting_at(start.unwrap_or_default()),
+            z_known: start.is_some(),
+        }
+    }
+
+    /// Check lines about to be sent, the first of them from `first_line` of the file. They
+    /// pass together or not at all, so after a violation none of them count as sent.
+    pub fn check(
+        &mut self,
+        first_line: Option<usize>,
+        lines: &[&str],
+    ) -> Result<(), MinZViolation> {
+        let mut motion = self.motion.clone();
+        let mut z_known = self.z_known;
+        for (index, line) in lines.iter().enumerate() {
+            let job_line = first_line.map(|first| first + index);
+            let step = motion.next_move(job_line.unwrap_or(0), line);
+            let words = gcode::tokenize_line(line);
+            let has_code = |code: f64| gcode::has_code(&words, 'G', code);
+            let moves_z = gcode::word_value(&words, 'Z').is_some();
+            let machine_move = has_code(28.0) || has_code(30.0) || has_code(53.0);
+            // G28 or G30 alone sends every axis to its stored position
+            let no_axes = !words.iter().any(|w| matches!(w.letter, 'X' | 'Y' | 'Z'));
+            let offset_change =
+                has_code(10.0) || has_code(92.0) || has_code(43.1) || has_code(49.0);
+            if offset_change || (machine_move && (moves_z || no_axes)) {
+                z_known = false;
+                continue;
+            }
+            let Some(step) = step.filter(|_| moves_z) else {
+                continue;
+            };
+            // An incremental Z from an unknown height lands somewhere unknown
+            z_known = z_known || motion.is_absolute();
+            if z_known && step.end[2] < self.guard.min_z - TOLERANCE {
+                return Err(MinZViolation {
+                    job_line,
+                    command: line.to_string(),
+                    z: step.end[2],
+                    min_z: self.guard.min_z,
+                    action: self.guard.action,
+                });
+            }
+        }
+        self.motion = motion;
+        self.z_known = z_known;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(work_pos: Option<&[f32]>) -> MinZTracker {
+        let guard = MinZGuard {
+            min_z: -5.0,
+            action: MinZAction::Hold,
+        };
+        MinZTracker::new(guard, work_pos)
+    }
+
+    #[test]
+    fn z_is_unknown_until_the_first_absolute_move() {
+        let mut tracker = tracker(None);
+        let violation = tracker
+            .check(Some(10), &["G91 G1 Z-10 F100", "G90 G0 Z1", "G1 Z-6"])
+            .unwrap_err();
+        assert_eq!(violation.job_line, Some(12));
+        assert_eq!(violation.command, "G1 Z-6");
+        assert!((violation.z + 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_known_start_catches_incremental_moves() {
+        let mut tracker = tracker(Some(&[0.0, 0.0, 2.0]));
+        let violation = tracker.check(Some(1), &["G91 G1 Z-8 F100"]).unwrap_err();
+        assert!((violation.z + 6.0).abs() < 1e-9);
+        // Rejected lines don't count as sent
+        assert!(tracker.check(Some(1), &["G1 Z-1 F100"]).is_ok());
+    }
+
+    #[test]
+    fn machine_moves_make_z_unknown_again() {
+        let mut tracker = tracker(Some(&[0.0, 0.0, 2.0]));
+        assert!(tracker
+            .check(None, &["G53 G0 Z-1", "G91 G1 Z-100 F100"])
+            .is_ok());
+    }
+
+    #[test]
+    fn tool_length_offsets_are_not_moves() {
+        let mut tracker = tracker(Some(&[0.0, 0.0, 2.0]));
+        assert!(tracker.check(Some(1), &["G43.1 Z-20"]).is_ok());
+        // Z is unknown under the new offset until an absolute move
+        assert!(tracker.check(Some(2), &["G91 G1 Z-10 F100"]).is_ok());
+        assert!(tracker.check(Some(3), &["G49", "G91 G1 Z-10"]).is_ok());
+        assert!(tracker.check(Some(5), &["G90 G1 Z-6"]).is_err());
+    }
+
+    #[test]
+    fn inch_coordinates_are_compared_in_mm() {
+        let mut tracker = tracker(None);
+        assert!(tracker.check(Some(1), &["G20 G90 G1 Z-0.1 F10"]).is_ok());
+        let violation = tracker.check(Some(2), &["G1 Z-0.25"]).unwrap_err();
+        assert_eq!(violation.job_line, Some(2));
+        assert!((violation.z + 6.35).abs() < 1e-6);
+    }
+}
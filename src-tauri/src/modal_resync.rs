@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// What to do when MDI during a pause left the parser in a different modal state than the job
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModalResyncPolicy {
+    /// Send the job's modal words back before resuming
+    #[default]
+    Restore,
+    /// Refuse to resume until the user puts the state back
+    Block,
+}
+
+/// Modal groups compared before resuming. Motion mode matters because a job line that
+/// only has axis words continues the previous G0/G1.
+const GROUPS: [(&str, &[&str]); 9] = [
+    (
+        "motion mode",
+        &[
+            "G0", "G1", "G2", "G3", "G38.2", "G38.3", "G38.4", "G38.5", "G80",
+        ],
+    ),
+    (
+        "work coordinate system",
+        &["G54", "G55", "G56", "G57", "G58", "G59"],
+    ),
+    ("plane", &["G17", "G18", "G19"]),
+    ("units", &["G20", "G21"]),
+    ("distance mode", &["G90", "G91"]),
+    ("feed rate mode", &["G93", "G94"]),
+    ("tool length offset", &["G43.1", "G49"]),
+    ("spindle", &["M3", "M4", "M5"]),
+    ("coolant", &["M7", "M8", "M9"]),
+];
+
+/// Groups that can't safely be put back by sending their word: restoring an arc or probe
+/// mode needs axis words, G43.1 needs its offset, and spindle or coolant changes should
+/// be the user's call
+const MANUAL_ONLY: [&str; 3] = ["tool length offset", "spindle", "coolant"];
+
+/// A modal group whose value changed while the job was paused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModalChange {
+    pub group: String,
+    /// What the job expects, e.g. "G21"
+    pub expected: String,
+    /// What the parser has now, e.g. "G20"
+    pub actual: String,
+}
+
+impl ModalChange {
+    pub fn restorable(&self) -> bool {
+        if MANUAL_ONLY.contains(&self.group.as_str()) {
+            return false;
+        }
+        self.group != "motion mode" || matches!(self.expected.as_str(), "G0" | "G1" | "G80")
+    }
+}
+
+/// Compare `$G` words from before and after the MDI
+pub fn modal_changes(expected: &[String], actual: &[String]) -> Vec<ModalChange> {
+    let mut changes = Vec::new();
+    for (group, words) in GROUPS {
+        let find = |state: &[String]| state.iter().find(|w| words.contains(&w.as_str())).cloned();
+        if let (Some(expected), Some(actual)) = (find(expected), find(actual)) {
+            if expected != actual {
+                changes.push(ModalChange {
+                    group: group.to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    let feed = |state: &[String]| {
+        state
+            .iter()
+            .find_map(|w| w.strip_prefix('F')?.parse::<f64>().ok())
+    };
+    if let (Some(expected), Some(actual)) = (feed(expected), feed(actual)) {
+        if (expected - actual).abs() > 1e-6 {
+            changes.push(ModalChange {
+                group: "feed rate".to_string(),
+                expected: format!("F{}", expected),
+                actual: format!("F{}", actual),
+            });
+        }
+    }
+    changes
+}
+
+/// The line that puts every change back, or the changes that can't be restored automatically
+pub fn restore_line(changes: &[ModalChange]) -> Result<String, Vec<ModalChange>> {
+    let manual: Vec<ModalChange> = changes
+        .iter()
+        .filter(|c| !c.restorable())
+        .cloned()
+        .collect();
+    if !manual.is_empty() {
+        return Err(manual);
+    }
+    let words: Vec<&str> = changes.iter().map(|c| c.expected.as_str()).collect();
+    Ok(words.join(" "))
+}
+
+/// "units G20 (job uses G21), ..." for error messages
+pub fn describe(changes: &[ModalChange]) -> String {
+    changes
+        .iter()
+        .map(|c| format!("{} {} (job uses {})", c.group, c.actual, c.expected))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
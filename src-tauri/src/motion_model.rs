@@ -0,0 +1,465 @@
+use crate::gcode::{tokenize_line, word_value, Word};
+use crate::machine_profile::MachineProfile;
+use serde::{Deserialize, Serialize};
+
+const MM_PER_INCH: f64 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MoveKind {
+    Rapid,
+    Linear,
+    Arc,
+    Dwell,
+}
+
+/// One motion (or dwell) produced by a program line, in mm and work coordinates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+    /// 1-based line number in the source program
+    pub line: usize,
+    pub kind: MoveKind,
+    pub start: [f64; 3],
+    pub end: [f64; 3],
+    /// Programmed feed in mm/min (0 for rapids and dwells)
+    pub feed_rate: f64,
+    /// Path length in mm (arc length for arcs)
+    pub length: f64,
+    pub dwell_seconds: f64,
+}
+
+/// Machine dynamics used when estimating how a program will execute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotionSettings {
+    /// mm/sec^2
+    pub acceleration: f64,
+    /// mm/min
+    pub rapid_rate: f64,
+    /// mm
+    pub junction_deviation: f64,
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        // Conservative values for a stock 3018-class machine
+        Self {
+            acceleration: 200.0,
+            rapid_rate: 1000.0,
+            junction_deviation: 0.01,
+        }
+    }
+}
+
+impl MotionSettings {
+    /// Use the slowest of the X/Y axes, since most cutting happens in the XY plane
+    pub fn from_profile(profile: &MachineProfile) -> Self {
+        let mut settings = Self::default();
+        let xy = profile
+            .axes
+            .iter()
+            .filter(|a| a.axis == 'X' || a.axis == 'Y');
+
+        let accelerations: Vec<f32> = xy.clone().filter_map(|a| a.acceleration).collect();
+        if !accelerations.is_empty() {
+            settings.acceleration = accelerations.iter().cloned().fold(f32::MAX, f32::min) as f64;
+        }
+        let rates: Vec<f32> = xy.filter_map(|a| a.max_rate).collect();
+        if !rates.is_empty() {
+            settings.rapid_rate = rates.iter().cloned().fold(f32::MAX, f32::min) as f64;
+        }
+        if let Some(deviation) = profile.junction_deviation {
+            settings.junction_deviation = deviation as f64;
+        }
+        settings
+    }
+}
+
+/// Sequence of moves derived from a G-code program by tracking modal state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MotionModel {
+    pub moves: Vec<Move>,
+}
+
+/// Position and modal state carried from line to line, so a program can be followed as
+/// it streams. Positions are in mm and work coordinates.
+#[derive(Debug, Clone)]
+pub struct MotionTracker {
+    pub position: [f64; 3],
+    motion_mode: u32,
+    absolute: bool,
+    scale: f64,
+    feed_rate: f64,
+}
+
+impl Default for MotionTracker {
+    fn default() -> Self {
+        Self::starting_at([0.0; 3])
+    }
+}
+
+impl MotionTracker {
+    pub fn starting_at(position: [f64; 3]) -> Self {
+        Self {
+            position,
+            motion_mode: 0,
+            absolute: true,
+            scale: 1.0,
+            feed_rate: 0.0,
+        }
+    }
+
+    /// G90 is in effect
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    /// Modal motion: 0 to 3 for G0 to G3, 80 after G80
+    pub fn motion_mode(&self) -> u32 {
+        self.motion_mode
+    }
+
+    /// Apply one line; returns the move it makes, if any. `line_number` is 1-based.
+    pub fn next_move(&mut self, line_number: usize, line: &str) -> Option<Move> {
+        let words = tokenize_line(line);
+        if words.is_empty() {
+            return None;
+        }
+
+        let mut dwell = false;
+        let mut non_modal_move = false;
+        for word in words.iter().filter(|w| w.letter == 'G') {
+            match (word.value * 10.0).round() as u32 {
+                0 => self.motion_mode = 0,
+                10 => self.motion_mode = 1,
+                20 => self.motion_mode = 2,
+                30 => self.motion_mode = 3,
+                40 => dwell = true,
+                200 => self.scale = MM_PER_INCH,
+                210 => self.scale = 1.0,
+                // G28/G30/G53 reference machine positions this model doesn't know about
+                280 | 300 | 530 => non_modal_move = true,
+                800 => self.motion_mode = 80,
+                900 => self.absolute = true,
+                910 => self.absolute = false,
+                _ => {}
+            }
+        }
+
+        if let Some(f) = word_value(&words, 'F') {
+            self.feed_rate = f * self.scale;
+        }
+
+        if dwell {
+            return Some(Move {
+                line: line_number,
+                kind: MoveKind::Dwell,
+                start: self.position,
+                end: self.position,
+                feed_rate: 0.0,
+                length: 0.0,
+                dwell_seconds: word_value(&words, 'P').unwrap_or(0.0),
+            });
+        }
+
+        let has_axis = words.iter().any(|w| matches!(w.letter, 'X' | 'Y' | 'Z'));
+        if !has_axis || self.motion_mode == 80 {
+            return None;
+        }
+
+        let mut target = self.position;
+        for (axis, letter) in ['X', 'Y', 'Z'].iter().enumerate() {
+            if let Some(value) = word_value(&words, *letter) {
+                // G53/G28/G30 targets are always absolute
+                target[axis] = if self.absolute || non_modal_move {
+                    value * self.scale
+                } else {
+                    self.position[axis] + value * self.scale
+                };
+            }
+        }
+
+        let (kind, length) = match self.motion_mode {
+            0 => (MoveKind::Rapid, distance(&self.position, &target)),
+            1 => (MoveKind::Linear, distance(&self.position, &target)),
+            _ => (
+                MoveKind::Arc,
+                arc_length(
+                    &self.position,
+                    &target,
+                    &words,
+                    self.scale,
+                    self.motion_mode == 2,
+                ),
+            ),
+        };
+
+        let step = Move {
+            line: line_number,
+            kind,
+            start: self.position,
+            end: target,
+            feed_rate: if kind == MoveKind::Rapid {
+                0.0
+            } else {
+                self.feed_rate
+            },
+            length,
+            dwell_seconds: 0.0,
+        };
+        self.position = target;
+        Some(step)
+    }
+}
+
+impl MotionModel {
+    pub fn from_program(program: &str) -> Self {
+        let mut tracker = MotionTracker::default();
+        let moves = program
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| tracker.next_move(index + 1, line))
+            .collect();
+        Self { moves }
+    }
+
+    /// Bounding box of all move end points as (min, max), or None if the program doesn't move
+    pub fn extents(&self) -> Option<([f64; 3], [f64; 3])> {
+        let mut points = self
+            .moves
+            .iter()
+            .filter(|m| m.kind != MoveKind::Dwell)
+            .map(|m| m.end);
+        let first = points.next()?;
+        Some(points.fold((first, first), |(mut min, mut max), p| {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+            (min, max)
+        }))
+    }
+
+    /// Estimated duration of every move in seconds, using a Grbl-style planner
+    /// (junction deviation cornering and trapezoidal acceleration).
+    /// `feed_scale` applies a feed override to cutting moves only.
+    pub fn move_durations(&self, settings: &MotionSettings, feed_scale: f64) -> Vec<f64> {
+        let accel = settings.acceleration.max(1.0);
+        let count = self.moves.len();
+
+        let nominal: Vec<f64> = self
+            .moves
+            .iter()
+            .map(|m| match m.kind {
+                MoveKind::Rapid => settings.rapid_rate / 60.0,
+                MoveKind::Linear | MoveKind::Arc => {
+                    (m.feed_rate * feed_scale).min(settings.rapid_rate).max(1.0) / 60.0
+                }
+                MoveKind::Dwell => 0.0,
+            })
+            .collect();
+
+        // Maximum speed allowed at the start of each move
+        let mut entry = vec![0.0f64; count];
+        for i in 1..count {
+            let (prev, current) = (&self.moves[i - 1], &self.moves[i]);
+            if prev.kind == MoveKind::Dwell || current.kind == MoveKind::Dwell {
+                continue;
+            }
+            let junction = junction_speed(prev, current, accel, settings.junction_deviation);
+            entry[i] = junction.min(nominal[i]).min(nominal[i - 1]);
+        }
+
+        // Backward pass: every move must be able to decelerate into the next one
+        let mut exit_speed = 0.0;
+        for i in (0..count).rev() {
+            let reachable = (exit_speed * exit_speed + 2.0 * accel * self.moves[i].length).sqrt();
+            entry[i] = entry[i].min(reachable);
+            exit_speed = entry[i];
+        }
+
+        // Forward pass: every move must be able to accelerate from the previous one
+        for i in 1..count {
+            let reachable =
+                (entry[i - 1] * entry[i - 1] + 2.0 * accel * self.moves[i - 1].length).sqrt();
+            entry[i] = entry[i].min(reachable);
+        }
+
+        (0..count)
+            .map(|i| {
+                let m = &self.moves[i];
+                if m.kind == MoveKind::Dwell {
+                    return m.dwell_seconds;
+                }
+                let exit = if i + 1 < count { entry[i + 1] } else { 0.0 };
+                trapezoid_time(m.length, entry[i], exit, nominal[i], accel)
+            })
+            .collect()
+    }
+
+    /// Estimated total run time in seconds
+    pub fn estimate_seconds(&self, settings: &MotionSettings, feed_scale: f64) -> f64 {
+        self.move_durations(settings, feed_scale).iter().sum()
+    }
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2) + (b[2] - a[2]).powi(2)).sqrt()
+}
+
+/// Arc length in the XY plane (plus helical Z) from either I/J or R words
+fn arc_length(
+    start: &[f64; 3],
+    end: &[f64; 3],
+    words: &[Word],
+    scale: f64,
+    clockwise: bool,
+) -> f64 {
+    let chord = ((end[0] - start[0]).powi(2) + (end[1] - start[1]).powi(2)).sqrt();
+    let dz = end[2] - start[2];
+
+    let (radius, sweep) = if let Some(r) = word_value(words, 'R') {
+        let radius = (r * scale).abs();
+        let half = (chord / (2.0 * radius)).clamp(-1.0, 1.0);
+        let mut sweep = 2.0 * half.asin();
+        // Negative R selects the long way around
+        if r < 0.0 {
+            sweep = 2.0 * std::f64::consts::PI - sweep;
+        }
+        (radius, sweep)
+    } else {
+        let i = word_value(words, 'I').unwrap_or(0.0) * scale;
+        let j = word_value(words, 'J').unwrap_or(0.0) * scale;
+        let center = [start[0] + i, start[1] + j];
+        let radius = (i * i + j * j).sqrt();
+        let a0 = (start[1] - center[1]).atan2(start[0] - center[0]);
+        let a1 = (end[1] - center[1]).atan2(end[0] - center[0]);
+        let mut sweep = if clockwise { a0 - a1 } else { a1 - a0 };
+        if sweep <= 1e-9 {
+            sweep += 2.0 * std::f64::consts::PI;
+        }
+        (radius, sweep)
+    };
+
+    ((radius * sweep).powi(2) + dz * dz).sqrt()
+}
+
+fn unit_vector(m: &Move) -> Option<[f64; 3]> {
+    let d = distance(&m.start, &m.end);
+    if d < 1e-9 {
+        return None;
+    }
+    Some([
+        (m.end[0] - m.start[0]) / d,
+        (m.end[1] - m.start[1]) / d,
+        (m.end[2] - m.start[2]) / d,
+    ])
+}
+
+/// Grbl's junction deviation cornering speed in mm/sec
+fn junction_speed(prev: &Move, current: &Move, accel: f64, deviation: f64) -> f64 {
+    let (Some(a), Some(b)) = (unit_vector(prev), unit_vector(current)) else {
+        return 0.0;
+    };
+    let cos_theta = -(a[0] * b[0] + a[1] * b[1] + a[2] * b[2]);
+    if cos_theta > 0.999999 {
+        return 0.0;
+    }
+    if cos_theta < -0.999999 {
+        return f64::MAX;
+    }
+    let sin_half = (0.5 * (1.0 - cos_theta)).sqrt();
+    (accel * deviation * sin_half / (1.0 - sin_half)).sqrt()
+}
+
+/// Time to cover `length` starting at `v0`, ending at `v1`, cruising at most at `vmax`
+fn trapezoid_time(length: f64, v0: f64, v1: f64, vmax: f64, accel: f64) -> f64 {
+    if length <= 0.0 {
+        return 0.0;
+    }
+    let accel_dist = (vmax * vmax - v0 * v0).max(0.0) / (2.0 * accel);
+    let decel_dist = (vmax * vmax - v1 * v1).max(0.0) / (2.0 * accel);
+
+    if accel_dist + decel_dist <= length {
+        (vmax - v0).max(0.0) / accel
+            + (vmax - v1).max(0.0) / accel
+            + (length - accel_dist - decel_dist) / vmax
+    } else {
+        let peak = ((2.0 * accel * length + v0 * v0 + v1 * v1) / 2.0)
+            .sqrt()
+            .max(v0.max(v1));
+        (peak - v0) / accel + (peak - v1) / accel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-3
+    }
+
+    #[test]
+    fn tracker_follows_units_and_distance_mode() {
+        let mut tracker = MotionTracker::default();
+        let step = tracker.next_move(1, "G20 G91 G1 X1 F10").unwrap();
+        assert_eq!(step.kind, MoveKind::Linear);
+        assert_eq!(step.end, [25.4, 0.0, 0.0]);
+        assert!(near(step.feed_rate, 254.0));
+        tracker.next_move(2, "X1");
+        assert!(near(tracker.position[0], 50.8));
+        assert!(!tracker.is_absolute());
+    }
+
+    #[test]
+    fn machine_moves_are_absolute_and_g80_stops_motion() {
+        let mut tracker = MotionTracker::starting_at([5.0, 5.0, 5.0]);
+        tracker.next_move(1, "G91");
+        let step = tracker.next_move(2, "G53 G0 Z-1").unwrap();
+        assert_eq!(step.end, [5.0, 5.0, -1.0]);
+        tracker.next_move(3, "G80");
+        assert_eq!(tracker.motion_mode(), 80);
+        assert!(tracker.next_move(4, "X1").is_none());
+    }
+
+    #[test]
+    fn arc_lengths_from_centre_and_radius_agree() {
+        let quarter = std::f64::consts::PI * 5.0;
+        for arc in ["G2 X0 Y-10 I-10 J0", "G2 X0 Y-10 R10"] {
+            let model = MotionModel::from_program(&format!("G0 X10 Y0\n{} F100\n", arc));
+            assert_eq!(model.moves[1].kind, MoveKind::Arc);
+            assert!(near(model.moves[1].length, quarter), "{}", arc);
+        }
+        // Same start and end with I/J is a full circle
+        let model = MotionModel::from_program("G0 X10 Y0\nG3 X10 Y0 I-10 J0 F100\n");
+        assert!(near(model.moves[1].length, 4.0 * quarter));
+    }
+
+    #[test]
+    fn extents_cover_every_end_point() {
+        let model = MotionModel::from_program("G0 X1 Y2 Z3\nG4 P1\nG1 X-4 Y6 Z-1 F100\n");
+        assert_eq!(model.moves[1].kind, MoveKind::Dwell);
+        assert_eq!(model.extents(), Some(([-4.0, 2.0, -1.0], [1.0, 6.0, 3.0])));
+        assert!(MotionModel::from_program("(nothing)\nM3 S1000\n")
+            .extents()
+            .is_none());
+    }
+
+    #[test]
+    fn long_moves_take_their_length_over_the_feed() {
+        let settings = MotionSettings {
+            rapid_rate: 5000.0,
+            ..Default::default()
+        };
+        // Accelerating to 10 mm/s at 200 mm/s² and back costs 0.05 s over the cruise
+        let cut = MotionModel::from_program("G1 X100 F600\n");
+        assert!(near(cut.estimate_seconds(&settings, 1.0), 10.05));
+        assert!(near(cut.estimate_seconds(&settings, 2.0), 5.1));
+        // Rapids ignore the feed override
+        let rapid = MotionModel::from_program("G0 X100\n");
+        let seconds = rapid.estimate_seconds(&settings, 1.0);
+        assert!(near(seconds, rapid.estimate_seconds(&settings, 2.0)));
+        assert!(near(seconds, 1.6167));
+        let dwell = MotionModel::from_program("G4 P2.5\n");
+        assert!(near(dwell.estimate_seconds(&settings, 1.0), 2.5));
+    }
+}
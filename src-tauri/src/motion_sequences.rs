@@ -0,0 +1,73 @@
+use crate::machine_profile::ClearanceHeights;
+
+/// Raise to the safe height, then travel to the machine XY origin
+pub fn park(clearance: &ClearanceHeights) -> Vec<String> {
+    vec![
+        format!("G53 G0 Z{:.3}", clearance.safe_z),
+        "G53 G0 X0 Y0".to_string(),
+    ]
+}
+
+/// Raise to the safe height, then travel to work X0 Y0 (Z is left at the safe height)
+pub fn return_to_work_zero(clearance: &ClearanceHeights) -> Vec<String> {
+    vec![
+        format!("G53 G0 Z{:.3}", clearance.safe_z),
+        "G90 G0 X0 Y0".to_string(),
+    ]
+}
+
+/// Stop the spindle and raise to the tool change height
+pub fn tool_change(clearance: &ClearanceHeights) -> Vec<String> {
+    vec![
+        "M5".to_string(),
+        format!("G53 G0 Z{:.3}", clearance.tool_change_z),
+    ]
+}
+
+/// Stop the spindle, raise to the tool change height and travel to machine XY `spot`
+pub fn tool_change_at(clearance: &ClearanceHeights, spot: [f32; 2]) -> Vec<String> {
+    let mut commands = tool_change(clearance);
+    commands.push(format!("G53 G0 X{:.3} Y{:.3}", spot[0], spot[1]));
+    commands
+}
+
+/// Probe down for the work surface, set Z zero of the active WCS at the top of the
+/// touch plate, then back off by the probe clearance. Each relative move carries its own
+/// G91 and G90 follows it, so no line relies on the mode an earlier one left.
+pub fn probe_z(
+    clearance: &ClearanceHeights,
+    max_distance: f32,
+    feed_rate: f32,
+    plate_thickness: f32,
+) -> Vec<String> {
+    vec![
+        format!("G91 G38.2 Z-{:.3} F{:.0}", max_distance.abs(), feed_rate),
+        format!("G90 G10 L20 P0 Z{:.3}", plate_thickness),
+        format!("G91 G0 Z{:.3}", clearance.probe_clearance),
+        "G90".to_string(),
+    ]
+}
+
+/// Back off upwards by the probe clearance after a failed Z probe
+pub fn probe_z_retract(clearance: &ClearanceHeights) -> Vec<String> {
+    vec![
+        format!("G91 G0 Z{:.3}", clearance.probe_clearance),
+        "G90".to_string(),
+    ]
+}
+
+/// Probe the surface at one work XY, starting and ending at the probe clearance height
+pub fn probe_point(
+    clearance: &ClearanceHeights,
+    x: f32,
+    y: f32,
+    max_depth: f32,
+    feed_rate: f32,
+) -> Vec<String> {
+    vec![
+        format!("G90 G0 Z{:.3}", clearance.probe_clearance),
+        format!("G0 X{:.3} Y{:.3}", x, y),
+        format!("G38.2 Z-{:.3} F{:.0}", max_depth.abs(), feed_rate),
+        format!("G0 Z{:.3}", clearance.probe_clearance),
+    ]
+}
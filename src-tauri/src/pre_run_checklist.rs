@@ -0,0 +1,287 @@
+use crate::machine_profile::MachineProfile;
+use crate::motion_model::MotionModel;
+use crate::stock::{self, Stock, StockIssueKind};
+use crate::unit_check::{self, Units};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A step the operator confirms by hand, e.g. "spindle warmup done" or "dust boot macro run"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManualCheck {
+    pub id: String,
+    pub label: String,
+    /// Result when the step hasn't been acknowledged this session
+    pub missing: CheckStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistConfig {
+    /// Result when the machine hasn't been homed (only checked if homing is enabled)
+    pub not_homed: CheckStatus,
+    /// Warn if work zero was set longer ago than this
+    pub work_zero_max_age_minutes: Option<u64>,
+    pub check_envelope: bool,
+    pub manual_checks: Vec<ManualCheck>,
+}
+
+impl Default for ChecklistConfig {
+    fn default() -> Self {
+        Self {
+            not_homed: CheckStatus::Warn,
+            work_zero_max_age_minutes: Some(240),
+            check_envelope: true,
+            manual_checks: vec![
+                ManualCheck {
+                    id: "spindle_warmup".to_string(),
+                    label: "Spindle warmup done".to_string(),
+                    missing: CheckStatus::Warn,
+                },
+                ManualCheck {
+                    id: "dust_boot".to_string(),
+                    label: "Dust boot macro executed".to_string(),
+                    missing: CheckStatus::Warn,
+                },
+            ],
+        }
+    }
+}
+
+impl ChecklistConfig {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("checklist.json")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub id: String,
+    pub label: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistResult {
+    pub items: Vec<ChecklistItem>,
+    pub overall: CheckStatus,
+    /// False when any item failed; the start button should stay disabled
+    pub can_start: bool,
+}
+
+/// Machine state the checklist is evaluated against
+pub struct ChecklistContext<'a> {
+    pub connected: bool,
+    pub machine_state: Option<&'a str>,
+    pub homed: Option<bool>,
+    pub work_zero_age: Option<Duration>,
+    pub work_offset: Option<&'a [f32]>,
+    pub profile: &'a MachineProfile,
+    pub acknowledged: &'a HashSet<String>,
+    /// Stock defined for the program, if any
+    pub stock: Option<&'a Stock>,
+    /// G20/G21 in the controller's modal state
+    pub controller_units: Option<Units>,
+}
+
+pub fn run_checklist(
+    config: &ChecklistConfig,
+    context: &ChecklistContext,
+    program: &str,
+) -> ChecklistResult {
+    let mut items = Vec::new();
+    let mut add = |id: &str, label: &str, status: CheckStatus, detail: String| {
+        items.push(ChecklistItem {
+            id: id.to_string(),
+            label: label.to_string(),
+            status,
+            detail,
+        });
+    };
+
+    let (status, detail) = match (context.connected, context.machine_state) {
+        (false, _) => (CheckStatus::Fail, "Not connected".to_string()),
+        (true, Some(state)) if state.starts_with("Alarm") => (
+            CheckStatus::Fail,
+            "Machine is in an alarm state".to_string(),
+        ),
+        (true, Some(state)) if state != "Idle" => {
+            (CheckStatus::Fail, format!("Machine is busy ({})", state))
+        }
+        (true, Some(_)) => (CheckStatus::Pass, "Idle".to_string()),
+        (true, None) => (CheckStatus::Warn, "No status received yet".to_string()),
+    };
+    add(
+        "machine_ready",
+        "Machine connected and idle",
+        status,
+        detail,
+    );
+
+    if context.profile.homing_enabled {
+        let (status, detail) = match context.homed {
+            Some(true) => (CheckStatus::Pass, "Homed".to_string()),
+            Some(false) => (config.not_homed, "Not homed since power-up".to_string()),
+            None => (CheckStatus::Warn, "Homing state unknown".to_string()),
+        };
+        add("homed", "Machine homed", status, detail);
+    }
+
+    if let Some(max_minutes) = config.work_zero_max_age_minutes {
+        let (status, detail) = match context.work_zero_age {
+            None => (
+                CheckStatus::Warn,
+                "Work zero not set this session".to_string(),
+            ),
+            Some(age) if age > Duration::from_secs(max_minutes * 60) => (
+                CheckStatus::Warn,
+                format!("Work zero set {} minutes ago", age.as_secs() / 60),
+            ),
+            Some(age) => (
+                CheckStatus::Pass,
+                format!("Work zero set {} minutes ago", age.as_secs() / 60),
+            ),
+        };
+        add("work_zero", "Work zero set recently", status, detail);
+    }
+
+    if config.check_envelope {
+        let (status, detail) = check_envelope(context, program);
+        add("envelope", "Job fits machine envelope", status, detail);
+    }
+
+    let units = unit_check::check_units(
+        program,
+        context.profile.expected_units,
+        context.controller_units,
+        &context.profile.axes,
+        context.stock,
+    );
+    let (status, detail) = if units.warnings.is_empty() {
+        let units = units.effective_units.unwrap_or(units.expected_units);
+        (
+            CheckStatus::Pass,
+            format!("Program runs in {}", units.gcode()),
+        )
+    } else {
+        (CheckStatus::Warn, units.warnings.join("; "))
+    };
+    add("units", "Program units match the machine", status, detail);
+
+    if let Some(stock) = context.stock {
+        let (status, detail) = check_stock(stock, program);
+        add("stock", "Job stays within the stock", status, detail);
+    }
+
+    for check in &config.manual_checks {
+        if context.acknowledged.contains(&check.id) {
+            add(
+                &check.id,
+                &check.label,
+                CheckStatus::Pass,
+                "Done".to_string(),
+            );
+        } else {
+            add(
+                &check.id,
+                &check.label,
+                check.missing,
+                "Not confirmed".to_string(),
+            );
+        }
+    }
+
+    let overall = items
+        .iter()
+        .map(|i| i.status)
+        .max()
+        .unwrap_or(CheckStatus::Pass);
+
+    ChecklistResult {
+        items,
+        overall,
+        can_start: overall != CheckStatus::Fail,
+    }
+}
+
+/// Convert the program's work-coordinate extents to machine coordinates and compare to travel
+fn check_envelope(context: &ChecklistContext, program: &str) -> (CheckStatus, String) {
+    if context.profile.axes.is_empty() {
+        return (
+            CheckStatus::Warn,
+            "Machine travel limits unknown".to_string(),
+        );
+    }
+    let Some(offset) = context.work_offset else {
+        return (CheckStatus::Warn, "Work offset unknown".to_string());
+    };
+    let Some((min, max)) = MotionModel::from_program(program).extents() else {
+        return (CheckStatus::Pass, "No motion in program".to_string());
+    };
+
+    let mut problems = Vec::new();
+    for range in context.profile.envelope() {
+        let Some(axis) = ['X', 'Y', 'Z'].iter().position(|a| *a == range.axis) else {
+            continue;
+        };
+        let Some(axis_offset) = offset.get(axis) else {
+            continue;
+        };
+        let low = min[axis] + *axis_offset as f64;
+        let high = max[axis] + *axis_offset as f64;
+        if low < range.min as f64 || high > range.max as f64 {
+            problems.push(format!(
+                "{} runs {:.1} to {:.1} (travel {:.1} to {:.1})",
+                range.axis, low, high, range.min, range.max
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        (CheckStatus::Pass, "Within travel limits".to_string())
+    } else {
+        (CheckStatus::Fail, problems.join("; "))
+    }
+}
+
+/// Cutting through the bottom of the stock fails; cutting outside its outline only warns,
+/// since facing and profile passes often run a little past the edge on purpose
+fn check_stock(stock: &Stock, program: &str) -> (CheckStatus, String) {
+    let report = stock::analyze(program, stock);
+    let through = report.count(StockIssueKind::Through);
+    let rapids = report.count(StockIssueKind::RapidPlunge);
+    let outside = report.count(StockIssueKind::Outside);
+
+    let mut problems = Vec::new();
+    if through > 0 {
+        problems.push(format!(
+            "{} moves cut up to {:.2} mm through the bottom of the stock",
+            through, report.through_depth
+        ));
+    }
+    if rapids > 0 {
+        problems.push(format!("{} rapids plunge into the stock", rapids));
+    }
+    if outside > 0 {
+        problems.push(format!("{} cutting moves are outside the stock", outside));
+    }
+
+    if through > 0 || rapids > 0 {
+        (CheckStatus::Fail, problems.join("; "))
+    } else if outside > 0 {
+        (CheckStatus::Warn, problems.join("; "))
+    } else {
+        (
+            CheckStatus::Pass,
+            format!("Cuts {:.2} mm into the stock", report.cut_depth),
+        )
+    }
+}
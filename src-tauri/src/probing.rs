@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// Alarms Grbl raises when G38.2 fails: probe already triggered, or no contact.
+/// Position is kept for both, so the machine can be unlocked and backed off safely.
+pub const PROBE_FAIL_ALARMS: [u32; 2] = [4, 5];
+
+/// Result of a probing sequence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    Touched {
+        /// Machine position where the probe triggered, from the `[PRB:...]` report
+        position: Option<Vec<f32>>,
+        responses: Vec<String>,
+    },
+    Failed(ProbeFailure),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeFailure {
+    /// ALARM:4 or ALARM:5; None when the probe reported no contact without an alarm (G38.3)
+    pub alarm: Option<u32>,
+    pub reason: String,
+    /// The alarm was cleared with `$X`
+    pub unlocked: bool,
+    /// The tool was backed off after the failure
+    pub retracted: bool,
+    /// Why unlocking or retracting didn't work, if it didn't
+    pub recovery_error: Option<String>,
+}
@@ -0,0 +1,170 @@
+use crate::cnc_comm::CncDevice;
+use crate::controller_reboot::RebootSession;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often the supervisor thread checks whether a reconnect attempt is due
+pub const SUPERVISOR_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Retrying a dropped connection: the delay starts at `initial_delay_ms` and doubles after
+/// each failed attempt up to `max_delay_ms`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Give up after this many attempts; 0 keeps trying until the user disconnects
+    pub max_attempts: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+            max_attempts: 20,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("reconnect.json")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.initial_delay_ms < 100 {
+            return Err(anyhow!("The first retry must wait at least 100ms"));
+        }
+        if self.max_delay_ms < self.initial_delay_ms {
+            return Err(anyhow!("The longest wait can't be shorter than the first"));
+        }
+        Ok(())
+    }
+
+    /// Wait before attempt `attempt` (1-based)
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(32);
+        let delay = self
+            .initial_delay_ms
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay_ms);
+        Duration::from_millis(delay)
+    }
+
+    fn gives_up_after(&self, attempt: u32) -> bool {
+        self.max_attempts > 0 && attempt >= self.max_attempts
+    }
+}
+
+/// Whether an I/O failure means the link itself is gone rather than a slow or
+/// unhappy controller
+pub fn is_link_broken(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<std::io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::UnexpectedEof
+        )
+    })
+}
+
+/// A dropped connection being retried
+#[derive(Debug, Clone)]
+pub struct PendingReconnect {
+    pub device: CncDevice,
+    pub read_only: bool,
+    /// Line numbering was on and has to be reset on the new link
+    pub line_numbering: bool,
+    pub reason: String,
+    /// A job was streaming when the link dropped
+    pub job_interrupted: bool,
+    /// The app restarted the controller; its session is restored on reconnecting
+    pub reboot: Option<RebootSession>,
+    pub lost_at: Instant,
+    /// Attempts made so far
+    pub attempts: u32,
+    pub next_attempt_at: Instant,
+}
+
+impl PendingReconnect {
+    pub fn new(
+        device: CncDevice,
+        read_only: bool,
+        line_numbering: bool,
+        reason: String,
+        policy: &ReconnectPolicy,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            device,
+            read_only,
+            line_numbering,
+            reason,
+            job_interrupted: false,
+            reboot: None,
+            lost_at: now,
+            attempts: 0,
+            next_attempt_at: now + policy.delay_before(1),
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        Instant::now() >= self.next_attempt_at
+    }
+
+    /// Note a failed attempt; false once the policy has run out of attempts
+    pub fn attempt_failed(&mut self, policy: &ReconnectPolicy, error: &str) -> bool {
+        self.attempts += 1;
+        self.reason = error.to_string();
+        if policy.gives_up_after(self.attempts) {
+            return false;
+        }
+        self.next_attempt_at = Instant::now() + policy.delay_before(self.attempts + 1);
+        true
+    }
+
+    pub fn reconnecting_event(&self, policy: &ReconnectPolicy) -> ReconnectingEvent {
+        ReconnectingEvent {
+            device_name: self.device.name.clone(),
+            attempt: self.attempts + 1,
+            max_attempts: policy.max_attempts,
+            delay_ms: self
+                .next_attempt_at
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64,
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+/// Payload of `cnc:reconnecting`, sent when the link drops and after each failed attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconnectingEvent {
+    pub device_name: String,
+    /// The attempt about to be made
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    /// Why the link dropped, or why the last attempt failed
+    pub reason: String,
+}
+
+/// Payload of `cnc:reconnected`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconnectedEvent {
+    pub device_name: String,
+    pub attempts: u32,
+    /// How long the link was down
+    pub down_ms: u64,
+    /// A job was streaming when it dropped; it has to be recovered, not resumed
+    pub job_interrupted: bool,
+}
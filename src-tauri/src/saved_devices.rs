@@ -0,0 +1,237 @@
+use crate::cnc_comm::CncDevice;
+use crate::tls_transport;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A machine kept for connecting to without discovery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedDevice {
+    pub device: CncDevice,
+    /// Last successful connection, seconds since the Unix epoch
+    pub last_connected: Option<u64>,
+}
+
+/// What to do with the certificate a TLS device presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificateCheck {
+    /// It's the pinned one
+    Matches,
+    /// Nothing is pinned yet; pin this one
+    FirstSeen,
+}
+
+/// Saved machines, most recently connected first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedDevices {
+    pub devices: Vec<SavedDevice>,
+    /// Connect to the last-used saved machine when the app starts
+    pub auto_connect: bool,
+}
+
+impl SavedDevices {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("devices.json")
+    }
+
+    /// Add the device, or update the saved one with the same machine key. A pinned
+    /// certificate is kept unless the update brings its own.
+    pub fn save(&mut self, mut device: CncDevice) {
+        let key = device.machine_key();
+        match self
+            .devices
+            .iter_mut()
+            .find(|d| d.device.machine_key() == key)
+        {
+            Some(saved) => {
+                if device.certificate_pin.is_none() {
+                    device.certificate_pin = saved.device.certificate_pin.take();
+                }
+                saved.device = device;
+            }
+            None => self.devices.push(SavedDevice {
+                device,
+                last_connected: None,
+            }),
+        }
+    }
+
+    pub fn forget(&mut self, machine_key: &str) -> bool {
+        let before = self.devices.len();
+        self.devices
+            .retain(|d| d.device.machine_key() != machine_key);
+        self.devices.len() != before
+    }
+
+    pub fn find(&self, machine_key: &str) -> Option<&CncDevice> {
+        self.devices
+            .iter()
+            .map(|d| &d.device)
+            .find(|d| d.machine_key() == machine_key)
+    }
+
+    /// The certificate `device` must present. A saved device's own pin counts, not the
+    /// caller's copy: discovered and typed-in devices carry none.
+    pub fn certificate_pin(&self, device: &CncDevice) -> Option<String> {
+        match self.find(&device.machine_key()) {
+            Some(saved) => saved.certificate_pin.clone(),
+            None => device.certificate_pin.clone(),
+        }
+    }
+
+    /// Hold a presented certificate to the pin. A different one is refused until the pin
+    /// is cleared with `clear_certificate_pin`.
+    pub fn check_certificate(
+        &self,
+        device: &CncDevice,
+        fingerprint: &str,
+    ) -> Result<CertificateCheck> {
+        match self.certificate_pin(device) {
+            None => Ok(CertificateCheck::FirstSeen),
+            Some(pin) if tls_transport::same_fingerprint(&pin, fingerprint) => {
+                Ok(CertificateCheck::Matches)
+            }
+            Some(pin) => Err(anyhow!(
+                "{} presented certificate {}, not the one pinned for it ({}); if the controller was given a new certificate, clear the pin first",
+                device.name,
+                fingerprint,
+                pin
+            )),
+        }
+    }
+
+    /// Record the certificate fingerprint of a saved TLS device; false if it isn't saved
+    /// or already has one
+    pub fn pin_certificate(&mut self, machine_key: &str, fingerprint: &str) -> bool {
+        let Some(saved) = self
+            .devices
+            .iter_mut()
+            .find(|d| d.device.machine_key() == machine_key)
+        else {
+            return false;
+        };
+        if saved.device.certificate_pin.is_some() {
+            return false;
+        }
+        saved.device.certificate_pin = Some(fingerprint.to_string());
+        true
+    }
+
+    pub fn clear_certificate_pin(&mut self, machine_key: &str) -> bool {
+        self.devices
+            .iter_mut()
+            .find(|d| d.device.machine_key() == machine_key)
+            .map(|d| d.device.certificate_pin = None)
+            .is_some()
+    }
+
+    /// Note a connection; returns false if the device isn't saved
+    pub fn mark_connected(&mut self, device: &CncDevice, now: u64) -> bool {
+        let key = device.machine_key();
+        let Some(index) = self
+            .devices
+            .iter()
+            .position(|d| d.device.machine_key() == key)
+        else {
+            return false;
+        };
+        let mut saved = self.devices.remove(index);
+        saved.last_connected = Some(now);
+        self.devices.insert(0, saved);
+        true
+    }
+
+    /// The saved machine to connect to on launch, if auto-connect is on
+    pub fn auto_connect_device(&self) -> Option<&CncDevice> {
+        if !self.auto_connect {
+            return None;
+        }
+        self.devices
+            .iter()
+            .filter(|d| d.last_connected.is_some())
+            .max_by_key(|d| d.last_connected)
+            .map(|d| &d.device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{SocketOptions, TransportKind};
+
+    const PIN: &str = "AA:BB:CC";
+
+    fn device(pin: Option<&str>) -> CncDevice {
+        CncDevice {
+            name: "Router".to_string(),
+            ip: "10.0.0.5".to_string(),
+            port: 8443,
+            mac: None,
+            firmware: None,
+            transport: TransportKind::Tls,
+            baud_rate: None,
+            certificate_pin: pin.map(str::to_string),
+            socket: SocketOptions::default(),
+        }
+    }
+
+    fn saved_with_pin() -> SavedDevices {
+        let mut saved = SavedDevices::default();
+        saved.save(device(Some(PIN)));
+        saved
+    }
+
+    #[test]
+    fn saved_pin_applies_to_devices_without_one() {
+        let saved = saved_with_pin();
+        assert_eq!(saved.certificate_pin(&device(None)), Some(PIN.to_string()));
+        assert_eq!(
+            saved.check_certificate(&device(None), "aa:bb:cc").unwrap(),
+            CertificateCheck::Matches
+        );
+    }
+
+    #[test]
+    fn mismatched_certificate_is_refused() {
+        let saved = saved_with_pin();
+        assert!(saved.check_certificate(&device(None), "DD:EE:FF").is_err());
+        // A caller's own pin doesn't stand in for the saved one
+        assert!(saved
+            .check_certificate(&device(Some("DD:EE:FF")), "DD:EE:FF")
+            .is_err());
+    }
+
+    #[test]
+    fn pin_is_not_overwritten() {
+        let mut saved = saved_with_pin();
+        let key = device(None).machine_key();
+        assert!(!saved.pin_certificate(&key, "DD:EE:FF"));
+        assert_eq!(saved.certificate_pin(&device(None)), Some(PIN.to_string()));
+    }
+
+    #[test]
+    fn cleared_pin_accepts_a_new_certificate() {
+        let mut saved = saved_with_pin();
+        let key = device(None).machine_key();
+        assert!(saved.clear_certificate_pin(&key));
+        assert_eq!(
+            saved.check_certificate(&device(None), "DD:EE:FF").unwrap(),
+            CertificateCheck::FirstSeen
+        );
+        assert!(saved.pin_certificate(&key, "DD:EE:FF"));
+        assert!(saved.check_certificate(&device(None), "DD:EE:FF").is_ok());
+    }
+
+    #[test]
+    fn unsaved_device_keeps_its_own_pin() {
+        let saved = SavedDevices::default();
+        assert!(saved
+            .check_certificate(&device(Some(PIN)), "DD:EE:FF")
+            .is_err());
+        assert_eq!(
+            saved.check_certificate(&device(None), "DD:EE:FF").unwrap(),
+            CertificateCheck::FirstSeen
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::transport::CncTransport;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serialport::{ClearBuffer, SerialPort};
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Baud rates tried during auto-detection, most common first
+pub const CANDIDATE_BAUD_RATES: [u32; 5] = [115200, 250000, 57600, 38400, 9600];
+
+/// Arduino-style boards reset when the port opens; Grbl is talking again after this
+const BOOT_DELAY: Duration = Duration::from_millis(2000);
+const READ_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// A serial port with whatever USB identification the OS reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerialPortEntry {
+    pub name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    /// Best guess at the controller behind the USB bridge, if recognised
+    pub controller_guess: Option<String>,
+}
+
+/// Guess the controller from the USB bridge chip. Boards don't identify themselves,
+/// but the bridge chip is a strong hint (a CH340 is almost always a 3018-style Grbl board).
+pub fn guess_controller(vid: u16, pid: u16) -> Option<&'static str> {
+    match (vid, pid) {
+        (0x1a86, 0x7523) => Some("Grbl (CH340, e.g. 3018)"),
+        (0x1a86, 0x55d4) => Some("ESP32 (CH9102, e.g. FluidNC)"),
+        (0x10c4, 0xea60) => Some("ESP32 (CP210x, e.g. FluidNC / Grbl_ESP32)"),
+        (0x303a, _) => Some("ESP32-S3 native USB (FluidNC)"),
+        (0x2341, _) | (0x2a03, _) => Some("Grbl (Arduino)"),
+        (0x0403, 0x6001) => Some("Grbl (FTDI)"),
+        (0x0483, 0x5740) => Some("grblHAL (STM32)"),
+        (0x16c0, 0x0483) => Some("grblHAL (Teensy)"),
+        _ => None,
+    }
+}
+
+/// List serial ports, USB ones first
+pub fn list_ports() -> Result<Vec<SerialPortEntry>> {
+    let mut entries: Vec<SerialPortEntry> = serialport::available_ports()?
+        .into_iter()
+        .map(|port| match port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => SerialPortEntry {
+                name: port.port_name,
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+                serial_number: usb.serial_number,
+                controller_guess: guess_controller(usb.vid, usb.pid).map(|g| g.to_string()),
+            },
+            _ => SerialPortEntry {
+                name: port.port_name,
+                vid: None,
+                pid: None,
+                manufacturer: None,
+                product: None,
+                serial_number: None,
+                controller_guess: None,
+            },
+        })
+        .collect();
+
+    entries.sort_by_key(|e| e.vid.is_none());
+    Ok(entries)
+}
+
+/// Find the baud rate a Grbl controller answers on by trying each candidate
+pub fn detect_baud_rate(port_name: &str) -> Result<u32> {
+    for baud in CANDIDATE_BAUD_RATES {
+        println!("🔌 Trying {} at {} baud...", port_name, baud);
+        match probe_baud_rate(port_name, baud) {
+            Ok(true) => {
+                println!("✅ Grbl answered at {} baud", baud);
+                return Ok(baud);
+            }
+            Ok(false) => {}
+            Err(e) => println!("⚠️  {} at {} baud: {}", port_name, baud, e),
+        }
+    }
+    Err(anyhow!(
+        "No Grbl response on {} at any common baud rate",
+        port_name
+    ))
+}
+
+fn probe_baud_rate(port_name: &str, baud: u32) -> Result<bool> {
+    let mut port = serialport::new(port_name, baud)
+        .timeout(Duration::from_millis(200))
+        .open()?;
+
+    // Arduino-style boards reset when the port opens and print the banner ~1.5s later,
+    // so keep listening for a while and nudge the controller with a status query
+    port.write_all(b"\r\n?")?;
+
+    let start = Instant::now();
+    let mut received = String::new();
+    let mut buffer = [0u8; 256];
+    while start.elapsed() < Duration::from_millis(2500) {
+        match port.read(&mut buffer) {
+            Ok(size) if size > 0 => {
+                received.push_str(&String::from_utf8_lossy(&buffer[..size]));
+                if received.contains("Grbl") || received.contains('<') || received.contains("ok") {
+                    return Ok(true);
+                }
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                let _ = port.write_all(b"?");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(false)
+}
+
+impl CncTransport for Box<dyn SerialPort> {
+    fn describe(&self) -> String {
+        format!(
+            "serial://{}@{}",
+            self.name().unwrap_or_default(),
+            self.baud_rate().unwrap_or(0)
+        )
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.bytes_to_read()? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "No serial data waiting",
+            ));
+        }
+        self.read(buf)
+    }
+}
+
+/// Open a controller's serial port with the timeouts the manager expects. Waits for a
+/// board that reset on open to boot, then drops whatever it printed meanwhile.
+pub fn open(port_name: &str, baud: u32) -> Result<Box<dyn SerialPort>> {
+    let port = serialport::new(port_name, baud)
+        .timeout(READ_TIMEOUT)
+        .open()?;
+    thread::sleep(BOOT_DELAY);
+    port.clear(ClearBuffer::Input)?;
+    Ok(port)
+}
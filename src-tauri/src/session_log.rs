@@ -0,0 +1,127 @@
+use crate::gcode;
+use crate::grbl_protocol::Overrides;
+use crate::job_queue::WORK_COORDINATE_SYSTEMS;
+use crate::work_offsets;
+use serde::{Deserialize, Serialize};
+
+/// Oldest entries are dropped beyond this
+const MAX_ENTRIES: usize = 200;
+
+/// Something done to the machine that changes how later moves land, with what it replaced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MachineAction {
+    /// G10 L2/L20, G92 or G92.1 changed a work offset. Offsets are as `$#` reports them,
+    /// None when it couldn't be read.
+    WorkOffset {
+        wcs: String,
+        command: String,
+        previous: Option<Vec<f32>>,
+        value: Option<Vec<f32>>,
+    },
+    /// G54-G59 selected another work coordinate system
+    WcsSelected { previous: String, value: String },
+    /// A `$` setting write, also in the settings audit log under `audit_id`
+    Setting {
+        number: u16,
+        previous: Option<String>,
+        value: String,
+        audit_id: u64,
+    },
+    /// Feed, rapid or spindle override changed; `previous` is None before the first `Ov:`
+    Overrides {
+        previous: Option<Overrides>,
+        value: Overrides,
+    },
+}
+
+/// One entry of the session log, also the payload of `cnc:session-action`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAction {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Machine it was done to (see `CncDevice::machine_key`)
+    pub machine: Option<String>,
+    pub action: MachineAction,
+    /// Put back with `revert_session_action`; the revert is an entry of its own
+    pub reverted: bool,
+}
+
+/// Rolling log of what the operator changed on the machine since the app started, so
+/// "what did I change that broke it?" has an answer. Not saved: the settings audit log
+/// keeps settings for good.
+#[derive(Debug, Default)]
+pub struct SessionLog {
+    entries: Vec<SessionAction>,
+    next_id: u64,
+}
+
+impl SessionLog {
+    /// Add an entry, assigning its id
+    pub fn push(&mut self, mut entry: SessionAction) -> SessionAction {
+        self.next_id += 1;
+        entry.id = self.next_id;
+        self.entries.push(entry.clone());
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        entry
+    }
+
+    pub fn get(&self, id: u64) -> Option<&SessionAction> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn mark_reverted(&mut self, id: u64) -> Option<SessionAction> {
+        let entry = self.entries.iter_mut().find(|e| e.id == id)?;
+        entry.reverted = true;
+        Some(entry.clone())
+    }
+
+    /// Newest first
+    pub fn recent(&self, limit: Option<usize>) -> Vec<SessionAction> {
+        self.entries
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+/// What a line is about to change, found before it's sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffsetTarget {
+    /// Work offset of this WCS, or `G92`
+    Offset(String),
+    /// Selects this WCS
+    Select(String),
+}
+
+/// The offset or WCS selection a line changes, given the active modal words. G10 L1,
+/// L10 and L11 write the tool table and don't count.
+pub fn offset_target(line: &str, modal_state: &[String]) -> Option<OffsetTarget> {
+    let words = gcode::tokenize_line(line);
+    let has_code = |code: f64| gcode::has_code(&words, 'G', code);
+    if has_code(10.0) {
+        let l = gcode::word_value(&words, 'L')?;
+        if l != 2.0 && l != 20.0 {
+            return None;
+        }
+        // P0, or no P, is the active system
+        let wcs = match gcode::word_value(&words, 'P').map_or(0, |p| p as usize) {
+            0 => work_offsets::active_wcs(modal_state),
+            p => WORK_COORDINATE_SYSTEMS.get(p - 1)?,
+        };
+        return Some(OffsetTarget::Offset(wcs.to_string()));
+    }
+    if has_code(92.0) || has_code(92.1) {
+        return Some(OffsetTarget::Offset("G92".to_string()));
+    }
+    let selected = WORK_COORDINATE_SYSTEMS
+        .iter()
+        .find(|wcs| has_code(wcs[1..].parse().unwrap_or_default()))?;
+    Some(OffsetTarget::Select(selected.to_string()))
+}
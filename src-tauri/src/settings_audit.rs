@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Oldest entries are dropped beyond this
+const MAX_ENTRIES: usize = 1000;
+
+/// What made the app write a setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    /// Typed in the console
+    User,
+    /// Changed from the machine profile editor, e.g. axis travel
+    Profile,
+    /// Written while importing a shared profile
+    Import,
+    /// Put back by reverting an earlier change
+    Revert,
+}
+
+/// One `$` setting write the controller accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingAuditEntry {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub number: u16,
+    /// Value before the write, None if it hadn't been read yet
+    pub previous: Option<String>,
+    pub value: String,
+    pub source: SettingSource,
+    /// Machine the setting was written to (see `CncDevice::machine_key`)
+    pub machine: Option<String>,
+}
+
+/// Persistent log of setting changes, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SettingsAudit {
+    pub entries: Vec<SettingAuditEntry>,
+    next_id: u64,
+}
+
+impl SettingsAudit {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("settings_audit.json")
+    }
+
+    /// Add an entry, assigning its id
+    pub fn push(&mut self, mut entry: SettingAuditEntry) -> SettingAuditEntry {
+        self.next_id += 1;
+        entry.id = self.next_id;
+        self.entries.push(entry.clone());
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        entry
+    }
+
+    pub fn get(&self, id: u64) -> Option<&SettingAuditEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Newest first, optionally only one setting
+    pub fn recent(&self, number: Option<u16>, limit: Option<usize>) -> Vec<SettingAuditEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| number.is_none_or(|n| e.number == n))
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+/// `$110=5000` -> (110, "5000"); None for anything that isn't a numbered setting write
+pub fn parse_setting_write(line: &str) -> Option<(u16, String)> {
+    let (number, value) = line.trim().strip_prefix('$')?.split_once('=')?;
+    let number = number.trim().parse().ok()?;
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    Some((number, value.to_string()))
+}
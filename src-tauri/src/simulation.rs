@@ -0,0 +1,183 @@
+use crate::motion_model::{MotionModel, MotionSettings, MoveKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Slowest and fastest playback relative to the estimated real run time
+const MIN_TIME_SCALE: f64 = 0.1;
+const MAX_TIME_SCALE: f64 = 1000.0;
+
+/// One move placed on the simulated timeline
+#[derive(Debug, Clone)]
+struct TimedMove {
+    line: usize,
+    kind: MoveKind,
+    start: [f64; 3],
+    end: [f64; 3],
+    start_time: f64,
+    duration: f64,
+}
+
+/// Where the simulated job is, returned by every playback command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationState {
+    /// Simulated seconds since the job started
+    pub time: f64,
+    pub total_seconds: f64,
+    pub playing: bool,
+    /// Simulated seconds per real second
+    pub time_scale: f64,
+    /// Tool position in work coordinates, mm
+    pub position: [f64; 3],
+    /// Program line of the move at this time, None before the first move
+    pub line: Option<usize>,
+    pub move_kind: Option<MoveKind>,
+}
+
+/// Virtual playback of a program on the motion model's timeline. Time only advances
+/// while playing, measured from the wall clock when the state is read, so the frontend
+/// can poll as often as it redraws.
+pub struct Simulation {
+    moves: Vec<TimedMove>,
+    total_seconds: f64,
+    time_scale: f64,
+    /// Simulated time at `anchor`
+    time: f64,
+    /// When playback last started or changed speed; None while paused
+    anchor: Option<Instant>,
+}
+
+impl Simulation {
+    pub fn new(program: &str, settings: &MotionSettings) -> Self {
+        let model = MotionModel::from_program(program);
+        let durations = model.move_durations(settings, 1.0);
+        let mut clock = 0.0;
+        let moves = model
+            .moves
+            .iter()
+            .zip(durations)
+            .map(|(m, duration)| {
+                let timed = TimedMove {
+                    line: m.line,
+                    kind: m.kind,
+                    start: m.start,
+                    end: m.end,
+                    start_time: clock,
+                    duration,
+                };
+                clock += duration;
+                timed
+            })
+            .collect();
+        Self {
+            moves,
+            total_seconds: clock,
+            time_scale: 1.0,
+            time: 0.0,
+            anchor: None,
+        }
+    }
+
+    /// Current simulated time, stopping playback at the end of the job
+    fn now(&mut self) -> f64 {
+        if let Some(anchor) = self.anchor {
+            let time = self.time + anchor.elapsed().as_secs_f64() * self.time_scale;
+            if time >= self.total_seconds {
+                self.time = self.total_seconds;
+                self.anchor = None;
+            } else {
+                return time;
+            }
+        }
+        self.time
+    }
+
+    pub fn play(&mut self) -> SimulationState {
+        if self.now() >= self.total_seconds {
+            self.time = 0.0;
+        }
+        if self.anchor.is_none() {
+            self.anchor = Some(Instant::now());
+        }
+        self.state()
+    }
+
+    pub fn pause(&mut self) -> SimulationState {
+        self.time = self.now();
+        self.anchor = None;
+        self.state()
+    }
+
+    pub fn seek(&mut self, seconds: f64) -> Result<SimulationState> {
+        if !seconds.is_finite() {
+            return Err(anyhow!("Seek time must be a number of seconds"));
+        }
+        self.time = seconds.clamp(0.0, self.total_seconds);
+        if self.anchor.is_some() {
+            self.anchor = Some(Instant::now());
+        }
+        Ok(self.state())
+    }
+
+    /// Jump to the start of the first move made by a program line at or after `line`
+    pub fn seek_line(&mut self, line: usize) -> Result<SimulationState> {
+        let start = self
+            .moves
+            .iter()
+            .find(|m| m.line >= line)
+            .map(|m| m.start_time)
+            .ok_or_else(|| anyhow!("No moves at or after line {}", line))?;
+        self.seek(start)
+    }
+
+    pub fn set_time_scale(&mut self, scale: f64) -> Result<SimulationState> {
+        if !(MIN_TIME_SCALE..=MAX_TIME_SCALE).contains(&scale) {
+            return Err(anyhow!(
+                "Time scale must be between {} and {}",
+                MIN_TIME_SCALE,
+                MAX_TIME_SCALE
+            ));
+        }
+        // Re-anchor so the change only affects time from now on
+        self.time = self.now();
+        if self.anchor.is_some() {
+            self.anchor = Some(Instant::now());
+        }
+        self.time_scale = scale;
+        Ok(self.state())
+    }
+
+    /// Tool position at the current time. Moves are interpolated at constant speed and
+    /// arcs along their chord, which is close enough to follow the job visually.
+    pub fn state(&mut self) -> SimulationState {
+        let time = self.now();
+        let index = self
+            .moves
+            .partition_point(|m| m.start_time <= time)
+            .checked_sub(1);
+        let (position, line, move_kind) = match index.map(|i| &self.moves[i]) {
+            Some(m) => {
+                let fraction = if m.duration > 0.0 {
+                    ((time - m.start_time) / m.duration).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                let mut position = m.start;
+                for (axis, value) in position.iter_mut().enumerate() {
+                    *value += (m.end[axis] - m.start[axis]) * fraction;
+                }
+                (position, Some(m.line), Some(m.kind))
+            }
+            None => ([0.0; 3], None, None),
+        };
+        SimulationState {
+            time,
+            total_seconds: self.total_seconds,
+            playing: self.anchor.is_some(),
+            time_scale: self.time_scale,
+            position,
+            line,
+            move_kind,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::grbl_protocol::StatusReport;
+use serde::{Deserialize, Serialize};
+
+/// Automatic feed reduction when the spindle is working too hard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpindleLoadConfig {
+    /// Off by default; the load is still reported either way
+    pub auto_reduce_feed: bool,
+    /// Load, in percent, above which the feed is reduced
+    pub threshold_percent: f32,
+    /// Load the spindle must drop below before the feed is put back
+    pub recover_below_percent: f32,
+    /// Feed override to use while overloaded
+    pub reduced_feed_percent: u32,
+    /// Consecutive overloaded status reports before reacting, to ignore spikes
+    pub consecutive_reports: u32,
+}
+
+impl Default for SpindleLoadConfig {
+    fn default() -> Self {
+        Self {
+            auto_reduce_feed: false,
+            threshold_percent: 90.0,
+            recover_below_percent: 75.0,
+            reduced_feed_percent: 70,
+            consecutive_reports: 3,
+        }
+    }
+}
+
+/// Payload of the `cnc:spindle-load` event, sent when the feed is reduced or put back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpindleLoadReaction {
+    pub load_percent: f32,
+    /// True when reducing the feed, false when restoring it
+    pub overloaded: bool,
+    pub feed_percent: u32,
+}
+
+/// Watches the spindle load of grblHAL status reports while a job runs
+pub struct SpindleLoadMonitor {
+    config: SpindleLoadConfig,
+    over_reports: u32,
+    /// Feed override to put back, set while the feed is reduced
+    restore_feed: Option<u32>,
+}
+
+impl SpindleLoadMonitor {
+    pub fn new(config: SpindleLoadConfig) -> Self {
+        Self {
+            config,
+            over_reports: 0,
+            restore_feed: None,
+        }
+    }
+
+    pub fn config(&self) -> &SpindleLoadConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SpindleLoadConfig) {
+        self.config = config;
+    }
+
+    /// Feed override to restore if the job ends while the feed is reduced
+    pub fn finish(&mut self) -> Option<u32> {
+        self.over_reports = 0;
+        self.restore_feed.take()
+    }
+
+    /// Feed a status report taken while a job runs; returns the feed override to switch to
+    pub fn observe(&mut self, report: &StatusReport) -> Option<SpindleLoadReaction> {
+        let load = report.spindle_load?;
+        if !self.config.auto_reduce_feed {
+            return None;
+        }
+
+        match self.restore_feed {
+            None if load > self.config.threshold_percent => {
+                self.over_reports += 1;
+                if self.over_reports < self.config.consecutive_reports {
+                    return None;
+                }
+                let current = report.overrides.map_or(100, |o| o.feed);
+                let reduced = self.config.reduced_feed_percent.min(current);
+                self.restore_feed = Some(current);
+                Some(SpindleLoadReaction {
+                    load_percent: load,
+                    overloaded: true,
+                    feed_percent: reduced,
+                })
+            }
+            None => {
+                self.over_reports = 0;
+                None
+            }
+            Some(_) if load < self.config.recover_below_percent => {
+                self.over_reports = 0;
+                Some(SpindleLoadReaction {
+                    load_percent: load,
+                    overloaded: false,
+                    feed_percent: self.restore_feed.take()?,
+                })
+            }
+            Some(_) => None,
+        }
+    }
+}
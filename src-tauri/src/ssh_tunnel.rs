@@ -0,0 +1,420 @@
+use crate::cnc_comm::CncDevice;
+use crate::discovery::DEFAULT_TCP_PORT;
+use crate::transport::{SocketOptions, TransportKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Time `ssh` gets to log in and start listening on the local port
+const OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Least time between restarts of a tunnel whose `ssh` exited, so a jump host that's
+/// down isn't hammered
+const RESTART_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How much of the end of `ssh`'s output is kept for the error when it exits
+const STDERR_KEPT: usize = 2048;
+
+/// A controller behind NAT, reached by forwarding its port through an SSH server in the
+/// shop (e.g. a Raspberry Pi). Runs the system `ssh`, so keys, the agent and
+/// `~/.ssh/config` work as they do on the command line; password logins aren't supported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TunnelConfig {
+    pub name: String,
+    pub jump_host: String,
+    pub jump_port: u16,
+    pub user: String,
+    /// Private key to log in with; None leaves it to the agent and `~/.ssh`
+    pub identity_file: Option<String>,
+    /// The controller's address as the jump host sees it
+    pub remote_host: String,
+    pub remote_port: u16,
+    /// Keep the same local port, so the machine keeps its saved profile between
+    /// sessions; None picks a free port each time
+    pub local_port: Option<u16>,
+    /// How to talk to the controller through the tunnel
+    pub transport: TransportKind,
+}
+
+impl Default for TunnelConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            jump_host: String::new(),
+            jump_port: 22,
+            user: String::new(),
+            identity_file: None,
+            remote_host: String::new(),
+            remote_port: DEFAULT_TCP_PORT,
+            local_port: None,
+            transport: TransportKind::Tcp,
+        }
+    }
+}
+
+impl TunnelConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Give the tunnel a name"));
+        }
+        if self.jump_host.trim().is_empty() || self.user.trim().is_empty() {
+            return Err(anyhow!("The tunnel needs an SSH host and user"));
+        }
+        if self.remote_host.trim().is_empty() || self.remote_port == 0 {
+            return Err(anyhow!(
+                "The tunnel needs the controller's address and port"
+            ));
+        }
+        if matches!(self.transport, TransportKind::Serial | TransportKind::Ble) {
+            return Err(anyhow!("Only network connections can go through a tunnel"));
+        }
+        // These become arguments of `ssh`, so none may pass for an option or split in two
+        let arguments = [
+            ("SSH user", Some(&self.user)),
+            ("SSH host", Some(&self.jump_host)),
+            ("Identity file", self.identity_file.as_ref()),
+            ("Controller address", Some(&self.remote_host)),
+        ];
+        for (what, value) in arguments {
+            let Some(value) = value else {
+                continue;
+            };
+            if value.starts_with('-') {
+                return Err(anyhow!("{} can't start with '-'", what));
+            }
+            if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(anyhow!(
+                    "{} can't contain spaces or control characters",
+                    what
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn ssh_command(&self, local_port: u16) -> Command {
+        let mut command = Command::new("ssh");
+        command
+            .arg("-N")
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ExitOnForwardFailure=yes"])
+            // Trust the jump host the first time, like the TLS certificate pin
+            .args(["-o", "StrictHostKeyChecking=accept-new"])
+            .args(["-o", "ServerAliveInterval=15"])
+            .args(["-o", "ServerAliveCountMax=3"])
+            .args(["-o", "LogLevel=ERROR"])
+            .args(["-p", &self.jump_port.to_string()]);
+        if let Some(identity) = &self.identity_file {
+            command.args(["-i", identity]);
+        }
+        command
+            .arg("-L")
+            .arg(format!(
+                "127.0.0.1:{}:{}:{}",
+                local_port, self.remote_host, self.remote_port
+            ))
+            .arg("--")
+            .arg(format!("{}@{}", self.user, self.jump_host))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        command
+    }
+
+    /// Start `ssh`, with its output read on a thread so it never blocks on a full pipe
+    fn spawn(&self, local_port: u16) -> Result<(Child, JoinHandle<String>)> {
+        let mut child = self
+            .ssh_command(local_port)
+            .spawn()
+            .map_err(|e| anyhow!("Could not run ssh: {}", e))?;
+        let stderr = child.stderr.take();
+        Ok((child, thread::spawn(move || read_stderr(stderr))))
+    }
+}
+
+/// Saved tunnels
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tunnels {
+    pub tunnels: Vec<TunnelConfig>,
+}
+
+impl Tunnels {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("tunnels.json")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TunnelConfig> {
+        self.tunnels.iter().find(|t| t.name == name)
+    }
+
+    /// Add the tunnel, replacing one with the same name
+    pub fn upsert(&mut self, tunnel: TunnelConfig) {
+        match self.tunnels.iter_mut().find(|t| t.name == tunnel.name) {
+            Some(existing) => *existing = tunnel,
+            None => self.tunnels.push(tunnel),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.tunnels.len();
+        self.tunnels.retain(|t| t.name != name);
+        self.tunnels.len() != before
+    }
+}
+
+/// Returned by `get_tunnels`, one per saved tunnel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelStatus {
+    pub config: TunnelConfig,
+    pub open: bool,
+    /// Forwarding to the controller while open
+    pub local_port: Option<u16>,
+    /// Times `ssh` exited and was started again
+    pub restarts: u32,
+    /// What `ssh` said when it last exited
+    pub last_error: Option<String>,
+}
+
+/// Payload of `cnc:tunnel-dropped`: `ssh` exited and is being restarted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelDropped {
+    pub name: String,
+    pub error: String,
+}
+
+/// A running `ssh -L`, stopped when dropped
+pub struct Tunnel {
+    config: TunnelConfig,
+    local_port: u16,
+    /// None between `ssh` exiting and the restart
+    child: Option<Child>,
+    /// Ends with what `ssh` printed, once it exits
+    stderr: Option<JoinHandle<String>>,
+    last_start: Instant,
+    restarts: u32,
+    last_error: Option<String>,
+}
+
+impl Tunnel {
+    /// Start `ssh` and wait until it is forwarding. Can take several seconds, so it
+    /// shouldn't run under the manager lock.
+    pub fn open(config: TunnelConfig) -> Result<Self> {
+        config.validate()?;
+        let local_port = match config.local_port {
+            Some(port) => port,
+            None => TcpListener::bind("127.0.0.1:0")?.local_addr()?.port(),
+        };
+        if !port_free(local_port) {
+            return Err(anyhow!("Local port {} is already in use", local_port));
+        }
+        println!(
+            "🚇 Opening tunnel {}: 127.0.0.1:{} -> {}:{} via {}@{}",
+            config.name,
+            local_port,
+            config.remote_host,
+            config.remote_port,
+            config.user,
+            config.jump_host
+        );
+        let (mut child, stderr) = config.spawn(local_port)?;
+
+        let started = Instant::now();
+        // ssh binds the local port once it has logged in and set up the forward. The
+        // controller isn't connected to, as the WiFi bridge may only take one client.
+        while port_free(local_port) {
+            if let Some(status) = child.try_wait()? {
+                return Err(anyhow!(
+                    "ssh to {} exited ({}): {}",
+                    config.jump_host,
+                    status,
+                    output_of(Some(stderr))
+                ));
+            }
+            if started.elapsed() > OPEN_TIMEOUT {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!(
+                    "ssh to {} didn't start forwarding within {}s",
+                    config.jump_host,
+                    OPEN_TIMEOUT.as_secs()
+                ));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        println!("🚇 Tunnel {} open on port {}", config.name, local_port);
+        Ok(Self {
+            config,
+            local_port,
+            child: Some(child),
+            stderr: Some(stderr),
+            last_start: started,
+            restarts: 0,
+            last_error: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// The controller at the tunnel's local end
+    pub fn device(&self) -> CncDevice {
+        CncDevice {
+            name: format!("{} (via {})", self.config.name, self.config.jump_host),
+            ip: "127.0.0.1".to_string(),
+            port: self.local_port,
+            mac: None,
+            firmware: None,
+            transport: self.config.transport,
+            baud_rate: None,
+            certificate_pin: None,
+            socket: SocketOptions::default(),
+        }
+    }
+
+    /// Whether the device is connected through this tunnel
+    pub fn carries(&self, device: &CncDevice) -> bool {
+        device.ip == "127.0.0.1" && device.port == self.local_port
+    }
+
+    /// Restart `ssh` if it exited, at most every few seconds; returns why it exited the
+    /// first time that's noticed. The restart isn't waited for: reconnecting retries
+    /// until it forwards again.
+    pub fn check(&mut self) -> Option<TunnelDropped> {
+        let mut dropped = None;
+        if let Some(child) = self.child.as_mut() {
+            let Ok(Some(status)) = child.try_wait() else {
+                return None;
+            };
+            let error = format!("ssh exited ({}): {}", status, output_of(self.stderr.take()));
+            self.last_error = Some(error.clone());
+            self.child = None;
+            dropped = Some(TunnelDropped {
+                name: self.config.name.clone(),
+                error,
+            });
+        }
+        if self.last_start.elapsed() >= RESTART_INTERVAL {
+            self.last_start = Instant::now();
+            self.restarts += 1;
+            match self.config.spawn(self.local_port) {
+                Ok((child, stderr)) => {
+                    self.child = Some(child);
+                    self.stderr = Some(stderr);
+                }
+                Err(e) => self.last_error = Some(e.to_string()),
+            }
+        }
+        dropped
+    }
+
+    pub fn status(&self) -> TunnelStatus {
+        TunnelStatus {
+            config: self.config.clone(),
+            open: self.child.is_some(),
+            local_port: Some(self.local_port),
+            restarts: self.restarts,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+            println!("🚇 Tunnel {} closed", self.config.name);
+        }
+    }
+}
+
+fn port_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// Read `ssh`'s output until it exits, keeping the end of it
+fn read_stderr(stderr: Option<ChildStderr>) -> String {
+    let mut kept = Vec::new();
+    let mut buffer = [0; 512];
+    if let Some(mut stderr) = stderr {
+        while let Ok(read @ 1..) = stderr.read(&mut buffer) {
+            kept.extend_from_slice(&buffer[..read]);
+            let excess = kept.len().saturating_sub(STDERR_KEPT);
+            kept.drain(..excess);
+        }
+    }
+    String::from_utf8_lossy(&kept).to_string()
+}
+
+/// What an exited `ssh` printed
+fn output_of(stderr: Option<JoinHandle<String>>) -> String {
+    let text = stderr
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    match text.trim() {
+        "" => "no output".to_string(),
+        text => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunnel() -> TunnelConfig {
+        TunnelConfig {
+            name: "shop".to_string(),
+            jump_host: "pi.example.com".to_string(),
+            user: "cnc".to_string(),
+            remote_host: "192.168.4.1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_plain_tunnel_is_valid() {
+        assert!(tunnel().validate().is_ok());
+    }
+
+    #[test]
+    fn arguments_that_look_like_options_are_refused() {
+        let mut config = tunnel();
+        config.jump_host = "-oProxyCommand=touch /tmp/x".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = tunnel();
+        config.user = "-F/tmp/config".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = tunnel();
+        config.identity_file = Some("-oLocalCommand=id".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn whitespace_and_control_characters_are_refused() {
+        let mut config = tunnel();
+        config.user = "cnc host".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = tunnel();
+        config.identity_file = Some("key\n".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn the_destination_follows_the_end_of_options() {
+        let command = tunnel().ssh_command(5000);
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy()).collect();
+        let end = args.iter().position(|a| a == "--").unwrap();
+        assert_eq!(args[end + 1], "cnc@pi.example.com");
+        assert_eq!(args.len(), end + 2);
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// Grbl setting holding the status report mask
+pub const STATUS_MASK_SETTING: u16 = 10;
+
+/// What `$10` asks the controller to put in status reports. Grbl 1.1 reports either
+/// MPos (bit 0 set) or WPos, plus `Bf:` when bit 1 is set; Grbl 0.9 has a bit each for
+/// MPos, WPos, planner blocks, RX bytes and limit pins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReportMask {
+    /// Raw `$10` value
+    pub value: u32,
+    pub machine_position: bool,
+    pub work_position: bool,
+    pub buffer_state: bool,
+    /// Decoded with the Grbl 0.9 bit layout
+    pub legacy: bool,
+    /// Features that lose data with this mask
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl StatusReportMask {
+    pub fn decode(value: u32, legacy: bool) -> Self {
+        let bit = |n: u32| value & (1 << n) != 0;
+        let mut mask = if legacy {
+            Self {
+                value,
+                machine_position: bit(0),
+                work_position: bit(1),
+                buffer_state: bit(2) && bit(3),
+                legacy,
+                warnings: Vec::new(),
+            }
+        } else {
+            Self {
+                value,
+                machine_position: bit(0),
+                work_position: !bit(0),
+                buffer_state: bit(1),
+                legacy,
+                warnings: Vec::new(),
+            }
+        };
+        mask.warnings = mask.check();
+        mask
+    }
+
+    /// `$10` value for the requested fields. Grbl 1.1 can only report one position; the
+    /// other is worked out from WCO, so machine position wins when both are asked for.
+    /// Bits this struct doesn't describe (0.9's limit pins) are kept from `value`.
+    pub fn encode(&self) -> u32 {
+        let set = |on: bool, n: u32| if on { 1 << n } else { 0 };
+        if self.legacy {
+            (self.value & !0b1111)
+                | set(self.machine_position, 0)
+                | set(self.work_position, 1)
+                | set(self.buffer_state, 2)
+                | set(self.buffer_state, 3)
+        } else {
+            (self.value & !0b11)
+                | set(self.machine_position || !self.work_position, 0)
+                | set(self.buffer_state, 1)
+        }
+    }
+
+    fn check(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.buffer_state {
+            warnings.push(
+                "Buffer state is off: stall detection, the job line map and feed zones need it"
+                    .to_string(),
+            );
+        }
+        if !self.machine_position && !self.work_position {
+            warnings.push("No position is reported: the DRO can't update".to_string());
+        } else if !self.machine_position {
+            warnings.push(
+                "Machine position is worked out from WCO, which Grbl only sends every few reports"
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+}
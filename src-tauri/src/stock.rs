@@ -0,0 +1,222 @@
+use crate::motion_model::{MotionModel, Move, MoveKind};
+use crate::storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Moves closer than this to a stock face count as on it
+const TOLERANCE: f64 = 0.001;
+
+/// The material a program cuts, as a box in work coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stock {
+    /// Width (X), depth (Y) and thickness (Z) in mm
+    pub size: [f64; 3],
+    /// Work position of the stock's front-left-bottom corner. With work zero on the
+    /// top-left-front corner this is `[0, 0, -thickness]`.
+    pub origin: [f64; 3],
+}
+
+impl Stock {
+    pub fn path_for(data_dir: &Path, program_name: &str) -> PathBuf {
+        data_dir
+            .join("stock")
+            .join(format!("{}.json", storage::file_key(program_name)))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.size.iter().any(|s| !s.is_finite() || *s <= 0.0) {
+            return Err(anyhow!("Stock dimensions must be positive numbers of mm"));
+        }
+        if self.origin.iter().any(|o| !o.is_finite()) {
+            return Err(anyhow!("Stock origin must be a position in mm"));
+        }
+        Ok(())
+    }
+
+    pub fn top(&self) -> f64 {
+        self.origin[2] + self.size[2]
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.origin[2]
+    }
+
+    /// How far a point lies outside the stock's outline in XY (0 when inside)
+    fn distance_outside_xy(&self, point: &[f64; 3]) -> f64 {
+        let outside = |axis: usize| {
+            let low = self.origin[axis] - point[axis];
+            let high = point[axis] - (self.origin[axis] + self.size[axis]);
+            low.max(high).max(0.0)
+        };
+        outside(0).hypot(outside(1))
+    }
+}
+
+/// Stock thickness from probing the spoilboard and then the stock top
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThicknessMeasurement {
+    /// Machine Z where the probe touched each surface
+    pub spoilboard_z: f64,
+    pub stock_top_z: f64,
+    pub thickness: f64,
+    /// The program's stock with the measured thickness, as saved
+    pub stock: Stock,
+    /// The program measured against it
+    pub report: StockReport,
+    /// Set when the program cuts deeper than the stock is thick
+    pub warning: Option<String>,
+}
+
+/// `existing` with its bottom moved to give `thickness`, keeping the top where it is.
+/// Without a stock the outline is taken from the program's XY extents, top at Z0.
+pub fn with_thickness(existing: Option<Stock>, program: &str, thickness: f64) -> Result<Stock> {
+    if !thickness.is_finite() || thickness <= 0.0 {
+        return Err(anyhow!(
+            "The stock top probed at or below the spoilboard ({:.3} mm)",
+            thickness
+        ));
+    }
+    let stock = match existing {
+        Some(mut stock) => {
+            stock.origin[2] = stock.top() - thickness;
+            stock.size[2] = thickness;
+            stock
+        }
+        None => {
+            let (min, max) = MotionModel::from_program(program)
+                .extents()
+                .ok_or_else(|| anyhow!("Set the stock outline first; the program doesn't move"))?;
+            Stock {
+                size: [max[0] - min[0], max[1] - min[1], thickness],
+                origin: [min[0], min[1], -thickness],
+            }
+        }
+    };
+    stock.validate()?;
+    Ok(stock)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StockIssueKind {
+    /// Cutting below the stock top outside its outline
+    Outside,
+    /// Cutting below the stock bottom, into whatever it sits on
+    Through,
+    /// A rapid descending below the stock top over the stock
+    RapidPlunge,
+}
+
+/// A run of consecutive moves with the same problem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockRegion {
+    pub kind: StockIssueKind,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub move_count: usize,
+    /// Furthest outside the outline, deepest below the bottom, or deepest into the
+    /// stock for a rapid, in mm
+    pub worst: f64,
+}
+
+/// The toolpath measured against the stock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockReport {
+    pub stock: Stock,
+    /// Lowest work Z of any cutting move
+    pub deepest_z: Option<f64>,
+    /// How far the deepest cut goes below the stock top, in mm
+    pub cut_depth: f64,
+    /// How far the deepest cut goes below the stock bottom, in mm (0 if it doesn't)
+    pub through_depth: f64,
+    pub regions: Vec<StockRegion>,
+}
+
+impl StockReport {
+    pub fn count(&self, kind: StockIssueKind) -> usize {
+        self.regions
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(|r| r.move_count)
+            .sum()
+    }
+}
+
+/// Check every move of a program against the stock. Only move end points are checked,
+/// and the tool radius isn't known, so a cut along the edge of the stock isn't flagged.
+pub fn analyze(program: &str, stock: &Stock) -> StockReport {
+    let model = MotionModel::from_program(program);
+    let top = stock.top();
+    let bottom = stock.bottom();
+
+    let mut regions: Vec<StockRegion> = Vec::new();
+    let mut deepest_z: Option<f64> = None;
+    // Kinds flagged on the previous move; a region continues while every move has its problem
+    let mut previous: Vec<StockIssueKind> = Vec::new();
+
+    for m in &model.moves {
+        if matches!(m.kind, MoveKind::Linear | MoveKind::Arc) {
+            deepest_z = Some(deepest_z.map_or(m.end[2], |z| z.min(m.end[2])));
+        }
+        let found = issues(m, stock, top, bottom);
+        for (kind, amount) in &found {
+            let open = previous
+                .contains(kind)
+                .then(|| regions.iter_mut().rev().find(|r| r.kind == *kind))
+                .flatten();
+            match open {
+                Some(region) => {
+                    region.end_line = m.line;
+                    region.move_count += 1;
+                    region.worst = region.worst.max(*amount);
+                }
+                None => regions.push(StockRegion {
+                    kind: *kind,
+                    start_line: m.line,
+                    end_line: m.line,
+                    move_count: 1,
+                    worst: *amount,
+                }),
+            }
+        }
+        previous = found.into_iter().map(|(kind, _)| kind).collect();
+    }
+
+    let lowest = deepest_z.unwrap_or(top);
+    StockReport {
+        stock: stock.clone(),
+        deepest_z,
+        cut_depth: (top - lowest).max(0.0),
+        through_depth: (bottom - lowest).max(0.0),
+        regions,
+    }
+}
+
+fn issues(m: &Move, stock: &Stock, top: f64, bottom: f64) -> Vec<(StockIssueKind, f64)> {
+    let mut found = Vec::new();
+    match m.kind {
+        MoveKind::Linear | MoveKind::Arc => {
+            let outside = stock.distance_outside_xy(&m.end);
+            if m.end[2] < top - TOLERANCE && outside > TOLERANCE {
+                found.push((StockIssueKind::Outside, outside));
+            }
+            if m.end[2] < bottom - TOLERANCE {
+                found.push((StockIssueKind::Through, bottom - m.end[2]));
+            }
+        }
+        MoveKind::Rapid => {
+            // Retracts and moves across already-cut areas are fine; descending into the
+            // stock at rapid rate is not
+            let descending = m.end[2] < m.start[2] - TOLERANCE;
+            if descending
+                && m.end[2] < top - TOLERANCE
+                && stock.distance_outside_xy(&m.end) <= TOLERANCE
+            {
+                found.push((StockIssueKind::RapidPlunge, top - m.end[2]));
+            }
+        }
+        MoveKind::Dwell => {}
+    }
+    found
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Load a JSON file, falling back to the default value if it is missing or unreadable
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> T {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            println!("⚠️  Ignoring unreadable {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Write a value as pretty JSON, creating parent directories as needed
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+/// Make a string safe to use as a file name
+pub fn file_key(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,229 @@
+use crate::grbl_protocol::StatusReport;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StallConfig {
+    pub enabled: bool,
+    /// The planner counts as starved when this many blocks or fewer are queued
+    pub min_queued_blocks: u32,
+    /// Consecutive starved status reports before warning
+    pub consecutive_reports: u32,
+    /// Feed hold instead of letting the machine dwell mid-cut
+    pub auto_pause: bool,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_queued_blocks: 1,
+            consecutive_reports: 3,
+            auto_pause: false,
+        }
+    }
+}
+
+/// How often status queries, the streamer's and the frontend's, reach the controller
+/// while a job streams. On a slow link each `?` and its report take bandwidth from the
+/// job's lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamingPollConfig {
+    /// Off stops status queries for the whole job; job progress then comes from acked lines
+    pub enabled: bool,
+    /// Shortest time between queries; polls in between get the last report
+    pub min_interval_ms: u64,
+}
+
+impl Default for StreamingPollConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_interval_ms: 0,
+        }
+    }
+}
+
+impl StreamingPollConfig {
+    /// Whether a status query last sent at `last_query` may be sent again now
+    pub fn query_due(&self, last_query: Option<Instant>) -> bool {
+        self.enabled
+            && last_query
+                .is_none_or(|at| at.elapsed() >= Duration::from_millis(self.min_interval_ms))
+    }
+}
+
+/// Payload of the `cnc:stream-stall` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StallWarning {
+    pub queued_blocks: u32,
+    pub rx_bytes_free: u32,
+    pub starved_reports: u32,
+    pub starved_ms: u64,
+    pub feed_rate: Option<f32>,
+    pub auto_paused: bool,
+}
+
+/// Watches status reports while a job streams and spots when the planner runs dry
+/// even though the sender still has lines to send (typically a WiFi hiccup)
+pub struct StallDetector {
+    config: StallConfig,
+    streaming: bool,
+    /// Largest free block count seen, i.e. the planner size reported when empty
+    planner_capacity: u32,
+    starved_reports: u32,
+    starved_since: Option<Instant>,
+    warned: bool,
+}
+
+impl StallDetector {
+    pub fn new(config: StallConfig) -> Self {
+        Self {
+            config,
+            streaming: false,
+            planner_capacity: 0,
+            starved_reports: 0,
+            starved_since: None,
+            warned: false,
+        }
+    }
+
+    pub fn config(&self) -> &StallConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: StallConfig) {
+        self.config = config;
+    }
+
+    /// Planner size learned from the emptiest report so far (0 until a `Bf:` field is seen)
+    pub fn planner_capacity(&self) -> u32 {
+        self.planner_capacity
+    }
+
+    /// Planner size from the controller's `$I` build options; replaces what was learned
+    pub fn set_planner_capacity(&mut self, blocks: u32) {
+        self.planner_capacity = blocks;
+    }
+
+    /// Called by whoever streams the job when streaming starts and stops
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.starved_reports = 0;
+        self.starved_since = None;
+        self.warned = false;
+    }
+
+    /// Feed a status report with the number of lines the sender has yet to write; returns
+    /// a warning once per stall. An empty planner with nothing left to send is just the
+    /// job's last moves, and so is one where the sender can't say (None).
+    pub fn observe(
+        &mut self,
+        report: &StatusReport,
+        lines_waiting: Option<usize>,
+    ) -> Option<StallWarning> {
+        let buffer = report.buffer?;
+        self.planner_capacity = self.planner_capacity.max(buffer.planner_blocks_free);
+
+        if !self.config.enabled
+            || !self.streaming
+            || report.state != "Run"
+            || lines_waiting.unwrap_or(0) == 0
+        {
+            self.reset();
+            return None;
+        }
+
+        let queued = self.planner_capacity - buffer.planner_blocks_free;
+        if queued > self.config.min_queued_blocks {
+            self.reset();
+            return None;
+        }
+
+        self.starved_reports += 1;
+        let since = *self.starved_since.get_or_insert_with(Instant::now);
+        if self.warned || self.starved_reports < self.config.consecutive_reports {
+            return None;
+        }
+
+        self.warned = true;
+        Some(StallWarning {
+            queued_blocks: queued,
+            rx_bytes_free: buffer.rx_bytes_free,
+            starved_reports: self.starved_reports,
+            starved_ms: since.elapsed().as_millis() as u64,
+            feed_rate: report.feed_rate,
+            auto_paused: self.config.auto_pause,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grbl_protocol::BufferState;
+
+    fn report(state: &str, planner_blocks_free: u32) -> StatusReport {
+        StatusReport {
+            state: state.to_string(),
+            buffer: Some(BufferState {
+                planner_blocks_free,
+                rx_bytes_free: 128,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn streaming_detector() -> StallDetector {
+        let mut detector = StallDetector::new(StallConfig::default());
+        detector.set_planner_capacity(15);
+        detector.set_streaming(true);
+        detector
+    }
+
+    #[test]
+    fn warns_once_when_starved_with_lines_waiting() {
+        let mut detector = streaming_detector();
+        assert!(detector.observe(&report("Run", 15), Some(40)).is_none());
+        assert!(detector.observe(&report("Run", 15), Some(40)).is_none());
+        let warning = detector.observe(&report("Run", 14), Some(40)).unwrap();
+        assert_eq!(warning.queued_blocks, 1);
+        assert_eq!(warning.starved_reports, 3);
+        assert!(detector.observe(&report("Run", 15), Some(40)).is_none());
+    }
+
+    #[test]
+    fn job_tail_is_not_a_stall() {
+        let mut detector = streaming_detector();
+        for _ in 0..10 {
+            assert!(detector.observe(&report("Run", 15), Some(0)).is_none());
+            assert!(detector.observe(&report("Run", 15), None).is_none());
+        }
+    }
+
+    #[test]
+    fn a_full_planner_or_a_hold_resets_the_count() {
+        let mut detector = streaming_detector();
+        detector.observe(&report("Run", 15), Some(5));
+        detector.observe(&report("Run", 15), Some(5));
+        detector.observe(&report("Run", 3), Some(5));
+        detector.observe(&report("Run", 15), Some(5));
+        detector.observe(&report("Hold:0", 15), Some(5));
+        assert!(detector.observe(&report("Run", 15), Some(5)).is_none());
+        assert!(detector.observe(&report("Run", 15), Some(5)).is_none());
+        assert!(detector.observe(&report("Run", 15), Some(5)).is_some());
+    }
+
+    #[test]
+    fn planner_capacity_is_learned_from_reports() {
+        let mut detector = StallDetector::new(StallConfig::default());
+        detector.observe(&report("Idle", 15), None);
+        detector.observe(&report("Run", 3), None);
+        assert_eq!(detector.planner_capacity(), 15);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::transport::{self, CncTransport, SocketOptions};
+use anyhow::Result;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+
+/// Grbl-ESP32 and other telnet servers
+pub const DEFAULT_TELNET_PORT: u16 = 23;
+
+/// "Interpret as command": starts every negotiation sequence
+pub const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+/// Subnegotiation begin and end
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+/// Where the filter is within a negotiation sequence; they can be split across reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterState {
+    Data,
+    Command,
+    /// After WILL/WONT/DO/DONT, waiting for the option byte
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+/// Grbl over telnet. Option negotiation is stripped from what's read, and every option
+/// the server offers or asks for is refused, leaving a plain byte stream.
+pub struct TelnetTransport {
+    stream: TcpStream,
+    state: FilterState,
+    /// Refusals to send on the next read or write
+    replies: Vec<u8>,
+}
+
+impl TelnetTransport {
+    pub fn connect(ip: &str, port: u16, options: &SocketOptions) -> Result<Self> {
+        let stream = transport::connect_tcp(ip, port, options)?;
+        Ok(Self {
+            stream,
+            state: FilterState::Data,
+            replies: Vec::new(),
+        })
+    }
+
+    /// Strip negotiation from `bytes` in place, returning how many data bytes are left
+    fn filter(&mut self, bytes: &mut [u8]) -> usize {
+        let mut kept = 0;
+        for i in 0..bytes.len() {
+            let byte = bytes[i];
+            self.state = match (self.state, byte) {
+                (FilterState::Data, IAC) => FilterState::Command,
+                (FilterState::Data, _) => {
+                    bytes[kept] = byte;
+                    kept += 1;
+                    FilterState::Data
+                }
+                // Escaped 255 in the data
+                (FilterState::Command, IAC) => {
+                    bytes[kept] = byte;
+                    kept += 1;
+                    FilterState::Data
+                }
+                (FilterState::Command, WILL..=DONT) => FilterState::Option(byte),
+                (FilterState::Command, SB) => FilterState::Subnegotiation,
+                // NOP, GA and the other two-byte commands
+                (FilterState::Command, _) => FilterState::Data,
+                (FilterState::Option(verb), option) => {
+                    let refusal = match verb {
+                        WILL => Some(DONT),
+                        DO => Some(WONT),
+                        // Agreeing to a WONT/DONT needs no answer
+                        _ => None,
+                    };
+                    if let Some(refusal) = refusal {
+                        self.replies.extend_from_slice(&[IAC, refusal, option]);
+                    }
+                    FilterState::Data
+                }
+                (FilterState::Subnegotiation, IAC) => FilterState::SubnegotiationCommand,
+                (FilterState::Subnegotiation, _) => FilterState::Subnegotiation,
+                (FilterState::SubnegotiationCommand, SE) => FilterState::Data,
+                (FilterState::SubnegotiationCommand, _) => FilterState::Subnegotiation,
+            };
+        }
+        kept
+    }
+
+    fn send_replies(&mut self) -> io::Result<()> {
+        if !self.replies.is_empty() {
+            let replies = std::mem::take(&mut self.replies);
+            self.stream.write_all(&replies)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TelnetTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let size = self.stream.read(buf)?;
+            if size == 0 {
+                return Ok(0);
+            }
+            let kept = self.filter(&mut buf[..size]);
+            self.send_replies()?;
+            // A read of nothing but negotiation isn't the end of the stream
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+impl Write for TelnetTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send_replies()?;
+        if buf.contains(&IAC) {
+            let escaped: Vec<u8> = buf
+                .iter()
+                .flat_map(|b| if *b == IAC { vec![IAC, IAC] } else { vec![*b] })
+                .collect();
+            self.stream.write_all(&escaped)?;
+        } else {
+            self.stream.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl CncTransport for TelnetTransport {
+    fn describe(&self) -> String {
+        match self.stream.peer_addr() {
+            Ok(addr) => format!("telnet://{}", addr),
+            Err(_) => "telnet://(closed)".to_string(),
+        }
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_nonblocking(true)?;
+        let result = self.read(buf);
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+}
@@ -0,0 +1,192 @@
+use crate::transport::{self, CncTransport, SocketOptions};
+use anyhow::{anyhow, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// SHA-256 of a certificate as colon-separated hex, the way `openssl x509 -fingerprint`
+/// prints it
+pub fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Compare fingerprints ignoring case and separators, so a pasted one matches
+pub fn same_fingerprint(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(char::is_ascii_hexdigit)
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<String>()
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Accepts exactly the pinned certificate, or any certificate when nothing is pinned yet
+/// (the caller pins what it saw). Shop controllers sit behind self-signed certificates,
+/// so there is no CA to check against; the signatures are still verified.
+#[derive(Debug)]
+struct PinnedCertificate {
+    pin: Option<String>,
+    seen: Arc<Mutex<Option<String>>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(end_entity);
+        if let Ok(mut seen) = self.seen.lock() {
+            *seen = Some(presented.clone());
+        }
+        match &self.pin {
+            Some(pin) if !same_fingerprint(pin, &presented) => {
+                Err(rustls::Error::General(format!(
+                    "Certificate {} doesn't match the one pinned for this machine ({})",
+                    presented, pin
+                )))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Grbl over TCP inside TLS, for a controller reached through a TLS port forward or a
+/// tunnel such as stunnel in front of its WiFi module
+pub struct TlsTransport {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+    fingerprint: String,
+}
+
+impl TlsTransport {
+    /// Connect and finish the handshake, so a certificate that doesn't match `pin` fails
+    /// here rather than on the first read
+    pub fn connect(
+        host: &str,
+        port: u16,
+        pin: Option<&str>,
+        options: &SocketOptions,
+    ) -> Result<Self> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let seen = Arc::new(Mutex::new(None));
+        let verifier = PinnedCertificate {
+            pin: pin.map(str::to_string),
+            seen: seen.clone(),
+            provider: provider.clone(),
+        };
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|_| anyhow!("{} isn't a valid TLS server name", host))?;
+        let connection = ClientConnection::new(Arc::new(config), server_name)?;
+
+        let socket = transport::connect_tcp(host, port, options)?;
+        let mut stream = StreamOwned::new(connection, socket);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        let fingerprint = seen
+            .lock()
+            .ok()
+            .and_then(|seen| seen.clone())
+            .ok_or_else(|| anyhow!("{}:{} presented no certificate", host, port))?;
+        Ok(Self {
+            stream,
+            fingerprint,
+        })
+    }
+}
+
+impl Read for TlsTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TlsTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl CncTransport for TlsTransport {
+    fn describe(&self) -> String {
+        match self.stream.sock.peer_addr() {
+            Ok(addr) => format!("tls://{}", addr),
+            Err(_) => "tls://(closed)".to_string(),
+        }
+    }
+
+    fn certificate_fingerprint(&self) -> Option<String> {
+        Some(self.fingerprint.clone())
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.conn.send_close_notify();
+        // Best effort: the peer may already be gone
+        let _ = self.stream.conn.complete_io(&mut self.stream.sock);
+        self.stream.sock.shutdown(Shutdown::Both)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.sock.set_nonblocking(true)?;
+        let result = self.stream.read(buf);
+        self.stream.sock.set_nonblocking(false)?;
+        result
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// Where the operator changed the tool, in machine XY, so later changes in the same job
+/// go back to the same spot for the wrench and touch plate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolChangeSpot {
+    /// Job the spot belongs to, matching the console and history tag
+    pub job_id: u64,
+    pub machine_xy: [f32; 2],
+}
+
+/// Tool change spot of the running job, forgotten when the job ends
+#[derive(Debug, Default)]
+pub struct ToolChangeMemory {
+    spot: Option<ToolChangeSpot>,
+    /// Job whose first tool change is under way, its XY not yet taken
+    pending: Option<u64>,
+}
+
+impl ToolChangeMemory {
+    /// A tool change is starting; returns the XY to go to if the job already has one
+    pub fn begin(&mut self, job_id: u64) -> Option<[f32; 2]> {
+        let remembered = self
+            .spot
+            .as_ref()
+            .filter(|s| s.job_id == job_id)
+            .map(|s| s.machine_xy);
+        if remembered.is_none() {
+            self.pending = Some(job_id);
+        }
+        remembered
+    }
+
+    /// The tool change is done (the new tool was touched off, or the job resumed): remember
+    /// where it happened if this was the job's first. Returns the spot when newly taken.
+    pub fn finish(&mut self, machine_xy: Option<[f32; 2]>) -> Option<ToolChangeSpot> {
+        let job_id = self.pending.take()?;
+        let spot = ToolChangeSpot {
+            job_id,
+            machine_xy: machine_xy?,
+        };
+        self.spot = Some(spot.clone());
+        Some(spot)
+    }
+
+    pub fn spot(&self) -> Option<&ToolChangeSpot> {
+        self.spot.as_ref()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A cutter or edge finder, by the `T` number programs select it with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    pub number: u32,
+    pub name: String,
+    /// Cutting diameter in mm
+    pub diameter: f32,
+}
+
+impl Tool {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Give the tool a name"));
+        }
+        if !self.diameter.is_finite() || self.diameter <= 0.0 {
+            return Err(anyhow!("Tool diameter must be above zero"));
+        }
+        Ok(())
+    }
+}
+
+/// Saved tools, by number
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolLibrary {
+    pub tools: Vec<Tool>,
+}
+
+impl ToolLibrary {
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("tools.json")
+    }
+
+    pub fn get(&self, number: u32) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.number == number)
+    }
+
+    /// Add the tool, replacing one with the same number, keeping the list in number order
+    pub fn upsert(&mut self, tool: Tool) {
+        match self.tools.iter_mut().find(|t| t.number == tool.number) {
+            Some(existing) => *existing = tool,
+            None => {
+                self.tools.push(tool);
+                self.tools.sort_by_key(|t| t.number);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, number: u32) -> bool {
+        let before = self.tools.len();
+        self.tools.retain(|t| t.number != number);
+        self.tools.len() != before
+    }
+}
+
+/// Tool number selected in the `$G` modal words, e.g. `T3`
+pub fn active_tool(modal_state: &[String]) -> Option<u32> {
+    modal_state
+        .iter()
+        .find_map(|w| w.strip_prefix('T'))
+        .and_then(|n| n.parse().ok())
+}
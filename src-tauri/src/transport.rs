@@ -0,0 +1,390 @@
+use crate::ble_transport::BleTransport;
+use crate::cnc_comm::CncDevice;
+use crate::serial_ports;
+use crate::telnet_transport::{self, TelnetTransport};
+use crate::tls_transport::TlsTransport;
+use crate::websocket_transport::{self, WebSocketTransport};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How a device is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Ble,
+    /// USB serial; the device's `ip` holds the port name, e.g. `/dev/ttyUSB0` or `COM3`
+    Serial,
+    /// Raw Grbl over telnet, with option negotiation filtered out
+    Telnet,
+    /// Grbl stream over a WebSocket, as on FluidNC and ESP3D boards
+    #[serde(rename = "websocket")]
+    WebSocket,
+    /// Raw Grbl over TCP inside TLS, checked against the device's pinned certificate
+    Tls,
+}
+
+/// Socket settings for network links, kept with each saved device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketOptions {
+    /// Send small writes straight away instead of holding them for the previous packet's
+    /// ACK (Nagle's algorithm), which can add up to a couple of hundred ms to each jog
+    pub nodelay: bool,
+    pub connect_timeout_ms: u64,
+    /// Longest a single read blocks. Replies arrive as soon as they're read either way;
+    /// a shorter timeout makes waits end closer to their deadlines.
+    pub read_timeout_ms: u64,
+    pub write_timeout_ms: u64,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            connect_timeout_ms: 5000,
+            read_timeout_ms: 5000,
+            write_timeout_ms: 1000,
+        }
+    }
+}
+
+impl SocketOptions {
+    pub fn validate(&self) -> Result<()> {
+        let timeouts = [
+            self.connect_timeout_ms,
+            self.read_timeout_ms,
+            self.write_timeout_ms,
+        ];
+        if timeouts.iter().any(|t| !(50..=60_000).contains(t)) {
+            return Err(anyhow!("Socket timeouts must be between 50ms and 60s"));
+        }
+        Ok(())
+    }
+}
+
+/// Byte stream to a controller. Reads should time out (TimedOut/WouldBlock) rather than
+/// block forever, and return 0 once the link is closed. The manager splits what's read
+/// into lines itself, so a backend only moves bytes; anything that implements this,
+/// including a scripted fake, can be handed to `CncManager::attach`.
+pub trait CncTransport: Read + Write + Send {
+    /// Endpoint description for logs
+    fn describe(&self) -> String;
+
+    /// Close the link cleanly before it is dropped, e.g. so the WiFi module frees its
+    /// single client slot straight away
+    fn shutdown(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// SHA-256 of the certificate the controller presented, for encrypted links
+    fn certificate_fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    /// Read only what has already arrived, failing with WouldBlock rather than waiting
+    /// when nothing has. Backends that can't tell fall back to a normal `read`.
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read(buf)
+    }
+}
+
+/// Plain TCP. Writes collect in a buffer until the manager flushes, which it does after
+/// every line and real-time byte, so each goes out in one packet.
+pub struct TcpTransport {
+    reader: TcpStream,
+    writer: BufWriter<TcpStream>,
+}
+
+impl TcpTransport {
+    pub fn connect(ip: &str, port: u16, options: &SocketOptions) -> Result<Self> {
+        let reader = connect_tcp(ip, port, options)?;
+        let writer = BufWriter::new(reader.try_clone()?);
+        Ok(Self { reader, writer })
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl CncTransport for TcpTransport {
+    fn describe(&self) -> String {
+        self.reader.describe()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        // Best effort: the peer may already be gone
+        let _ = self.writer.flush();
+        self.reader.shutdown(Shutdown::Both)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read_available(buf)
+    }
+}
+
+impl CncTransport for TcpStream {
+    fn describe(&self) -> String {
+        match self.peer_addr() {
+            Ok(addr) => format!("tcp://{}", addr),
+            Err(_) => "tcp://(closed)".to_string(),
+        }
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.set_nonblocking(true)?;
+        let result = self.read(buf);
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// Open the backend the device asks for
+pub fn connect(device: &CncDevice) -> Result<Box<dyn CncTransport>> {
+    Ok(match device.transport {
+        TransportKind::Tcp => Box::new(TcpTransport::connect(
+            &device.ip,
+            device.port,
+            &device.socket,
+        )?),
+        TransportKind::Ble => Box::new(BleTransport::connect(&device.ip, 5000)?),
+        TransportKind::Serial => {
+            let baud = match device.baud_rate {
+                Some(baud) => baud,
+                None => serial_ports::detect_baud_rate(&device.ip)?,
+            };
+            Box::new(serial_ports::open(&device.ip, baud)?)
+        }
+        TransportKind::Telnet => {
+            let port = match device.port {
+                0 => telnet_transport::DEFAULT_TELNET_PORT,
+                port => port,
+            };
+            Box::new(TelnetTransport::connect(&device.ip, port, &device.socket)?)
+        }
+        TransportKind::WebSocket => {
+            let port = match device.port {
+                0 => websocket_transport::DEFAULT_WEBSOCKET_PORT,
+                port => port,
+            };
+            Box::new(WebSocketTransport::connect(
+                &device.ip,
+                port,
+                &device.socket,
+            )?)
+        }
+        TransportKind::Tls => Box::new(TlsTransport::connect(
+            &device.ip,
+            device.port,
+            device.certificate_pin.as_deref(),
+            &device.socket,
+        )?),
+    })
+}
+
+/// Open a TCP connection with the device's socket options
+pub fn connect_tcp(ip: &str, port: u16, options: &SocketOptions) -> Result<TcpStream> {
+    options.validate()?;
+    // Host names too, for a machine reached through dynamic DNS
+    let addr = (ip, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{} has no address", ip))?;
+    let stream =
+        TcpStream::connect_timeout(&addr, Duration::from_millis(options.connect_timeout_ms))?;
+
+    stream.set_nodelay(options.nodelay)?;
+    stream.set_read_timeout(Some(Duration::from_millis(options.read_timeout_ms)))?;
+    stream.set_write_timeout(Some(Duration::from_millis(options.write_timeout_ms)))?;
+    Ok(stream)
+}
+
+/// A line from the controller, stamped when the read that completed it returned
+#[derive(Debug, Clone, Serialize)]
+pub struct TimedLine {
+    pub text: String,
+    /// Milliseconds since the Unix epoch
+    pub received_at_ms: u64,
+    #[serde(skip)]
+    pub received_at: Instant,
+}
+
+/// Splits the received byte stream into timed lines. Stamping happens here, as the bytes
+/// come off the transport, so time spent waiting on the manager lock doesn't skew it.
+#[derive(Default)]
+pub struct LineAssembler {
+    partial: String,
+    lines: VecDeque<TimedLine>,
+}
+
+impl LineAssembler {
+    pub fn push(&mut self, bytes: &[u8]) {
+        let received_at = Instant::now();
+        let received_at_ms = unix_millis(SystemTime::now());
+        self.partial.push_str(&String::from_utf8_lossy(bytes));
+        while let Some(newline) = self.partial.find('\n') {
+            let text = self.partial[..newline].trim().to_string();
+            self.partial.drain(..=newline);
+            if !text.is_empty() {
+                self.lines.push_back(TimedLine {
+                    text,
+                    received_at_ms,
+                    received_at,
+                });
+            }
+        }
+    }
+
+    pub fn next_line(&mut self) -> Option<TimedLine> {
+        self.lines.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.partial.clear();
+        self.lines.clear();
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A scripted controller for tests: answers each line with `ok` and `?` with a report, as
+/// Grbl does, and records what it was sent
+#[cfg(test)]
+pub mod fake {
+    use super::CncTransport;
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    pub struct FakeState {
+        /// Lines written, without their newlines
+        pub received: Vec<String>,
+        /// Most bytes of lines written and not yet answered at any point
+        pub max_unacked: usize,
+        /// Lines answered with `error:20` instead of `ok`
+        pub reject: Vec<String>,
+        /// `?` queries answered
+        pub status_queries: usize,
+        unacked: usize,
+        partial: Vec<u8>,
+        /// Replies not read yet, with the bytes of the line each acknowledges
+        output: VecDeque<(String, usize)>,
+        state: String,
+    }
+
+    impl FakeState {
+        fn reply(&mut self, text: String, acked_bytes: usize) {
+            self.output.push_back((text, acked_bytes));
+        }
+
+        fn receive_line(&mut self) {
+            let line = String::from_utf8_lossy(&self.partial).trim().to_string();
+            let bytes = self.partial.len() + 1;
+            self.partial.clear();
+            self.unacked += bytes;
+            self.max_unacked = self.max_unacked.max(self.unacked);
+            let reply = if line == "$$" {
+                "$130=300.000\r\n$131=200.000\r\n$132=80.000\r\nok\r\n".to_string()
+            } else if self.reject.contains(&line) {
+                "error:20\r\n".to_string()
+            } else {
+                "ok\r\n".to_string()
+            };
+            self.received.push(line);
+            self.reply(reply, bytes);
+        }
+    }
+
+    /// Clones share the controller, so a test keeps one to look at after attaching another
+    #[derive(Clone, Default)]
+    pub struct FakeGrbl(pub Arc<Mutex<FakeState>>);
+
+    impl FakeGrbl {
+        pub fn new() -> Self {
+            let fake = Self::default();
+            fake.state().state = "Idle".to_string();
+            fake
+        }
+
+        pub fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+            self.0.lock().unwrap()
+        }
+    }
+
+    impl Read for FakeGrbl {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut state = self.state();
+            let Some((text, acked_bytes)) = state.output.pop_front() else {
+                return Err(io::ErrorKind::WouldBlock.into());
+            };
+            state.unacked -= acked_bytes;
+            let size = text.len().min(buf.len());
+            buf[..size].copy_from_slice(&text.as_bytes()[..size]);
+            Ok(size)
+        }
+    }
+
+    impl Write for FakeGrbl {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut state = self.state();
+            for byte in buf {
+                match byte {
+                    b'?' => {
+                        state.status_queries += 1;
+                        let report = format!("<{}|MPos:0.000,0.000,0.000|FS:0,0>\r\n", state.state);
+                        state.reply(report, 0);
+                    }
+                    b'!' => state.state = "Hold:0".to_string(),
+                    b'~' => state.state = "Idle".to_string(),
+                    0x18 => {
+                        // A reset throws away the receive buffer and any replies
+                        state.output.clear();
+                        state.unacked = 0;
+                        state.state = "Idle".to_string();
+                        state.reply("\r\nGrbl 1.1h ['$' for help]\r\n".to_string(), 0);
+                    }
+                    b'\n' => state.receive_line(),
+                    b'\r' => {}
+                    _ => state.partial.push(*byte),
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CncTransport for FakeGrbl {
+        fn describe(&self) -> String {
+            "fake Grbl".to_string()
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::gcode;
+use crate::machine_profile::AxisLimits;
+use crate::motion_model::MotionModel;
+use crate::stock::Stock;
+use serde::{Deserialize, Serialize};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// A program whose whole XY motion fits in this many mm was most likely written in inches
+/// and is about to run in mm
+const SMALLEST_PLAUSIBLE_SPAN_MM: f64 = 3.0;
+
+/// How far off 25.4 a size ratio can be and still count as an inch/mm mixup
+const RATIO_TOLERANCE: f64 = 1.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    #[default]
+    Millimeters,
+    Inches,
+}
+
+impl Units {
+    pub fn gcode(self) -> &'static str {
+        match self {
+            Units::Millimeters => "G21",
+            Units::Inches => "G20",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Units::Millimeters => "mm",
+            Units::Inches => "inches",
+        }
+    }
+
+    /// Units selected in the `$G` modal words
+    pub fn from_modal_state(modal_state: &[String]) -> Option<Self> {
+        modal_state.iter().find_map(|w| match w.as_str() {
+            "G20" => Some(Units::Inches),
+            "G21" => Some(Units::Millimeters),
+            _ => None,
+        })
+    }
+}
+
+/// Units the program sets with its first G20 or G21; None leaves them to the controller
+pub fn program_units(program: &str) -> Option<Units> {
+    program.lines().find_map(|line| {
+        let words = gcode::tokenize_line(line);
+        if gcode::has_code(&words, 'G', 20.0) {
+            Some(Units::Inches)
+        } else if gcode::has_code(&words, 'G', 21.0) {
+            Some(Units::Millimeters)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returned by `check_program_units`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitCheck {
+    /// None when the program doesn't set its units
+    pub program_units: Option<Units>,
+    /// Units the program will run in: its own, else the controller's
+    pub effective_units: Option<Units>,
+    pub expected_units: Units,
+    /// 25.4 when the program looks 25.4 times too large, 1/25.4 when too small
+    pub suspected_scale: Option<f64>,
+    /// What looks wrong, in plain words; empty when nothing does
+    pub warnings: Vec<String>,
+}
+
+/// Look for an inch/mm mixup before the run: units other than the machine expects, or a
+/// toolpath 25.4 times too large for the machine or stock, or too small to be a real part
+pub fn check_units(
+    program: &str,
+    expected: Units,
+    controller: Option<Units>,
+    axes: &[AxisLimits],
+    stock: Option<&Stock>,
+) -> UnitCheck {
+    let program_units = program_units(program);
+    let effective_units = program_units.or(controller);
+    let mut warnings = Vec::new();
+    match (program_units, controller) {
+        (Some(units), _) if units != expected => warnings.push(format!(
+            "Program is in {} ({}) but this machine expects {}",
+            units.name(),
+            units.gcode(),
+            expected.name()
+        )),
+        (None, Some(units)) if units != expected => warnings.push(format!(
+            "Program doesn't set its units and the controller is in {} ({}); this machine expects {}",
+            units.name(),
+            units.gcode(),
+            expected.name()
+        )),
+        (None, None) => warnings.push(format!(
+            "Program doesn't set its units and the controller's are unknown; add {} at the top",
+            expected.gcode()
+        )),
+        _ => {}
+    }
+
+    // The model takes a program without G20/G21 as mm; it will run in the controller's units
+    let scale = if program_units.is_none() && controller == Some(Units::Inches) {
+        MM_PER_INCH
+    } else {
+        1.0
+    };
+    let spans = xy_spans(program).map(|spans| spans.map(|s| s * scale));
+    let suspected_scale = spans.and_then(|spans| {
+        let (scale, warning) = implausible_size(spans, axes, stock)?;
+        warnings.push(warning);
+        Some(scale)
+    });
+
+    UnitCheck {
+        program_units,
+        effective_units,
+        expected_units: expected,
+        suspected_scale,
+        warnings,
+    }
+}
+
+/// X and Y extent of the moves in mm, None if the program doesn't move in XY
+fn xy_spans(program: &str) -> Option<[f64; 2]> {
+    let (min, max) = MotionModel::from_program(program).extents()?;
+    let spans = [max[0] - min[0], max[1] - min[1]];
+    (spans.iter().any(|s| *s > 0.001)).then_some(spans)
+}
+
+fn near_inch_ratio(ratio: f64) -> bool {
+    ratio > MM_PER_INCH / RATIO_TOLERANCE && ratio < MM_PER_INCH * RATIO_TOLERANCE
+}
+
+/// The suspected scale and why, when the toolpath's size points at the wrong units
+fn implausible_size(
+    spans: [f64; 2],
+    axes: &[AxisLimits],
+    stock: Option<&Stock>,
+) -> Option<(f64, String)> {
+    for (axis, span) in ['X', 'Y'].iter().zip(spans) {
+        let Some(travel) = axes
+            .iter()
+            .find(|a| a.axis == *axis)
+            .map(|a| a.max_travel as f64)
+        else {
+            continue;
+        };
+        if span > travel && span / MM_PER_INCH <= travel {
+            return Some((
+                MM_PER_INCH,
+                format!(
+                    "Toolpath spans {:.0} mm in {} but the machine travels {:.0} mm; it looks 25.4 times too large (mm run as inches?)",
+                    span, axis, travel
+                ),
+            ));
+        }
+    }
+
+    let largest = spans[0].max(spans[1]);
+    if let Some(stock) = stock {
+        let stock_largest = stock.size[0].max(stock.size[1]);
+        if near_inch_ratio(stock_largest / largest) {
+            return Some((
+                1.0 / MM_PER_INCH,
+                format!(
+                    "Toolpath spans {:.2} mm on {:.0} mm stock; it looks 25.4 times too small (inches run as mm?)",
+                    largest, stock_largest
+                ),
+            ));
+        }
+        if near_inch_ratio(largest / stock_largest) {
+            return Some((
+                MM_PER_INCH,
+                format!(
+                    "Toolpath spans {:.0} mm on {:.1} mm stock; it looks 25.4 times too large (mm run as inches?)",
+                    largest, stock_largest
+                ),
+            ));
+        }
+    } else if largest < SMALLEST_PLAUSIBLE_SPAN_MM {
+        return Some((
+            1.0 / MM_PER_INCH,
+            format!(
+                "Whole toolpath fits in {:.2} mm; it may be 25.4 times too small (inches run as mm?)",
+                largest
+            ),
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axes(travel: f32) -> Vec<AxisLimits> {
+        ['X', 'Y', 'Z']
+            .iter()
+            .map(|axis| AxisLimits {
+                axis: *axis,
+                max_travel: travel,
+                max_rate: None,
+                acceleration: None,
+                steps_per_mm: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn program_units_come_from_the_first_g20_or_g21() {
+        assert_eq!(program_units("G0 X1\nG20\nG21\n"), Some(Units::Inches));
+        assert_eq!(program_units("(G20)\nG0 X1\n"), None);
+        let modal = ["G0".to_string(), "G21".to_string()];
+        assert_eq!(Units::from_modal_state(&modal), Some(Units::Millimeters));
+    }
+
+    #[test]
+    fn a_matching_program_passes() {
+        let check = check_units(
+            "G21\nG0 X0 Y0\nG1 X50 Y40 F500\n",
+            Units::Millimeters,
+            Some(Units::Inches),
+            &axes(300.0),
+            None,
+        );
+        assert_eq!(check.effective_units, Some(Units::Millimeters));
+        assert!(check.warnings.is_empty(), "{:?}", check.warnings);
+        assert_eq!(check.suspected_scale, None);
+    }
+
+    #[test]
+    fn units_other_than_expected_are_flagged() {
+        let program = "G0 X0 Y0\nG1 X50 Y40 F500\n";
+        let check = check_units(program, Units::Millimeters, Some(Units::Inches), &[], None);
+        assert_eq!(check.effective_units, Some(Units::Inches));
+        assert!(check.warnings[0].contains("controller is in inches (G20)"));
+
+        let check = check_units(program, Units::Millimeters, None, &[], None);
+        assert!(check.warnings[0].ends_with("add G21 at the top"));
+    }
+
+    #[test]
+    fn a_toolpath_too_large_for_the_machine_looks_like_mm_run_as_inches() {
+        // 50 units run as inches is 1270 mm
+        let check = check_units(
+            "G0 X0 Y0\nG1 X50 Y40 F500\n",
+            Units::Millimeters,
+            Some(Units::Inches),
+            &axes(300.0),
+            None,
+        );
+        assert_eq!(check.suspected_scale, Some(MM_PER_INCH));
+        assert!(check.warnings[1].contains("25.4 times too large"));
+    }
+
+    #[test]
+    fn a_tiny_toolpath_looks_like_inches_run_as_mm() {
+        let check = check_units(
+            "G21\nG0 X0 Y0\nG1 X2 Y1.5 F500\n",
+            Units::Millimeters,
+            None,
+            &axes(300.0),
+            None,
+        );
+        assert_eq!(check.suspected_scale, Some(1.0 / MM_PER_INCH));
+        assert_eq!(check.warnings.len(), 1);
+    }
+}
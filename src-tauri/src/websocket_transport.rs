@@ -0,0 +1,284 @@
+use crate::transport::{self, CncTransport, SocketOptions};
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// FluidNC and ESP3D serve their Grbl stream here; WebUI v3 builds use 82
+pub const DEFAULT_WEBSOCKET_PORT: u16 = 81;
+
+/// Response headers bigger than this aren't from a controller
+const MAX_HANDSHAKE_BYTES: usize = 8192;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+
+/// Grbl stream over a WebSocket, as FluidNC and ESP3D boards offer. The controller's
+/// output arrives in binary frames; text frames carry the web UI's own messages
+/// (`CURRENT_ID:`, `PING:`) and are dropped. Commands go out as binary frames.
+pub struct WebSocketTransport {
+    stream: TcpStream,
+    /// Received bytes not yet decoded into frames
+    raw: Vec<u8>,
+    /// Stream bytes from decoded frames, waiting to be read
+    pending: VecDeque<u8>,
+    /// Opcode of the fragmented message being received
+    fragment_op: Option<u8>,
+    closed: bool,
+    mask_seed: u64,
+    url: String,
+}
+
+impl WebSocketTransport {
+    pub fn connect(ip: &str, port: u16, options: &SocketOptions) -> Result<Self> {
+        let mut stream = transport::connect_tcp(ip, port, options)?;
+        let mut mask_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0x9e37_79b9, |d| d.as_nanos() as u64)
+            | 1;
+        let key: Vec<u8> = (0..2)
+            .flat_map(|_| next_random(&mut mask_seed).to_be_bytes())
+            .collect();
+        // FluidNC's web UI asks for the "arduino" subprotocol; servers that don't know it
+        // just leave it out of the response
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Protocol: arduino\r\n\r\n",
+            ip,
+            port,
+            base64(&key)
+        );
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 512];
+        let header_end = loop {
+            if let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if response.len() > MAX_HANDSHAKE_BYTES {
+                return Err(anyhow!("{}:{} sent an oversized handshake", ip, port));
+            }
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Err(anyhow!(
+                    "{}:{} closed during the WebSocket handshake",
+                    ip,
+                    port
+                ));
+            }
+            response.extend_from_slice(&buf[..n]);
+        };
+        let status_line = String::from_utf8_lossy(&response[..header_end])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        // The Sec-WebSocket-Accept hash isn't checked: a 101 is enough to know it upgraded
+        if status_line.split_whitespace().nth(1) != Some("101") {
+            return Err(anyhow!(
+                "{}:{} refused the WebSocket upgrade ({})",
+                ip,
+                port,
+                status_line.trim()
+            ));
+        }
+
+        println!("🕸️  WebSocket open to {}:{}", ip, port);
+        Ok(Self {
+            stream,
+            raw: response[header_end..].to_vec(),
+            pending: VecDeque::new(),
+            fragment_op: None,
+            closed: false,
+            mask_seed,
+            url: format!("ws://{}:{}", ip, port),
+        })
+    }
+
+    /// Decode every complete frame in `raw`
+    fn decode_frames(&mut self) -> io::Result<()> {
+        while let Some((fin, opcode, payload, used)) = parse_frame(&self.raw) {
+            self.raw.drain(..used);
+            match opcode {
+                OP_TEXT | OP_BINARY => {
+                    if !fin {
+                        self.fragment_op = Some(opcode);
+                    }
+                    if opcode == OP_BINARY {
+                        self.pending.extend(payload);
+                    }
+                }
+                OP_CONTINUATION => {
+                    if self.fragment_op == Some(OP_BINARY) {
+                        self.pending.extend(payload);
+                    }
+                    if fin {
+                        self.fragment_op = None;
+                    }
+                }
+                OP_CLOSE => {
+                    self.closed = true;
+                    let _ = self.send_frame(OP_CLOSE, &[]);
+                    return Ok(());
+                }
+                OP_PING => self.send_frame(0xA, &payload)?,
+                // Pongs and anything unknown
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Client frames must be masked
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let mask = (next_random(&mut self.mask_seed) as u32).to_be_bytes();
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        self.stream.write_all(&frame)
+    }
+}
+
+/// `(fin, opcode, payload, bytes used)` of the first frame, None until it has all arrived
+fn parse_frame(raw: &[u8]) -> Option<(bool, u8, Vec<u8>, usize)> {
+    if raw.len() < 2 {
+        return None;
+    }
+    let fin = raw[0] & 0x80 != 0;
+    let opcode = raw[0] & 0x0F;
+    let masked = raw[1] & 0x80 != 0;
+    let (len, mut offset) = match raw[1] & 0x7F {
+        126 => (
+            u16::from_be_bytes(raw.get(2..4)?.try_into().ok()?) as usize,
+            4,
+        ),
+        127 => (
+            u64::from_be_bytes(raw.get(2..10)?.try_into().ok()?) as usize,
+            10,
+        ),
+        len => (len as usize, 2),
+    };
+    let mask = if masked {
+        let mask: [u8; 4] = raw.get(offset..offset + 4)?.try_into().ok()?;
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    let end = offset.checked_add(len)?;
+    let mut payload = raw.get(offset..end)?.to_vec();
+    if let Some(mask) = mask {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Some((fin, opcode, payload, end))
+}
+
+/// xorshift64; masking keys only need to be unpredictable to proxies, not secure
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl Read for WebSocketTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = [0u8; 1024];
+        while self.pending.is_empty() {
+            if self.closed {
+                return Ok(0);
+            }
+            self.decode_frames()?;
+            if !self.pending.is_empty() || self.closed {
+                continue;
+            }
+            let n = self.stream.read(&mut chunk)?;
+            if n == 0 {
+                self.closed = true;
+            }
+            self.raw.extend_from_slice(&chunk[..n]);
+        }
+        let n = buf.len().min(self.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for WebSocketTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "WebSocket closed",
+            ));
+        }
+        self.send_frame(OP_BINARY, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl CncTransport for WebSocketTransport {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        if !self.closed {
+            self.closed = true;
+            let _ = self.send_frame(OP_CLOSE, &[]);
+        }
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Half a frame stays in `raw` until the rest arrives
+        self.stream.set_nonblocking(true)?;
+        let result = self.read(buf);
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+}
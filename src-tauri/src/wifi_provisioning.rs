@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+/// Time the WiFi module gets to answer a query or store a setting
+pub const AT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the WiFi module reports about the network it's on, from `AT+CWJAP?` and
+/// `AT+CIPSTA?`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WifiStatus {
+    /// None when the module isn't joined to a network
+    pub ssid: Option<String>,
+    /// Signal strength in dBm
+    pub rssi: Option<i32>,
+    pub ip: Option<String>,
+    pub gateway: Option<String>,
+    pub netmask: Option<String>,
+}
+
+/// Address settings for a module that shouldn't use DHCP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticIp {
+    pub ip: String,
+    pub gateway: String,
+    pub netmask: String,
+}
+
+/// Network to move the WiFi module to. The password is only sent to the module, never
+/// saved by the app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WifiProvisioning {
+    pub ssid: String,
+    pub password: String,
+    /// None goes back to DHCP
+    pub static_ip: Option<StaticIp>,
+}
+
+impl WifiProvisioning {
+    pub fn validate(&self) -> Result<()> {
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            return Err(anyhow!("The network name must be 1 to 32 characters"));
+        }
+        if !self.password.is_empty() && !(8..=64).contains(&self.password.len()) {
+            return Err(anyhow!(
+                "A WiFi password is 8 to 64 characters, or empty for an open network"
+            ));
+        }
+        // A line break would end the AT command early and start another
+        if self
+            .ssid
+            .chars()
+            .chain(self.password.chars())
+            .any(char::is_control)
+        {
+            return Err(anyhow!(
+                "The network name and password can't contain control characters"
+            ));
+        }
+        if let Some(static_ip) = &self.static_ip {
+            for (what, value) in [
+                ("IP address", &static_ip.ip),
+                ("gateway", &static_ip.gateway),
+                ("netmask", &static_ip.netmask),
+            ] {
+                if value.parse::<Ipv4Addr>().is_err() {
+                    return Err(anyhow!("{} isn't a valid {}", value, what));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Commands that set the address, sent before joining since the module drops off the
+    /// current network as soon as it's told to join another
+    pub fn address_commands(&self) -> Vec<String> {
+        match &self.static_ip {
+            Some(s) => vec![format!(
+                "AT+CIPSTA={},{},{}",
+                quote(&s.ip),
+                quote(&s.gateway),
+                quote(&s.netmask)
+            )],
+            None => vec!["AT+CWDHCP=1,1".to_string()],
+        }
+    }
+
+    /// Join the new network and keep it across power cycles
+    pub fn join_command(&self) -> String {
+        format!("AT+CWJAP={},{}", quote(&self.ssid), quote(&self.password))
+    }
+}
+
+/// Quote a value for an AT command; the module wants `"`, `,` and `\` escaped
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | ',' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The line that ends an AT command's reply, with whether it succeeded
+pub fn final_result(line: &str) -> Option<bool> {
+    match line.trim() {
+        "OK" => Some(true),
+        "ERROR" | "FAIL" => Some(false),
+        _ => None,
+    }
+}
+
+/// Fill in the status from the reply lines of `AT+CWJAP?` and `AT+CIPSTA?`
+pub fn parse_status(lines: &[String], status: &mut WifiStatus) {
+    for line in lines {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("+CWJAP:") {
+            // +CWJAP:"ssid","bssid",channel,rssi,...
+            let fields = split_fields(rest);
+            status.ssid = fields.first().cloned();
+            status.rssi = fields.get(3).and_then(|r| r.parse().ok());
+        } else if let Some(rest) = line.strip_prefix("+CIPSTA:") {
+            // +CIPSTA:ip:"192.168.1.20", also gateway and netmask
+            let Some((key, value)) = rest.split_once(':') else {
+                continue;
+            };
+            let value = Some(value.trim_matches('"').to_string());
+            match key {
+                "ip" => status.ip = value,
+                "gateway" => status.gateway = value,
+                "netmask" => status.netmask = value,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Comma-separated fields with quotes and escapes removed
+fn split_fields(text: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_quotes => field.extend(chars.next()),
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, password: &str) -> WifiProvisioning {
+        WifiProvisioning {
+            ssid: ssid.to_string(),
+            password: password.to_string(),
+            static_ip: None,
+        }
+    }
+
+    #[test]
+    fn control_characters_are_refused() {
+        assert!(network("Workshop", "hunter22").validate().is_ok());
+        assert!(network("Work\r\nAT+RST", "hunter22").validate().is_err());
+        assert!(network("Workshop", "hunter22\n").validate().is_err());
+        assert!(network("Work\tshop", "").validate().is_err());
+    }
+}
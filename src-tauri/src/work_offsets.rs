@@ -0,0 +1,116 @@
+use crate::job_queue::WORK_COORDINATE_SYSTEMS;
+use crate::tool_library::Tool;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Work coordinate system selected in the `$G` modal words; G54 if none is listed
+pub fn active_wcs(modal_state: &[String]) -> &'static str {
+    WORK_COORDINATE_SYSTEMS
+        .iter()
+        .find(|wcs| modal_state.iter().any(|w| w == *wcs))
+        .copied()
+        .unwrap_or("G54")
+}
+
+/// `P` number of a WCS for G10
+fn wcs_number(wcs: &str) -> usize {
+    WORK_COORDINATE_SYSTEMS
+        .iter()
+        .position(|w| *w == wcs)
+        .map_or(1, |i| i + 1)
+}
+
+/// G10 takes values in the active units; everything here is worked out in mm
+fn units_per_mm(modal_state: &[String]) -> f32 {
+    if modal_state.iter().any(|w| w == "G20") {
+        1.0 / 25.4
+    } else {
+        1.0
+    }
+}
+
+fn axis_index(axis: char) -> Result<usize> {
+    match axis.to_ascii_uppercase() {
+        'X' => Ok(0),
+        'Y' => Ok(1),
+        'Z' => Ok(2),
+        other => Err(anyhow!("Work zero can be set on X, Y or Z, not {}", other)),
+    }
+}
+
+/// Sets `axis` of the active WCS so the edge the tool is touching becomes zero. The edge is
+/// a radius away from the tool's centre, on the `direction` side (1 or -1) along the axis:
+/// touching the left face of stock from the left is X with direction 1.
+pub fn edge_zero_command(
+    modal_state: &[String],
+    axis: char,
+    direction: f32,
+    diameter: f32,
+) -> Result<String> {
+    let axis = axis.to_ascii_uppercase();
+    axis_index(axis)?;
+    if direction.abs() != 1.0 {
+        return Err(anyhow!("Edge direction must be 1 or -1"));
+    }
+    if !diameter.is_finite() || diameter < 0.0 {
+        return Err(anyhow!("Tool diameter can't be negative"));
+    }
+    // The tool's centre is a radius short of the edge, so that's its work coordinate
+    let position = -direction * diameter / 2.0 * units_per_mm(modal_state);
+    Ok(format!(
+        "G10 L20 P{} {}{:.4}",
+        wcs_number(active_wcs(modal_state)),
+        axis,
+        position
+    ))
+}
+
+/// Moves the active WCS origin by `delta` mm, from its offset `current` as read from `$#`
+pub fn shift_command(modal_state: &[String], current: &[f32], delta: [f32; 3]) -> Result<String> {
+    if current.len() < 3 {
+        return Err(anyhow!("The work offset from $# was missing axes"));
+    }
+    if delta.iter().any(|d| !d.is_finite()) {
+        return Err(anyhow!("The shift must be a number on each axis"));
+    }
+    let scale = units_per_mm(modal_state);
+    Ok(format!(
+        "G10 L2 P{} X{:.4} Y{:.4} Z{:.4}",
+        wcs_number(active_wcs(modal_state)),
+        (current[0] + delta[0]) * scale,
+        (current[1] + delta[1]) * scale,
+        (current[2] + delta[2]) * scale
+    ))
+}
+
+/// Puts `wcs` back to an offset read from `$#`. G92 can only be put back to zero, since
+/// G92 sets its offset from where the machine is.
+pub fn restore_command(modal_state: &[String], wcs: &str, offset: &[f32]) -> Result<String> {
+    if wcs == "G92" {
+        if offset.iter().any(|v| *v != 0.0) {
+            return Err(anyhow!("A G92 offset other than zero can't be put back"));
+        }
+        return Ok("G92.1".to_string());
+    }
+    if !WORK_COORDINATE_SYSTEMS.contains(&wcs) {
+        return Err(anyhow!("{} isn't a work coordinate system", wcs));
+    }
+    let scale = units_per_mm(modal_state);
+    let axes: Vec<String> = "XYZABC"
+        .chars()
+        .zip(offset)
+        .map(|(axis, value)| format!("{}{:.4}", axis, value * scale))
+        .collect();
+    Ok(format!("G10 L2 P{} {}", wcs_number(wcs), axes.join(" ")))
+}
+
+/// Result of an edge zero or shift, so the user can see what was sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkOffsetChange {
+    pub wcs: String,
+    pub command: String,
+    /// Tool whose radius the edge zero allowed for, when it came from the library
+    pub tool: Option<Tool>,
+    /// Diameter allowed for, in mm
+    pub tool_diameter: Option<f32>,
+}
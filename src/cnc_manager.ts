@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { GrblErrorTranslator } from './grbl_error_translator';
 
 export interface CncDevice {
@@ -7,6 +8,54 @@ export interface CncDevice {
   port: number;
   mac?: string;
   firmware?: string;
+  /** For serial devices `ip` holds the port name; port 0 picks 81, FluidNC's usual WebSocket port */
+  transport?: 'tcp' | 'ble' | 'serial' | 'telnet' | 'websocket' | 'tls';
+  /** Serial only; leave out to detect it */
+  baud_rate?: number;
+  /** TLS only: SHA-256 fingerprint of the controller's certificate, pinned on first connect */
+  certificate_pin?: string;
+  /** Network links only; leave out for TCP_NODELAY and the default timeouts */
+  socket?: SocketOptions;
+}
+
+export interface SocketOptions {
+  nodelay: boolean;
+  connect_timeout_ms: number;
+  read_timeout_ms: number;
+  write_timeout_ms: number;
+}
+
+export interface SerialPortEntry {
+  name: string;
+  vid?: number;
+  pid?: number;
+  manufacturer?: string;
+  product?: string;
+  serial_number?: string;
+  controller_guess?: string;
+}
+
+export interface SavedAddress {
+  ip: string;
+  port: number;
+  /** Seconds since the Unix epoch */
+  last_used: number;
+}
+
+/** Result of `jog`; a `cnc:jog-feedback` event with the same id reports how it ended */
+export interface JogFeedback {
+  id: number;
+  outcome: 'queued' | 'rejected' | 'completed' | 'interrupted';
+  command: string;
+  error_code?: number;
+  message?: string;
+}
+
+/** Payload of `cnc:discovery-finished` */
+export interface DiscoveryFinished {
+  devices: CncDevice[];
+  cancelled: boolean;
+  error?: string;
 }
 
 export interface CncConnection {
@@ -16,17 +65,63 @@ export interface CncConnection {
 
 export class CncManager {
   /**
-   * Discover CNC devices on the network
+   * Discover CNC devices on the network. `on_found` is called as each one answers; a
+   * device that announced itself can come again with its name and MAC, so key by address.
+   * Resolves with the whole list once the pass ends or `cancel_discovery` stops it.
+   */
+  static async discover_devices(on_found?: (device: CncDevice) => void): Promise<CncDevice[]> {
+    let resolve_finished: (finished: DiscoveryFinished) => void = () => {};
+    const finished = new Promise<DiscoveryFinished>((resolve) => { resolve_finished = resolve; });
+    const unlisten_found = await listen<CncDevice>("cnc:device-found", (event) => on_found?.(event.payload));
+    const unlisten_finished = await listen<DiscoveryFinished>("cnc:discovery-finished", (event) => resolve_finished(event.payload));
+    try {
+      await invoke("discover_cnc_devices");
+      const result = await finished;
+      if (result.error) {
+        throw new Error(result.error);
+      }
+      return result.devices;
+    } finally {
+      unlisten_found();
+      unlisten_finished();
+    }
+  }
+
+  /**
+   * Stop a discovery pass or subnet scan early; it finishes with what it found so far
+   */
+  static async cancel_discovery(): Promise<void> {
+    await invoke("cancel_discovery");
+  }
+
+  /**
+   * List serial ports, USB ones first
    */
-  static async discover_devices(): Promise<CncDevice[]> {
-    return await invoke<CncDevice[]>("discover_cnc_devices");
+  static async list_serial_ports(): Promise<SerialPortEntry[]> {
+    return await invoke<SerialPortEntry[]>("list_serial_ports");
   }
 
   /**
-   * Connect to a specific CNC device
+   * Device for a USB serial port. The USB serial number, when there is one, keeps the
+   * machine's saved profile attached to it whichever port it shows up on.
    */
-  static async connect(device: CncDevice): Promise<void> {
-    await invoke("connect_to_cnc", { device });
+  static serial_device(port: SerialPortEntry, baud_rate?: number): CncDevice {
+    return {
+      name: port.product ?? port.controller_guess ?? port.name,
+      ip: port.name,
+      port: 0,
+      mac: port.serial_number,
+      transport: 'serial',
+      baud_rate,
+    };
+  }
+
+  /**
+   * Connect to a specific CNC device. A read-only connection only watches:
+   * status polling works, every command is refused by the backend.
+   */
+  static async connect(device: CncDevice, read_only: boolean = false): Promise<void> {
+    await invoke("connect_to_cnc", { device, read_only });
     
     // Check for alarm status immediately after connecting
     try {
@@ -37,6 +132,22 @@ export class CncManager {
     }
   }
 
+  /**
+   * Connect to a typed-in address. The backend remembers it once connected, and
+   * discovery probes remembered addresses too.
+   */
+  static async connect_to_address(ip: string, port?: number, read_only: boolean = false): Promise<void> {
+    await invoke("connect_to_address", { ip, port, read_only });
+  }
+
+  static async get_saved_addresses(): Promise<SavedAddress[]> {
+    return await invoke<SavedAddress[]>("get_saved_addresses");
+  }
+
+  static async forget_saved_address(ip: string, port: number): Promise<void> {
+    await invoke("forget_saved_address", { ip, port });
+  }
+
   /**
    * Check current alarm status - can be called when needed
    */
@@ -44,6 +155,14 @@ export class CncManager {
     return await invoke<string>("check_cnc_alarm_status");
   }
 
+  /**
+   * Tell the backend the user is at the machine, restarting the idle power-down clock
+   * and waking the controller if the idle policy put it to sleep
+   */
+  static async wake(): Promise<void> {
+    await invoke("wake_machine");
+  }
+
   /**
    * Disconnect from current CNC device
    */
@@ -68,8 +187,8 @@ export class CncManager {
   /**
    * Jog the machine in a specific direction
    */
-  static async jog(axis: string, distance: number, feed_rate: number = 1000): Promise<string> {
-    return await invoke<string>("jog_cnc", { 
+  static async jog(axis: string, distance: number, feed_rate: number = 1000): Promise<JogFeedback> {
+    return await invoke<JogFeedback>("jog_cnc", { 
       axis: axis, 
       distance: distance, 
       feed_rate: feed_rate 
@@ -139,6 +258,27 @@ export class CncManager {
     return await this.send_command("~");
   }
 
+  /**
+   * Feed hold the running job; no more job lines are sent until it resumes
+   */
+  static async pause_job(): Promise<void> {
+    await invoke("pause_job");
+  }
+
+  /**
+   * Cycle start a held job
+   */
+  static async resume_job(): Promise<void> {
+    await invoke("resume_job");
+  }
+
+  /**
+   * Stop the running job: feed hold, then a soft reset once the machine is at rest
+   */
+  static async stop_job(): Promise<void> {
+    await invoke("stop_job");
+  }
+
   /**
    * Translate GRBL error responses to human-readable format
    */
@@ -160,6 +300,26 @@ export class CncManager {
     return GrblErrorTranslator.extractErrorCodes(response);
   }
 
+  /**
+   * Rewrite a Grbl 0.9 status report (<Idle,MPos:x,y,z,WPos:x,y,z,Buf:0,RX:0>)
+   * into the 1.1 pipe-separated form. Buf/RX are usage counts, not free space, so they are dropped.
+   */
+  static normalize_legacy_status(response: string): string {
+    return response.replace(/<([^|>]*,[^|>]*)>/, (_match, body: string) => {
+      const pieces = body.split(',');
+      const fields: string[] = [pieces[0]];
+      for (const piece of pieces.slice(1)) {
+        if (piece.includes(':')) {
+          fields.push(piece);
+        } else if (fields.length > 1) {
+          fields[fields.length - 1] += ',' + piece;
+        }
+      }
+      const kept = fields.filter(f => !f.startsWith('Buf:') && !f.startsWith('RX:'));
+      return '<' + kept.join('|') + '>';
+    });
+  }
+
   /**
    * Parse Grbl status response
    */
@@ -170,6 +330,7 @@ export class CncManager {
     workOffset?: { x: number; y: number; z: number };
     buffer_info?: { planner_blocks: number; rx_bytes: number };
     feed_speed?: { feed_rate: number; spindle_speed: number };
+    spindle_load?: number;
   } | null {
     // Clean up the response - remove "ok" and trim whitespace
     const clean_response = statusResponse.replace(/\n?ok\s*$/m, '').trim();
@@ -189,7 +350,7 @@ export class CncManager {
     
     // Parse Grbl status format: <State|MPos:x,y,z|...>
     // May include: Bf:, FS:, Pn:, WCO:, WPos:, Ov:, etc.
-    const status_match = clean_response.match(/<([^|>]+)(?:\|([^>]+))?>/);
+    const status_match = CncManager.normalize_legacy_status(clean_response).match(/<([^|>]+)(?:\|([^>]+))?>/);
     if (!status_match) return null;
 
     const [, state, status_fields] = status_match;
@@ -225,6 +386,7 @@ export class CncManager {
     let wpos: number[] = []; // Work position, if explicitly provided
     let buffer_info: { planner_blocks: number; rx_bytes: number } | undefined;
     let feed_speed: { feed_rate: number; spindle_speed: number } | undefined;
+    let spindle_load: number | undefined;
     
     if (status_fields) {
       const fields = status_fields.split('|');
@@ -254,6 +416,12 @@ export class CncManager {
               spindle_speed: fs_parts[1]
             };
           }
+        } else if (field.startsWith('SL:')) {
+          // Spindle load in percent (grblHAL builds whose spindle reports it)
+          const load = parseFloat(field.substring(3));
+          if (!isNaN(load)) {
+            spindle_load = load;
+          }
         }
       }
     }
@@ -299,6 +467,10 @@ export class CncManager {
       (result as any).feed_speed = feed_speed;
     }
 
+    if (spindle_load !== undefined) {
+      (result as any).spindle_load = spindle_load;
+    }
+
     return result;
   }
 }
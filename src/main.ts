@@ -2038,6 +2038,18 @@ window.addEventListener("DOMContentLoaded", () => {
   // Restore log visibility from saved state
   restore_log_visibility();
   
+  // Any interaction counts as activity for the idle power-down policy (at most every 10s)
+  let last_wake_ms = 0;
+  const note_user_activity = () => {
+    const now = Date.now();
+    if (is_connected && now - last_wake_ms > 10000) {
+      last_wake_ms = now;
+      CncManager.wake().catch((error) => console.warn('Could not report activity:', error));
+    }
+  };
+  window.addEventListener('pointerdown', note_user_activity);
+  window.addEventListener('keydown', note_user_activity);
+
   // Status update with self-scheduling setTimeout to prevent backups
   function scheduleStatusUpdate() {
     setTimeout(async () => {